@@ -7,7 +7,7 @@ use std::cell::RefCell;
 // Alloy imports
 use alloy::signers::icp::IcpSigner;
 use alloy::signers::Signer; // The Signer trait
-use alloy::primitives::Address;
+use alloy::primitives::{Address, U256};
 
 // Types module
 mod types;
@@ -15,10 +15,20 @@ use types::{
     Permissions, CreatePermissionsRequest, UpdatePermissionsRequest,
     ProtocolPermission, Recommendation, ExecutionResult,
     StorablePrincipal, StorableString, StorablePermissions,
-    StorableUserPosition, StorableApyHistoryRecord, StorableRebalanceExecution,
-    ProtocolApyInfo, ApyResponse, ApyParserStatus,
+    StorableUserPosition, StorableApyHistoryRecord, StorableApyHistoryKey, StorableApyAggregate, StorableApyAggregateKey,
+    StorableRebalanceExecution,
+    StorableUserSchedulerSettings, StorableDryRunTick, StorablePendingRecommendation, StorableOperationLock,
+    StorableSchedulerRun, StorableSchedulerRunEntry, StorableSchedulerExecutionSummary, StorableRebalanceSaga,
+    StorableYieldTenure, StorableYieldSnapshot, StorableTokenPrice, StorableChainConfig, StorableSentTransaction, StorableNonceState, StorableApyRetentionConfig, StorableFeePolicyConfig, StorableSchedulerConfig,
+    ProtocolApyInfo, ApyResponse, ApyParserStatus, ApyAnalytics,
     SchedulerConfig, SchedulerStatus, RebalanceExecution,
-    UserPosition, ApyHistoryRecord, // 🆕 APY Parser types
+    UserPosition, ApyHistoryRecord, ApyHistoryPage, // 🆕 APY Parser types
+    ApyResolution, ApyAggregate, ApyRetentionConfig, ApyRetentionReport,
+    ApySignal, ApySignalSnapshot, UserSchedulerSettings, DryRunTick,
+    ExecutionMode, PendingRecommendation, OperationLock, SchedulerRun,
+    SchedulerExecutionSummary, RebalanceSaga, PositionReconciliationReport, YieldReport,
+    BacktestRequest, BacktestReport, Portfolio, TokenPrice, PriceOracleConfig, ChainConfig,
+    FeePolicyConfig, FeeQuote, SentTransaction, NonceState,
 };
 
 // Services module
@@ -29,6 +39,9 @@ use services::{
     get_balance_usdc::get_balance_usdc,
     transfer_link::{transfer_link, transfer_link_human},
     send_eth::{send_eth, send_eth_human},
+    erc20, // 🆕 Chain-parameterized native and ERC-20 transfers
+    tx_tracker, // 🆕 Sent transaction tracking, speed-up and cancel
+    nonce_manager, // 🆕 Persistent nonce manager
     approve_usdc::{approve_usdc, approve_usdc_human, get_usdc_allowance, revoke_usdc_approval},
     approve_weth::{approve_weth_for_uniswap, approve_weth, approve_weth_human, get_weth_allowance, get_weth_balance, revoke_weth_approval},
     sign_message::{sign_message, sign_message_with_address, sign_hash},
@@ -38,8 +51,16 @@ use services::{
     compound::{supply_usdc_to_compound_with_permissions, withdraw_usdc_from_compound_with_permissions, get_compound_usdc_balance, get_apy as get_compound_apy}, // 🆕 Compound Service Methods
     rebalance::{execute_recommendation as execute_recommendation_impl, validate_recommendation}, // 🆕 Rebalance Service Methods
     rpc_service::{is_supported_chain, get_supported_chains_info}, // 🆕 RPC Service imports
+    chain_registry, // 🆕 Stable-memory chain registry
     scheduler, // 🆕 Scheduler module
+    approval_queue, // 🆕 Approval queue for scheduler recommendations
+    locks, // 🆕 Scheduler and per-user operation locks
+    rebalance_saga, // 🆕 Resumable rebalance sagas
+    position_reconciler, // 🆕 On-chain position reconciliation
+    yield_ledger, // 🆕 Per-position yield accounting
+    indexes, // 🆕 Secondary indexes over positions, permissions and history
     apy_parser, // 🆕 APY Parser module
+    apy_retention, // 🆕 APY history roll-up and pruning
 };
 
 // --- Types ---
@@ -51,6 +72,38 @@ const PERMISSIONS_MAP_MEMORY_ID: MemoryId = MemoryId::new(1);
 const APY_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(2);
 const USER_POSITIONS_MEMORY_ID: MemoryId = MemoryId::new(3);
 const REBALANCE_HISTORY_MEMORY_ID: MemoryId = MemoryId::new(4);
+const USER_SCHEDULER_SETTINGS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const DRY_RUN_TICKS_MEMORY_ID: MemoryId = MemoryId::new(6);
+const PENDING_RECOMMENDATIONS_MEMORY_ID: MemoryId = MemoryId::new(7);
+const OPERATION_LOCKS_MEMORY_ID: MemoryId = MemoryId::new(8);
+const SCHEDULER_RUN_MEMORY_ID: MemoryId = MemoryId::new(9);
+const SCHEDULER_SUMMARIES_MEMORY_ID: MemoryId = MemoryId::new(10);
+const REBALANCE_SAGAS_MEMORY_ID: MemoryId = MemoryId::new(11);
+const YIELD_TENURES_MEMORY_ID: MemoryId = MemoryId::new(12);
+const YIELD_SNAPSHOTS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const POSITIONS_BY_USER_MEMORY_ID: MemoryId = MemoryId::new(14);
+const POSITIONS_BY_MARKET_MEMORY_ID: MemoryId = MemoryId::new(15);
+const PERMISSIONS_BY_OWNER_MEMORY_ID: MemoryId = MemoryId::new(16);
+const REBALANCE_HISTORY_BY_USER_MEMORY_ID: MemoryId = MemoryId::new(17);
+const APY_SERIES_MEMORY_ID: MemoryId = MemoryId::new(18);
+const APY_AGGREGATES_MEMORY_ID: MemoryId = MemoryId::new(19);
+const PRICE_CACHE_MEMORY_ID: MemoryId = MemoryId::new(20);
+const CHAIN_REGISTRY_MEMORY_ID: MemoryId = MemoryId::new(21);
+const SENT_TRANSACTIONS_MEMORY_ID: MemoryId = MemoryId::new(22);
+const NONCE_STATE_MEMORY_ID: MemoryId = MemoryId::new(23);
+const SCHEDULER_RUN_ENTRIES_MEMORY_ID: MemoryId = MemoryId::new(24);
+const TENURES_BY_USER_MEMORY_ID: MemoryId = MemoryId::new(25);
+const TENURES_BY_MARKET_MEMORY_ID: MemoryId = MemoryId::new(26);
+const MIGRATION_CURSORS_MEMORY_ID: MemoryId = MemoryId::new(27);
+const APY_RETENTION_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(28);
+const FEE_POLICY_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(29);
+const SENT_TX_BY_OWNER_MEMORY_ID: MemoryId = MemoryId::new(30);
+const PENDING_TX_MEMORY_ID: MemoryId = MemoryId::new(31);
+const SETTLED_TX_BY_TIME_MEMORY_ID: MemoryId = MemoryId::new(32);
+const SCHEDULER_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(33);
+const SCHEDULER_RUN_QUEUE_MEMORY_ID: MemoryId = MemoryId::new(34);
+const PROPOSALS_BY_USER_MEMORY_ID: MemoryId = MemoryId::new(35);
+const PROPOSALS_BY_POSITION_MEMORY_ID: MemoryId = MemoryId::new(36);
 
 // Admin principals - hardcoded list of authorized administrators
 const ADMIN_PRINCIPALS: &[&str] = &[
@@ -75,13 +128,27 @@ thread_local! {
         )
     );
 
-    // Map RecordId -> APY History Record
-    pub static APY_HISTORY_MAP: RefCell<StableBTreeMap<StorableString, StorableApyHistoryRecord, Memory>> = RefCell::new(
+    // Map RecordId -> APY History Record (legacy string keys, migrated into APY_HISTORY_MAP)
+    pub static LEGACY_APY_HISTORY_MAP: RefCell<StableBTreeMap<StorableString, StorableApyHistoryRecord, Memory>> = RefCell::new(
         StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(APY_HISTORY_MEMORY_ID)),
         )
     );
 
+    // Map (Protocol, Chain, Token, Timestamp) -> APY History Record
+    pub static APY_HISTORY_MAP: RefCell<StableBTreeMap<StorableApyHistoryKey, StorableApyHistoryRecord, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(APY_SERIES_MEMORY_ID)),
+        )
+    );
+
+    // Map (Resolution, Protocol, Chain, Token, Bucket) -> Rolled-up APY history
+    pub static APY_AGGREGATES_MAP: RefCell<StableBTreeMap<StorableApyAggregateKey, StorableApyAggregate, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(APY_AGGREGATES_MEMORY_ID)),
+        )
+    );
+
     // Map PositionId -> User Position
     pub static USER_POSITIONS_MAP: RefCell<StableBTreeMap<StorableString, StorableUserPosition, Memory>> = RefCell::new(
         StableBTreeMap::init(
@@ -95,6 +162,216 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(REBALANCE_HISTORY_MEMORY_ID)),
         )
     );
+
+    // Map Principal -> Per-user scheduler settings
+    pub static USER_SCHEDULER_SETTINGS_MAP: RefCell<StableBTreeMap<StorablePrincipal, StorableUserSchedulerSettings, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(USER_SCHEDULER_SETTINGS_MEMORY_ID)),
+        )
+    );
+
+    // Map TickId -> Dry-run results of that scheduler tick
+    pub static DRY_RUN_TICKS_MAP: RefCell<StableBTreeMap<StorableString, StorableDryRunTick, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(DRY_RUN_TICKS_MEMORY_ID)),
+        )
+    );
+
+    // Map ProposalId -> Recommendation awaiting user approval
+    pub static PENDING_RECOMMENDATIONS_MAP: RefCell<StableBTreeMap<StorableString, StorablePendingRecommendation, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_RECOMMENDATIONS_MEMORY_ID)),
+        )
+    );
+
+    // Map LockKey -> Lock held by an in-progress operation
+    pub static OPERATION_LOCKS_MAP: RefCell<StableBTreeMap<StorableString, StorableOperationLock, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OPERATION_LOCKS_MEMORY_ID)),
+        )
+    );
+
+    // Map RunId -> Checkpoint of the scheduler run in progress
+    pub static SCHEDULER_RUN_MAP: RefCell<StableBTreeMap<StorableString, StorableSchedulerRun, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEDULER_RUN_MEMORY_ID)),
+        )
+    );
+
+    // Map "RunId|Index" -> Outcome of a position of the scheduler run in progress
+    pub static SCHEDULER_RUN_ENTRIES_MAP: RefCell<StableBTreeMap<StorableString, StorableSchedulerRunEntry, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEDULER_RUN_ENTRIES_MEMORY_ID)),
+        )
+    );
+
+    // Map TickId -> Summary of a completed scheduler tick
+    pub static SCHEDULER_SUMMARIES_MAP: RefCell<StableBTreeMap<StorableString, StorableSchedulerExecutionSummary, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEDULER_SUMMARIES_MEMORY_ID)),
+        )
+    );
+
+    // Map SagaId -> Rebalance saga (withdraw → approve → supply)
+    pub static REBALANCE_SAGAS_MAP: RefCell<StableBTreeMap<StorableString, StorableRebalanceSaga, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REBALANCE_SAGAS_MEMORY_ID)),
+        )
+    );
+
+    // Map TenureId -> Yield accounting of one protocol tenure
+    pub static YIELD_TENURES_MAP: RefCell<StableBTreeMap<StorableString, StorableYieldTenure, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(YIELD_TENURES_MEMORY_ID)),
+        )
+    );
+
+    // Map "TenureId#Timestamp" -> Accounting snapshot of a tenure
+    pub static YIELD_SNAPSHOTS_MAP: RefCell<StableBTreeMap<StorableString, StorableYieldSnapshot, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(YIELD_SNAPSHOTS_MEMORY_ID)),
+        )
+    );
+
+    // Index "Principal|TenureId" -> () over YIELD_TENURES_MAP
+    pub static TENURES_BY_USER_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TENURES_BY_USER_MEMORY_ID)),
+        )
+    );
+
+    // Index "MarketKey|TenureId" -> () over YIELD_TENURES_MAP
+    pub static TENURES_BY_MARKET_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TENURES_BY_MARKET_MEMORY_ID)),
+        )
+    );
+
+    // Index "Principal|PositionId" -> () over USER_POSITIONS_MAP
+    pub static POSITIONS_BY_USER_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(POSITIONS_BY_USER_MEMORY_ID)),
+        )
+    );
+
+    // Index "MarketKey|PositionId" -> () over USER_POSITIONS_MAP
+    pub static POSITIONS_BY_MARKET_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(POSITIONS_BY_MARKET_MEMORY_ID)),
+        )
+    );
+
+    // Index "Principal|PermissionsId" -> () over PERMISSIONS_MAP
+    pub static PERMISSIONS_BY_OWNER_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PERMISSIONS_BY_OWNER_MEMORY_ID)),
+        )
+    );
+
+    // Index "Principal|Timestamp|ExecutionId" -> () over REBALANCE_HISTORY_MAP
+    pub static REBALANCE_HISTORY_BY_USER_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REBALANCE_HISTORY_BY_USER_MEMORY_ID)),
+        )
+    );
+
+    // Map "ChainId|Asset" -> Last accepted Chainlink price
+    pub static PRICE_CACHE_MAP: RefCell<StableBTreeMap<StorableString, StorableTokenPrice, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PRICE_CACHE_MEMORY_ID)),
+        )
+    );
+
+    // Map ChainId -> Chain registry entry
+    pub static CHAIN_REGISTRY_MAP: RefCell<StableBTreeMap<u64, StorableChainConfig, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CHAIN_REGISTRY_MEMORY_ID)),
+        )
+    );
+
+    // Map TxHash -> Transaction signed by the canister
+    pub static SENT_TRANSACTIONS_MAP: RefCell<StableBTreeMap<StorableString, StorableSentTransaction, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SENT_TRANSACTIONS_MEMORY_ID)),
+        )
+    );
+
+    // Index "OwnerDigest|SentAt|TxHash" -> () over SENT_TRANSACTIONS_MAP
+    pub static SENT_TX_BY_OWNER_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SENT_TX_BY_OWNER_MEMORY_ID)),
+        )
+    );
+
+    // Index "TxHash" -> () over the pending records of SENT_TRANSACTIONS_MAP
+    pub static PENDING_TX_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_TX_MEMORY_ID)),
+        )
+    );
+
+    // Index "UpdatedAt|TxHash" -> () over the settled records of SENT_TRANSACTIONS_MAP
+    pub static SETTLED_TX_BY_TIME_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SETTLED_TX_BY_TIME_MEMORY_ID)),
+        )
+    );
+
+    // Map "chain_id:address" -> Nonce bookkeeping of a signing address
+    pub static NONCE_STATE_MAP: RefCell<StableBTreeMap<StorableString, StorableNonceState, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(NONCE_STATE_MEMORY_ID)),
+        )
+    );
+
+    // Map migration name -> Last legacy key migrated by an in-progress chunked migration
+    pub static MIGRATION_CURSORS_MAP: RefCell<StableBTreeMap<StorableString, StorableString, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MIGRATION_CURSORS_MEMORY_ID)),
+        )
+    );
+
+    // Single entry "config" -> APY retention policy
+    pub static APY_RETENTION_CONFIG_MAP: RefCell<StableBTreeMap<StorableString, StorableApyRetentionConfig, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(APY_RETENTION_CONFIG_MEMORY_ID)),
+        )
+    );
+
+    // Single entry "config" -> gas and fee settings
+    pub static FEE_POLICY_CONFIG_MAP: RefCell<StableBTreeMap<StorableString, StorableFeePolicyConfig, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FEE_POLICY_CONFIG_MEMORY_ID)),
+        )
+    );
+
+    // Single entry "config" -> scheduler configuration
+    pub static SCHEDULER_CONFIG_MAP: RefCell<StableBTreeMap<StorableString, StorableSchedulerConfig, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEDULER_CONFIG_MEMORY_ID)),
+        )
+    );
+
+    // Map "RunId|Index" -> PositionId queued for the scheduler run in progress
+    pub static SCHEDULER_RUN_QUEUE_MAP: RefCell<StableBTreeMap<StorableString, StorableString, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SCHEDULER_RUN_QUEUE_MEMORY_ID)),
+        )
+    );
+
+    // Index "Principal|ProposalId" -> () over PENDING_RECOMMENDATIONS_MAP
+    pub static PROPOSALS_BY_USER_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PROPOSALS_BY_USER_MEMORY_ID)),
+        )
+    );
+
+    // Index "PositionId|ProposalId" -> () over PENDING_RECOMMENDATIONS_MAP
+    pub static PROPOSALS_BY_POSITION_INDEX: RefCell<StableBTreeMap<StorableString, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PROPOSALS_BY_POSITION_MEMORY_ID)),
+        )
+    );
 }
 
 // --- Helper Functions ---
@@ -226,6 +503,16 @@ fn verify_user(user: Principal) -> Result<bool, String> {
     }
 }
 
+/// Caller of an endpoint that makes HTTPS outcalls; must be a registered user
+fn require_registered_caller() -> Result<Principal, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers are not allowed".to_string());
+    }
+    verify_user(caller)?;
+    Ok(caller)
+}
+
 // --- Permissions Management ---
 
 #[update]
@@ -320,6 +607,8 @@ async fn create_permissions(req: CreatePermissionsRequest) -> Result<Permissions
     // Normalize transfer limit addresses
     let normalized_limits = req.transfer_limits.into_iter().map(|mut l| {
         l.token_address = normalize_address(&l.token_address);
+        l.used_today = None;
+        l.last_reset_date = None;
         l
     }).collect();
 
@@ -345,12 +634,7 @@ async fn create_permissions(req: CreatePermissionsRequest) -> Result<Permissions
     
     // Store in stable memory
     ic_cdk::println!("✅ Step 5: Storing permissions in stable memory...");
-    PERMISSIONS_MAP.with(|map| {
-        map.borrow_mut().insert(
-            StorableString(permissions_id.clone()), 
-            StorablePermissions(permissions.clone())
-        );
-    });
+    indexes::save_permissions(&permissions);
     ic_cdk::println!("✅ Step 5 Complete: Permissions stored successfully");
     
     ic_cdk::println!("📋 Creation Summary:");
@@ -418,25 +702,9 @@ fn get_all_permissions() -> Result<Vec<Permissions>, String> {
     ic_cdk::println!("📋 Starting retrieval of all permissions for principal: {}", caller);
     
     // Collect all permissions owned by the caller
-    let mut result = Vec::new();
-    
-    PERMISSIONS_MAP.with(|map| {
-        let borrowed_map = map.borrow();
-        
-        ic_cdk::println!("✅ Step 1: Scanning permissions database...");
-        let total_permissions = borrowed_map.len();
-        ic_cdk::println!("  - Total permissions in database: {}", total_permissions);
-        
-        // Iterate through all permissions and filter by owner
-        for (id, permissions) in borrowed_map.iter() {
-            if permissions.0.owner == caller {
-                ic_cdk::println!("  - Found owned permission: {}", id.0);
-                result.push(permissions.0.clone());
-            }
-        }
-        
-        ic_cdk::println!("✅ Step 1 Complete: Database scan finished");
-    });
+    ic_cdk::println!("✅ Step 1: Looking up permissions by owner...");
+    let result = indexes::get_owner_permissions(caller);
+    ic_cdk::println!("✅ Step 1 Complete: Owner index scan finished");
     
     ic_cdk::println!("📋 Retrieval Summary:");
     ic_cdk::println!("  - Requested by: {}", caller);
@@ -531,7 +799,14 @@ fn update_permissions(req: UpdatePermissionsRequest) -> Result<Permissions, Stri
             ic_cdk::println!("    {}. Token {} - Daily: {}, Max TX: {}",
                             i + 1, limit.token_address, limit.daily_limit, limit.max_tx_amount);
         }
-        permissions.transfer_limits = limits;
+        // Transfers already made today keep counting against the new limits
+        permissions.transfer_limits = limits.into_iter().map(|mut limit| {
+            let previous = permissions.transfer_limits.iter()
+                .find(|l| normalize_address(&l.token_address) == normalize_address(&limit.token_address));
+            limit.used_today = previous.and_then(|l| l.used_today);
+            limit.last_reset_date = previous.and_then(|l| l.last_reset_date);
+            limit
+        }).collect();
         changes_made += 1;
     }
 
@@ -572,12 +847,7 @@ fn update_permissions(req: UpdatePermissionsRequest) -> Result<Permissions, Stri
     
     // Save the updated permissions
     ic_cdk::println!("✅ Step 5: Saving updated permissions to stable memory...");
-    PERMISSIONS_MAP.with(|map| {
-        map.borrow_mut().insert(
-            StorableString(permissions_id.clone()), 
-            StorablePermissions(permissions.clone())
-        );
-    });
+    indexes::save_permissions(&permissions);
     ic_cdk::println!("✅ Step 5 Complete: Permissions saved successfully");
     
     ic_cdk::println!("📋 Update Summary:");
@@ -634,9 +904,7 @@ fn delete_permissions(permissions_id: String) -> Result<bool, String> {
     
     // Delete the permissions
     ic_cdk::println!("✅ Step 3: Deleting permissions from stable memory...");
-    let removed = PERMISSIONS_MAP.with(|map| {
-        map.borrow_mut().remove(&StorableString(permissions_id.clone())).is_some()
-    });
+    let removed = indexes::remove_permissions(&permissions_id).is_some();
     
     if removed {
         ic_cdk::println!("✅ Step 3 Complete: Permissions successfully deleted");
@@ -689,6 +957,9 @@ fn update_protocol_permission(
     } else {
         ic_cdk::println!("  - Max TX Amount: No limit");
     }
+    if let Some(max_usd) = protocol_permission.max_usd_per_tx {
+        ic_cdk::println!("  - Max TX Value: ${:.2}", max_usd);
+    }
     if let Some(daily) = protocol_permission.daily_limit {
         ic_cdk::println!("  - Daily Limit: {}", daily);
     } else {
@@ -777,6 +1048,68 @@ async fn send_eth_human_readable(to_address: String, amount_ether: String) -> Re
     send_eth_human(to_address, amount_ether).await
 }
 
+/// Send the native token of a chain (ETH on every built-in chain) to an address
+/// Amount should be in Wei format; the native token must be whitelisted as
+/// the zero address in the caller's permissions for the chain
+#[update]
+async fn send_native(chain_id: u64, to: String, amount: String) -> Result<String, String> {
+    let to = to.parse::<Address>().map_err(|e| format!("Invalid address: {}", e))?;
+    let amount = amount.parse::<U256>().map_err(|e| format!("Invalid amount: {}", e))?;
+
+    let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "send_native")?;
+    erc20::send_native(chain_id, to, amount, caller).await
+}
+
+/// Transfer ERC-20 tokens to an address on any supported chain
+/// `token` is a token address or a registry symbol (e.g. "USDC"); amount is in
+/// token units and subject to the caller's whitelist and transfer limits
+#[update]
+async fn transfer_token(chain_id: u64, token: String, to: String, amount: String) -> Result<String, String> {
+    let token_address = resolve_token_address(&token, chain_id)?;
+    let to = to.parse::<Address>().map_err(|e| format!("Invalid address: {}", e))?;
+    let amount = amount.parse::<U256>().map_err(|e| format!("Invalid amount: {}", e))?;
+
+    let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "transfer_token")?;
+    erc20::transfer_token(chain_id, token_address, to, amount, caller).await
+}
+
+/// Approve a whitelisted protocol to spend ERC-20 tokens on any supported chain
+/// `token` is a token address or a registry symbol; amount is in token units,
+/// zero revokes the approval
+#[update]
+async fn approve(chain_id: u64, token: String, spender: String, amount: String) -> Result<String, String> {
+    let token_address = resolve_token_address(&token, chain_id)?;
+    let spender = spender.parse::<Address>().map_err(|e| format!("Invalid spender address: {}", e))?;
+    let amount = amount.parse::<U256>().map_err(|e| format!("Invalid amount: {}", e))?;
+
+    let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "approve")?;
+    erc20::approve_token(chain_id, token_address, spender, amount, caller).await
+}
+
+// --- Sent Transaction Methods ---
+
+/// Re-sign a pending transaction at the same nonce with bumped fees
+/// Returns the replacement; a hash that was already replaced resolves to its latest replacement
+#[update]
+async fn speed_up_tx(tx_hash: String) -> Result<SentTransaction, String> {
+    tx_tracker::speed_up_transaction(ic_cdk::caller(), &tx_hash).await
+}
+
+/// Cancel a pending transaction with a zero-value self-transfer at the same nonce
+#[update]
+async fn cancel_tx(tx_hash: String) -> Result<SentTransaction, String> {
+    tx_tracker::cancel_transaction(ic_cdk::caller(), &tx_hash).await
+}
+
+/// Get the transactions signed for the caller, newest first
+#[query]
+fn get_my_transactions() -> Vec<SentTransaction> {
+    tx_tracker::get_user_transactions(ic_cdk::caller())
+}
+
 // --- Approve Service Methods ---
 
 /// Approve USDC spending for a spender address
@@ -905,6 +1238,7 @@ async fn supply_link_to_aave_secured(
     permissions_id: String
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "supply_link_to_aave")?;
     supply_link_to_aave_with_permissions(amount_human, permissions_id, caller).await
 }
 
@@ -915,6 +1249,7 @@ async fn withdraw_link_from_aave_secured(
     permissions_id: String
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "withdraw_link_from_aave")?;
     withdraw_link_from_aave_with_permissions(amount_human, permissions_id, caller).await
 }
 
@@ -927,6 +1262,7 @@ async fn supply_to_aave_secured(
     token_symbol: String,
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "supply_to_aave")?;
     let perminissions = get_permissions(permissions_id.clone())
         .map_err(|e| format!("Failed to get permissions: {}", e))?;
     let chain_id = perminissions.chain_id;
@@ -935,6 +1271,8 @@ async fn supply_to_aave_secured(
 }
 
 /// Withdraw any token from AAVE with permission verification
+///
+/// Pass "max" as the amount to withdraw the full aToken balance.
 #[update]
 async fn withdraw_from_aave_secured(
     amount_human: String,
@@ -943,6 +1281,7 @@ async fn withdraw_from_aave_secured(
     token_symbol: String,
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "withdraw_from_aave")?;
     let perminissions = get_permissions(permissions_id.clone())
         .map_err(|e| format!("Failed to get permissions: {}", e))?;
     let chain_id = perminissions.chain_id;
@@ -966,16 +1305,20 @@ async fn supply_usdc_to_compound_secured(
     permissions_id: String
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "supply_usdc_to_compound")?;
     supply_usdc_to_compound_with_permissions(amount_human, permissions_id, caller).await
 }
 
 /// Withdraw USDC from Compound with permission verification
+///
+/// Pass "max" as the amount to withdraw the full Comet balance.
 #[update]
 async fn withdraw_usdc_from_compound_secured(
     amount_human: String, 
     permissions_id: String
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "withdraw_usdc_from_compound")?;
     withdraw_usdc_from_compound_with_permissions(amount_human, permissions_id, caller).await
 }
 
@@ -994,9 +1337,28 @@ async fn execute_recommendation(
     permissions_id: String
 ) -> Result<ExecutionResult, String> {
     let caller = ic_cdk::caller();
+    let _lock = locks::try_acquire_user_lock(caller, "execute_recommendation")?;
     execute_recommendation_impl(recommendation, permissions_id, caller).await
 }
 
+/// Get the caller's rebalance sagas (most recent first)
+#[query]
+fn get_my_rebalance_sagas(active_only: bool) -> Vec<RebalanceSaga> {
+    let caller = ic_cdk::caller();
+    ic_cdk::println!("🧭 Getting rebalance sagas for user: {}", caller);
+
+    rebalance_saga::get_sagas(Some(caller), active_only, None)
+}
+
+/// Retry a stuck rebalance saga of the caller immediately
+#[update]
+async fn resume_my_rebalance_saga(saga_id: String) -> Result<RebalanceSaga, String> {
+    let caller = ic_cdk::caller();
+    ic_cdk::println!("🔁 Resuming rebalance saga {} for user: {}", saga_id, caller);
+
+    rebalance_saga::resume_saga(saga_id, Some(caller)).await
+}
+
 /// Validate recommendation without executing
 #[query]
 fn validate_recommendation_input(
@@ -1026,9 +1388,6 @@ fn validate_recommendation_input(
 #[query]
 fn get_supported_chains() -> Vec<(u64, String)> {
     get_supported_chains_info()
-        .into_iter()
-        .map(|(id, name)| (id, name.to_string()))
-        .collect()
 }
 
 /// Check if a chain is supported
@@ -1037,6 +1396,46 @@ fn is_chain_supported(chain_id: u64) -> bool {
     is_supported_chain(chain_id)
 }
 
+/// Get every chain registry entry, including disabled chains (Admin only)
+#[query]
+fn admin_get_chains() -> Result<Vec<ChainConfig>, String> {
+    is_admin()?;
+    ic_cdk::println!("🔍 [ADMIN] Getting chain registry");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(chain_registry::list_chains())
+}
+
+/// Add a chain or replace its registry entry (Admin only)
+#[update]
+fn admin_upsert_chain(config: ChainConfig) -> Result<ChainConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🌐 [ADMIN] Upserting chain {} ({})", config.chain_id, config.name);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    chain_registry::upsert_chain(config)
+}
+
+/// Enable or disable a chain (Admin only)
+#[update]
+fn admin_set_chain_enabled(chain_id: u64, enabled: bool) -> Result<ChainConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🌐 [ADMIN] Setting chain {} enabled: {}", chain_id, enabled);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    chain_registry::set_chain_enabled(chain_id, enabled)
+}
+
+/// Remove a chain no position refers to (Admin only)
+#[update]
+fn admin_remove_chain(chain_id: u64) -> Result<ChainConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🗑️ [ADMIN] Removing chain {}", chain_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    chain_registry::remove_chain(chain_id)
+}
+
 // --- Admin API ---
 
 /// Helper function to get token address from symbol or address string
@@ -1046,20 +1445,19 @@ fn resolve_token_address(token: &str, chain_id: u64) -> Result<Address, String>
         token.parse::<Address>()
             .map_err(|_| format!("Invalid token address format: {}", token))
     } else {
-        // Resolve common token symbols to addresses based on chain_id
-        use services::rpc_service::{SEPOLIA_CHAIN_ID, ARBITRUM_CHAIN_ID, BASE_CHAIN_ID, OPTIMISM_CHAIN_ID};
-
+        // Resolve token symbols to the assets of the chain's protocol deployments
         let token_upper = token.to_uppercase();
-        match (token_upper.as_str(), chain_id) {
-            // USDC addresses
-            ("USDC", ARBITRUM_CHAIN_ID) => "0xaf88d065e77c8cc2239327c5edb3a432268e5831".parse().map_err(|_| "Parse error".to_string()),
-            ("USDC", BASE_CHAIN_ID) => "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913".parse().map_err(|_| "Parse error".to_string()),
-            ("USDC", OPTIMISM_CHAIN_ID) => "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85".parse().map_err(|_| "Parse error".to_string()),
-            ("USDC", SEPOLIA_CHAIN_ID) => "0x1c7D4B196Cb0C7B01d743Fbc6116a902379C7238".parse().map_err(|_| "Parse error".to_string()),
-
-
-            _ => Err(format!("Token '{}' not supported on chain_id {}", token, chain_id))
-        }
+        chain_registry::get_enabled_chain(chain_id)
+            .and_then(|chain| {
+                chain.protocols
+                    .into_iter()
+                    .flat_map(|deployment| deployment.assets)
+                    .find(|asset| asset.symbol == token_upper)
+            })
+            .ok_or_else(|| format!("Token '{}' not supported on chain_id {}", token, chain_id))?
+            .token_address
+            .parse::<Address>()
+            .map_err(|_| "Parse error".to_string())
     }
 }
 
@@ -1107,8 +1505,8 @@ async fn get_current_apy(token: String, chain_id: u64) -> Result<ApyResponse, St
         }
     }
 
-    // Try to get Compound APY (only on Arbitrum for USDC market)
-    if chain_id == services::rpc_service::ARBITRUM_CHAIN_ID {
+    // Try to get Compound APY (USDC market, where deployed)
+    if chain_registry::get_protocol_deployment(chain_id, "COMPOUND").is_some() {
         ic_cdk::println!("📊 Fetching Compound APY...");
         match get_compound_apy(chain_id).await {
             Ok(apy) => {
@@ -1124,7 +1522,7 @@ async fn get_current_apy(token: String, chain_id: u64) -> Result<ApyResponse, St
             }
         }
     } else {
-        ic_cdk::println!("ℹ️ Compound is only available on Arbitrum (chain_id: 42161)");
+        ic_cdk::println!("ℹ️ Compound is not deployed on chain_id {}", chain_id);
     }
 
     if rates.is_empty() {
@@ -1140,6 +1538,95 @@ async fn get_current_apy(token: String, chain_id: u64) -> Result<ApyResponse, St
     })
 }
 
+/// Get the USD price of a token from its Chainlink feed
+///
+/// `token` is a symbol ("ETH", "USDC", "LINK") or a token address on `chain_id`.
+/// Served from the price cache while fresh. Only registered users can refresh it.
+#[update]
+async fn get_price(token: String, chain_id: u64) -> Result<TokenPrice, String> {
+    require_registered_caller()?;
+    ic_cdk::println!("💲 Getting price of '{}' on chain_id {}", token, chain_id);
+
+    services::price_oracle::get_price(&token, chain_id).await
+}
+
+/// Get price oracle configuration (Admin only)
+#[query]
+fn admin_get_price_oracle_config() -> Result<PriceOracleConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🔍 [ADMIN] Getting price oracle configuration");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(services::price_oracle::get_price_oracle_config())
+}
+
+/// Update price oracle configuration (Admin only)
+#[update]
+fn admin_set_price_oracle_config(config: PriceOracleConfig) -> Result<PriceOracleConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🔧 [ADMIN] Updating price oracle configuration");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    services::price_oracle::set_price_oracle_config(config)
+}
+
+/// Get fee policy configuration (Admin only)
+#[query]
+fn admin_get_fee_policy_config() -> Result<FeePolicyConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🔍 [ADMIN] Getting fee policy configuration");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(services::fee_policy::get_fee_policy_config())
+}
+
+/// Update fee policy configuration (Admin only)
+///
+/// Per-chain fee caps are part of the chain registry (`admin_upsert_chain`).
+#[update]
+fn admin_set_fee_policy_config(config: FeePolicyConfig) -> Result<FeePolicyConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("🔧 [ADMIN] Updating fee policy configuration");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    services::fee_policy::set_fee_policy_config(config)
+}
+
+/// Get the fees the canister would sign with on a chain right now (Admin only)
+///
+/// Fails with "Fee cap exceeded" while the chain's fee cap blocks sending.
+#[update]
+async fn admin_get_fee_quote(chain_id: u64) -> Result<FeeQuote, String> {
+    is_admin()?;
+    ic_cdk::println!("⛽ [ADMIN] Getting fee quote for chain_id {}", chain_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    services::fee_policy::get_fee_quote(chain_id).await
+}
+
+/// Get the nonce state of every signing address (Admin only)
+#[query]
+fn admin_get_nonce_states() -> Result<Vec<NonceState>, String> {
+    is_admin()?;
+    ic_cdk::println!("🔍 [ADMIN] Getting nonce states");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(nonce_manager::get_nonce_states())
+}
+
+/// Reconcile the nonce of an address with the network before its next transaction (Admin only)
+#[update]
+fn admin_resync_nonce(chain_id: u64, address: String) -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("🔢 [ADMIN] Resyncing nonce of {} on chain_id {}", address, chain_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    let address = address.parse::<Address>().map_err(|e| format!("Invalid address: {}", e))?;
+    nonce_manager::mark_for_resync(address, chain_id);
+
+    Ok(format!("Nonce of 0x{:x} on chain {} will be reconciled before its next transaction", address, chain_id))
+}
+
 // --- Scheduler Admin API ---
 
 /// Initialize scheduler (Admin only) - for existing canisters that were deployed before scheduler
@@ -1228,6 +1715,59 @@ fn admin_set_min_position_size(amount_usd: f64) -> Result<String, String> {
     scheduler::set_min_position_size(amount_usd)
 }
 
+/// Set the APY signal used for rebalance decisions (Admin only)
+#[update]
+fn admin_set_apy_signal(signal: ApySignal, window_seconds: u64, min_samples: u64) -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("📈 [ADMIN] Setting APY signal to {:?}", signal);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::set_apy_signal(signal, window_seconds, min_samples)
+}
+
+/// Set the EMA signal half-life in seconds (Admin only)
+#[update]
+fn admin_set_ema_half_life(seconds: u64) -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("📈 [ADMIN] Setting EMA half-life to {} seconds", seconds);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::set_ema_half_life(seconds)
+}
+
+/// Get all APY signals for a protocol/asset/chain using the scheduler window (Admin only)
+#[query]
+fn admin_get_apy_signals(protocol: String, asset: String, chain_id: u64) -> Result<ApySignalSnapshot, String> {
+    is_admin()?;
+    ic_cdk::println!("📈 [ADMIN] Getting APY signals for {} {} on chain {}", protocol, asset, chain_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    let config = scheduler::get_scheduler_config()?;
+    Ok(services::apy_signals::get_apy_signal_snapshot(&protocol, &asset, chain_id, &config))
+}
+
+/// Get APY statistics, lead times and protocol spreads of an asset on a chain (Admin only)
+/// Defaults to a 30-day window; used to pick `apy_threshold_percent` per market
+#[query]
+fn admin_get_apy_analytics(asset: String, chain_id: u64, window_seconds: Option<u64>) -> Result<ApyAnalytics, String> {
+    is_admin()?;
+    ic_cdk::println!("📊 [ADMIN] Getting APY analytics for {} on chain {}", asset, chain_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    services::apy_analytics::get_apy_analytics(&asset, chain_id, window_seconds)
+}
+
+/// Replay APY history through the scheduler's decision logic (Admin only)
+/// Unset strategy fields use the live scheduler config; `fixtures` replace stored history
+#[query]
+fn admin_run_backtest(request: BacktestRequest) -> Result<BacktestReport, String> {
+    is_admin()?;
+    ic_cdk::println!("🧪 [ADMIN] Running backtest for {} on chain {}", request.asset, request.chain_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    services::backtester::run_backtest(&request)
+}
+
 /// Manually trigger scheduler execution (Admin only)
 #[update]
 async fn admin_trigger_rebalance() -> Result<Vec<RebalanceExecution>, String> {
@@ -1238,6 +1778,158 @@ async fn admin_trigger_rebalance() -> Result<Vec<RebalanceExecution>, String> {
     scheduler::trigger_manual_execution().await
 }
 
+/// Enable or disable global scheduler dry-run mode (Admin only)
+#[update]
+fn admin_set_scheduler_dry_run(enabled: bool) -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("🧪 [ADMIN] Setting scheduler dry-run mode to {}", enabled);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::set_dry_run(enabled)
+}
+
+/// Set the dry-run override for a specific user (Admin only)
+/// Pass None to make the user follow the global setting
+#[update]
+fn admin_set_user_dry_run(user: Principal, dry_run: Option<bool>) -> Result<UserSchedulerSettings, String> {
+    is_admin()?;
+    ic_cdk::println!("🧪 [ADMIN] Setting dry-run override for user {} to {:?}", user, dry_run);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::set_user_dry_run(user, dry_run)
+}
+
+/// Evaluate all tracked positions without executing anything (Admin only)
+/// Returns the tick ID to read the results with once the run completes
+#[update]
+async fn admin_trigger_dry_run() -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("🧪 [ADMIN] Manually triggering scheduler dry run");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::trigger_dry_run().await
+}
+
+/// Get dry-run results of a specific scheduler tick (Admin only)
+#[query]
+fn admin_get_dry_run_tick(tick_id: String) -> Result<DryRunTick, String> {
+    is_admin()?;
+    ic_cdk::println!("🧪 [ADMIN] Getting dry-run results for {}", tick_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::get_dry_run_tick(tick_id)
+}
+
+/// Get the most recent dry-run ticks (Admin only)
+#[query]
+fn admin_get_dry_run_ticks(limit: Option<u64>) -> Result<Vec<DryRunTick>, String> {
+    is_admin()?;
+    ic_cdk::println!("🧪 [ADMIN] Getting recent dry-run ticks (limit: {:?})", limit);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(scheduler::get_dry_run_ticks(limit))
+}
+
+/// Set how long pending recommendations stay approvable (Admin only)
+#[update]
+fn admin_set_proposal_ttl(seconds: u64) -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("📬 [ADMIN] Setting proposal TTL to {} seconds", seconds);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::set_proposal_ttl(seconds)
+}
+
+/// Get all recommendations in the approval queue (Admin only)
+#[query]
+fn admin_get_pending_recommendations(limit: Option<u64>) -> Result<Vec<PendingRecommendation>, String> {
+    is_admin()?;
+    ic_cdk::println!("📬 [ADMIN] Getting approval queue (limit: {:?})", limit);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(approval_queue::get_all_proposals(limit))
+}
+
+/// Set scheduler batch size and delay between batches (Admin only)
+#[update]
+fn admin_set_scheduler_batching(batch_size: u64, batch_interval_seconds: u64) -> Result<String, String> {
+    is_admin()?;
+    ic_cdk::println!("📦 [ADMIN] Setting scheduler batches to {} positions every {}s", batch_size, batch_interval_seconds);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::set_batch_size(batch_size, batch_interval_seconds)
+}
+
+/// Get progress of the scheduler run in progress (Admin only)
+#[query]
+fn admin_get_scheduler_run() -> Result<Option<SchedulerRun>, String> {
+    is_admin()?;
+    ic_cdk::println!("📦 [ADMIN] Getting active scheduler run");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(scheduler::get_active_run())
+}
+
+/// Get scheduler tick summaries, most recent first (Admin only)
+#[query]
+fn admin_get_tick_summaries(offset: Option<u64>, limit: Option<u64>) -> Result<Vec<SchedulerExecutionSummary>, String> {
+    is_admin()?;
+    ic_cdk::println!("📋 [ADMIN] Getting tick summaries (offset: {:?}, limit: {:?})", offset, limit);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(scheduler::get_tick_summaries(offset, limit))
+}
+
+/// Get the summary of a specific scheduler tick (Admin only)
+#[query]
+fn admin_get_tick_summary(tick_id: String) -> Result<SchedulerExecutionSummary, String> {
+    is_admin()?;
+    ic_cdk::println!("📋 [ADMIN] Getting tick summary {}", tick_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    scheduler::get_tick_summary(tick_id)
+}
+
+/// Get the number of stored scheduler tick summaries (Admin only)
+#[query]
+fn admin_get_tick_summary_count() -> Result<u64, String> {
+    is_admin()?;
+    ic_cdk::println!("📋 [ADMIN] Getting tick summary count");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(scheduler::get_tick_summary_count())
+}
+
+/// Get rebalance sagas of all users (Admin only)
+#[query]
+fn admin_get_rebalance_sagas(active_only: bool, limit: Option<u64>) -> Result<Vec<RebalanceSaga>, String> {
+    is_admin()?;
+    ic_cdk::println!("🧭 [ADMIN] Getting rebalance sagas (active only: {}, limit: {:?})", active_only, limit);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(rebalance_saga::get_sagas(None, active_only, limit))
+}
+
+/// Retry any stuck rebalance saga immediately (Admin only)
+#[update]
+async fn admin_resume_rebalance_saga(saga_id: String) -> Result<RebalanceSaga, String> {
+    is_admin()?;
+    ic_cdk::println!("🔁 [ADMIN] Resuming rebalance saga {}", saga_id);
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    rebalance_saga::resume_saga(saga_id, None).await
+}
+
+/// Get locks held by in-progress operations (Admin only)
+#[query]
+fn admin_get_active_locks() -> Result<Vec<OperationLock>, String> {
+    is_admin()?;
+    ic_cdk::println!("🔒 [ADMIN] Getting active operation locks");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(locks::get_active_locks())
+}
+
 /// Get scheduler status and statistics (Admin only)
 #[query]
 fn admin_get_scheduler_status() -> Result<SchedulerStatus, String> {
@@ -1315,6 +2007,20 @@ async fn create_position(
     ).await
 }
 
+/// Import balances the caller already holds in supported protocols as positions
+///
+/// Meant to be called once after `generate_evm_address`. Every registered
+/// protocol market on every supported chain is scanned; non-zero balances
+/// without a position are imported and linked to `permissions_id`.
+#[update]
+async fn import_my_positions(permissions_id: String) -> Result<Vec<UserPosition>, String> {
+    let caller = ic_cdk::caller();
+
+    ic_cdk::println!("📥 Importing existing positions for user: {}", caller);
+
+    position_reconciler::import_positions(caller, permissions_id).await
+}
+
 /// Get all positions for the current user
 #[query]
 fn get_my_positions() -> Vec<UserPosition> {
@@ -1324,6 +2030,26 @@ fn get_my_positions() -> Vec<UserPosition> {
     apy_parser::get_user_positions(caller)
 }
 
+/// Get the caller's wallet, protocol and native gas balances on every supported chain, valued in USD
+#[update]
+async fn get_my_portfolio() -> Result<Portfolio, String> {
+    let caller = require_registered_caller()?;
+    ic_cdk::println!("💼 Getting portfolio for user: {}", caller);
+
+    services::portfolio::get_portfolio(caller).await
+}
+
+/// Get the caller's earned interest per protocol tenure for a period
+///
+/// `from` and `to` are timestamps in milliseconds; both default to the full history.
+#[query]
+fn get_my_yield_report(from: Option<u64>, to: Option<u64>) -> Result<YieldReport, String> {
+    let caller = ic_cdk::caller();
+    ic_cdk::println!("📈 Getting yield report for user: {}", caller);
+
+    yield_ledger::get_yield_report(caller, from, to)
+}
+
 /// Update position tracking status or size
 #[update]
 fn update_position(
@@ -1363,6 +2089,59 @@ fn get_position(position_id: String) -> Result<UserPosition, String> {
     Ok(position)
 }
 
+/// Choose auto-execute or approval-required for a position (None = follow user setting)
+#[update]
+fn set_position_execution_mode(position_id: String, execution_mode: Option<ExecutionMode>) -> Result<UserPosition, String> {
+    let caller = ic_cdk::caller();
+
+    ic_cdk::println!("🔄 Setting execution mode of position {} for user: {}", position_id, caller);
+
+    apy_parser::set_position_execution_mode(position_id, caller, execution_mode)
+}
+
+// --- Recommendation Approval API ---
+
+/// Choose auto-execute or approval-required for all of the caller's positions
+#[update]
+fn set_my_execution_mode(execution_mode: Option<ExecutionMode>) -> Result<UserSchedulerSettings, String> {
+    let caller = ic_cdk::caller();
+
+    ic_cdk::println!("📬 Setting execution mode for user: {}", caller);
+
+    scheduler::set_user_execution_mode(caller, execution_mode)
+}
+
+/// Get scheduler recommendations waiting for the caller's approval
+#[query]
+fn get_my_pending_recommendations() -> Vec<PendingRecommendation> {
+    let caller = ic_cdk::caller();
+    ic_cdk::println!("📬 Getting pending recommendations for user: {}", caller);
+
+    approval_queue::get_user_pending_proposals(caller)
+}
+
+/// Approve a pending recommendation and execute it
+#[update]
+async fn approve_recommendation(proposal_id: String) -> Result<RebalanceExecution, String> {
+    let caller = ic_cdk::caller();
+
+    ic_cdk::println!("✅ Approving recommendation {} for user: {}", proposal_id, caller);
+
+    let _lock = locks::try_acquire_user_lock(caller, "approve_recommendation")?;
+
+    approval_queue::approve_proposal(proposal_id, caller).await
+}
+
+/// Reject a pending recommendation
+#[update]
+fn reject_recommendation(proposal_id: String) -> Result<PendingRecommendation, String> {
+    let caller = ic_cdk::caller();
+
+    ic_cdk::println!("🚫 Rejecting recommendation {} for user: {}", proposal_id, caller);
+
+    approval_queue::reject_proposal(proposal_id, caller)
+}
+
 // --- APY Parser Admin API ---
 
 /// Initialize APY parser (Admin only)
@@ -1416,24 +2195,69 @@ async fn admin_trigger_apy_collection() -> Result<String, String> {
     apy_parser::trigger_manual_apy_collection().await
 }
 
-/// Get APY history for a protocol/asset/chain (Admin only)
+/// Get a page of APY history for a protocol/asset/chain within [from, to] (Admin only)
 #[query]
 fn admin_get_apy_history(
     protocol: String,
     asset: String,
     chain_id: u64,
+    from: Option<u64>,
+    to: Option<u64>,
+    cursor: Option<u64>,
     limit: Option<u64>,
-) -> Vec<ApyHistoryRecord> {
-    // Admin check
-    if let Err(e) = is_admin() {
-        ic_cdk::println!("❌ Admin check failed: {}", e);
-        return vec![];
-    }
-
+) -> Result<ApyHistoryPage, String> {
+    is_admin()?;
     ic_cdk::println!("📜 [ADMIN] Getting APY history for {} {} on chain {}", protocol, asset, chain_id);
     ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
 
-    apy_parser::get_apy_history(&protocol, &asset, chain_id, limit)
+    apy_parser::get_apy_history(&protocol, &asset, chain_id, from, to, cursor, limit)
+}
+
+/// Get rolled-up APY history of a protocol/asset/chain within [from, to] (Public query method)
+#[query]
+fn get_apy_aggregates(
+    protocol: String,
+    asset: String,
+    chain_id: u64,
+    resolution: ApyResolution,
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<u64>,
+) -> Result<Vec<ApyAggregate>, String> {
+    ic_cdk::println!("📜 [PUBLIC] Getting {:?} APY aggregates for {} {} on chain {}", resolution, protocol, asset, chain_id);
+    ic_cdk::println!("📝 Requested by principal: {}", ic_cdk::caller());
+
+    apy_retention::get_apy_aggregates(&protocol, &asset, chain_id, resolution, from, to, limit)
+}
+
+/// Get the APY history retention policy (Admin only)
+#[query]
+fn admin_get_apy_retention_config() -> Result<ApyRetentionConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("📋 [ADMIN] Getting APY retention policy");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(apy_retention::get_retention_config())
+}
+
+/// Set the APY history retention policy (Admin only)
+#[update]
+fn admin_set_apy_retention_config(config: ApyRetentionConfig) -> Result<ApyRetentionConfig, String> {
+    is_admin()?;
+    ic_cdk::println!("⚙️ [ADMIN] Setting APY retention policy");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    apy_retention::set_retention_config(config)
+}
+
+/// Roll up and prune APY history now, even if the retention timer is disabled (Admin only)
+#[update]
+fn admin_run_apy_retention() -> Result<ApyRetentionReport, String> {
+    is_admin()?;
+    ic_cdk::println!("🧹 [ADMIN] Running APY history retention");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(apy_retention::run_retention())
 }
 
 /// Get all APY history (Public query method)
@@ -1530,6 +2354,26 @@ fn admin_disable_position_auto_sync() -> Result<String, String> {
     apy_parser::disable_position_auto_sync()
 }
 
+/// Reconcile recorded positions with on-chain aToken/Comet balances now (Admin only)
+#[update]
+async fn admin_reconcile_positions() -> Result<PositionReconciliationReport, String> {
+    is_admin()?;
+    ic_cdk::println!("🔎 [ADMIN] Reconciling positions with on-chain balances");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    position_reconciler::reconcile_positions().await
+}
+
+/// Get the report of the most recent position reconciliation (Admin only)
+#[query]
+fn admin_get_last_reconciliation_report() -> Result<Option<PositionReconciliationReport>, String> {
+    is_admin()?;
+    ic_cdk::println!("📋 [ADMIN] Getting last position reconciliation report");
+    ic_cdk::println!("📝 Requested by admin principal: {}", ic_cdk::caller());
+
+    Ok(position_reconciler::get_last_reconciliation_report())
+}
+
 /// Update the permissions_id for a user position (Admin only)
 #[update]
 fn admin_update_position_permissions_id(
@@ -1577,12 +2421,7 @@ fn admin_update_position_permissions_id(
     position.updated_at = now();
 
     // Save updated position
-    USER_POSITIONS_MAP.with(|map| {
-        map.borrow_mut().insert(
-            StorableString(position_id.clone()),
-            StorableUserPosition(position.clone())
-        );
-    });
+    indexes::save_position(&position);
 
     ic_cdk::println!("✅ Position permissions_id updated successfully");
     Ok(position)
@@ -1622,12 +2461,30 @@ async fn create_icp_signer() -> Result<IcpSigner, String> {
 fn init() {
     ic_cdk::println!("🚀 Initializing SmartWallet Manager...");
 
+    // Register the built-in chains
+    chain_registry::seed_chain_registry();
+
     // Initialize scheduler
     scheduler::init_scheduler();
 
     // Initialize APY parser
     apy_parser::init_apy_parser();
 
+    // Expired recommendation proposals are always garbage-collected
+    approval_queue::start_proposal_gc_timer();
+
+    // Interrupted rebalances are always retried
+    rebalance_saga::start_saga_retry_timer();
+
+    // Recorded positions are periodically reconciled with on-chain balances
+    position_reconciler::start_reconciliation_timer();
+
+    // Stuck transactions are settled and sped up
+    tx_tracker::start_tx_watchdog_timer();
+
+    // Old APY history is rolled up and pruned
+    apy_retention::start_apy_retention_timer();
+
     // Note: Timers will not auto-start - admin must enable them
     ic_cdk::println!("✅ SmartWallet Manager Initialized.");
     ic_cdk::println!("ℹ️ Scheduler initialized but not started. Use admin_start_scheduler() to enable.");
@@ -1640,7 +2497,29 @@ fn post_upgrade() {
 
     // Stable memory is automatically preserved, no specific restore needed for StableBTreeMap
 
-    // Check if scheduler needs initialization (for canisters upgraded before scheduler was added)
+    // Register the built-in chains on canisters upgraded before the chain registry existed
+    chain_registry::seed_chain_registry();
+    chain_registry::backfill_chain_defaults();
+
+    // Re-key yield tenures stored under legacy descriptive IDs
+    yield_ledger::migrate_legacy_tenure_ids();
+
+    // Build secondary indexes missing from canisters upgraded before they existed
+    indexes::rebuild_indexes_if_needed();
+
+    // Move APY records stored under legacy string keys to the composite-key map, in chunks
+    apy_parser::start_legacy_apy_migration();
+
+    // Nonces may have moved while the canister was upgraded
+    nonce_manager::mark_all_for_resync();
+
+    // Operations interrupted by the upgrade cannot complete, so their locks are stale
+    let cleared_locks = locks::clear_all_locks();
+    if cleared_locks > 0 {
+        ic_cdk::println!("🔓 Cleared {} stale operation locks", cleared_locks);
+    }
+
+    // The scheduler config is kept in stable memory; canisters upgraded from before that start from the defaults
     if scheduler::get_scheduler_config().is_err() {
         ic_cdk::println!("🔧 Scheduler not initialized, initializing now...");
         scheduler::init_scheduler();
@@ -1654,6 +2533,9 @@ fn post_upgrade() {
         ic_cdk::println!("ℹ️ Scheduler is disabled, timer not started.");
     }
 
+    // Resume a scheduler run interrupted by the upgrade
+    scheduler::resume_scheduler_run();
+
     // APY parser is always initialized (it has a default config)
     // No need for explicit initialization check, just restore timer if needed
 
@@ -1665,6 +2547,21 @@ fn post_upgrade() {
         ic_cdk::println!("ℹ️ APY Parser is disabled, timer not started.");
     }
 
+    // Restart proposal garbage collection
+    approval_queue::start_proposal_gc_timer();
+
+    // Restart saga retries
+    rebalance_saga::start_saga_retry_timer();
+
+    // Restart position reconciliation
+    position_reconciler::start_reconciliation_timer();
+
+    // Restart the stuck-transaction watchdog
+    tx_tracker::start_tx_watchdog_timer();
+
+    // Restart APY history retention
+    apy_retention::start_apy_retention_timer();
+
     ic_cdk::println!("✅ SmartWallet Manager Upgraded.");
 }
 
@@ -7,11 +7,22 @@ use alloy::{
     transports::icp::IcpConfig,
 };
 use candid::Principal;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use crate::{PRINCIPAL_TO_ADDRESS_MAP, StorablePrincipal};
-use crate::services::rpc_service::{get_rpc_service_by_chain_id, SEPOLIA_CHAIN_ID, BASE_CHAIN_ID, OPTIMISM_CHAIN_ID, ARBITRUM_CHAIN_ID};
-use crate::services::permissions::{is_permissions_owner, verify_protocol_permission, set_daily_usage};
+use crate::types::{TokenAmount, WithdrawOutcome};
+use crate::types::amount::decimals_for_symbol;
+use crate::services::rpc_service::{get_rpc_service_by_chain_id, SEPOLIA_CHAIN_ID};
+use crate::services::chain_registry;
+use crate::services::permissions::{is_permissions_owner, verify_protocol_permission, check_usd_limit, set_daily_usage, usage_units};
 use crate::services::get_balance_link::get_balance_link;
-use crate::services::nonce_manager::{get_next_nonce, reserve_nonce, commit_nonce, rollback_nonce, invalidate_cache};
+use crate::services::multicall::Multicall;
+use crate::services::{erc20, fee_policy, rebalance_saga, tx_tracker};
+use crate::services::erc20::AllowanceStatus;
+use crate::services::nonce_manager::{reserve_nonce, commit_nonce, rollback_nonce};
+
+/// Gas limit for a supply sent before its approval is mined, when it can't be estimated
+const SUPPLY_GAS_LIMIT_WITHOUT_ESTIMATE: u128 = 400_000;
 
 // AAVE V3 chain configuration
 #[derive(Clone)]
@@ -20,27 +31,17 @@ struct AaveChainConfig {
     chain_id: u64,
 }
 
-/// Get AAVE configuration for a specific chain
+/// Get AAVE configuration for a specific chain from the chain registry
 fn get_aave_config(chain_id: u64) -> Result<AaveChainConfig, String> {
-    match chain_id {
-        SEPOLIA_CHAIN_ID => Ok(AaveChainConfig {
-            pool_address: address!("6Ae43d3271ff6888e7Fc43Fd7321a503ff738951"), // AAVE V3 Pool on Sepolia
-            chain_id: SEPOLIA_CHAIN_ID,
-        }),
-        ARBITRUM_CHAIN_ID => Ok(AaveChainConfig {
-            pool_address: address!("794a61358D6845594F94dc1DB02A252b5b4814aD"), // AAVE V3 Pool on Arbitrum
-            chain_id: ARBITRUM_CHAIN_ID,
-        }),
-        BASE_CHAIN_ID => Ok(AaveChainConfig {
-            pool_address: address!("794a61358D6845594F94dc1DB02A252b5b4814aD"), // AAVE V3 Pool on Base
-            chain_id: BASE_CHAIN_ID,
-        }),
-        OPTIMISM_CHAIN_ID => Ok(AaveChainConfig {
-            pool_address: address!("794a61358D6845594F94dc1DB02A252b5b4814aD"), // AAVE V3 Pool on Optimism
-            chain_id: OPTIMISM_CHAIN_ID,
-        }),
-        _ => Err(format!("AAVE V3 not supported on chain_id: {}", chain_id))
-    }
+    let pool_address = chain_registry::get_protocol_address(chain_id, "AAVE")
+        .ok_or_else(|| format!("AAVE V3 not supported on chain_id: {}", chain_id))?;
+
+    Ok(AaveChainConfig { pool_address, chain_id })
+}
+
+thread_local! {
+    /// aToken address per (chain_id, underlying token); fixed once a reserve is listed
+    static ATOKEN_ADDRESSES: RefCell<BTreeMap<(u64, Address), Address>> = RefCell::new(BTreeMap::new());
 }
 
 // Codegen from ABI file to interact with AAVE Pool contract
@@ -87,6 +88,43 @@ fn get_ecdsa_key_name() -> String {
     }
 }
 
+/// Approve the AAVE Pool to spend a token ahead of a supply
+/// Skips the approval transaction if the current allowance already covers the amount
+pub async fn approve_token_for_aave_with_permissions(
+    token_address: Address,
+    token_symbol: String,
+    amount_human: String,
+    permissions_id: String,
+    user_principal: Principal,
+    chain_id: u64
+) -> Result<String, String> {
+    ic_cdk::println!("🚀 Starting AAVE {} approval: {} {} for principal {} on chain {}", token_symbol, amount_human, token_symbol, user_principal, chain_id);
+
+    let aave_config = get_aave_config(chain_id)?;
+    is_permissions_owner(&permissions_id, user_principal)?;
+
+    let amount_wei = parse_token_amount(&amount_human, &token_symbol)?;
+
+    let signer = create_icp_signer_for_principal(user_principal).await?;
+    let address = signer.address();
+
+    let wallet = EthereumWallet::from(signer);
+    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
+    let config = IcpConfig::new(rpc_service);
+    let provider = ProviderBuilder::new()
+        .with_gas_estimation()
+        .wallet(wallet)
+        .on_icp(config);
+
+    let allowance = ensure_token_allowance_for_aave(&provider, token_address, amount_wei, address, user_principal, &aave_config).await?;
+
+    ic_cdk::println!("🎉 AAVE {} approval completed", token_symbol);
+    match allowance {
+        AllowanceStatus::Pending(tx_hash) => Ok(format!("⏳ Approval of {} {} for AAVE Pool sent, not mined yet. Transaction: {:?}", amount_human, token_symbol, tx_hash)),
+        _ => Ok(format!("✅ {} {} allowance confirmed for AAVE Pool", amount_human, token_symbol)),
+    }
+}
+
 /// Supply any token to AAVE with permission verification
 pub async fn supply_to_aave_with_permissions(
     token_address: Address,
@@ -110,7 +148,8 @@ pub async fn supply_to_aave_with_permissions(
     
     // 3. Convert amount
     ic_cdk::println!("✅ Step 3: Converting amount {} {} to wei...", amount_human, token_symbol);
-    let amount_wei = parse_token_amount(&amount_human, &token_symbol)?;
+    let supplied_amount = TokenAmount::parse_for_symbol(&amount_human, &token_symbol)?;
+    let amount_wei = supplied_amount.to_u256();
     ic_cdk::println!("✅ Step 3 Complete: Amount converted to {} wei", amount_wei);
     
     // 3. Create signer on behalf of user
@@ -146,18 +185,32 @@ pub async fn supply_to_aave_with_permissions(
     
     // 7. Check/set allowance for AAVE Pool
     ic_cdk::println!("✅ Step 7: Ensuring {} allowance for AAVE Pool...", token_symbol);
-    ensure_token_allowance_for_aave(&provider, token_address, amount_wei, address, &aave_config).await?;
+    let allowance = ensure_token_allowance_for_aave(&provider, token_address, amount_wei, address, user_principal, &aave_config).await?;
     ic_cdk::println!("✅ Step 7 Complete: {} allowance confirmed for AAVE Pool", token_symbol);
 
+    // 7. Estimate gas and fees with the fee policy (fails while the chain's fee cap is exceeded)
+    ic_cdk::println!("✅ Step 7: Estimating gas and fees...");
+    let pool_contract = AavePool::new(aave_config.pool_address, provider.clone());
+    let call_builder = pool_contract
+        .supply(token_address, amount_wei, address, 0u16)
+        .chain_id(chain_id)
+        .from(address);
+    let call_builder = if allowance.is_effective() {
+        fee_policy::apply_to_call(call_builder, chain_id).await?
+    } else {
+        // The estimate would revert on the missing allowance until the approval is mined
+        ic_cdk::println!("⏳ Approval not mined yet, using a gas limit of {}", SUPPLY_GAS_LIMIT_WITHOUT_ESTIMATE);
+        fee_policy::apply_to_call_with_gas_limit(call_builder, chain_id, SUPPLY_GAS_LIMIT_WITHOUT_ESTIMATE).await?
+    };
+    ic_cdk::println!("✅ Step 7 Complete: Gas and fees set");
+
     // 7. Handle nonce management
     ic_cdk::println!("✅ Step 7: Getting transaction nonce...");
-    let nonce = get_next_nonce(address, &provider, chain_id).await?;
-    reserve_nonce(address, chain_id, nonce);
+    let nonce = reserve_nonce(address, &provider, chain_id).await?;
     ic_cdk::println!("✅ Step 7 Complete: Reserved nonce {} for transaction", nonce);
     
     // 8. Execute supply to AAVE
     ic_cdk::println!("✅ Step 8: Preparing AAVE supply transaction...");
-    let pool_contract = AavePool::new(aave_config.pool_address, provider.clone());
     
     ic_cdk::println!("📋 AAVE Supply Parameters:");
     ic_cdk::println!("  - Pool Address: 0x{:x}", aave_config.pool_address);
@@ -168,18 +221,8 @@ pub async fn supply_to_aave_with_permissions(
     ic_cdk::println!("  - Chain ID: {}", chain_id);
     
     ic_cdk::println!("🚀 Sending AAVE supply transaction...");
-    
-    // Try to estimate gas first
-    ic_cdk::println!("📊 Estimating gas for transaction...");
-    let call_builder = pool_contract
-        .supply(token_address, amount_wei, address, 0u16)
-        .nonce(nonce)
-        .chain_id(chain_id)
-        .from(address);
-    
-    // Send with increased gas limit
     match call_builder
-        .gas(1_000_000u128) // Increase gas limit even more
+        .nonce(nonce)
         .send()
         .await
     {
@@ -190,6 +233,7 @@ pub async fn supply_to_aave_with_permissions(
             // Transaction successfully sent - commit nonce
             // Even if transaction reverts in blockchain, nonce is consumed
             commit_nonce(address, chain_id, nonce);
+            rebalance_saga::record_step_tx(user_principal, format!("{:?}", tx_hash));
 
             ic_cdk::println!("✅ Step 9: Waiting for transaction confirmation...");
             let tx_response = provider.get_transaction_by_hash(tx_hash).await
@@ -201,6 +245,7 @@ pub async fn supply_to_aave_with_permissions(
 
             match tx_response {
                 Some(tx) => {
+                    tx_tracker::record_sent(user_principal, chain_id, &tx);
                     ic_cdk::println!("✅ Step 9 Complete: Transaction confirmed in block");
                     ic_cdk::println!("📋 Transaction Details:");
                     ic_cdk::println!("  - Hash: {:?}", tx_hash);
@@ -212,7 +257,7 @@ pub async fn supply_to_aave_with_permissions(
                     
                     // Update daily usage
                     ic_cdk::println!("✅ Step 11: Updating daily usage limits...");
-                    if let Err(e) = set_daily_usage(permissions_id.clone(), aave_config.pool_address.to_string(), amount_wei.to::<u64>(), user_principal) {
+                    if let Err(e) = set_daily_usage(permissions_id.clone(), aave_config.pool_address.to_string(), usage_units(amount_wei), user_principal) {
                         ic_cdk::println!("⚠️ Warning: Failed to update daily usage: {}", e);
                     } else {
                         ic_cdk::println!("✅ Step 11 Complete: Daily usage limits updated");
@@ -228,7 +273,7 @@ pub async fn supply_to_aave_with_permissions(
                         token_symbol.clone(),
                         token_address_str,
                         chain_id,
-                        amount_human.clone(),
+                        supplied_amount,
                     ).await {
                         Ok(_) => ic_cdk::println!("✅ Step 12 Complete: User position synced"),
                         Err(e) => ic_cdk::println!("⚠️ Step 12 Warning: Position sync failed: {}", e),
@@ -251,12 +296,7 @@ pub async fn supply_to_aave_with_permissions(
             // Transaction failed to send - rollback nonce
             rollback_nonce(address, chain_id, nonce);
 
-            // Check for nonce too low error
             let error_str = e.to_string();
-            if error_str.contains("nonce too low") {
-                ic_cdk::println!("⚠️ Nonce too low error - invalidating cache");
-                invalidate_cache(address, chain_id);
-            }
 
             // Try to decode specific AAVE errors
             let decoded_error = if error_str.contains("execution reverted") {
@@ -282,6 +322,9 @@ pub async fn supply_to_aave_with_permissions(
 }
 
 /// Withdraw any token from AAVE with permission verification
+///
+/// `amount_human` may be "max" to withdraw the full aToken balance, including
+/// accrued interest.
 pub async fn withdraw_from_aave_with_permissions(
     token_address: Address,
     token_symbol: String,
@@ -290,31 +333,40 @@ pub async fn withdraw_from_aave_with_permissions(
     user_principal: Principal,
     chain_id: u64
 ) -> Result<String, String> {
+    withdraw_from_aave_with_outcome(token_address, token_symbol, amount_human, permissions_id, user_principal, chain_id)
+        .await
+        .map(|outcome| outcome.message)
+}
+
+/// Withdraw any token from AAVE and report the amount that actually reached the wallet
+///
+/// Waits for the withdraw to be mined and takes the amount from the token's
+/// `Transfer` log to the wallet. If it is not mined yet, the amount requested
+/// from the pool is reported.
+pub async fn withdraw_from_aave_with_outcome(
+    token_address: Address,
+    token_symbol: String,
+    amount_human: String,
+    permissions_id: String,
+    user_principal: Principal,
+    chain_id: u64
+) -> Result<WithdrawOutcome, String> {
     ic_cdk::println!("🚀 Starting AAVE {} withdraw: {} {} for principal {} on chain {}", token_symbol, amount_human, token_symbol, user_principal, chain_id);
+    let withdraw_max = amount_human.eq_ignore_ascii_case("max");
     
     // 1. Get chain configuration
     ic_cdk::println!("✅ Step 1: Getting AAVE configuration for chain {}...", chain_id);
     let aave_config = get_aave_config(chain_id)?;
     ic_cdk::println!("✅ Step 1 Complete: AAVE config loaded for chain {}", chain_id);
     
-    // 2. Check permissions
-    ic_cdk::println!("✅ Step 2: Verifying AAVE withdraw permissions...");
-    verify_aave_permission(&permissions_id, "withdraw", &amount_human, &token_symbol, user_principal, chain_id).await?;
-    ic_cdk::println!("✅ Step 2 Complete: AAVE withdraw permissions verified");
-    
-    // 3. Convert amount
-    ic_cdk::println!("✅ Step 3: Converting amount {} {} to wei...", amount_human, token_symbol);
-    let amount_wei = parse_token_amount(&amount_human, &token_symbol)?;
-    ic_cdk::println!("✅ Step 3 Complete: Amount converted to {} wei", amount_wei);
-    
-    // 4. Create signer on behalf of user
-    ic_cdk::println!("✅ Step 4: Creating ICP signer for principal...");
+    // 2. Create signer on behalf of user
+    ic_cdk::println!("✅ Step 2: Creating ICP signer for principal...");
     let signer = create_icp_signer_for_principal(user_principal).await?;
     let address = signer.address();
-    ic_cdk::println!("✅ Step 4 Complete: Signer created for address 0x{:x}", address);
+    ic_cdk::println!("✅ Step 2 Complete: Signer created for address 0x{:x}", address);
 
-    // 5. Setup provider
-    ic_cdk::println!("✅ Step 5: Setting up provider and wallet...");
+    // 3. Setup provider
+    ic_cdk::println!("✅ Step 3: Setting up provider and wallet...");
     let wallet = EthereumWallet::from(signer);
     let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
     let config = IcpConfig::new(rpc_service);
@@ -322,54 +374,70 @@ pub async fn withdraw_from_aave_with_permissions(
         .with_gas_estimation()
         .wallet(wallet)
         .on_icp(config);
-    ic_cdk::println!("✅ Step 5 Complete: Provider and wallet configured");
+    ic_cdk::println!("✅ Step 3 Complete: Provider and wallet configured");
     
-    // 6. Check aToken balance
-    ic_cdk::println!("✅ Step 6: Checking a{} balance for address 0x{:x}...", token_symbol, address);
+    // 4. Check aToken balance
+    ic_cdk::println!("✅ Step 4: Checking a{} balance for address 0x{:x}...", token_symbol, address);
     let atoken_balance = get_atoken_balance_for_address(format!("0x{:x}", address), token_address, chain_id).await?;
     let atoken_balance_wei = U256::from_str_radix(&atoken_balance.replace("0x", ""), 16)
         .map_err(|_| format!("Failed to parse a{} balance", token_symbol))?;
+    ic_cdk::println!("✅ Step 4 Complete: a{} balance: {} wei", token_symbol, atoken_balance_wei);
     
-    ic_cdk::println!("✅ Step 6 Complete: a{} balance: {} wei (need: {} wei)", token_symbol, atoken_balance_wei, amount_wei);
+    // 5. Resolve the amount to withdraw
+    // For "max" the pool is passed type(uint256).max so accrued interest is included
+    let (amount_wei, call_amount) = if withdraw_max {
+        if atoken_balance_wei.is_zero() {
+            return Err(format!("No a{} balance to withdraw", token_symbol));
+        }
+        (atoken_balance_wei, U256::MAX)
+    } else {
+        let amount_wei = parse_token_amount(&amount_human, &token_symbol)?;
+        if atoken_balance_wei < amount_wei {
+            let error_msg = format!("Insufficient a{} balance. Have: {} wei, Need: {} wei", token_symbol, atoken_balance_wei, amount_wei);
+            ic_cdk::println!("❌ AAVE {} withdraw failed: {}", token_symbol, error_msg);
+            return Err(error_msg);
+        }
+        (amount_wei, amount_wei)
+    };
+    let decimals = decimals_for_symbol(&token_symbol)?;
+    let expected_amount_human = TokenAmount::from_u256(amount_wei, decimals)?.to_string();
+    ic_cdk::println!("✅ Step 5: Withdrawing {} {} ({} wei)", expected_amount_human, token_symbol, amount_wei);
     
-    if atoken_balance_wei < amount_wei {
-        let error_msg = format!("Insufficient a{} balance. Have: {} wei, Need: {} wei", token_symbol, atoken_balance_wei, amount_wei);
-        ic_cdk::println!("❌ AAVE {} withdraw failed: {}", token_symbol, error_msg);
-        return Err(error_msg);
-    }
+    // 6. Check permissions
+    ic_cdk::println!("✅ Step 6: Verifying AAVE withdraw permissions...");
+    verify_aave_permission(&permissions_id, "withdraw", &expected_amount_human, &token_symbol, user_principal, chain_id).await?;
+    ic_cdk::println!("✅ Step 6 Complete: AAVE withdraw permissions verified");
     
+    // 7. Estimate gas and fees with the fee policy (fails while the chain's fee cap is exceeded)
+    ic_cdk::println!("✅ Step 7: Estimating gas and fees...");
+    let pool_address = aave_config.pool_address;
+    let pool_contract = AavePool::new(pool_address, provider.clone());
+    let call_builder = pool_contract
+        .withdraw(token_address, call_amount, address)
+        .chain_id(chain_id)
+        .from(address);
+    let call_builder = fee_policy::apply_to_call(call_builder, chain_id).await?;
+    ic_cdk::println!("✅ Step 7 Complete: Gas and fees set");
+
     // 7. Handle nonce management
     ic_cdk::println!("✅ Step 7: Getting transaction nonce...");
-    let nonce = get_next_nonce(address, &provider, chain_id).await?;
-    reserve_nonce(address, chain_id, nonce);
+    let nonce = reserve_nonce(address, &provider, chain_id).await?;
     ic_cdk::println!("✅ Step 7 Complete: Reserved nonce {} for transaction", nonce);
 
     // 8. Execute withdraw from AAVE
     ic_cdk::println!("✅ Step 8: Preparing AAVE withdraw transaction...");
-    let pool_address = aave_config.pool_address;
-    let pool_contract = AavePool::new(pool_address, provider.clone());
     
     ic_cdk::println!("📋 AAVE Withdraw Parameters:");
     ic_cdk::println!("  - Pool Address: 0x{:x}", pool_address);
     ic_cdk::println!("  - {} Address: 0x{:x}", token_symbol, token_address);
-    ic_cdk::println!("  - Amount: {} wei", amount_wei);
+    ic_cdk::println!("  - Amount: {} wei{}", amount_wei, if withdraw_max { " (max)" } else { "" });
     ic_cdk::println!("  - User Address: 0x{:x}", address);
     ic_cdk::println!("  - Nonce: {}", nonce);
     ic_cdk::println!("  - Chain ID: {}", chain_id);
     
     ic_cdk::println!("🚀 Sending AAVE withdraw transaction...");
-    
-    // Try to estimate gas first
-    ic_cdk::println!("📊 Estimating gas for withdraw transaction...");
-    let call_builder = pool_contract
-        .withdraw(token_address, amount_wei, address)
-        .nonce(nonce)
-        .chain_id(chain_id)
-        .from(address);
-    
-    // Send with increased gas limit
     match call_builder
-        .gas(1_000_000u128) // Increase gas limit even more
+        .nonce(nonce)
         .send()
         .await
     {
@@ -380,6 +448,7 @@ pub async fn withdraw_from_aave_with_permissions(
             // Transaction successfully sent - commit nonce
             // Even if transaction reverts in blockchain, nonce is consumed
             commit_nonce(address, chain_id, nonce);
+            rebalance_saga::record_step_tx(user_principal, format!("{:?}", tx_hash));
 
             ic_cdk::println!("✅ Step 9: Waiting for transaction confirmation...");
             let tx_response = provider.get_transaction_by_hash(tx_hash).await
@@ -391,6 +460,7 @@ pub async fn withdraw_from_aave_with_permissions(
 
             match tx_response {
                 Some(tx) => {
+                    tx_tracker::record_sent(user_principal, chain_id, &tx);
                     ic_cdk::println!("✅ Step 9 Complete: Transaction confirmed in block");
                     ic_cdk::println!("📋 Transaction Details:");
                     ic_cdk::println!("  - Hash: {:?}", tx_hash);
@@ -400,9 +470,35 @@ pub async fn withdraw_from_aave_with_permissions(
                     
                     // Nonce already committed after send() succeeded
                     
+                    // 10. Take the amount that actually reached the wallet from the receipt
+                    ic_cdk::println!("✅ Step 10: Waiting for receipt to measure withdrawn amount...");
+                    let withdrawn_wei = match tx_tracker::wait_for_receipt(&provider, tx_hash).await? {
+                        Some(receipt) if !receipt.status() => {
+                            let error_msg = format!("Withdraw transaction {:?} reverted", tx_hash);
+                            ic_cdk::println!("❌ Step 10 Failed: {}", error_msg);
+                            return Err(error_msg);
+                        }
+                        Some(receipt) => {
+                            let received = erc20::received_amount(&receipt, token_address, address);
+                            if received.is_zero() {
+                                ic_cdk::println!("⚠️ Step 10 Warning: No {} transfer in receipt, using requested amount", token_symbol);
+                                amount_wei
+                            } else {
+                                received
+                            }
+                        }
+                        None => {
+                            ic_cdk::println!("⚠️ Step 10 Warning: Withdraw not mined yet, using requested amount");
+                            amount_wei
+                        }
+                    };
+                    let withdrawn = TokenAmount::from_u256(withdrawn_wei, decimals)?;
+                    let withdrawn_human = withdrawn.to_string();
+                    ic_cdk::println!("✅ Step 10 Complete: Withdrawn {} {} ({} wei)", withdrawn_human, token_symbol, withdrawn_wei);
+                    
                     // Update daily usage
                     ic_cdk::println!("✅ Step 11: Updating daily usage limits...");
-                    if let Err(e) = set_daily_usage(permissions_id, format!("0x{:x}", aave_config.pool_address), amount_wei.to::<u64>(), user_principal) {
+                    if let Err(e) = set_daily_usage(permissions_id, format!("0x{:x}", aave_config.pool_address), usage_units(withdrawn_wei), user_principal) {
                         ic_cdk::println!("⚠️ Warning: Failed to update daily usage: {}", e);
                     } else {
                         ic_cdk::println!("✅ Step 11 Complete: Daily usage limits updated");
@@ -415,15 +511,19 @@ pub async fn withdraw_from_aave_with_permissions(
                         "AAVE".to_string(),
                         token_symbol.clone(),
                         chain_id,
-                        amount_human.clone(),
+                        withdrawn,
                     ).await {
                         Ok(_) => ic_cdk::println!("✅ Step 12 Complete: User position synced"),
                         Err(e) => ic_cdk::println!("⚠️ Step 12 Warning: Position sync failed: {}", e),
                     }
 
-                    let success_msg = format!("Successfully withdrew {} {} from AAVE. Transaction: {:?}", amount_human, token_symbol, tx_hash);
+                    let success_msg = format!("Successfully withdrew {} {} from AAVE. Transaction: {:?}", withdrawn_human, token_symbol, tx_hash);
                     ic_cdk::println!("🎉 AAVE {} withdraw completed successfully: {}", token_symbol, success_msg);
-                    Ok(success_msg)
+                    Ok(WithdrawOutcome {
+                        tx_hash: format!("{:?}", tx_hash),
+                        amount_withdrawn: withdrawn_human,
+                        message: success_msg,
+                    })
                 }
                 None => {
                     let error_msg = "Transaction not found after sending".to_string();
@@ -434,7 +534,10 @@ pub async fn withdraw_from_aave_with_permissions(
         }
         Err(e) => {
             ic_cdk::println!("❌ Step 8 Failed: Withdraw transaction failed: {:?}", e);
-            
+
+            // Transaction failed to send - rollback nonce
+            rollback_nonce(address, chain_id, nonce);
+
             // Try to decode specific AAVE errors
             let error_str = e.to_string();
             let decoded_error = if error_str.contains("execution reverted") {
@@ -521,33 +624,26 @@ async fn verify_aave_permission(
     let aave_config = get_aave_config(chain_id)?;
 
     // Convert amount with correct token decimals
-    let amount_wei = parse_token_amount(amount_human, token_symbol)?.to::<u64>();
+    let amount = TokenAmount::parse_for_symbol(amount_human, token_symbol)?;
+    let amount_wei = usage_units(amount.to_u256());
+    let pool_address = format!("{:x}", aave_config.pool_address);
 
     // Check protocol permission
     verify_protocol_permission(
         permissions_id.to_string(),
-        format!("{:x}", aave_config.pool_address),
+        pool_address.clone(),
         function_name.to_string(),
         amount_wei,
         user_principal
-    ).map(|_| ())
+    )?;
+
+    // Check USD limit
+    check_usd_limit(permissions_id, &pool_address, token_symbol, chain_id, &amount).await
 }
 
 /// Parse token amount with support for different decimals
 fn parse_token_amount(amount_human: &str, token_symbol: &str) -> Result<U256, String> {
-    let amount_f64: f64 = amount_human.parse()
-        .map_err(|_| "Invalid amount format".to_string())?;
-    
-    if amount_f64 < 0.0 {
-        return Err("Amount cannot be negative".to_string());
-    }
-    
-    let amount_wei = match token_symbol {
-        "LINK" | "WETH" | "ETH" => (amount_f64 * 1e18) as u128, // 18 decimals
-        "USDC" | "USDT" => (amount_f64 * 1e6) as u128,         // 6 decimals
-        _ => return Err(format!("Unsupported token: {}", token_symbol))
-    };
-    Ok(U256::from(amount_wei))
+    Ok(TokenAmount::parse_for_symbol(amount_human, token_symbol)?.to_u256())
 }
 
 /// Legacy function - Parse LINK amount (18 decimals) for backward compatibility
@@ -584,15 +680,8 @@ async fn get_atoken_balance_for_address(address: String, token_address: Address,
     let config = IcpConfig::new(rpc_service);
     let provider = ProviderBuilder::new().on_icp(config);
     
-    // Get AAVE config for the chain
-    let aave_config = get_aave_config(chain_id)?;
-    let pool_contract = AavePool::new(aave_config.pool_address, provider.clone());
-    
-    let reserve_data = pool_contract.getReserveData(token_address).call().await
-        .map_err(|e| format!("Failed to get reserve data: {}", e))?;
-    
-    // aToken address is in reserve data
-    let atoken_address = reserve_data._0.aTokenAddress;
+    // aToken address is in reserve data (cached after the first read)
+    let atoken_address = get_atoken_addresses(&[token_address], chain_id).await?[0];
     
     // Get aToken balance
     let token_contract = LINK::new(atoken_address, provider);
@@ -612,6 +701,9 @@ async fn get_alink_balance_for_address(address: String) -> Result<String, String
 }
 
 /// Ensure sufficient token allowance for AAVE Pool
+///
+/// An approval that is sent is waited for, so the supply that follows can be
+/// estimated against the new allowance.
 async fn ensure_token_allowance_for_aave(
     provider: &alloy::providers::fillers::FillProvider<
         alloy::providers::fillers::JoinFill<
@@ -628,8 +720,9 @@ async fn ensure_token_allowance_for_aave(
     token_address: Address,
     amount: U256,
     user_address: Address,
+    user_principal: Principal,
     aave_config: &AaveChainConfig
-) -> Result<(), String> {
+) -> Result<AllowanceStatus, String> {
     let pool_address = aave_config.pool_address;
     
     ic_cdk::println!("🔍 Checking token allowance for AAVE Pool...");
@@ -653,10 +746,15 @@ async fn ensure_token_allowance_for_aave(
     
     if current_allowance._0 < amount {
         ic_cdk::println!("⚠️ Insufficient allowance, need to approve more tokens...");
+        let approve_call = token_contract
+            .approve(pool_address, amount)
+            .chain_id(aave_config.chain_id)
+            .from(user_address);
+        let approve_call = fee_policy::apply_to_call(approve_call, aave_config.chain_id).await?;
+
         // Get nonce for approval transaction
         ic_cdk::println!("🔧 Getting nonce for token approval transaction...");
-        let nonce = get_next_nonce(user_address, provider, aave_config.chain_id).await?;
-        reserve_nonce(user_address, aave_config.chain_id, nonce);
+        let nonce = reserve_nonce(user_address, provider, aave_config.chain_id).await?;
         ic_cdk::println!("🔧 Reserved nonce {} for approval", nonce);
 
         // Increase allowance
@@ -666,11 +764,8 @@ async fn ensure_token_allowance_for_aave(
         ic_cdk::println!("  - Amount: {} wei", amount);
         ic_cdk::println!("  - Nonce: {}", nonce);
 
-        match token_contract
-            .approve(pool_address, amount)
+        match approve_call
             .nonce(nonce)
-            .chain_id(aave_config.chain_id)
-            .from(user_address)
             .send()
             .await
         {
@@ -686,8 +781,8 @@ async fn ensure_token_allowance_for_aave(
 
                 match tx_response {
                     Some(tx) => {
-                        ic_cdk::println!("✅ Token approved for AAVE Pool successfully: {:?}", tx_hash);
-                        ic_cdk::println!("📋 Approval confirmed in block: {:?}", tx.block_number);
+                        tx_tracker::record_sent(user_principal, aave_config.chain_id, &tx);
+                        ic_cdk::println!("✅ Token approval for AAVE Pool sent: {:?}", tx_hash);
                     }
                     None => {
                         let error_msg = "Approve transaction not found after sending".to_string();
@@ -695,18 +790,13 @@ async fn ensure_token_allowance_for_aave(
                         return Err(error_msg);
                     }
                 }
+
+                erc20::settle_approval(provider, tx_hash).await
             }
             Err(e) => {
                 // Transaction failed to send - rollback nonce
                 rollback_nonce(user_address, aave_config.chain_id, nonce);
 
-                // Check for nonce too low error
-                let error_str = e.to_string();
-                if error_str.contains("nonce too low") {
-                    ic_cdk::println!("⚠️ Nonce too low error - invalidating cache");
-                    invalidate_cache(user_address, aave_config.chain_id);
-                }
-
                 let error_msg = format!("Approve transaction failed: {:?}", e);
                 ic_cdk::println!("❌ {}", error_msg);
                 return Err(error_msg);
@@ -714,9 +804,8 @@ async fn ensure_token_allowance_for_aave(
         }
     } else {
         ic_cdk::println!("✅ Sufficient token allowance already exists, no approval needed");
+        Ok(AllowanceStatus::Sufficient)
     }
-    
-    Ok(())
 }
 
 /// Legacy wrapper function for backward compatibility - Supply LINK to AAVE on Sepolia
@@ -753,39 +842,77 @@ pub async fn withdraw_link_from_aave_with_permissions_legacy(
     ).await
 }
 
-/// Get current supply APY for a specific asset from AAVE on-chain
-/// Returns APY as a percentage string (e.g., "5.23")
-pub async fn get_apy(token_address: Address, chain_id: u64) -> Result<String, String> {
-    ic_cdk::println!("🔍 Getting AAVE APY for token 0x{:x} on chain {}", token_address, chain_id);
-
-    // 1. Get AAVE configuration for the chain
-    let aave_config = get_aave_config(chain_id)?;
-    ic_cdk::println!("✅ Got AAVE config for chain {}", chain_id);
-
-    // 2. Create read-only provider (no signer needed)
-    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
-    let config = IcpConfig::new(rpc_service);
-    let provider = ProviderBuilder::new().on_icp(config);
+/// Get aToken addresses for several underlying tokens on one chain
+///
+/// Addresses not cached yet are read with one batched getReserveData call.
+pub(crate) async fn get_atoken_addresses(token_addresses: &[Address], chain_id: u64) -> Result<Vec<Address>, String> {
+    let cached = |token: &Address| ATOKEN_ADDRESSES.with(|cache| cache.borrow().get(&(chain_id, *token)).copied());
+
+    let mut missing: Vec<Address> = token_addresses.iter().filter(|token| cached(token).is_none()).copied().collect();
+    missing.sort_unstable();
+    missing.dedup();
+
+    if !missing.is_empty() {
+        let aave_config = get_aave_config(chain_id)?;
+        let mut multicall = Multicall::new(chain_id);
+        for token in &missing {
+            multicall.add(aave_config.pool_address, AavePool::getReserveDataCall { asset: *token });
+        }
+        let results = multicall.execute().await?;
+
+        for (index, token) in missing.iter().enumerate() {
+            let reserve_data = results.decode::<AavePool::getReserveDataCall>(index)
+                .map_err(|e| format!("Failed to get reserve data for 0x{:x}: {}", token, e))?;
+            let atoken_address = reserve_data._0.aTokenAddress;
+            if atoken_address == Address::ZERO {
+                return Err(format!("Token 0x{:x} is not listed on AAVE on chain {}", token, chain_id));
+            }
+            ATOKEN_ADDRESSES.with(|cache| cache.borrow_mut().insert((chain_id, *token), atoken_address));
+        }
+    }
 
-    // 3. Create Pool contract instance
-    let pool_contract = AavePool::new(aave_config.pool_address, provider);
+    token_addresses.iter()
+        .map(|token| cached(token).ok_or_else(|| format!("aToken address for 0x{:x} unavailable", token)))
+        .collect()
+}
 
-    // 4. Call getReserveData to get current liquidity rate
-    ic_cdk::println!("📞 Calling getReserveData for token 0x{:x}...", token_address);
-    let reserve_data = pool_contract.getReserveData(token_address).call().await
-        .map_err(|e| format!("Failed to get reserve data: {}", e))?;
+/// Get current supply APYs for several assets from AAVE on-chain with one batched call
+/// Returns one APY percentage string (e.g., "5.23") or error per token, in order
+pub async fn get_apys(token_addresses: &[Address], chain_id: u64) -> Result<Vec<Result<String, String>>, String> {
+    let aave_config = get_aave_config(chain_id)?;
 
-    // 5. Extract currentLiquidityRate (in Ray units, 1e27)
-    let liquidity_rate_ray = reserve_data._0.currentLiquidityRate;
-    ic_cdk::println!("✅ Current liquidity rate (Ray): {}", liquidity_rate_ray);
+    let mut multicall = Multicall::new(chain_id);
+    for token in token_addresses {
+        multicall.add(aave_config.pool_address, AavePool::getReserveDataCall { asset: *token });
+    }
+    let results = multicall.execute().await?;
+
+    Ok((0..token_addresses.len())
+        .map(|index| {
+            let reserve_data = results.decode::<AavePool::getReserveDataCall>(index)
+                .map_err(|e| format!("Failed to get reserve data: {}", e))?;
+            Ok(format!("{:.2}", liquidity_rate_to_apy_percent(reserve_data._0.currentLiquidityRate)))
+        })
+        .collect())
+}
 
-    // 6. Convert Ray to APY percentage
-    // Simplified formula: APY% ≈ (rate / 1e25) gives percentage with 2 decimal precision
-    // More accurate would be: APY = (e^(rate/1e27) - 1) * 100, but we use simplified linear approximation
-    let apy_percent = (liquidity_rate_ray as f64) / 1e25;
+/// Get current supply APY for a specific asset from AAVE on-chain
+/// Returns APY as a percentage string (e.g., "5.23")
+pub async fn get_apy(token_address: Address, chain_id: u64) -> Result<String, String> {
+    ic_cdk::println!("🔍 Getting AAVE APY for token 0x{:x} on chain {}", token_address, chain_id);
 
-    let apy_string = format!("{:.2}", apy_percent);
+    let apy_string = get_apys(&[token_address], chain_id).await?
+        .pop()
+        .ok_or_else(|| "No APY result".to_string())??;
     ic_cdk::println!("🎯 AAVE APY: {}%", apy_string);
 
     Ok(apy_string)
 }
+
+/// Convert a reserve's currentLiquidityRate (Ray units, 1e27) to an APY percentage
+///
+/// Simplified formula: APY% ≈ rate / 1e25. More accurate would be
+/// APY = (e^(rate/1e27) - 1) * 100, but we use the linear approximation.
+fn liquidity_rate_to_apy_percent(liquidity_rate_ray: u128) -> f64 {
+    (liquidity_rate_ray as f64) / 1e25
+}
@@ -0,0 +1,412 @@
+use candid::Principal;
+use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::types::{PendingRecommendation, Recommendation, RebalanceExecution, UserPosition};
+use crate::{PENDING_RECOMMENDATIONS_MAP, StorableString, now};
+use crate::services::{apy_parser, indexes, rebalance_saga, scheduler};
+
+// =============================================================================
+// Approval Queue
+// =============================================================================
+//
+// Positions in approval-required mode do not execute scheduler recommendations
+// directly. Instead the recommendation is stored as a proposal that the user
+// can approve (which executes it) or reject before it expires.
+//
+// A proposal is marked "executing" before its execution starts. If that call
+// traps or is cut short by an upgrade, the proposal is marked failed once it
+// has been executing for longer than STUCK_EXECUTION_MS; a rebalance that had
+// already started carries on as its saga.
+//
+
+/// How often expired proposals are garbage-collected (1 hour)
+const PROPOSAL_GC_INTERVAL_SECONDS: u64 = 3600;
+
+/// How long resolved proposals are kept after their expiry (7 days)
+const RESOLVED_PROPOSAL_RETENTION_MS: u64 = 7 * 24 * 3600 * 1000;
+
+/// An execution running for longer than this was interrupted (30 minutes)
+///
+/// Matches the lock timeout: an execution that is still running holds its
+/// user lock until then.
+const STUCK_EXECUTION_MS: u64 = 30 * 60 * 1000;
+
+thread_local! {
+    /// Active timer ID for proposal garbage collection
+    static PROPOSAL_GC_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+}
+
+// =============================================================================
+// Proposal Creation
+// =============================================================================
+
+/// Deposit a scheduler recommendation in the approval queue
+///
+/// If the position already has a pending proposal for the same move (same
+/// protocols, assets and size) it is refreshed instead of creating a duplicate.
+/// A different move supersedes it under a new proposal ID, so a user never
+/// approves something other than what they were shown.
+pub async fn propose_recommendation(
+    position: &UserPosition,
+    recommendation: Recommendation,
+    apy_difference: f64,
+    ttl_seconds: u64,
+) -> Result<PendingRecommendation, String> {
+    let timestamp = now();
+    let expires_at = timestamp + ttl_seconds * 1000;
+
+    if let Some(mut existing) = find_pending_for_position(&position.position_id) {
+        if is_same_move(&existing.recommendation, &recommendation) {
+            existing.recommendation = recommendation;
+            existing.apy_difference = apy_difference;
+            existing.expires_at = expires_at;
+            save_proposal(&existing);
+
+            ic_cdk::println!("  🔁 Refreshed pending proposal {} for position {}",
+                existing.proposal_id, position.position_id);
+            return Ok(existing);
+        }
+
+        existing.status = "superseded".to_string();
+        existing.resolved_at = Some(timestamp);
+        save_proposal(&existing);
+
+        ic_cdk::println!("  🔀 Proposal {} for position {} superseded by a different recommendation",
+            existing.proposal_id, position.position_id);
+    }
+
+    let proposal = PendingRecommendation {
+        proposal_id: generate_proposal_id().await,
+        user_principal: position.user_principal,
+        position_id: position.position_id.clone(),
+        permissions_id: position.permissions_id.clone(),
+        recommendation,
+        apy_difference,
+        status: "pending".to_string(),
+        execution_id: None,
+        error: None,
+        created_at: timestamp,
+        expires_at,
+        resolved_at: None,
+    };
+    save_proposal(&proposal);
+
+    ic_cdk::println!("  📬 Proposal {} queued for user approval (expires at {})",
+        proposal.proposal_id, proposal.expires_at);
+
+    Ok(proposal)
+}
+
+// =============================================================================
+// User Actions
+// =============================================================================
+
+/// Approve a pending proposal and execute its recommendation
+pub async fn approve_proposal(proposal_id: String, user: Principal) -> Result<RebalanceExecution, String> {
+    ic_cdk::println!("✅ Approving proposal {} for user {}", proposal_id, user);
+
+    let mut proposal = get_owned_proposal(&proposal_id, user)?;
+
+    if proposal.status != "pending" {
+        return Err(format!("Proposal {} is not pending (status: {})", proposal_id, proposal.status));
+    }
+
+    if proposal.expires_at <= now() {
+        return Err(format!("Proposal {} has expired", proposal_id));
+    }
+
+    // The position must still be held in the protocol the recommendation moves from
+    let position = apy_parser::get_position_by_id(proposal.position_id.clone())?;
+    if !position.protocol.eq_ignore_ascii_case(&proposal.recommendation.from_protocol) {
+        return Err(format!(
+            "Proposal {} is stale: position is now in {} (proposal was for {})",
+            proposal_id, position.protocol, proposal.recommendation.from_protocol
+        ));
+    }
+
+    // Mark as executing before the first await so it cannot be approved twice
+    proposal.status = "executing".to_string();
+    proposal.resolved_at = Some(now());
+    save_proposal(&proposal);
+
+    let result = scheduler::execute_rebalance(
+        proposal.user_principal,
+        proposal.position_id.clone(),
+        proposal.permissions_id.clone(),
+        proposal.recommendation.clone(),
+        proposal.apy_difference,
+    ).await;
+
+    match result {
+        Ok(execution) => {
+            proposal.status = "executed".to_string();
+            proposal.execution_id = Some(execution.execution_id.clone());
+            save_proposal(&proposal);
+
+            scheduler::store_rebalance_execution(execution.clone());
+
+            ic_cdk::println!("✅ Proposal {} executed: {} (status: {})",
+                proposal_id, execution.execution_id, execution.result.status);
+            Ok(execution)
+        },
+        Err(e) => {
+            proposal.status = "failed".to_string();
+            proposal.error = Some(e.clone());
+            save_proposal(&proposal);
+
+            ic_cdk::println!("❌ Proposal {} execution failed: {}", proposal_id, e);
+            Err(e)
+        }
+    }
+}
+
+/// Reject a pending proposal
+pub fn reject_proposal(proposal_id: String, user: Principal) -> Result<PendingRecommendation, String> {
+    ic_cdk::println!("🚫 Rejecting proposal {} for user {}", proposal_id, user);
+
+    let mut proposal = get_owned_proposal(&proposal_id, user)?;
+
+    if proposal.status != "pending" {
+        return Err(format!("Proposal {} is not pending (status: {})", proposal_id, proposal.status));
+    }
+
+    proposal.status = "rejected".to_string();
+    proposal.resolved_at = Some(now());
+    save_proposal(&proposal);
+
+    ic_cdk::println!("✅ Proposal {} rejected", proposal_id);
+    Ok(proposal)
+}
+
+// =============================================================================
+// Queries
+// =============================================================================
+
+/// Get a user's approvable proposals (pending and not expired)
+pub fn get_user_pending_proposals(user: Principal) -> Vec<PendingRecommendation> {
+    let current_time = now();
+
+    indexes::get_user_proposals(user)
+        .into_iter()
+        .filter(|p| p.status == "pending" && p.expires_at > current_time)
+        .collect()
+}
+
+/// Get all proposals in the queue (most recent first)
+pub fn get_all_proposals(limit: Option<u64>) -> Vec<PendingRecommendation> {
+    PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        let mut all: Vec<PendingRecommendation> = map.borrow()
+            .iter()
+            .map(|(_, proposal)| proposal.0)
+            .collect();
+
+        // Sort by creation time descending
+        all.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        let limit = limit.unwrap_or(100) as usize;
+        all.into_iter().take(limit).collect()
+    })
+}
+
+// =============================================================================
+// Garbage Collection
+// =============================================================================
+
+/// Remove expired pending proposals and old resolved proposals
+///
+/// Returns the number of proposals removed.
+pub fn gc_expired_proposals() -> u64 {
+    let current_time = now();
+
+    let expired_ids: Vec<String> = PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        map.borrow()
+            .iter()
+            .filter(|(_, proposal)| {
+                let p = &proposal.0;
+                if p.status == "pending" {
+                    p.expires_at <= current_time
+                } else {
+                    p.expires_at + RESOLVED_PROPOSAL_RETENTION_MS <= current_time
+                }
+            })
+            .map(|(key, _)| key.0)
+            .collect()
+    });
+
+    let removed = expired_ids.len() as u64;
+    for proposal_id in expired_ids {
+        indexes::remove_proposal(&proposal_id);
+    }
+
+    if removed > 0 {
+        ic_cdk::println!("🧹 Garbage-collected {} expired proposals", removed);
+    }
+
+    removed
+}
+
+/// Mark proposals whose execution was interrupted as failed
+///
+/// Returns the number of proposals reconciled.
+pub fn reconcile_interrupted_executions() -> u64 {
+    let current_time = now();
+
+    let stuck: Vec<PendingRecommendation> = PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        map.borrow()
+            .iter()
+            .map(|(_, proposal)| proposal.0)
+            .filter(|p| {
+                p.status == "executing" &&
+                p.resolved_at.map(|at| at + STUCK_EXECUTION_MS <= current_time).unwrap_or(true)
+            })
+            .collect()
+    });
+
+    let reconciled = stuck.len() as u64;
+    for mut proposal in stuck {
+        let started_at = proposal.resolved_at.unwrap_or(proposal.created_at);
+        let saga = rebalance_saga::get_sagas(Some(proposal.user_principal), false, None)
+            .into_iter()
+            .filter(|saga| {
+                saga.created_at >= started_at &&
+                saga.recommendation.from_protocol.eq_ignore_ascii_case(&proposal.recommendation.from_protocol) &&
+                saga.recommendation.to_protocol.eq_ignore_ascii_case(&proposal.recommendation.to_protocol)
+            })
+            .min_by_key(|saga| saga.created_at);
+
+        proposal.status = "failed".to_string();
+        proposal.error = Some(match saga {
+            Some(saga) => format!("Execution was interrupted; the rebalance continues as saga {} ({:?})",
+                saga.saga_id, saga.state),
+            None => "Execution was interrupted before the rebalance started".to_string(),
+        });
+        save_proposal(&proposal);
+
+        ic_cdk::println!("⚠️ Proposal {} was stuck executing: {}",
+            proposal.proposal_id, proposal.error.clone().unwrap_or_default());
+    }
+
+    reconciled
+}
+
+/// Start the periodic proposal garbage-collection timer
+pub fn start_proposal_gc_timer() {
+    PROPOSAL_GC_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow().as_ref() {
+            clear_timer(*id);
+        }
+    });
+
+    let timer_id = set_timer_interval(Duration::from_secs(PROPOSAL_GC_INTERVAL_SECONDS), || {
+        reconcile_interrupted_executions();
+        gc_expired_proposals();
+    });
+
+    PROPOSAL_GC_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+
+    ic_cdk::println!("✅ Proposal GC timer started with interval: {} seconds", PROPOSAL_GC_INTERVAL_SECONDS);
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+/// Load a proposal and verify it belongs to the user
+fn get_owned_proposal(proposal_id: &str, user: Principal) -> Result<PendingRecommendation, String> {
+    let proposal = PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(proposal_id.to_string()))
+            .map(|p| p.0)
+            .ok_or_else(|| format!("Proposal {} not found", proposal_id))
+    })?;
+
+    if proposal.user_principal != user {
+        return Err("You do not own this proposal".to_string());
+    }
+
+    Ok(proposal)
+}
+
+/// Find the pending proposal for a position, if any
+fn find_pending_for_position(position_id: &str) -> Option<PendingRecommendation> {
+    let current_time = now();
+
+    indexes::get_position_proposals(position_id)
+        .into_iter()
+        .find(|p| p.status == "pending" && p.expires_at > current_time)
+}
+
+/// Whether two recommendations move the same amount between the same protocols and assets
+fn is_same_move(a: &Recommendation, b: &Recommendation) -> bool {
+    a.from_protocol.eq_ignore_ascii_case(&b.from_protocol)
+        && a.to_protocol.eq_ignore_ascii_case(&b.to_protocol)
+        && a.asset.eq_ignore_ascii_case(&b.asset)
+        && a.to_asset.eq_ignore_ascii_case(&b.to_asset)
+        && a.to_chain == b.to_chain
+        && a.position_size == b.position_size
+}
+
+/// Persist a proposal
+fn save_proposal(proposal: &PendingRecommendation) {
+    indexes::save_proposal(proposal);
+}
+
+/// Generate unique proposal ID
+async fn generate_proposal_id() -> String {
+    let timestamp = now();
+    let random_result = ic_cdk::api::management_canister::main::raw_rand().await;
+    let random_bytes = match random_result {
+        Ok((bytes,)) => bytes,
+        Err((_, err)) => {
+            ic_cdk::println!("Warning: Random generation failed: {}", err);
+            vec![0u8; 8]
+        }
+    };
+
+    let mut id_bytes = timestamp.to_be_bytes().to_vec();
+    if random_bytes.len() >= 8 {
+        id_bytes.extend_from_slice(&random_bytes[0..8]);
+    }
+
+    format!("prop_{}", hex::encode(id_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RecommendationType;
+
+    fn recommendation(to_protocol: &str, position_size: &str) -> Recommendation {
+        Recommendation {
+            asset: "USDC".to_string(),
+            to_asset: "USDC".to_string(),
+            from_chain: "arbitrum".to_string(),
+            to_chain: None,
+            from_protocol: "aave-v3".to_string(),
+            to_protocol: to_protocol.to_string(),
+            current_apy: 3.0,
+            target_apy: 4.0,
+            estimated_profit: 1.0,
+            gas_cost: 0.1,
+            position_size: position_size.to_string(),
+            position_amount: None,
+            pool_id: None,
+            recommendation_type: RecommendationType::StandardTransfer,
+            swap_details: None,
+        }
+    }
+
+    #[test]
+    fn test_same_move_ignores_apy_figures() {
+        let original = recommendation("compound-v3", "100");
+        let refreshed = Recommendation { target_apy: 4.5, estimated_profit: 1.5, ..original.clone() };
+
+        assert!(is_same_move(&original, &refreshed));
+        assert!(!is_same_move(&original, &recommendation("compound-v3", "150")));
+        assert!(!is_same_move(&original, &recommendation("morpho", "100")));
+    }
+}
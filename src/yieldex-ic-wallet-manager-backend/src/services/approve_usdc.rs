@@ -1,5 +1,3 @@
-use std::cell::RefCell;
-
 use alloy::{
     network::EthereumWallet,
     primitives::{address, Address, U256},
@@ -10,12 +8,10 @@ use alloy::{
 };
 
 use crate::create_icp_signer;
+use crate::services::{fee_policy, tx_tracker};
+use crate::services::nonce_manager::{reserve_nonce, commit_nonce, rollback_nonce};
 use crate::services::rpc_service::{get_rpc_service_by_chain_id, SEPOLIA_CHAIN_ID};
 
-thread_local! {
-    static NONCE: RefCell<Option<u64>> = const { RefCell::new(None) };
-}
-
 // Codegen from ABI file to interact with the USDC contract.
 sol!(
     #[allow(missing_docs, clippy::too_many_arguments)]
@@ -60,18 +56,6 @@ pub async fn approve_usdc(spender_address: String, amount: String) -> Result<Str
         .wallet(wallet)
         .on_icp(config);
 
-    // Handle nonce management
-    let maybe_nonce = NONCE.with_borrow(|maybe_nonce| {
-        maybe_nonce.map(|nonce| nonce + 1)
-    });
-
-    let nonce = if let Some(nonce) = maybe_nonce {
-        nonce
-    } else {
-        provider.get_transaction_count(address).await
-            .map_err(|e| format!("Failed to get nonce: {}", e))?
-    };
-
     // Create USDC contract instance (Sepolia USDC address)
     let contract = USDC::new(
         address!("1c7d4b196cb0c7b01d743fbc6116a902379c7238"),
@@ -85,25 +69,28 @@ pub async fn approve_usdc(spender_address: String, amount: String) -> Result<Str
     ic_cdk::println!("Current allowance for {}: {}", spender_address, current_allowance._0);
 
     // Execute the approve transaction
-    match contract
+    let call = contract
         .approve(spender_address, amount)
-        .nonce(nonce)
         .chain_id(11155111) // Sepolia chain ID
-        .from(address)
+        .from(address);
+    let call = fee_policy::apply_to_call(call, SEPOLIA_CHAIN_ID).await?;
+    let nonce = reserve_nonce(address, &provider, SEPOLIA_CHAIN_ID).await?;
+    match call
+        .nonce(nonce)
         .send()
         .await
     {
         Ok(builder) => {
+            // Transaction sent - commit nonce
+            commit_nonce(address, SEPOLIA_CHAIN_ID, nonce);
+
             let tx_hash = *builder.tx_hash();
             let tx_response = provider.get_transaction_by_hash(tx_hash).await
                 .map_err(|e| format!("Failed to get transaction: {}", e))?;
 
             match tx_response {
                 Some(tx) => {
-                    // Update nonce cache
-                    NONCE.with_borrow_mut(|nonce| {
-                        *nonce = Some(tx.nonce);
-                    });
+                    tx_tracker::record_sent(ic_cdk::caller(), SEPOLIA_CHAIN_ID, &tx);
                     
                     // Log the approval
                     ic_cdk::println!(
@@ -124,7 +111,11 @@ pub async fn approve_usdc(spender_address: String, amount: String) -> Result<Str
                 None => Err("Transaction not found after sending".to_string()),
             }
         }
-        Err(e) => Err(format!("Approve transaction failed: {:?}", e)),
+        Err(e) => {
+            // Transaction failed to send - rollback nonce
+            rollback_nonce(address, SEPOLIA_CHAIN_ID, nonce);
+            Err(format!("Approve transaction failed: {:?}", e))
+        }
     }
 }
 
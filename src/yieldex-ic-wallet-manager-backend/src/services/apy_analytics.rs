@@ -0,0 +1,463 @@
+use crate::types::{
+    ApyAnalytics, ApyProtocolStatistics, ApySpreadDistribution, ApySpreadThreshold, ApyResolution,
+};
+use crate::services::{apy_parser, apy_retention};
+use crate::services::apy_signals::{self, ApySample};
+use crate::now;
+
+// =============================================================================
+// APY Analytics
+// =============================================================================
+//
+// Aggregate view of stored APY history, used to choose scheduler thresholds:
+// - per-protocol min/max/mean/median/standard deviation
+// - share of time each protocol offered the highest APY
+// - time-weighted distribution of the spread between each pair of protocols
+//
+// Raw samples are used where they are still kept; older parts of the window
+// fall back to the hourly, then daily, rolled-up buckets. Statistics weight
+// each bucket by the number of samples rolled into it and take the extremes
+// from the stored bucket min/max.
+//
+
+/// Default analytics window (30 days)
+const DEFAULT_WINDOW_SECONDS: u64 = 30 * 24 * 3600;
+
+/// Spread thresholds (percentage points) reported in the exceedance table
+const SPREAD_THRESHOLDS: [f64; 6] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0];
+
+/// Summary statistics of a set of values
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Summary {
+    min: f64,
+    max: f64,
+    mean: f64,
+    median: f64,
+    std_dev: f64,
+}
+
+/// A stored point of a series: a raw sample or a rolled-up bucket
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SeriesPoint {
+    /// Sample, or bucket start and mean for rolled-up buckets
+    sample: ApySample,
+    min: f64,
+    max: f64,
+    /// Raw samples the point stands for
+    sample_count: u64,
+}
+
+impl SeriesPoint {
+    fn raw(sample: ApySample) -> Self {
+        SeriesPoint { sample, min: sample.apy, max: sample.apy, sample_count: 1 }
+    }
+
+    /// Weight of the point in statistics, at least one sample
+    fn weight(&self) -> u64 {
+        self.sample_count.max(1)
+    }
+}
+
+/// Result of walking several series over a shared timeline
+#[derive(Clone, Debug, Default)]
+struct Comparison {
+    /// Time each series led, indexed like the input
+    lead_ms: Vec<u64>,
+    /// (spread, duration) points per pair, in (0,1), (0,2), .., (1,2), .. order
+    spreads: Vec<Vec<(f64, u64)>>,
+    /// Time during which every series had a value
+    compared_ms: u64,
+}
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Compute APY analytics of an asset on a chain across the monitored protocols
+pub fn get_apy_analytics(asset: &str, chain_id: u64, window_seconds: Option<u64>) -> Result<ApyAnalytics, String> {
+    let window_seconds = window_seconds.unwrap_or(DEFAULT_WINDOW_SECONDS);
+    if window_seconds == 0 {
+        return Err("Window must be at least 1 second".to_string());
+    }
+
+    let window_end = now();
+    let window_start = window_end.saturating_sub(window_seconds.saturating_mul(1000));
+
+    let series: Vec<(String, Vec<SeriesPoint>, Option<ApySample>)> = apy_parser::get_apy_parser_config()
+        .monitored_protocols
+        .iter()
+        .map(|protocol| {
+            let (points, prior) = load_series_points(protocol, asset, chain_id, window_start);
+            (protocol.clone(), points, prior)
+        })
+        .filter(|(_, points, _)| !points.is_empty())
+        .collect();
+
+    if series.is_empty() {
+        return Err(format!("No APY history for {} on chain {} in the window", asset, chain_id));
+    }
+
+    ic_cdk::println!("📊 APY analytics for {} on chain {}: {} protocols with history",
+        asset, chain_id, series.len());
+
+    let step_series: Vec<Vec<ApySample>> = series.iter()
+        .map(|(_, points, _)| points.iter().map(|p| p.sample).collect())
+        .collect();
+    let inputs: Vec<(&[ApySample], Option<ApySample>)> = series.iter()
+        .zip(&step_series)
+        .map(|((_, _, prior), samples)| (samples.as_slice(), *prior))
+        .collect();
+    let comparison = compare_series(&inputs, window_start, window_end);
+
+    let protocols = series.iter()
+        .zip(&step_series)
+        .enumerate()
+        .filter_map(|(i, ((protocol, points, prior), samples))| {
+            let summary = summarize(points)?;
+            Some(ApyProtocolStatistics {
+                protocol: protocol.clone(),
+                sample_count: points.iter().map(SeriesPoint::weight).sum(),
+                min: summary.min,
+                max: summary.max,
+                mean: summary.mean,
+                median: summary.median,
+                std_dev: summary.std_dev,
+                time_weighted_mean: apy_signals::time_weighted_average(samples, *prior, window_start, window_end)
+                    .unwrap_or(summary.mean),
+                lead_time_percent: share_percent(comparison.lead_ms[i], comparison.compared_ms),
+            })
+        })
+        .collect();
+
+    let mut spreads = Vec::new();
+    let mut pair_points = comparison.spreads.iter();
+    for (a, (protocol_a, _, _)) in series.iter().enumerate() {
+        for (protocol_b, _, _) in &series[a + 1..] {
+            let points = pair_points.next().map(Vec::as_slice).unwrap_or_default();
+            if let Some(distribution) = spread_distribution(protocol_a, protocol_b, points) {
+                spreads.push(distribution);
+            }
+        }
+    }
+
+    Ok(ApyAnalytics {
+        asset: asset.to_string(),
+        chain_id,
+        window_start,
+        window_end,
+        protocols,
+        spreads,
+        compared_duration_ms: comparison.compared_ms,
+    })
+}
+
+// =============================================================================
+// Stored History Access
+// =============================================================================
+
+/// Samples of a series inside the window (oldest first) plus the last raw sample before it
+///
+/// Rolled-up buckets appear as one sample at the bucket mean.
+pub(crate) fn load_series(protocol: &str, asset: &str, chain_id: u64, window_start: u64) -> (Vec<ApySample>, Option<ApySample>) {
+    let (points, prior) = load_series_points(protocol, asset, chain_id, window_start);
+    (points.iter().map(|p| p.sample).collect(), prior)
+}
+
+/// Points of a series inside the window (oldest first) plus the last raw sample before it
+fn load_series_points(protocol: &str, asset: &str, chain_id: u64, window_start: u64) -> (Vec<SeriesPoint>, Option<ApySample>) {
+    let raw: Vec<SeriesPoint> = apy_parser::get_apy_samples_since(protocol, asset, chain_id, window_start)
+        .iter()
+        .map(|record| SeriesPoint::raw(ApySample::from(record)))
+        .collect();
+
+    // Rolled-up buckets only cover the part of the window before finer data starts
+    let raw_start = raw.first().map_or(u64::MAX, |p| p.sample.timestamp);
+    let hourly = rolled_up_points(protocol, asset, chain_id, ApyResolution::Hourly, window_start, raw_start);
+    let hourly_start = hourly.first().map_or(raw_start, |p| p.sample.timestamp);
+    let daily = rolled_up_points(protocol, asset, chain_id, ApyResolution::Daily, window_start, hourly_start);
+
+    let mut points = daily;
+    points.extend(hourly);
+    points.extend(raw);
+
+    let prior = apy_parser::get_last_apy_before(protocol, asset, chain_id, window_start)
+        .as_ref()
+        .map(ApySample::from);
+
+    (points, prior)
+}
+
+/// Buckets of a resolution with buckets in `[from, before)`
+fn rolled_up_points(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    resolution: ApyResolution,
+    from: u64,
+    before: u64,
+) -> Vec<ApySample> {
+    if from >= before {
+        return Vec::new();
+    }
+
+    apy_retention::aggregates_in_range(protocol, asset, chain_id, resolution, from, before - 1, usize::MAX)
+        .iter()
+        .map(|aggregate| SeriesPoint {
+            sample: ApySample { timestamp: aggregate.bucket_start, apy: aggregate.mean },
+            min: aggregate.min,
+            max: aggregate.max,
+            sample_count: aggregate.sample_count,
+        })
+        .collect()
+}
+
+// =============================================================================
+// Pure Analytics Functions
+// =============================================================================
+
+/// Min/max/mean/median/population standard deviation, weighted by sample count
+///
+/// Min and max come from the stored bucket extremes. A bucket's samples are
+/// all taken at its mean for the median and standard deviation, since the
+/// spread inside a bucket is not stored.
+fn summarize(points: &[SeriesPoint]) -> Option<Summary> {
+    if points.is_empty() {
+        return None;
+    }
+
+    let mut sorted: Vec<(f64, u64)> = points.iter().map(|p| (p.sample.apy, p.weight())).collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let n: u64 = sorted.iter().map(|(_, w)| w).sum();
+    let mean = sorted.iter().map(|(v, w)| v * *w as f64).sum::<f64>() / n as f64;
+    let median = if n % 2 == 0 {
+        (value_at_rank(&sorted, n / 2) + value_at_rank(&sorted, n / 2 + 1)) / 2.0
+    } else {
+        value_at_rank(&sorted, n / 2 + 1)
+    };
+    let variance = sorted.iter().map(|(v, w)| (v - mean).powi(2) * *w as f64).sum::<f64>() / n as f64;
+
+    Some(Summary {
+        min: points.iter().map(|p| p.min).fold(f64::INFINITY, f64::min),
+        max: points.iter().map(|p| p.max).fold(f64::NEG_INFINITY, f64::max),
+        mean,
+        median,
+        std_dev: variance.sqrt(),
+    })
+}
+
+/// Value of the `rank`-th sample (1-based) of weighted values sorted ascending
+fn value_at_rank(sorted: &[(f64, u64)], rank: u64) -> f64 {
+    let mut cumulative = 0u64;
+    for (value, weight) in sorted {
+        cumulative += weight;
+        if cumulative >= rank {
+            return *value;
+        }
+    }
+    sorted[sorted.len() - 1].0
+}
+
+/// Walk step-function series over their merged timeline
+///
+/// Each sample holds its value until the next one; `prior` covers the start
+/// of the window. Time only counts while every series has a value.
+fn compare_series(series: &[(&[ApySample], Option<ApySample>)], window_start: u64, window_end: u64) -> Comparison {
+    let pair_count = series.len() * series.len().saturating_sub(1) / 2;
+    let mut comparison = Comparison {
+        lead_ms: vec![0; series.len()],
+        spreads: vec![Vec::new(); pair_count],
+        compared_ms: 0,
+    };
+
+    let mut times: Vec<u64> = series.iter()
+        .flat_map(|(samples, _)| samples.iter().map(|s| s.timestamp))
+        .filter(|t| *t >= window_start && *t <= window_end)
+        .chain(std::iter::once(window_start))
+        .collect();
+    times.sort_unstable();
+    times.dedup();
+
+    for (i, &t) in times.iter().enumerate() {
+        let until = times.get(i + 1).copied().unwrap_or(window_end);
+        let duration = until.saturating_sub(t);
+        if duration == 0 {
+            continue;
+        }
+
+        let values: Option<Vec<f64>> = series.iter()
+            .map(|(samples, prior)| value_at(samples, *prior, t))
+            .collect();
+        let Some(values) = values else {
+            continue;
+        };
+
+        // Ties go to the protocol listed first
+        let leader = values.iter()
+            .enumerate()
+            .fold(0, |best, (j, v)| if *v > values[best] { j } else { best });
+        comparison.lead_ms[leader] += duration;
+
+        let mut pair = 0;
+        for (a, value_a) in values.iter().enumerate() {
+            for value_b in &values[a + 1..] {
+                comparison.spreads[pair].push((value_a - value_b, duration));
+                pair += 1;
+            }
+        }
+
+        comparison.compared_ms += duration;
+    }
+
+    comparison
+}
+
+/// Value of a step-function series at a point in time
+fn value_at(samples: &[ApySample], prior: Option<ApySample>, timestamp: u64) -> Option<f64> {
+    let held = samples.partition_point(|s| s.timestamp <= timestamp);
+    if held > 0 {
+        Some(samples[held - 1].apy)
+    } else {
+        prior.map(|p| p.apy)
+    }
+}
+
+/// Time-weighted spread distribution of a protocol pair
+fn spread_distribution(protocol_a: &str, protocol_b: &str, points: &[(f64, u64)]) -> Option<ApySpreadDistribution> {
+    let total: u64 = points.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mean = sorted.iter().map(|(v, w)| v * *w as f64).sum::<f64>() / total as f64;
+    let exceedance = SPREAD_THRESHOLDS.iter()
+        .map(|threshold| {
+            let above: u64 = sorted.iter()
+                .filter(|(v, _)| v.abs() >= *threshold)
+                .map(|(_, w)| w)
+                .sum();
+            ApySpreadThreshold {
+                threshold_percent: *threshold,
+                time_percent: share_percent(above, total),
+            }
+        })
+        .collect();
+
+    Some(ApySpreadDistribution {
+        protocol_a: protocol_a.to_string(),
+        protocol_b: protocol_b.to_string(),
+        mean,
+        min: sorted[0].0,
+        max: sorted[sorted.len() - 1].0,
+        p10: weighted_percentile(&sorted, total, 0.10),
+        p25: weighted_percentile(&sorted, total, 0.25),
+        p50: weighted_percentile(&sorted, total, 0.50),
+        p75: weighted_percentile(&sorted, total, 0.75),
+        p90: weighted_percentile(&sorted, total, 0.90),
+        exceedance,
+    })
+}
+
+/// Smallest value whose cumulative weight reaches `fraction` of the total
+fn weighted_percentile(sorted: &[(f64, u64)], total: u64, fraction: f64) -> f64 {
+    let target = fraction * total as f64;
+    let mut cumulative = 0u64;
+    for (value, weight) in sorted {
+        cumulative += weight;
+        if cumulative as f64 >= target {
+            return *value;
+        }
+    }
+    sorted[sorted.len() - 1].0
+}
+
+fn share_percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 / total as f64 * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, apy: f64) -> ApySample {
+        ApySample { timestamp, apy }
+    }
+
+    fn raw_points(values: &[f64]) -> Vec<SeriesPoint> {
+        values.iter().enumerate().map(|(i, v)| SeriesPoint::raw(sample(i as u64, *v))).collect()
+    }
+
+    #[test]
+    fn test_summarize_median_and_std_dev() {
+        let summary = summarize(&raw_points(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0])).unwrap();
+        assert_eq!(summary.min, 2.0);
+        assert_eq!(summary.max, 9.0);
+        assert!((summary.mean - 5.0).abs() < 1e-9);
+        assert!((summary.median - 4.5).abs() < 1e-9);
+        assert!((summary.std_dev - 2.0).abs() < 1e-9);
+        assert!(summarize(&[]).is_none());
+    }
+
+    #[test]
+    fn test_summarize_weights_buckets_by_sample_count() {
+        // A bucket of 3 samples with mean 4% (range 1-8%) and one raw 8% sample
+        let bucket = SeriesPoint { sample: sample(0, 4.0), min: 1.0, max: 8.0, sample_count: 3 };
+        let summary = summarize(&[bucket, SeriesPoint::raw(sample(10, 8.0))]).unwrap();
+
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.max, 8.0);
+        assert!((summary.mean - 5.0).abs() < 1e-9);
+        assert!((summary.median - 4.0).abs() < 1e-9);
+        assert!((summary.std_dev - 3.0_f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lead_time_and_spread_follow_step_functions() {
+        // A: 5% for the whole window; B: 4% for 6 units, then 7% for 4 units
+        let a = vec![sample(0, 5.0)];
+        let b = vec![sample(0, 4.0), sample(6, 7.0)];
+        let comparison = compare_series(&[(a.as_slice(), None), (b.as_slice(), None)], 0, 10);
+
+        assert_eq!(comparison.compared_ms, 10);
+        assert_eq!(comparison.lead_ms, vec![6, 4]);
+        assert_eq!(comparison.spreads[0], vec![(1.0, 6), (-2.0, 4)]);
+    }
+
+    #[test]
+    fn test_time_is_only_compared_when_all_series_have_values() {
+        let a = vec![sample(0, 5.0)];
+        let b = vec![sample(4, 3.0)];
+        let comparison = compare_series(&[(a.as_slice(), None), (b.as_slice(), None)], 0, 10);
+        assert_eq!(comparison.compared_ms, 6);
+
+        // A prior sample before the window fills the gap
+        let comparison = compare_series(&[(a.as_slice(), None), (b.as_slice(), Some(sample(0, 1.0)))], 0, 10);
+        assert_eq!(comparison.compared_ms, 10);
+    }
+
+    #[test]
+    fn test_spread_distribution_is_time_weighted() {
+        let points = vec![(1.0, 6), (-2.0, 4)];
+        let distribution = spread_distribution("AAVE", "COMPOUND", &points).unwrap();
+
+        assert!((distribution.mean - (-0.2)).abs() < 1e-9);
+        assert_eq!(distribution.min, -2.0);
+        assert_eq!(distribution.p25, -2.0);
+        assert_eq!(distribution.p50, 1.0);
+
+        let half_percent = distribution.exceedance.iter()
+            .find(|e| e.threshold_percent == 0.5)
+            .unwrap();
+        assert!((half_percent.time_percent - 100.0).abs() < 1e-9);
+        let two_percent = distribution.exceedance.iter()
+            .find(|e| e.threshold_percent == 2.0)
+            .unwrap();
+        assert!((two_percent.time_percent - 40.0).abs() < 1e-9);
+    }
+}
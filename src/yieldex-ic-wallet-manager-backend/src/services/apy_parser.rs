@@ -1,19 +1,34 @@
 use candid::Principal;
-use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use ic_cdk_timers::{set_timer, set_timer_interval, clear_timer, TimerId};
 use std::cell::RefCell;
+use std::ops::Bound;
 use std::time::Duration;
 use alloy::primitives::Address;
 
-use crate::types::{UserPosition, ApyHistoryRecord, StorableUserPosition, StorableApyHistoryRecord};
+use crate::types::{
+    UserPosition, ApyHistoryRecord, ApyHistoryKey, ApyHistoryPage, StorableApyHistoryRecord,
+    StorableApyHistoryKey, ExecutionMode, TokenAmount,
+};
+use crate::types::amount::decimals_for_symbol;
+use crate::services::{chain_registry, indexes};
 use crate::{
     StorableString,
-    APY_HISTORY_MAP, USER_POSITIONS_MAP, now
+    APY_HISTORY_MAP, LEGACY_APY_HISTORY_MAP, MIGRATION_CURSORS_MAP, USER_POSITIONS_MAP, now
 };
 
 // =============================================================================
 // Global State for APY Parser
 // =============================================================================
 
+/// Maximum number of records in one APY history page
+const MAX_APY_HISTORY_PAGE: u64 = 1000;
+
+/// Legacy APY records migrated per timer callback
+const LEGACY_APY_MIGRATION_CHUNK: usize = 500;
+
+/// Cursor name of the legacy APY history migration
+const LEGACY_APY_MIGRATION: &str = "legacy_apy_history";
+
 thread_local! {
     /// Configuration for APY collection
     static APY_PARSER_CONFIG: RefCell<ApyParserConfig> = RefCell::new(ApyParserConfig::default());
@@ -40,7 +55,8 @@ pub struct ApyParserConfig {
     /// List of protocols to monitor
     pub monitored_protocols: Vec<String>,
 
-    /// List of chains to monitor
+    /// List of chains to monitor; empty = every enabled registry chain with a
+    /// deployment of the protocol
     pub monitored_chains: Vec<u64>,
 
     /// Whether automatic position synchronization is enabled
@@ -54,9 +70,7 @@ impl Default for ApyParserConfig {
             interval_seconds: 900, // 15 minutes
             last_execution: None,
             monitored_protocols: vec!["AAVE".to_string(), "COMPOUND".to_string()],
-            monitored_chains: vec![
-                crate::services::rpc_service::ARBITRUM_CHAIN_ID,
-            ],
+            monitored_chains: Vec::new(),
             auto_sync_positions: true, // Enabled by default to track user positions
         }
     }
@@ -78,7 +92,7 @@ pub fn init_apy_parser() {
     ic_cdk::println!("✅ APY Parser initialized");
     ic_cdk::println!("  - Interval: {} seconds", ApyParserConfig::default().interval_seconds);
     ic_cdk::println!("  - Monitored Protocols: {:?}", ApyParserConfig::default().monitored_protocols);
-    ic_cdk::println!("  - Monitored Chains: {:?}", ApyParserConfig::default().monitored_chains);
+    ic_cdk::println!("  - Monitored Chains: {:?}", monitored_chains(&ApyParserConfig::default()));
 }
 
 /// Start the APY collection timer
@@ -163,7 +177,7 @@ async fn execute_apy_collection_internal(force: bool) {
 
     // Iterate through all monitored protocols and chains
     for protocol in &config.monitored_protocols {
-        for &chain_id in &config.monitored_chains {
+        for chain_id in chains_for_protocol(&config, protocol) {
             ic_cdk::println!("📊 Collecting APY for {} on chain {}", protocol, chain_id);
 
             match collect_protocol_apy(protocol, chain_id).await {
@@ -192,16 +206,19 @@ async fn execute_apy_collection_internal(force: bool) {
 }
 
 /// Collect APY for a specific protocol on a specific chain
+///
+/// The rates of all tokens are read in one batched call.
 async fn collect_protocol_apy(protocol: &str, chain_id: u64) -> Result<u32, String> {
     let mut collected_count = 0;
 
     // Get supported tokens for this protocol and chain
     let tokens = get_supported_tokens(protocol, chain_id)?;
 
-    for token_info in tokens {
-        ic_cdk::println!("  Fetching APY for {} ({}) on {}", token_info.symbol, token_info.address, protocol);
+    ic_cdk::println!("  Fetching APY for {} tokens on {}", tokens.len(), protocol);
+    let apys = fetch_protocol_apys(protocol, &tokens, chain_id).await?;
 
-        match fetch_protocol_apy(protocol, &token_info, chain_id).await {
+    for (token_info, apy) in tokens.into_iter().zip(apys) {
+        match apy {
             Ok(apy_value) => {
                 // Create APY history record
                 let record = ApyHistoryRecord {
@@ -229,28 +246,28 @@ async fn collect_protocol_apy(protocol: &str, chain_id: u64) -> Result<u32, Stri
     Ok(collected_count)
 }
 
-/// Fetch APY from a specific protocol
-async fn fetch_protocol_apy(
+/// Fetch APYs of several tokens from a specific protocol, one result per token
+async fn fetch_protocol_apys(
     protocol: &str,
-    token_info: &TokenInfo,
+    tokens: &[TokenInfo],
     chain_id: u64,
-) -> Result<f64, String> {
+) -> Result<Vec<Result<f64, String>>, String> {
+    let parse_apy = |apy_str: String| apy_str.parse::<f64>()
+        .map_err(|_| format!("Failed to parse APY: {}", apy_str));
+
     match protocol {
         "AAVE" => {
-            let token_address: Address = token_info.address.parse()
-                .map_err(|_| "Invalid token address".to_string())?;
-            let apy_str = crate::services::aave::get_apy(token_address, chain_id).await?;
-            apy_str.parse::<f64>()
-                .map_err(|_| format!("Failed to parse APY: {}", apy_str))
+            let token_addresses = tokens.iter()
+                .map(|token_info| token_info.address.parse::<Address>()
+                    .map_err(|_| format!("Invalid token address: {}", token_info.address)))
+                .collect::<Result<Vec<Address>, String>>()?;
+            let apys = crate::services::aave::get_apys(&token_addresses, chain_id).await?;
+            Ok(apys.into_iter().map(|apy| apy.and_then(parse_apy)).collect())
         }
         "COMPOUND" => {
-            // Compound only supports USDC on Arbitrum currently
-            if chain_id != crate::services::rpc_service::ARBITRUM_CHAIN_ID {
-                return Err("Compound only available on Arbitrum".to_string());
-            }
-            let apy_str = crate::services::compound::get_apy(chain_id).await?;
-            apy_str.parse::<f64>()
-                .map_err(|_| format!("Failed to parse APY: {}", apy_str))
+            // A single USDC market per chain: every token gets the market's rate
+            let apy = crate::services::compound::get_apy(chain_id).await.and_then(parse_apy);
+            Ok(tokens.iter().map(|_| apy.clone()).collect())
         }
         _ => Err(format!("Unknown protocol: {}", protocol))
     }
@@ -266,43 +283,18 @@ struct TokenInfo {
     address: String,
 }
 
-/// Get list of supported tokens for a protocol on a chain
+/// Get list of supported tokens for a protocol on a chain from the chain registry
 fn get_supported_tokens(protocol: &str, chain_id: u64) -> Result<Vec<TokenInfo>, String> {
-    use crate::services::rpc_service::{ARBITRUM_CHAIN_ID, SEPOLIA_CHAIN_ID, BASE_CHAIN_ID, OPTIMISM_CHAIN_ID};
-
-    match (protocol, chain_id) {
-        ("AAVE", ARBITRUM_CHAIN_ID) => Ok(vec![
-            TokenInfo {
-                symbol: "USDC".to_string(),
-                address: "0xaf88d065e77c8cC2239327C5EDb3A432268e5831".to_string()
-            },
-        ]),
-        ("AAVE", SEPOLIA_CHAIN_ID) => Ok(vec![
-            TokenInfo {
-                symbol: "USDC".to_string(),
-                address: "0x94a9D9AC8a22534E3FaCa9954e183B2c3736704F".to_string()
-            },
-        ]),
-        ("AAVE", BASE_CHAIN_ID) => Ok(vec![
-            TokenInfo {
-                symbol: "USDC".to_string(),
-                address: "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913".to_string()
-            },
-        ]),
-        ("AAVE", OPTIMISM_CHAIN_ID) => Ok(vec![
-            TokenInfo {
-                symbol: "USDC".to_string(),
-                address: "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85".to_string()
-            },
-        ]),
-        ("COMPOUND", ARBITRUM_CHAIN_ID) => Ok(vec![
-            TokenInfo {
-                symbol: "USDC".to_string(),
-                address: "0xaf88d065e77c8cC2239327C5EDb3A432268e5831".to_string()
-            },
-        ]),
-        _ => Err(format!("Protocol {} not supported on chain {}", protocol, chain_id))
-    }
+    let deployment = crate::services::chain_registry::get_protocol_deployment(chain_id, protocol)
+        .ok_or_else(|| format!("Protocol {} not supported on chain {}", protocol, chain_id))?;
+
+    Ok(deployment.assets
+        .into_iter()
+        .map(|asset| TokenInfo {
+            symbol: asset.symbol,
+            address: asset.token_address,
+        })
+        .collect())
 }
 
 /// Generate unique record ID for APY history
@@ -318,7 +310,7 @@ fn store_apy_record(record: ApyHistoryRecord) -> Result<(), String> {
 
     APY_HISTORY_MAP.with(|map| {
         map.borrow_mut().insert(
-            StorableString(record.record_id.clone()),
+            StorableApyHistoryKey(ApyHistoryKey::for_record(&record)),
             StorableApyHistoryRecord(record)
         );
     });
@@ -326,6 +318,82 @@ fn store_apy_record(record: ApyHistoryRecord) -> Result<(), String> {
     Ok(())
 }
 
+/// Start moving records stored under legacy "PROTOCOL:CHAIN:TOKEN:TIMESTAMP"
+/// string keys into the composite-key map
+///
+/// Records are migrated in chunks from a timer so a large legacy map cannot
+/// exhaust the instruction limit of `post_upgrade`. The last migrated key is
+/// persisted, so a migration interrupted by another upgrade resumes where it
+/// stopped. Legacy records become visible to queries as they are migrated.
+pub fn start_legacy_apy_migration() {
+    let remaining = LEGACY_APY_HISTORY_MAP.with(|map| map.borrow().len());
+    if remaining == 0 {
+        MIGRATION_CURSORS_MAP.with(|map| {
+            map.borrow_mut().remove(&StorableString(LEGACY_APY_MIGRATION.to_string()));
+        });
+        return;
+    }
+
+    ic_cdk::println!("🗂️ Migrating {} legacy APY history records in chunks of {}",
+        remaining, LEGACY_APY_MIGRATION_CHUNK);
+    schedule_legacy_apy_migration_chunk();
+}
+
+fn schedule_legacy_apy_migration_chunk() {
+    set_timer(Duration::ZERO, || {
+        if migrate_legacy_apy_chunk() {
+            schedule_legacy_apy_migration_chunk();
+        } else {
+            ic_cdk::println!("✅ Legacy APY history migrated");
+        }
+    });
+}
+
+/// Migrate the next chunk of legacy records, returning whether more remain
+fn migrate_legacy_apy_chunk() -> bool {
+    let cursor_key = StorableString(LEGACY_APY_MIGRATION.to_string());
+    let cursor = MIGRATION_CURSORS_MAP.with(|map| map.borrow().get(&cursor_key));
+
+    let chunk: Vec<(StorableString, ApyHistoryRecord)> = LEGACY_APY_HISTORY_MAP.with(|map| {
+        let map = map.borrow();
+        let lower = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded,
+        };
+        map.range((lower, Bound::Unbounded))
+            .take(LEGACY_APY_MIGRATION_CHUNK)
+            .map(|(key, record)| (key, record.0))
+            .collect()
+    });
+
+    let Some((last_key, _)) = chunk.last() else {
+        MIGRATION_CURSORS_MAP.with(|map| map.borrow_mut().remove(&cursor_key));
+        return false;
+    };
+    let last_key = last_key.clone();
+
+    for (key, record) in &chunk {
+        APY_HISTORY_MAP.with(|map| {
+            map.borrow_mut().insert(
+                StorableApyHistoryKey(ApyHistoryKey::for_record(record)),
+                StorableApyHistoryRecord(record.clone())
+            );
+        });
+        LEGACY_APY_HISTORY_MAP.with(|map| {
+            map.borrow_mut().remove(key);
+        });
+    }
+
+    if chunk.len() < LEGACY_APY_MIGRATION_CHUNK {
+        MIGRATION_CURSORS_MAP.with(|map| map.borrow_mut().remove(&cursor_key));
+        return false;
+    }
+
+    MIGRATION_CURSORS_MAP.with(|map| map.borrow_mut().insert(cursor_key, last_key));
+    ic_cdk::println!("🗂️ Migrated {} legacy APY history records", chunk.len());
+    true
+}
+
 // =============================================================================
 // Public Query Functions
 // =============================================================================
@@ -335,24 +403,7 @@ pub async fn get_latest_apy(protocol: &str, asset: &str, chain_id: u64) -> Resul
     ic_cdk::println!("🔍 Getting latest APY for {} {} on chain {}", protocol, asset, chain_id);
 
     // First try to get from APY_HISTORY_MAP (get most recent)
-    let cached_apy = APY_HISTORY_MAP.with(|map| {
-        let borrowed = map.borrow();
-        let mut matching_records: Vec<ApyHistoryRecord> = borrowed
-            .iter()
-            .filter_map(|(_, record)| {
-                let r = record.0.clone();
-                if r.protocol == protocol && r.asset == asset && r.chain_id == chain_id {
-                    Some(r)
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        // Sort by timestamp descending and get the most recent
-        matching_records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-        matching_records.first().map(|r| r.apy)
-    });
+    let cached_apy = get_last_apy_before(protocol, asset, chain_id, u64::MAX).map(|r| r.apy);
 
     if let Some(apy) = cached_apy {
         ic_cdk::println!("  ✅ Found cached APY: {}%", apy);
@@ -363,43 +414,96 @@ pub async fn get_latest_apy(protocol: &str, asset: &str, chain_id: u64) -> Resul
 
     // Get token address for the asset
     let tokens = get_supported_tokens(protocol, chain_id)?;
-    let token_info = tokens.iter()
+    let token_info = tokens.into_iter()
         .find(|t| t.symbol == asset)
         .ok_or_else(|| format!("Token {} not found for protocol {}", asset, protocol))?;
 
-    fetch_protocol_apy(protocol, token_info, chain_id).await
+    fetch_protocol_apys(protocol, &[token_info], chain_id).await?
+        .pop()
+        .ok_or_else(|| "No APY result".to_string())?
 }
 
-/// Get APY history for a specific protocol/asset/chain
+/// Get a page of APY history for a specific protocol/asset/chain, oldest first
+///
+/// Covers samples in `[from, to]`; `cursor` is the `next_cursor` of the
+/// previous page.
 pub fn get_apy_history(
     protocol: &str,
     asset: &str,
     chain_id: u64,
+    from: Option<u64>,
+    to: Option<u64>,
+    cursor: Option<u64>,
     limit: Option<u64>,
+) -> Result<ApyHistoryPage, String> {
+    ic_cdk::println!("📜 Getting APY history for {} {} on chain {} (from: {:?}, to: {:?}, cursor: {:?}, limit: {:?})",
+        protocol, asset, chain_id, from, to, cursor, limit);
+
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or(u64::MAX);
+    if from > to {
+        return Err(format!("Invalid period: from {} is after to {}", from, to));
+    }
+
+    let start = cursor.map_or(from, |c| c.max(from));
+    let limit = limit.unwrap_or(100).clamp(1, MAX_APY_HISTORY_PAGE) as usize;
+
+    let mut records = series_range(protocol, asset, chain_id, start, to, limit + 1);
+    let next_cursor = if records.len() > limit {
+        records.pop().map(|r| r.timestamp)
+    } else {
+        None
+    };
+
+    Ok(ApyHistoryPage { records, next_cursor })
+}
+
+/// Get APY samples recorded at or after `since` (oldest first)
+pub fn get_apy_samples_since(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    since: u64,
 ) -> Vec<ApyHistoryRecord> {
-    ic_cdk::println!("📜 Getting APY history for {} {} on chain {} (limit: {:?})",
-        protocol, asset, chain_id, limit);
+    series_range(protocol, asset, chain_id, since, u64::MAX, usize::MAX)
+}
+
+/// Get the most recent APY sample recorded strictly before `before`
+pub fn get_last_apy_before(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    before: u64,
+) -> Option<ApyHistoryRecord> {
+    let bound = ApyHistoryKey::new(protocol, chain_id, asset, before);
 
     APY_HISTORY_MAP.with(|map| {
-        let borrowed = map.borrow();
-        let mut matching_records: Vec<ApyHistoryRecord> = borrowed
-            .iter()
-            .filter_map(|(_, record)| {
-                let r = record.0.clone();
-                if r.protocol == protocol && r.asset == asset && r.chain_id == chain_id {
-                    Some(r)
-                } else {
-                    None
-                }
-            })
-            .collect();
+        map.borrow()
+            .iter_upper_bound(&StorableApyHistoryKey(bound.clone()))
+            .next()
+            .filter(|(key, _)| key.0.same_series(&bound) && key.0.timestamp < before)
+            .map(|(_, record)| record.0)
+    })
+}
 
-        // Sort by timestamp descending (most recent first)
-        matching_records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+/// Samples of one series with timestamps in `[from, to]`, oldest first
+fn series_range(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    from: u64,
+    to: u64,
+    limit: usize,
+) -> Vec<ApyHistoryRecord> {
+    let start = StorableApyHistoryKey(ApyHistoryKey::new(protocol, chain_id, asset, from));
+    let end = StorableApyHistoryKey(ApyHistoryKey::new(protocol, chain_id, asset, to));
 
-        // Apply limit
-        let limit = limit.unwrap_or(100) as usize;
-        matching_records.into_iter().take(limit).collect()
+    APY_HISTORY_MAP.with(|map| {
+        map.borrow()
+            .range(start..=end)
+            .take(limit)
+            .map(|(_, record)| record.0)
+            .collect()
     })
 }
 
@@ -427,6 +531,18 @@ pub fn get_all_apy_history(limit: Option<u64>) -> Vec<ApyHistoryRecord> {
 // User Position Management
 // =============================================================================
 
+/// Parse a position size exactly when the asset's decimals are known
+///
+/// Returns None for assets without registered decimals.
+fn parse_position_amount(position_size: &str, asset: &str) -> Result<Option<TokenAmount>, String> {
+    match decimals_for_symbol(asset) {
+        Ok(decimals) => TokenAmount::parse(position_size, decimals)
+            .map(Some)
+            .map_err(|e| format!("Invalid position size: {}", e)),
+        Err(_) => Ok(None),
+    }
+}
+
 /// Generate unique position ID
 async fn generate_position_id() -> String {
     let timestamp = now();
@@ -470,6 +586,10 @@ pub async fn add_user_position(
         return Err("Protocol cannot be empty".to_string());
     }
 
+    // Keep an exact amount for assets with known decimals
+    let position_amount = parse_position_amount(&position_size, &asset)?;
+    let position_size = position_amount.map(|a| a.to_string()).unwrap_or(position_size);
+
     // Generate unique position ID
     let position_id = generate_position_id().await;
     let timestamp = now();
@@ -484,18 +604,15 @@ pub async fn add_user_position(
         token_address,
         chain_id,
         position_size,
+        position_amount,
         tracked,
+        execution_mode: None,
         added_at: timestamp,
         updated_at: timestamp,
     };
 
     // Store in USER_POSITIONS_MAP
-    USER_POSITIONS_MAP.with(|map| {
-        map.borrow_mut().insert(
-            StorableString(position_id.clone()),
-            StorableUserPosition(position.clone())
-        );
-    });
+    indexes::save_position(&position);
 
     ic_cdk::println!("✅ User position added successfully: {}", position_id);
 
@@ -526,20 +643,7 @@ pub fn get_tracked_positions() -> Vec<UserPosition> {
 pub fn get_user_positions(user: Principal) -> Vec<UserPosition> {
     ic_cdk::println!("📋 Getting positions for user: {}", user);
 
-    USER_POSITIONS_MAP.with(|map| {
-        let borrowed = map.borrow();
-        borrowed
-            .iter()
-            .filter_map(|(_, position)| {
-                let p = position.0.clone();
-                if p.user_principal == user {
-                    Some(p)
-                } else {
-                    None
-                }
-            })
-            .collect()
-    })
+    indexes::get_user_positions(user)
 }
 
 /// Update user position
@@ -551,74 +655,84 @@ pub fn update_user_position(
 ) -> Result<UserPosition, String> {
     ic_cdk::println!("🔄 Updating position: {}", position_id);
 
-    USER_POSITIONS_MAP.with(|map| {
-        let mut borrowed = map.borrow_mut();
+    // Get existing position
+    let mut position = get_position_by_id(position_id)?;
 
-        // Get existing position
-        let storable_position = borrowed
-            .get(&StorableString(position_id.clone()))
-            .ok_or_else(|| format!("Position {} not found", position_id))?;
+    // Verify ownership
+    if position.user_principal != user {
+        return Err("You do not own this position".to_string());
+    }
 
-        let mut position = storable_position.0.clone();
+    // Apply updates
+    let mut updated = false;
 
-        // Verify ownership
-        if position.user_principal != user {
-            return Err("You do not own this position".to_string());
+    if let Some(size) = position_size {
+        match parse_position_amount(&size, &position.asset)? {
+            Some(amount) => position.set_size(amount),
+            None => position.position_size = size,
         }
+        updated = true;
+    }
 
-        // Apply updates
-        let mut updated = false;
+    if let Some(track) = tracked {
+        position.tracked = track;
+        updated = true;
+    }
 
-        if let Some(size) = position_size {
-            position.position_size = size;
-            updated = true;
-        }
+    if updated {
+        position.updated_at = now();
 
-        if let Some(track) = tracked {
-            position.tracked = track;
-            updated = true;
-        }
+        // Save back to map
+        indexes::save_position(&position);
 
-        if updated {
-            position.updated_at = now();
+        ic_cdk::println!("✅ Position updated successfully");
+        Ok(position)
+    } else {
+        ic_cdk::println!("⚠️ No changes to apply");
+        Ok(position)
+    }
+}
 
-            // Save back to map
-            borrowed.insert(
-                StorableString(position_id.clone()),
-                StorableUserPosition(position.clone())
-            );
+/// Set or clear the execution mode override of a position
+pub fn set_position_execution_mode(
+    position_id: String,
+    user: Principal,
+    execution_mode: Option<ExecutionMode>,
+) -> Result<UserPosition, String> {
+    ic_cdk::println!("🔄 Setting execution mode of position {} to {:?}", position_id, execution_mode);
 
-            ic_cdk::println!("✅ Position updated successfully");
-            Ok(position)
-        } else {
-            ic_cdk::println!("⚠️ No changes to apply");
-            Ok(position)
-        }
-    })
+    let mut position = get_position_by_id(position_id)?;
+
+    // Verify ownership
+    if position.user_principal != user {
+        return Err("You do not own this position".to_string());
+    }
+
+    position.execution_mode = execution_mode;
+    position.updated_at = now();
+
+    indexes::save_position(&position);
+
+    ic_cdk::println!("✅ Position execution mode updated");
+    Ok(position)
 }
 
 /// Delete user position
 pub fn delete_user_position(position_id: String, user: Principal) -> Result<bool, String> {
     ic_cdk::println!("🗑️ Deleting position: {} for user {}", position_id, user);
 
-    USER_POSITIONS_MAP.with(|map| {
-        let mut borrowed = map.borrow_mut();
-
-        // First verify ownership
-        let storable_position = borrowed
-            .get(&StorableString(position_id.clone()))
-            .ok_or_else(|| format!("Position {} not found", position_id))?;
+    // First verify ownership
+    let position = get_position_by_id(position_id.clone())?;
 
-        if storable_position.0.user_principal != user {
-            return Err("You do not own this position".to_string());
-        }
+    if position.user_principal != user {
+        return Err("You do not own this position".to_string());
+    }
 
-        // Remove from map
-        borrowed.remove(&StorableString(position_id.clone()));
+    // Remove from map
+    indexes::remove_position(&position_id);
 
-        ic_cdk::println!("✅ Position deleted successfully");
-        Ok(true)
-    })
+    ic_cdk::println!("✅ Position deleted successfully");
+    Ok(true)
 }
 
 /// Get a single position by ID
@@ -720,10 +834,36 @@ pub fn get_apy_parser_status() -> crate::types::ApyParserStatus {
         last_execution: config.last_execution,
         total_records,
         monitored_protocols: config.monitored_protocols.clone(),
-        monitored_chains: config.monitored_chains.clone(),
+        monitored_chains: monitored_chains(&config),
     }
 }
 
+/// Chains APY is collected on for a protocol
+///
+/// The configured chains, or every enabled registry chain with a deployment
+/// of the protocol when none are configured.
+fn chains_for_protocol(config: &ApyParserConfig, protocol: &str) -> Vec<u64> {
+    if !config.monitored_chains.is_empty() {
+        return config.monitored_chains.clone();
+    }
+
+    chain_registry::list_enabled_chains()
+        .into_iter()
+        .filter(|chain| chain.protocols.iter().any(|deployment| deployment.protocol.eq_ignore_ascii_case(protocol)))
+        .map(|chain| chain.chain_id)
+        .collect()
+}
+
+/// Chains APY is collected on for any monitored protocol, ordered by chain ID
+fn monitored_chains(config: &ApyParserConfig) -> Vec<u64> {
+    let mut chains: Vec<u64> = config.monitored_protocols.iter()
+        .flat_map(|protocol| chains_for_protocol(config, protocol))
+        .collect();
+    chains.sort_unstable();
+    chains.dedup();
+    chains
+}
+
 /// Clear all APY history records (Admin only - for data migration)
 pub fn clear_apy_history() -> Result<String, String> {
     ic_cdk::println!("🗑️ Clearing all APY history...");
@@ -739,6 +879,14 @@ pub fn clear_apy_history() -> Result<String, String> {
         len
     });
 
+    // Rolled-up history goes with the raw samples
+    crate::APY_AGGREGATES_MAP.with(|map| {
+        let keys: Vec<_> = map.borrow().iter().map(|(k, _)| k).collect();
+        for key in keys {
+            map.borrow_mut().remove(&key);
+        }
+    });
+
     ic_cdk::println!("✅ Cleared {} APY history records", count);
     Ok(format!("Cleared {} APY history records", count))
 }
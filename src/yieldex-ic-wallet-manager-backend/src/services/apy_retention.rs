@@ -0,0 +1,501 @@
+use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::types::{
+    ApyHistoryKey, ApyHistoryRecord, ApyAggregate, ApyAggregateKey, ApyResolution,
+    ApyRetentionConfig, ApyRetentionReport, StorableApyHistoryKey, StorableApyAggregate,
+    StorableApyAggregateKey, StorableApyRetentionConfig,
+};
+use crate::{StorableString, APY_HISTORY_MAP, APY_AGGREGATES_MAP, APY_RETENTION_CONFIG_MAP, now};
+use crate::services::yield_ledger;
+
+// =============================================================================
+// APY History Retention
+// =============================================================================
+//
+// Keeps APY history bounded while long-range charts stay available:
+// - raw samples older than `raw_retention_days` are rolled into hourly aggregates
+// - hourly aggregates older than `hourly_retention_days` are rolled into daily ones
+// - daily aggregates older than `daily_retention_days` (if set) are deleted
+// - yield ledger snapshots older than `yield_snapshot_retention_days` (if set)
+//   are deleted, except each tenure's latest one before the cutoff
+//
+// Aggregates keep min/max/mean/last and a sample count, so rolling up in
+// several passes gives the same result as rolling up at once.
+//
+
+/// How often retention runs (1 hour)
+const RETENTION_INTERVAL_SECONDS: u64 = 3600;
+
+const DAY_MS: u64 = 24 * 3600 * 1000;
+
+/// Maximum number of aggregates returned by one query
+const MAX_AGGREGATES_PER_QUERY: u64 = 1000;
+
+/// Key of the retention policy in its single-entry map
+const RETENTION_CONFIG_KEY: &str = "config";
+
+thread_local! {
+    /// Active timer ID for retention runs
+    static RETENTION_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+}
+
+// =============================================================================
+// Configuration
+// =============================================================================
+
+/// Policy used until an admin sets one
+fn default_retention_config() -> ApyRetentionConfig {
+    ApyRetentionConfig {
+        enabled: true,
+        raw_retention_days: 7,
+        hourly_retention_days: 90,
+        daily_retention_days: None,
+        yield_snapshot_retention_days: Some(365),
+        max_records_per_run: 5000,
+    }
+}
+
+/// Get the retention policy
+pub fn get_retention_config() -> ApyRetentionConfig {
+    APY_RETENTION_CONFIG_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(RETENTION_CONFIG_KEY.to_string()))
+            .map(|config| config.0)
+            .unwrap_or_else(default_retention_config)
+    })
+}
+
+/// Replace the retention policy
+pub fn set_retention_config(config: ApyRetentionConfig) -> Result<ApyRetentionConfig, String> {
+    if config.raw_retention_days == 0 {
+        return Err("raw_retention_days must be at least 1".to_string());
+    }
+    if config.hourly_retention_days < config.raw_retention_days {
+        return Err("hourly_retention_days cannot be shorter than raw_retention_days".to_string());
+    }
+    if let Some(daily) = config.daily_retention_days {
+        if daily < config.hourly_retention_days {
+            return Err("daily_retention_days cannot be shorter than hourly_retention_days".to_string());
+        }
+    }
+    if config.yield_snapshot_retention_days == Some(0) {
+        return Err("yield_snapshot_retention_days must be at least 1".to_string());
+    }
+    if config.max_records_per_run == 0 {
+        return Err("max_records_per_run must be positive".to_string());
+    }
+
+    APY_RETENTION_CONFIG_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(RETENTION_CONFIG_KEY.to_string()),
+            StorableApyRetentionConfig(config.clone())
+        );
+    });
+    ic_cdk::println!("✅ APY retention policy updated: raw {}d, hourly {}d, daily {:?}d",
+        config.raw_retention_days, config.hourly_retention_days, config.daily_retention_days);
+    Ok(config)
+}
+
+// =============================================================================
+// Retention Run
+// =============================================================================
+
+/// Roll up and prune APY history according to the retention policy
+pub fn run_retention() -> ApyRetentionReport {
+    let config = get_retention_config();
+    let started_at = now();
+    let mut budget = config.max_records_per_run;
+
+    ic_cdk::println!("🧹 APY retention run started at {}", started_at);
+
+    // Cutoffs are aligned to bucket boundaries so only complete buckets are rolled up
+    let raw_cutoff = align(started_at.saturating_sub(config.raw_retention_days * DAY_MS), ApyResolution::Hourly);
+    let hourly_cutoff = align(started_at.saturating_sub(config.hourly_retention_days * DAY_MS), ApyResolution::Daily);
+
+    let raw_rolled_up = roll_up_raw(raw_cutoff, &mut budget);
+    let hourly_rolled_up = roll_up_hourly(hourly_cutoff, &mut budget);
+    let daily_pruned = match config.daily_retention_days {
+        Some(days) => prune_daily(started_at.saturating_sub(days * DAY_MS), &mut budget),
+        None => 0,
+    };
+    let yield_snapshots_pruned = match config.yield_snapshot_retention_days {
+        Some(days) => yield_ledger::prune_snapshots(started_at.saturating_sub(days * DAY_MS), &mut budget),
+        None => 0,
+    };
+
+    let report = ApyRetentionReport {
+        raw_rolled_up,
+        hourly_rolled_up,
+        daily_pruned,
+        yield_snapshots_pruned,
+        budget_exhausted: budget == 0,
+        started_at,
+        completed_at: now(),
+    };
+
+    ic_cdk::println!("✅ APY retention: {} raw → hourly, {} hourly → daily, {} daily pruned, {} yield snapshots pruned{}",
+        report.raw_rolled_up, report.hourly_rolled_up, report.daily_pruned, report.yield_snapshots_pruned,
+        if report.budget_exhausted { " (budget exhausted, continuing next run)" } else { "" });
+
+    report
+}
+
+/// Start the background retention timer
+pub fn start_apy_retention_timer() {
+    RETENTION_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow().as_ref() {
+            clear_timer(*id);
+        }
+    });
+
+    let timer_id = set_timer_interval(Duration::from_secs(RETENTION_INTERVAL_SECONDS), || {
+        if !get_retention_config().enabled {
+            ic_cdk::println!("ℹ️ APY retention is disabled, skipping run");
+            return;
+        }
+        run_retention();
+    });
+
+    RETENTION_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+
+    ic_cdk::println!("✅ APY retention timer started with interval: {} seconds", RETENTION_INTERVAL_SECONDS);
+}
+
+// =============================================================================
+// Queries
+// =============================================================================
+
+/// Aggregates of one series with buckets in `[from, to]`, oldest first
+pub fn get_apy_aggregates(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    resolution: ApyResolution,
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<u64>,
+) -> Result<Vec<ApyAggregate>, String> {
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or(u64::MAX);
+    if from > to {
+        return Err(format!("Invalid period: from {} is after to {}", from, to));
+    }
+
+    let limit = limit.unwrap_or(500).clamp(1, MAX_AGGREGATES_PER_QUERY) as usize;
+    Ok(aggregates_in_range(protocol, asset, chain_id, resolution, from, to, limit))
+}
+
+/// Up to `limit` aggregates of one series with buckets in `[from, to]`, oldest first
+pub(crate) fn aggregates_in_range(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    resolution: ApyResolution,
+    from: u64,
+    to: u64,
+    limit: usize,
+) -> Vec<ApyAggregate> {
+    let start = aggregate_key(resolution, protocol, chain_id, asset, from);
+    let end = aggregate_key(resolution, protocol, chain_id, asset, to);
+
+    APY_AGGREGATES_MAP.with(|map| {
+        map.borrow()
+            .range(StorableApyAggregateKey(start)..=StorableApyAggregateKey(end))
+            .take(limit)
+            .map(|(_, aggregate)| aggregate.0)
+            .collect()
+    })
+}
+
+// =============================================================================
+// Roll-up Steps
+// =============================================================================
+
+/// Roll raw samples before the cutoff into hourly aggregates
+fn roll_up_raw(cutoff: u64, budget: &mut u64) -> u64 {
+    let mut rolled_up = 0;
+    let mut series = next_raw_series(None);
+
+    while let Some(series_key) = series {
+        if *budget == 0 {
+            break;
+        }
+
+        let start = ApyHistoryKey { timestamp: 0, ..series_key.clone() };
+        let end = ApyHistoryKey { timestamp: cutoff, ..series_key.clone() };
+        let samples: Vec<(StorableApyHistoryKey, ApyHistoryRecord)> = APY_HISTORY_MAP.with(|map| {
+            map.borrow()
+                .range(StorableApyHistoryKey(start)..StorableApyHistoryKey(end))
+                .take(*budget as usize)
+                .map(|(key, record)| (key, record.0))
+                .collect()
+        });
+
+        let mut buckets: BTreeMap<u64, ApyAggregate> = BTreeMap::new();
+        for (_, record) in &samples {
+            let sample = aggregate_from_sample(record, ApyResolution::Hourly);
+            match buckets.get_mut(&sample.bucket_start) {
+                Some(bucket) => merge_aggregate(bucket, &sample),
+                None => { buckets.insert(sample.bucket_start, sample); },
+            }
+        }
+        for aggregate in buckets.into_values() {
+            save_merged(aggregate);
+        }
+
+        APY_HISTORY_MAP.with(|map| {
+            let mut map = map.borrow_mut();
+            for (key, _) in &samples {
+                map.remove(key);
+            }
+        });
+
+        rolled_up += samples.len() as u64;
+        *budget -= samples.len() as u64;
+        series = next_raw_series(Some(&series_key));
+    }
+
+    rolled_up
+}
+
+/// Roll hourly aggregates before the cutoff into daily aggregates
+fn roll_up_hourly(cutoff: u64, budget: &mut u64) -> u64 {
+    let mut rolled_up = 0;
+    let mut series = next_aggregate_series(ApyResolution::Hourly, None);
+
+    while let Some(series_key) = series {
+        if *budget == 0 {
+            break;
+        }
+
+        let hourly = take_aggregates_before(&series_key, cutoff, *budget);
+
+        let mut buckets: BTreeMap<u64, ApyAggregate> = BTreeMap::new();
+        for (_, aggregate) in &hourly {
+            let mut daily = aggregate.clone();
+            daily.resolution = ApyResolution::Daily;
+            daily.bucket_start = align(aggregate.bucket_start, ApyResolution::Daily);
+            match buckets.get_mut(&daily.bucket_start) {
+                Some(bucket) => merge_aggregate(bucket, &daily),
+                None => { buckets.insert(daily.bucket_start, daily); },
+            }
+        }
+        for aggregate in buckets.into_values() {
+            save_merged(aggregate);
+        }
+
+        remove_aggregates(&hourly);
+        rolled_up += hourly.len() as u64;
+        *budget -= hourly.len() as u64;
+        series = next_aggregate_series(ApyResolution::Hourly, Some(&series_key));
+    }
+
+    rolled_up
+}
+
+/// Delete daily aggregates before the cutoff
+fn prune_daily(cutoff: u64, budget: &mut u64) -> u64 {
+    let mut pruned = 0;
+    let mut series = next_aggregate_series(ApyResolution::Daily, None);
+
+    while let Some(series_key) = series {
+        if *budget == 0 {
+            break;
+        }
+
+        let expired = take_aggregates_before(&series_key, cutoff, *budget);
+        remove_aggregates(&expired);
+        pruned += expired.len() as u64;
+        *budget -= expired.len() as u64;
+        series = next_aggregate_series(ApyResolution::Daily, Some(&series_key));
+    }
+
+    pruned
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+/// First key of the raw series following `after` (or of the first series)
+fn next_raw_series(after: Option<&ApyHistoryKey>) -> Option<ApyHistoryKey> {
+    // Seeking to the largest possible timestamp of a series skips the rest of it
+    let start = match after {
+        Some(key) => ApyHistoryKey { timestamp: u64::MAX, ..key.clone() },
+        None => ApyHistoryKey::new("", 0, "", 0),
+    };
+
+    APY_HISTORY_MAP.with(|map| {
+        map.borrow()
+            .range(StorableApyHistoryKey(start)..)
+            .next()
+            .map(|(key, _)| key.0)
+    })
+}
+
+/// First key of the aggregate series following `after` within a resolution
+fn next_aggregate_series(resolution: ApyResolution, after: Option<&ApyAggregateKey>) -> Option<ApyAggregateKey> {
+    let start = match after {
+        Some(key) => ApyAggregateKey { bucket_start: u64::MAX, ..key.clone() },
+        None => aggregate_key(resolution, "", 0, "", 0),
+    };
+
+    APY_AGGREGATES_MAP.with(|map| {
+        map.borrow()
+            .range(StorableApyAggregateKey(start)..)
+            .next()
+            .map(|(key, _)| key.0)
+            .filter(|key| key.resolution == resolution)
+    })
+}
+
+/// Up to `limit` aggregates of a series with buckets before the cutoff
+fn take_aggregates_before(series_key: &ApyAggregateKey, cutoff: u64, limit: u64) -> Vec<(StorableApyAggregateKey, ApyAggregate)> {
+    let start = ApyAggregateKey { bucket_start: 0, ..series_key.clone() };
+    let end = ApyAggregateKey { bucket_start: cutoff, ..series_key.clone() };
+
+    APY_AGGREGATES_MAP.with(|map| {
+        map.borrow()
+            .range(StorableApyAggregateKey(start)..StorableApyAggregateKey(end))
+            .take(limit as usize)
+            .map(|(key, aggregate)| (key, aggregate.0))
+            .collect()
+    })
+}
+
+fn remove_aggregates(aggregates: &[(StorableApyAggregateKey, ApyAggregate)]) {
+    APY_AGGREGATES_MAP.with(|map| {
+        let mut map = map.borrow_mut();
+        for (key, _) in aggregates {
+            map.remove(key);
+        }
+    });
+}
+
+/// Store an aggregate, merging it into an existing one for the same bucket
+fn save_merged(mut aggregate: ApyAggregate) {
+    let key = StorableApyAggregateKey(aggregate.key());
+
+    APY_AGGREGATES_MAP.with(|map| {
+        let mut map = map.borrow_mut();
+        if let Some(existing) = map.get(&key) {
+            let mut merged = existing.0;
+            merge_aggregate(&mut merged, &aggregate);
+            aggregate = merged;
+        }
+        map.insert(key, StorableApyAggregate(aggregate));
+    });
+}
+
+fn aggregate_key(resolution: ApyResolution, protocol: &str, chain_id: u64, asset: &str, bucket_start: u64) -> ApyAggregateKey {
+    ApyAggregateKey {
+        resolution,
+        protocol: protocol.to_uppercase(),
+        chain_id,
+        asset: asset.to_uppercase(),
+        bucket_start,
+    }
+}
+
+/// Start of the bucket containing a timestamp
+fn align(timestamp: u64, resolution: ApyResolution) -> u64 {
+    timestamp - timestamp % resolution.bucket_ms()
+}
+
+/// Single-sample aggregate of a raw record
+fn aggregate_from_sample(record: &ApyHistoryRecord, resolution: ApyResolution) -> ApyAggregate {
+    ApyAggregate {
+        protocol: record.protocol.clone(),
+        asset: record.asset.clone(),
+        chain_id: record.chain_id,
+        resolution,
+        bucket_start: align(record.timestamp, resolution),
+        min: record.apy,
+        max: record.apy,
+        mean: record.apy,
+        last: record.apy,
+        last_timestamp: record.timestamp,
+        sample_count: 1,
+    }
+}
+
+/// Combine two aggregates of the same bucket
+fn merge_aggregate(into: &mut ApyAggregate, other: &ApyAggregate) {
+    let total = into.sample_count + other.sample_count;
+    if total > 0 {
+        into.mean = (into.mean * into.sample_count as f64 + other.mean * other.sample_count as f64) / total as f64;
+    }
+    into.min = into.min.min(other.min);
+    into.max = into.max.max(other.max);
+    if other.last_timestamp >= into.last_timestamp {
+        into.last = other.last;
+        into.last_timestamp = other.last_timestamp;
+    }
+    into.sample_count = total;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(apy: f64, timestamp: u64) -> ApyHistoryRecord {
+        ApyHistoryRecord {
+            record_id: format!("AAVE:42161:0x:{}", timestamp),
+            protocol: "AAVE".to_string(),
+            asset: "USDC".to_string(),
+            token_address: "0x".to_string(),
+            chain_id: 42161,
+            apy,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn buckets_align_to_resolution() {
+        let hour = ApyResolution::Hourly.bucket_ms();
+        assert_eq!(align(5 * hour + 17, ApyResolution::Hourly), 5 * hour);
+        assert_eq!(align(DAY_MS + 3 * hour, ApyResolution::Daily), DAY_MS);
+        assert_eq!(aggregate_from_sample(&sample(4.0, hour + 1), ApyResolution::Hourly).bucket_start, hour);
+    }
+
+    #[test]
+    fn merge_keeps_min_max_weighted_mean_and_last() {
+        let mut bucket = aggregate_from_sample(&sample(4.0, 1_000), ApyResolution::Hourly);
+        merge_aggregate(&mut bucket, &aggregate_from_sample(&sample(6.0, 3_000), ApyResolution::Hourly));
+        merge_aggregate(&mut bucket, &aggregate_from_sample(&sample(2.0, 2_000), ApyResolution::Hourly));
+
+        assert_eq!(bucket.sample_count, 3);
+        assert_eq!(bucket.min, 2.0);
+        assert_eq!(bucket.max, 6.0);
+        assert!((bucket.mean - 4.0).abs() < 1e-9);
+        assert_eq!(bucket.last, 6.0);
+        assert_eq!(bucket.last_timestamp, 3_000);
+    }
+
+    #[test]
+    fn merging_in_passes_matches_merging_at_once() {
+        let samples: Vec<_> = [3.0, 5.0, 4.0, 8.0].iter().enumerate()
+            .map(|(i, apy)| aggregate_from_sample(&sample(*apy, i as u64), ApyResolution::Daily))
+            .collect();
+
+        let mut at_once = samples[0].clone();
+        for s in &samples[1..] {
+            merge_aggregate(&mut at_once, s);
+        }
+
+        let mut first = samples[0].clone();
+        merge_aggregate(&mut first, &samples[1]);
+        let mut second = samples[2].clone();
+        merge_aggregate(&mut second, &samples[3]);
+        merge_aggregate(&mut first, &second);
+
+        assert_eq!(first.sample_count, at_once.sample_count);
+        assert!((first.mean - at_once.mean).abs() < 1e-9);
+        assert_eq!(first.last, at_once.last);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::types::{ApyHistoryRecord, ApySignal, ApySignalSnapshot, SchedulerConfig};
+use crate::services::apy_parser;
+use crate::now;
+
+// =============================================================================
+// APY Signal Module
+// =============================================================================
+//
+// Derives smoothed APY signals from the samples stored in APY_HISTORY_MAP so
+// that a single utilization spike does not trigger a rebalance on its own.
+//
+// Supported signals:
+// - Latest: most recent sample
+// - TimeWeightedAverage: step-function average over the window
+// - Ema: exponential moving average with a time-based half-life
+// - WindowMin: lowest sample inside the window
+//
+
+/// A single APY observation (timestamp in milliseconds, APY percentage)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ApySample {
+    pub timestamp: u64,
+    pub apy: f64,
+}
+
+impl From<&ApyHistoryRecord> for ApySample {
+    fn from(record: &ApyHistoryRecord) -> Self {
+        ApySample {
+            timestamp: record.timestamp,
+            apy: record.apy,
+        }
+    }
+}
+
+// =============================================================================
+// Pure Signal Functions
+// =============================================================================
+
+/// Most recent sample value
+pub fn latest(samples: &[ApySample]) -> Option<f64> {
+    samples.iter().max_by_key(|s| s.timestamp).map(|s| s.apy)
+}
+
+/// Time-weighted average over [window_start, window_end]
+///
+/// Each sample holds its value until the next sample (step function).
+/// `prior` is the last sample before the window, used to cover the gap
+/// between `window_start` and the first sample inside the window.
+pub fn time_weighted_average(
+    samples: &[ApySample],
+    prior: Option<ApySample>,
+    window_start: u64,
+    window_end: u64,
+) -> Option<f64> {
+    let mut points: Vec<ApySample> = samples
+        .iter()
+        .filter(|s| s.timestamp >= window_start && s.timestamp <= window_end)
+        .copied()
+        .collect();
+    points.sort_by_key(|s| s.timestamp);
+
+    let first = points.first()?;
+
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0u64;
+
+    // Cover the gap before the first in-window sample with the prior value
+    if let Some(prior) = prior {
+        let gap = first.timestamp.saturating_sub(window_start);
+        weighted_sum += prior.apy * gap as f64;
+        total_weight += gap;
+    }
+
+    for (i, point) in points.iter().enumerate() {
+        let until = points.get(i + 1).map(|n| n.timestamp).unwrap_or(window_end);
+        let duration = until.saturating_sub(point.timestamp);
+        weighted_sum += point.apy * duration as f64;
+        total_weight += duration;
+    }
+
+    if total_weight == 0 {
+        // All samples share one timestamp at the window end - plain mean
+        let mean = points.iter().map(|s| s.apy).sum::<f64>() / points.len() as f64;
+        return Some(mean);
+    }
+
+    Some(weighted_sum / total_weight as f64)
+}
+
+/// Exponential moving average for irregularly spaced samples
+///
+/// The smoothing factor for each step is derived from the elapsed time,
+/// so a sample's weight halves every `half_life_ms`.
+pub fn ema(samples: &[ApySample], half_life_ms: u64) -> Option<f64> {
+    let mut points: Vec<ApySample> = samples.to_vec();
+    points.sort_by_key(|s| s.timestamp);
+
+    let mut iter = points.iter();
+    let first = iter.next()?;
+    let mut value = first.apy;
+    let mut last_ts = first.timestamp;
+
+    if half_life_ms == 0 {
+        return latest(&points);
+    }
+
+    let tau = half_life_ms as f64 / std::f64::consts::LN_2;
+    for point in iter {
+        let dt = point.timestamp.saturating_sub(last_ts) as f64;
+        let alpha = 1.0 - (-dt / tau).exp();
+        value += alpha * (point.apy - value);
+        last_ts = point.timestamp;
+    }
+
+    Some(value)
+}
+
+/// Lowest sample value
+pub fn window_min(samples: &[ApySample]) -> Option<f64> {
+    samples
+        .iter()
+        .map(|s| s.apy)
+        .fold(None, |acc: Option<f64>, v| Some(acc.map_or(v, |a| a.min(v))))
+}
+
+/// Evaluate a signal over a sample series
+///
+/// Returns `None` when fewer than `min_samples` samples fall inside the window.
+pub fn evaluate_signal(
+    signal: &ApySignal,
+    samples: &[ApySample],
+    prior: Option<ApySample>,
+    window_start: u64,
+    window_end: u64,
+    half_life_ms: u64,
+    min_samples: u64,
+) -> Option<f64> {
+    let in_window: Vec<ApySample> = samples
+        .iter()
+        .filter(|s| s.timestamp >= window_start && s.timestamp <= window_end)
+        .copied()
+        .collect();
+
+    if (in_window.len() as u64) < min_samples.max(1) {
+        return None;
+    }
+
+    match signal {
+        ApySignal::Latest => latest(&in_window),
+        ApySignal::TimeWeightedAverage => time_weighted_average(&in_window, prior, window_start, window_end),
+        ApySignal::Ema => ema(&in_window, half_life_ms),
+        ApySignal::WindowMin => window_min(&in_window),
+    }
+}
+
+/// Start of the scheduler signal window ending at `window_end`
+pub fn signal_window_start(config: &SchedulerConfig, window_end: u64) -> u64 {
+    window_end.saturating_sub(config.signal_window_seconds * 1000)
+}
+
+/// Evaluate the scheduler's configured signal over the window ending at `window_end`
+///
+/// `prior` is the last sample before the window start.
+pub fn scheduler_signal(
+    config: &SchedulerConfig,
+    samples: &[ApySample],
+    prior: Option<ApySample>,
+    window_end: u64,
+) -> Option<f64> {
+    evaluate_signal(
+        &config.apy_signal,
+        samples,
+        prior,
+        signal_window_start(config, window_end),
+        window_end,
+        config.ema_half_life_seconds * 1000,
+        config.min_apy_samples,
+    )
+}
+
+// =============================================================================
+// Stored History Access
+// =============================================================================
+
+/// Load samples for a window plus the last sample before it
+fn load_window(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    window_start: u64,
+) -> (Vec<ApySample>, Option<ApySample>) {
+    let records = apy_parser::get_apy_samples_since(protocol, asset, chain_id, window_start);
+    let samples: Vec<ApySample> = records.iter().map(ApySample::from).collect();
+    let prior = apy_parser::get_last_apy_before(protocol, asset, chain_id, window_start)
+        .as_ref()
+        .map(ApySample::from);
+    (samples, prior)
+}
+
+/// Compute the configured scheduler signal for a protocol/asset/chain
+///
+/// Returns `Ok(None)` when there is not enough history to act on.
+pub fn get_apy_signal(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    config: &SchedulerConfig,
+) -> Result<Option<f64>, String> {
+    let window_end = now();
+    let window_start = signal_window_start(config, window_end);
+
+    let (samples, prior) = load_window(protocol, asset, chain_id, window_start);
+
+    ic_cdk::println!("  📈 {:?} signal for {} {} on chain {}: {} samples in window",
+        config.apy_signal, protocol, asset, chain_id, samples.len());
+
+    Ok(scheduler_signal(config, &samples, prior, window_end))
+}
+
+/// Compute every signal for a protocol/asset/chain (for monitoring)
+pub fn get_apy_signal_snapshot(
+    protocol: &str,
+    asset: &str,
+    chain_id: u64,
+    config: &SchedulerConfig,
+) -> ApySignalSnapshot {
+    let window_end = now();
+    let window_start = signal_window_start(config, window_end);
+
+    let (samples, prior) = load_window(protocol, asset, chain_id, window_start);
+
+    ApySignalSnapshot {
+        protocol: protocol.to_string(),
+        asset: asset.to_string(),
+        chain_id,
+        samples_in_window: samples.len() as u64,
+        window_start,
+        latest: latest(&samples),
+        time_weighted_average: time_weighted_average(&samples, prior, window_start, window_end),
+        ema: ema(&samples, config.ema_half_life_seconds * 1000),
+        window_min: window_min(&samples),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: u64, apy: f64) -> ApySample {
+        ApySample { timestamp, apy }
+    }
+
+    #[test]
+    fn test_time_weighted_average_weights_by_duration() {
+        // 4.0 for 3 units, 8.0 for 1 unit
+        let samples = vec![sample(0, 4.0), sample(3, 8.0)];
+        let twa = time_weighted_average(&samples, None, 0, 4).unwrap();
+        assert!((twa - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_time_weighted_average_uses_prior_sample() {
+        let samples = vec![sample(5, 10.0)];
+        let prior = Some(sample(0, 2.0));
+        // 2.0 from 0..5, 10.0 from 5..10
+        let twa = time_weighted_average(&samples, prior, 0, 10).unwrap();
+        assert!((twa - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_spike_is_damped() {
+        let mut samples: Vec<ApySample> = (0..8).map(|i| sample(i * 900_000, 4.0)).collect();
+        samples.push(sample(8 * 900_000, 20.0));
+        let end = 8 * 900_000 + 60_000;
+
+        let twa = time_weighted_average(&samples, None, 0, end).unwrap();
+        let smoothed = ema(&samples, 2 * 3_600_000).unwrap();
+
+        assert!(twa < 5.0);
+        assert!(smoothed < 6.0);
+        assert_eq!(latest(&samples), Some(20.0));
+        assert_eq!(window_min(&samples), Some(4.0));
+    }
+
+    #[test]
+    fn test_min_samples_required() {
+        let samples = vec![sample(0, 4.0), sample(10, 5.0)];
+        assert!(evaluate_signal(&ApySignal::Latest, &samples, None, 0, 20, 0, 3).is_none());
+        assert_eq!(evaluate_signal(&ApySignal::Latest, &samples, None, 0, 20, 0, 2), Some(5.0));
+    }
+
+    #[test]
+    fn test_empty_series() {
+        assert!(latest(&[]).is_none());
+        assert!(ema(&[], 1000).is_none());
+        assert!(window_min(&[]).is_none());
+        assert!(time_weighted_average(&[], None, 0, 10).is_none());
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::BTreeMap;
+
+use crate::types::{
+    ApyHistoryRecord, BacktestBuyAndHold, BacktestMove, BacktestReport, BacktestRequest, SchedulerConfig,
+};
+use crate::services::{apy_analytics, scheduler};
+use crate::services::apy_signals::{self, ApySample};
+
+// =============================================================================
+// Strategy Backtester
+// =============================================================================
+//
+// Replays APY history through the scheduler's decision logic to evaluate
+// thresholds, signals and cooldowns before changing the live configuration:
+// - every `interval_seconds` the held protocol is compared against its
+//   alternative using the configured signal, exactly as the scheduler does
+// - a move pays the gas model's cost and starts the cooldown
+// - between ticks the position accrues the held protocol's actual APY
+//
+// `simulate` is pure so strategies can be tested without a canister; history
+// comes either from stored APY records or from imported fixtures.
+//
+
+/// Milliseconds in a year, for annualizing APYs
+const YEAR_MS: f64 = 365.0 * 24.0 * 3600.0 * 1000.0;
+
+/// Upper bound on evaluation ticks so a query stays within its instruction limit
+const MAX_BACKTEST_TICKS: u64 = 50_000;
+
+/// APY samples of one protocol, oldest first
+#[derive(Clone, Debug, PartialEq)]
+pub struct BacktestSeries {
+    pub protocol: String,
+    pub samples: Vec<ApySample>,
+}
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Run a backtest against stored history (or the request's fixtures)
+///
+/// Strategy fields left unset use the live scheduler configuration.
+pub fn run_backtest(request: &BacktestRequest) -> Result<BacktestReport, String> {
+    let config = strategy_config(scheduler::get_scheduler_config()?, request);
+
+    let series = match &request.fixtures {
+        Some(records) => fixture_series(records, &request.asset, request.chain_id),
+        None => stored_series(request, &config),
+    };
+
+    ic_cdk::println!("🧪 Backtesting {} on chain {} from {} to {}: {:?} signal, {}% threshold, {} series",
+        request.asset, request.chain_id, request.from, request.to,
+        config.apy_signal, config.apy_threshold_percent, series.len());
+
+    let report = simulate(request, &config, &series)?;
+
+    ic_cdk::println!("✅ Backtest finished: {} moves, ${:.2} gas, ${:.2} net yield",
+        report.move_count, report.gas_spent_usd, report.net_yield_usd);
+
+    Ok(report)
+}
+
+/// Scheduler configuration with the request's strategy overrides applied
+pub fn strategy_config(mut config: SchedulerConfig, request: &BacktestRequest) -> SchedulerConfig {
+    if let Some(signal) = &request.apy_signal {
+        config.apy_signal = signal.clone();
+    }
+    if let Some(threshold) = request.apy_threshold_percent {
+        config.apy_threshold_percent = threshold;
+    }
+    if let Some(window) = request.signal_window_seconds {
+        config.signal_window_seconds = window;
+    }
+    if let Some(half_life) = request.ema_half_life_seconds {
+        config.ema_half_life_seconds = half_life;
+    }
+    if let Some(min_samples) = request.min_apy_samples {
+        config.min_apy_samples = min_samples;
+    }
+    if let Some(interval) = request.interval_seconds {
+        config.interval_seconds = interval;
+    }
+    config
+}
+
+// =============================================================================
+// History Sources
+// =============================================================================
+
+/// Stored history of the start protocol and its alternative
+///
+/// Loading starts one signal window before `from` so the first tick has a full window.
+fn stored_series(request: &BacktestRequest, config: &SchedulerConfig) -> Vec<BacktestSeries> {
+    let start_protocol = request.start_protocol.to_uppercase();
+    let load_from = apy_signals::signal_window_start(config, request.from);
+
+    [start_protocol.as_str(), scheduler::alternative_protocol(&start_protocol)]
+        .iter()
+        .map(|protocol| {
+            let (samples, prior) = apy_analytics::load_series(protocol, &request.asset, request.chain_id, load_from);
+            let samples = prior.into_iter()
+                .chain(samples)
+                .filter(|s| s.timestamp <= request.to)
+                .collect();
+            BacktestSeries { protocol: protocol.to_string(), samples }
+        })
+        .filter(|series| !series.samples.is_empty())
+        .collect()
+}
+
+/// Group fixture records of an asset/chain into per-protocol series
+pub fn fixture_series(records: &[ApyHistoryRecord], asset: &str, chain_id: u64) -> Vec<BacktestSeries> {
+    let mut by_protocol: BTreeMap<String, Vec<ApySample>> = BTreeMap::new();
+    for record in records {
+        if record.chain_id == chain_id && record.asset.eq_ignore_ascii_case(asset) {
+            by_protocol.entry(record.protocol.to_uppercase()).or_default().push(ApySample::from(record));
+        }
+    }
+
+    by_protocol
+        .into_iter()
+        .map(|(protocol, mut samples)| {
+            samples.sort_by_key(|s| s.timestamp);
+            BacktestSeries { protocol, samples }
+        })
+        .collect()
+}
+
+// =============================================================================
+// Simulation
+// =============================================================================
+
+/// Replay the series through the scheduler's decision logic
+pub fn simulate(
+    request: &BacktestRequest,
+    config: &SchedulerConfig,
+    series: &[BacktestSeries],
+) -> Result<BacktestReport, String> {
+    if request.to <= request.from {
+        return Err("Backtest end must be after its start".to_string());
+    }
+    if request.initial_value_usd <= 0.0 {
+        return Err("Initial value must be positive".to_string());
+    }
+    if config.interval_seconds == 0 {
+        return Err("Evaluation interval must be at least 1 second".to_string());
+    }
+
+    let step_ms = config.interval_seconds.saturating_mul(1000);
+    let tick_count = (request.to - request.from) / step_ms + 1;
+    if tick_count > MAX_BACKTEST_TICKS {
+        return Err(format!("Backtest needs {} ticks, maximum is {}; use a longer interval or a shorter range",
+            tick_count, MAX_BACKTEST_TICKS));
+    }
+
+    let min_position_size: f64 = config.min_position_size.parse()
+        .map_err(|e| format!("Invalid min position size: {}", e))?;
+    let gas = request.gas.clone().unwrap_or_default();
+
+    let mut held = request.start_protocol.to_uppercase();
+    if samples_of(series, &held).is_empty() {
+        return Err(format!("No APY history for {} {} on chain {}", held, request.asset, request.chain_id));
+    }
+
+    let mut value = request.initial_value_usd;
+    let mut gas_spent = 0.0;
+    let mut moves = Vec::new();
+    let mut last_move: Option<u64> = None;
+    let mut ticks_without_signal = 0;
+    let mut accrued_until = request.from;
+
+    for tick in 0..tick_count {
+        let now = request.from + tick * step_ms;
+        value = accrue(value, samples_of(series, &held), accrued_until, now);
+        accrued_until = now;
+
+        if value < min_position_size {
+            continue;
+        }
+
+        let alternative = scheduler::alternative_protocol(&held);
+        let (Some(current_apy), Some(alternative_apy)) = (
+            signal_at(config, samples_of(series, &held), now),
+            signal_at(config, samples_of(series, alternative), now),
+        ) else {
+            ticks_without_signal += 1;
+            continue;
+        };
+
+        if !scheduler::should_rebalance(alternative_apy - current_apy, config) {
+            continue;
+        }
+
+        let cooling_down = last_move.is_some_and(|at| now - at < request.cooldown_seconds.saturating_mul(1000));
+        if cooling_down {
+            continue;
+        }
+
+        let gas_cost = gas.move_cost_usd();
+        value = (value - gas_cost).max(0.0);
+        gas_spent += gas_cost;
+        last_move = Some(now);
+
+        moves.push(BacktestMove {
+            timestamp: now,
+            from_protocol: held.clone(),
+            to_protocol: alternative.to_string(),
+            current_apy,
+            target_apy: alternative_apy,
+            gas_cost_usd: gas_cost,
+            value_after_usd: value,
+        });
+        held = alternative.to_string();
+    }
+    value = accrue(value, samples_of(series, &held), accrued_until, request.to);
+
+    let net_yield = value - request.initial_value_usd;
+    let buy_and_hold = series.iter()
+        .map(|s| {
+            let final_value = accrue(request.initial_value_usd, &s.samples, request.from, request.to);
+            let yield_usd = final_value - request.initial_value_usd;
+            BacktestBuyAndHold {
+                protocol: s.protocol.clone(),
+                final_value_usd: final_value,
+                yield_usd,
+                apy_percent: annualized_percent(yield_usd, request.initial_value_usd, request.to - request.from),
+                strategy_excess_usd: net_yield - yield_usd,
+            }
+        })
+        .collect();
+
+    Ok(BacktestReport {
+        asset: request.asset.clone(),
+        chain_id: request.chain_id,
+        from: request.from,
+        to: request.to,
+        apy_signal: config.apy_signal.clone(),
+        apy_threshold_percent: config.apy_threshold_percent,
+        initial_value_usd: request.initial_value_usd,
+        final_value_usd: value,
+        final_protocol: held,
+        move_count: moves.len() as u64,
+        gas_spent_usd: gas_spent,
+        gross_yield_usd: net_yield + gas_spent,
+        net_yield_usd: net_yield,
+        net_apy_percent: annualized_percent(net_yield, request.initial_value_usd, request.to - request.from),
+        ticks_evaluated: tick_count,
+        ticks_without_signal,
+        moves,
+        buy_and_hold,
+    })
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+fn samples_of<'a>(series: &'a [BacktestSeries], protocol: &str) -> &'a [ApySample] {
+    series.iter()
+        .find(|s| s.protocol.eq_ignore_ascii_case(protocol))
+        .map(|s| s.samples.as_slice())
+        .unwrap_or_default()
+}
+
+/// Scheduler signal as it would have been computed at `now`
+fn signal_at(config: &SchedulerConfig, samples: &[ApySample], now: u64) -> Option<f64> {
+    let window_start = apy_signals::signal_window_start(config, now);
+    let start = samples.partition_point(|s| s.timestamp < window_start);
+    let end = samples.partition_point(|s| s.timestamp <= now);
+    let prior = start.checked_sub(1).map(|i| samples[i]);
+
+    apy_signals::scheduler_signal(config, &samples[start..end.max(start)], prior, now)
+}
+
+/// Grow a value by the step-function APY of a series over [from, to]
+///
+/// Periods before the series' first sample earn nothing.
+fn accrue(value: f64, samples: &[ApySample], from: u64, to: u64) -> f64 {
+    if to <= from {
+        return value;
+    }
+
+    let first = samples.partition_point(|s| s.timestamp <= from).saturating_sub(1);
+    let mut value = value;
+    for (i, sample) in samples.iter().enumerate().skip(first) {
+        let start = sample.timestamp.max(from);
+        let end = samples.get(i + 1).map_or(to, |next| next.timestamp.min(to));
+        if start >= to {
+            break;
+        }
+        if end > start {
+            value *= 1.0 + sample.apy / 100.0 * (end - start) as f64 / YEAR_MS;
+        }
+    }
+    value
+}
+
+fn annualized_percent(yield_usd: f64, initial_value: f64, duration_ms: u64) -> f64 {
+    if initial_value <= 0.0 || duration_ms == 0 {
+        return 0.0;
+    }
+    yield_usd / initial_value * YEAR_MS / duration_ms as f64 * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ApySignal;
+
+    const HOUR_MS: u64 = 3_600_000;
+
+    fn series(protocol: &str, points: &[(u64, f64)]) -> BacktestSeries {
+        BacktestSeries {
+            protocol: protocol.to_string(),
+            samples: points.iter().map(|&(hour, apy)| ApySample { timestamp: hour * HOUR_MS, apy }).collect(),
+        }
+    }
+
+    fn request(from_hour: u64, to_hour: u64) -> BacktestRequest {
+        BacktestRequest {
+            asset: "USDC".to_string(),
+            chain_id: 42161,
+            start_protocol: "AAVE".to_string(),
+            initial_value_usd: 10_000.0,
+            from: from_hour * HOUR_MS,
+            to: to_hour * HOUR_MS,
+            apy_signal: None,
+            apy_threshold_percent: None,
+            signal_window_seconds: None,
+            ema_half_life_seconds: None,
+            min_apy_samples: None,
+            interval_seconds: None,
+            cooldown_seconds: 0,
+            gas: None,
+            fixtures: None,
+        }
+    }
+
+    fn config() -> SchedulerConfig {
+        SchedulerConfig {
+            apy_signal: ApySignal::Latest,
+            apy_threshold_percent: 0.5,
+            interval_seconds: 3600,
+            min_apy_samples: 1,
+            signal_window_seconds: 3 * 3600,
+            ..SchedulerConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_accrue_follows_step_function() {
+        let samples = series("AAVE", &[(0, 10.0), (24, 0.0)]).samples;
+        let year_hours = 365 * 24;
+        // 10% for one day, then nothing
+        let value = accrue(1000.0, &samples, 0, year_hours * HOUR_MS);
+        let expected = 1000.0 * (1.0 + 0.10 / 365.0);
+        assert!((value - expected).abs() < 1e-9);
+        // Nothing before the first sample
+        assert_eq!(accrue(1000.0, &samples[1..], 0, 24 * HOUR_MS), 1000.0);
+    }
+
+    #[test]
+    fn test_moves_when_spread_exceeds_threshold() {
+        let aave: Vec<(u64, f64)> = (0..=20).map(|h| (h, 3.0)).collect();
+        let compound: Vec<(u64, f64)> = (0..=20).map(|h| (h, if h < 5 { 3.2 } else { 6.0 })).collect();
+        let history = vec![series("AAVE", &aave), series("COMPOUND", &compound)];
+        let report = simulate(&request(0, 20), &config(), &history).unwrap();
+
+        assert_eq!(report.move_count, 1);
+        assert_eq!(report.moves[0].timestamp, 5 * HOUR_MS);
+        assert_eq!(report.final_protocol, "COMPOUND");
+        assert!((report.gross_yield_usd - report.net_yield_usd - report.gas_spent_usd).abs() < 1e-9);
+
+        let compound = report.buy_and_hold.iter().find(|b| b.protocol == "COMPOUND").unwrap();
+        let aave = report.buy_and_hold.iter().find(|b| b.protocol == "AAVE").unwrap();
+        assert!(report.gross_yield_usd > aave.yield_usd);
+        assert!(report.gross_yield_usd < compound.yield_usd);
+    }
+
+    #[test]
+    fn test_cooldown_limits_flapping() {
+        // Protocols swap leadership every hour
+        let aave: Vec<(u64, f64)> = (0..24).map(|h| (h, if h % 2 == 0 { 2.0 } else { 6.0 })).collect();
+        let compound: Vec<(u64, f64)> = (0..24).map(|h| (h, if h % 2 == 0 { 6.0 } else { 2.0 })).collect();
+        let history = vec![series("AAVE", &aave), series("COMPOUND", &compound)];
+
+        let free = simulate(&request(0, 23), &config(), &history).unwrap();
+        let mut cooled_request = request(0, 23);
+        cooled_request.cooldown_seconds = 6 * 3600;
+        let cooled = simulate(&cooled_request, &config(), &history).unwrap();
+
+        assert_eq!(free.move_count, 24);
+        assert_eq!(cooled.move_count, 4);
+        assert!(cooled.gas_spent_usd < free.gas_spent_usd);
+    }
+
+    #[test]
+    fn test_missing_signal_is_counted_and_not_acted_on() {
+        let history = vec![series("AAVE", &[(0, 3.0)]), series("COMPOUND", &[(10, 9.0)])];
+        let report = simulate(&request(0, 4), &config(), &history).unwrap();
+
+        assert_eq!(report.move_count, 0);
+        assert_eq!(report.ticks_evaluated, 5);
+        assert_eq!(report.ticks_without_signal, 5);
+    }
+
+    #[test]
+    fn test_fixture_series_groups_by_protocol() {
+        let record = |protocol: &str, asset: &str, chain_id: u64, timestamp: u64| ApyHistoryRecord {
+            record_id: format!("{}-{}", protocol, timestamp),
+            protocol: protocol.to_string(),
+            asset: asset.to_string(),
+            token_address: String::new(),
+            chain_id,
+            apy: 4.0,
+            timestamp,
+        };
+        let records = vec![
+            record("aave", "usdc", 1, 20),
+            record("AAVE", "USDC", 1, 10),
+            record("COMPOUND", "USDC", 1, 15),
+            record("AAVE", "WETH", 1, 10),
+            record("AAVE", "USDC", 10, 10),
+        ];
+
+        let grouped = fixture_series(&records, "USDC", 1);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].protocol, "AAVE");
+        assert_eq!(grouped[0].samples.iter().map(|s| s.timestamp).collect::<Vec<_>>(), vec![10, 20]);
+        assert_eq!(grouped[1].protocol, "COMPOUND");
+    }
+}
@@ -0,0 +1,487 @@
+use alloy::primitives::Address;
+
+use crate::types::{ChainConfig, DeploymentAsset, PriceFeedConfig, ProtocolDeployment, StorableChainConfig};
+use crate::{CHAIN_REGISTRY_MAP, USER_POSITIONS_MAP};
+use crate::services::rpc_service::{SEPOLIA_CHAIN_ID, ARBITRUM_CHAIN_ID, BASE_CHAIN_ID, OPTIMISM_CHAIN_ID};
+
+// =============================================================================
+// Chain Registry
+// =============================================================================
+//
+// Every EVM network the canister talks to, kept in CHAIN_REGISTRY_MAP:
+// RPC endpoint, gas token, fee caps, USDC token, Chainlink price feeds and the
+// lending protocol deployments with the assets the strategy uses on them.
+//
+// The registry is seeded with the built-in networks on install (and on the
+// first upgrade after it was introduced). Admins add, edit, disable and
+// remove networks afterwards without code changes.
+//
+
+/// Protocols the canister has integrations for
+const SUPPORTED_PROTOCOLS: [&str; 2] = ["AAVE", "COMPOUND"];
+
+/// Gas of a plain value transfer on L1-style chains
+pub const TRANSFER_GAS: u64 = 21_000;
+
+/// Cancellation gas floor on Arbitrum, whose gas limit also covers the L1 calldata cost
+const ARBITRUM_CANCEL_GAS_LIMIT: u64 = 500_000;
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Seed the registry with the built-in chains if it is empty
+pub fn seed_chain_registry() {
+    let is_empty = CHAIN_REGISTRY_MAP.with(|map| map.borrow().is_empty());
+    if !is_empty {
+        return;
+    }
+
+    let chains = default_chains();
+    ic_cdk::println!("🌐 Seeding chain registry with {} chains", chains.len());
+    CHAIN_REGISTRY_MAP.with(|map| {
+        let mut map = map.borrow_mut();
+        for chain in chains {
+            map.insert(chain.chain_id, StorableChainConfig(chain));
+        }
+    });
+}
+
+/// Fill the USDC token, price feeds and cancellation gas floor of built-in chains stored before the registry had them
+pub fn backfill_chain_defaults() {
+    for default in default_chains() {
+        let Some(mut chain) = get_chain(default.chain_id) else {
+            continue;
+        };
+        if chain.usdc_address.is_some() && chain.price_feeds.is_some()
+            && (chain.cancel_gas_limit.is_some() || default.cancel_gas_limit.is_none()) {
+            continue;
+        }
+
+        chain.usdc_address = chain.usdc_address.or(default.usdc_address);
+        chain.price_feeds = chain.price_feeds.or(default.price_feeds);
+        chain.cancel_gas_limit = chain.cancel_gas_limit.or(default.cancel_gas_limit);
+        ic_cdk::println!("🌐 Chain {} ({}): filled in USDC token, price feeds and cancellation gas", chain.chain_id, chain.name);
+        CHAIN_REGISTRY_MAP.with(|map| {
+            map.borrow_mut().insert(chain.chain_id, StorableChainConfig(chain));
+        });
+    }
+}
+
+/// Get a registry entry, enabled or not
+pub fn get_chain(chain_id: u64) -> Option<ChainConfig> {
+    CHAIN_REGISTRY_MAP.with(|map| map.borrow().get(&chain_id).map(|c| c.0))
+}
+
+/// Get an enabled chain
+pub fn get_enabled_chain(chain_id: u64) -> Option<ChainConfig> {
+    get_chain(chain_id).filter(|chain| chain.enabled)
+}
+
+/// All registry entries, ordered by chain ID
+pub fn list_chains() -> Vec<ChainConfig> {
+    CHAIN_REGISTRY_MAP.with(|map| map.borrow().iter().map(|(_, c)| c.0).collect())
+}
+
+/// All enabled chains, ordered by chain ID
+pub fn list_enabled_chains() -> Vec<ChainConfig> {
+    list_chains().into_iter().filter(|chain| chain.enabled).collect()
+}
+
+/// Add a chain or replace its entry
+pub fn upsert_chain(config: ChainConfig) -> Result<ChainConfig, String> {
+    let config = normalize_chain_config(config)?;
+
+    let existed = get_chain(config.chain_id).is_some();
+    CHAIN_REGISTRY_MAP.with(|map| {
+        map.borrow_mut().insert(config.chain_id, StorableChainConfig(config.clone()));
+    });
+
+    ic_cdk::println!("🌐 Chain {} ({}) {}: {} RPC endpoints, {} protocol deployments",
+        config.chain_id, config.name, if existed { "updated" } else { "added" },
+        config.rpc_endpoints.len(), config.protocols.len());
+
+    Ok(config)
+}
+
+/// Enable or disable a chain
+pub fn set_chain_enabled(chain_id: u64, enabled: bool) -> Result<ChainConfig, String> {
+    let mut config = get_chain(chain_id)
+        .ok_or_else(|| format!("Chain {} is not in the registry", chain_id))?;
+    config.enabled = enabled;
+
+    CHAIN_REGISTRY_MAP.with(|map| {
+        map.borrow_mut().insert(chain_id, StorableChainConfig(config.clone()));
+    });
+
+    ic_cdk::println!("🌐 Chain {} ({}) {}", chain_id, config.name, if enabled { "enabled" } else { "disabled" });
+    Ok(config)
+}
+
+/// Remove a chain that no position refers to
+pub fn remove_chain(chain_id: u64) -> Result<ChainConfig, String> {
+    let positions = USER_POSITIONS_MAP.with(|map| {
+        map.borrow().iter().filter(|(_, p)| p.0.chain_id == chain_id).count()
+    });
+    if positions > 0 {
+        return Err(format!("Chain {} has {} positions; disable it instead", chain_id, positions));
+    }
+
+    let removed = CHAIN_REGISTRY_MAP.with(|map| map.borrow_mut().remove(&chain_id))
+        .map(|c| c.0)
+        .ok_or_else(|| format!("Chain {} is not in the registry", chain_id))?;
+
+    ic_cdk::println!("🌐 Chain {} ({}) removed", chain_id, removed.name);
+    Ok(removed)
+}
+
+/// Deployment of a protocol on an enabled chain
+pub fn get_protocol_deployment(chain_id: u64, protocol: &str) -> Option<ProtocolDeployment> {
+    get_enabled_chain(chain_id)?
+        .protocols
+        .into_iter()
+        .find(|deployment| deployment.protocol.eq_ignore_ascii_case(protocol))
+}
+
+/// USDC token of an enabled chain
+pub fn get_usdc_address(chain_id: u64) -> Result<Address, String> {
+    get_enabled_chain(chain_id)
+        .and_then(|chain| chain.usdc_address)
+        .and_then(|address| address.parse::<Address>().ok())
+        .ok_or_else(|| format!("USDC not configured for chain_id: {}", chain_id))
+}
+
+/// Chainlink USD price feeds of an enabled chain
+pub fn get_price_feeds(chain_id: u64) -> Vec<PriceFeedConfig> {
+    get_enabled_chain(chain_id)
+        .and_then(|chain| chain.price_feeds)
+        .unwrap_or_default()
+}
+
+/// Entry contract of a protocol on an enabled chain
+pub fn get_protocol_address(chain_id: u64, protocol: &str) -> Option<Address> {
+    get_protocol_deployment(chain_id, protocol)?
+        .contract_address
+        .parse::<Address>()
+        .ok()
+}
+
+// =============================================================================
+// Validation
+// =============================================================================
+
+/// Validate a registry entry and normalize symbols and protocol names to uppercase
+fn normalize_chain_config(mut config: ChainConfig) -> Result<ChainConfig, String> {
+    if config.chain_id == 0 {
+        return Err("Chain ID must be greater than 0".to_string());
+    }
+
+    config.name = config.name.trim().to_string();
+    if config.name.is_empty() {
+        return Err("Chain name cannot be empty".to_string());
+    }
+
+    config.native_symbol = config.native_symbol.trim().to_uppercase();
+    if config.native_symbol.is_empty() {
+        return Err("Native symbol cannot be empty".to_string());
+    }
+    if config.native_decimals > 36 {
+        return Err(format!("Native decimals {} out of range (max 36)", config.native_decimals));
+    }
+
+    config.rpc_endpoints = config.rpc_endpoints.iter()
+        .map(|url| url.trim().to_string())
+        .collect();
+    if config.rpc_endpoints.len() != 1 {
+        return Err(format!("Exactly one RPC endpoint is required, got {}", config.rpc_endpoints.len()));
+    }
+    if let Some(url) = config.rpc_endpoints.iter().find(|url| !url.starts_with("https://")) {
+        return Err(format!("RPC endpoint must use https: {}", url));
+    }
+
+    for (cap, label) in [
+        (config.max_fee_per_gas_gwei, "max_fee_per_gas_gwei"),
+        (config.max_priority_fee_per_gas_gwei, "max_priority_fee_per_gas_gwei"),
+    ] {
+        if cap.is_some_and(|gwei| !gwei.is_finite() || gwei <= 0.0) {
+            return Err(format!("{} must be positive", label));
+        }
+    }
+
+    if let Some(usdc) = &config.usdc_address {
+        parse_address(usdc, "USDC")?;
+    }
+    if config.cancel_gas_limit.is_some_and(|gas| gas < TRANSFER_GAS) {
+        return Err(format!("cancel_gas_limit must be at least {}", TRANSFER_GAS));
+    }
+
+    let mut feed_assets: Vec<String> = Vec::new();
+    for feed in config.price_feeds.iter_mut().flatten() {
+        feed.asset = feed.asset.trim().to_uppercase();
+        if feed.asset.is_empty() {
+            return Err("Price feed asset cannot be empty".to_string());
+        }
+        if feed_assets.contains(&feed.asset) {
+            return Err(format!("Price feed for {} listed twice", feed.asset));
+        }
+        feed_assets.push(feed.asset.clone());
+
+        parse_address(&feed.feed_address, &format!("{} price feed", feed.asset))?;
+        if feed.decimals > 36 {
+            return Err(format!("{} price feed decimals {} out of range (max 36)", feed.asset, feed.decimals));
+        }
+        if feed.heartbeat_seconds == 0 {
+            return Err(format!("{} price feed heartbeat must be positive", feed.asset));
+        }
+    }
+
+    let mut protocols: Vec<String> = Vec::new();
+    for deployment in &mut config.protocols {
+        deployment.protocol = deployment.protocol.trim().to_uppercase();
+        if !SUPPORTED_PROTOCOLS.contains(&deployment.protocol.as_str()) {
+            return Err(format!("Unsupported protocol: {}. Supported: {:?}", deployment.protocol, SUPPORTED_PROTOCOLS));
+        }
+        if protocols.contains(&deployment.protocol) {
+            return Err(format!("Protocol {} listed twice", deployment.protocol));
+        }
+        protocols.push(deployment.protocol.clone());
+
+        parse_address(&deployment.contract_address, &deployment.protocol)?;
+
+        for asset in &mut deployment.assets {
+            asset.symbol = asset.symbol.trim().to_uppercase();
+            if asset.symbol.is_empty() {
+                return Err(format!("{} asset symbol cannot be empty", deployment.protocol));
+            }
+            if asset.decimals > 36 {
+                return Err(format!("{} {} decimals {} out of range (max 36)", deployment.protocol, asset.symbol, asset.decimals));
+            }
+            parse_address(&asset.token_address, &asset.symbol)?;
+        }
+    }
+
+    Ok(config)
+}
+
+fn parse_address(address: &str, label: &str) -> Result<Address, String> {
+    address.parse::<Address>()
+        .map_err(|_| format!("Invalid {} address: {}", label, address))
+}
+
+// =============================================================================
+// Built-in Chains
+// =============================================================================
+
+fn deployment(protocol: &str, contract_address: &str, assets: Vec<DeploymentAsset>) -> ProtocolDeployment {
+    ProtocolDeployment {
+        protocol: protocol.to_string(),
+        contract_address: contract_address.to_string(),
+        assets,
+    }
+}
+
+fn asset(symbol: &str, token_address: &str, decimals: u8) -> DeploymentAsset {
+    DeploymentAsset {
+        symbol: symbol.to_string(),
+        token_address: token_address.to_string(),
+        decimals,
+    }
+}
+
+/// Chainlink USD feed with 8 answer decimals
+fn feed(asset: &str, feed_address: &str, heartbeat_seconds: u64) -> PriceFeedConfig {
+    PriceFeedConfig {
+        asset: asset.to_string(),
+        feed_address: feed_address.to_string(),
+        decimals: 8,
+        heartbeat_seconds,
+    }
+}
+
+/// Chains the canister shipped with before the registry existed
+fn default_chains() -> Vec<ChainConfig> {
+    let chain = |chain_id: u64, name: &str, rpc: &str, explorer: &str, fee_caps_gwei: (f64, f64), protocols: Vec<ProtocolDeployment>, usdc: &str, price_feeds: Vec<PriceFeedConfig>| ChainConfig {
+        chain_id,
+        name: name.to_string(),
+        native_symbol: "ETH".to_string(),
+        native_decimals: 18,
+        rpc_endpoints: vec![rpc.to_string()],
+        block_explorer: Some(explorer.to_string()),
+        eip1559: true,
+        max_fee_per_gas_gwei: Some(fee_caps_gwei.0),
+        max_priority_fee_per_gas_gwei: Some(fee_caps_gwei.1),
+        protocols,
+        usdc_address: Some(usdc.to_string()),
+        price_feeds: Some(price_feeds),
+        cancel_gas_limit: None,
+        enabled: true,
+    };
+
+    vec![
+        chain(
+            SEPOLIA_CHAIN_ID,
+            "Ethereum Sepolia",
+            "https://ic-alloy-evm-rpc-proxy.kristofer-977.workers.dev/eth-sepolia",
+            "https://sepolia.etherscan.io",
+            (200.0, 5.0),
+            vec![
+                deployment("AAVE", "0x6Ae43d3271ff6888e7Fc43Fd7321a503ff738951", vec![
+                    asset("USDC", "0x94a9D9AC8a22534E3FaCa9954e183B2c3736704F", 6),
+                    asset("LINK", "0xf8fb3713d459d7c1018bd0a49d19b4c44290ebe5", 18),
+                ]),
+            ],
+            "0x94a9D9AC8a22534E3FaCa9954e183B2c3736704F",
+            vec![
+                feed("ETH", "0x694AA1769357215DE4FAC081bf1f309aDC325306", 3600),
+                feed("USDC", "0xA2F78ab2355fe2f984D808B5CeE7FD0A93D5270E", 86400),
+                feed("LINK", "0xc59E3633BAAC79493d908e63626716e204A45EdF", 3600),
+            ],
+        ),
+        ChainConfig { cancel_gas_limit: Some(ARBITRUM_CANCEL_GAS_LIMIT), ..chain(
+            ARBITRUM_CHAIN_ID,
+            "Arbitrum One",
+            "https://yieldex-evm-proxy.exectrogod.workers.dev/arb-mainnet",
+            "https://arbiscan.io",
+            (2.0, 0.5),
+            vec![
+                deployment("AAVE", "0x794a61358D6845594F94dc1DB02A252b5b4814aD", vec![
+                    asset("USDC", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", 6),
+                ]),
+                deployment("COMPOUND", "0x9c4ec768c28520b50860ea7a15bd7213a9ff58bf", vec![
+                    asset("USDC", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", 6),
+                ]),
+            ],
+            "0xaf88d065e77c8cC2239327C5EDb3A432268e5831",
+            vec![
+                feed("ETH", "0x639Fe6ab55C921f74e7fac1ee960C0B6293ba612", 86400),
+                feed("USDC", "0x50834F3163758fcC1Df9973b6e91f0F0F0434aD3", 86400),
+                feed("LINK", "0x86E53CF1B870786351Da77A57575e79CB55812CB", 3600),
+            ],
+        ) },
+        chain(
+            BASE_CHAIN_ID,
+            "Base Mainnet",
+            "https://base-rpc.publicnode.com",
+            "https://basescan.org",
+            (2.0, 0.5),
+            vec![
+                deployment("AAVE", "0x794a61358D6845594F94dc1DB02A252b5b4814aD", vec![
+                    asset("USDC", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", 6),
+                ]),
+            ],
+            "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913",
+            vec![
+                feed("ETH", "0x71041dddad3595F9CEd3DcCFBe3D1F4b0a16Bb70", 1200),
+                feed("USDC", "0x7e860098F58bBFC8648a4311b374B1D669a2bc6B", 86400),
+                feed("LINK", "0x17CAb8FE31E32f08326e5E27412894e49B0f9D65", 86400),
+            ],
+        ),
+        chain(
+            OPTIMISM_CHAIN_ID,
+            "Optimism Mainnet",
+            "https://optimism-rpc.publicnode.com",
+            "https://optimistic.etherscan.io",
+            (2.0, 0.5),
+            vec![
+                deployment("AAVE", "0x794a61358D6845594F94dc1DB02A252b5b4814aD", vec![
+                    asset("USDC", "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85", 6),
+                ]),
+            ],
+            "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85",
+            vec![
+                feed("ETH", "0x13e3Ee699D1909E989722E753853AE30b17e08c5", 1200),
+                feed("USDC", "0x16a9FA2FDa030272Ce99B29CF780dFA30361E0f3", 86400),
+                feed("LINK", "0xCc232dcFAAE6354cE191Bd574108c1aD03f86450", 1200),
+            ],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon() -> ChainConfig {
+        ChainConfig {
+            chain_id: 137,
+            name: " Polygon PoS ".to_string(),
+            native_symbol: "pol".to_string(),
+            native_decimals: 18,
+            rpc_endpoints: vec!["https://polygon-rpc.com ".to_string()],
+            block_explorer: Some("https://polygonscan.com".to_string()),
+            eip1559: true,
+            max_fee_per_gas_gwei: Some(500.0),
+            max_priority_fee_per_gas_gwei: None,
+            protocols: vec![deployment("aave", "0x794a61358D6845594F94dc1DB02A252b5b4814aD", vec![
+                asset("usdc", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359", 6),
+            ])],
+            usdc_address: Some("0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359".to_string()),
+            price_feeds: Some(vec![feed("pol", "0xAB594600376Ec9fD91F8e885dADF0CE036862dE0", 27)]),
+            cancel_gas_limit: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn default_chains_are_valid() {
+        for chain in default_chains() {
+            let chain_id = chain.chain_id;
+            assert!(normalize_chain_config(chain).is_ok(), "chain {} invalid", chain_id);
+        }
+    }
+
+    #[test]
+    fn normalizes_names_symbols_and_endpoints() {
+        let config = normalize_chain_config(polygon()).unwrap();
+
+        assert_eq!(config.name, "Polygon PoS");
+        assert_eq!(config.native_symbol, "POL");
+        assert_eq!(config.rpc_endpoints, vec!["https://polygon-rpc.com".to_string()]);
+        assert_eq!(config.protocols[0].protocol, "AAVE");
+        assert_eq!(config.protocols[0].assets[0].symbol, "USDC");
+        assert_eq!(config.price_feeds.unwrap()[0].asset, "POL");
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        let mut no_rpc = polygon();
+        no_rpc.rpc_endpoints.clear();
+        assert!(normalize_chain_config(no_rpc).is_err());
+
+        let mut fallback_rpc = polygon();
+        fallback_rpc.rpc_endpoints.push("https://polygon.drpc.org".to_string());
+        assert!(normalize_chain_config(fallback_rpc).is_err());
+
+        let mut plain_http = polygon();
+        plain_http.rpc_endpoints = vec!["http://polygon-rpc.com".to_string()];
+        assert!(normalize_chain_config(plain_http).is_err());
+
+        let mut unknown_protocol = polygon();
+        unknown_protocol.protocols[0].protocol = "MORPHO".to_string();
+        assert!(normalize_chain_config(unknown_protocol).is_err());
+
+        let mut duplicate_protocol = polygon();
+        duplicate_protocol.protocols.push(duplicate_protocol.protocols[0].clone());
+        assert!(normalize_chain_config(duplicate_protocol).is_err());
+
+        let mut bad_address = polygon();
+        bad_address.protocols[0].assets[0].token_address = "0x1234".to_string();
+        assert!(normalize_chain_config(bad_address).is_err());
+
+        let mut duplicate_feed = polygon();
+        duplicate_feed.price_feeds.as_mut().unwrap().push(feed("POL", "0xAB594600376Ec9fD91F8e885dADF0CE036862dE0", 27));
+        assert!(normalize_chain_config(duplicate_feed).is_err());
+
+        let mut bad_usdc = polygon();
+        bad_usdc.usdc_address = Some("usdc".to_string());
+        assert!(normalize_chain_config(bad_usdc).is_err());
+
+        let mut low_cancel_gas = polygon();
+        low_cancel_gas.cancel_gas_limit = Some(20_000);
+        assert!(normalize_chain_config(low_cancel_gas).is_err());
+
+        let mut negative_cap = polygon();
+        negative_cap.max_priority_fee_per_gas_gwei = Some(-1.0);
+        assert!(normalize_chain_config(negative_cap).is_err());
+    }
+}
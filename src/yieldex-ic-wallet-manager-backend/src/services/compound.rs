@@ -8,13 +8,20 @@ use alloy::{
 };
 use candid::Principal;
 use crate::{PRINCIPAL_TO_ADDRESS_MAP, StorablePrincipal, PERMISSIONS_MAP, StorableString};
-use crate::services::permissions::{verify_protocol_permission, set_daily_usage};
-use crate::services::rpc_service::{get_rpc_service_by_chain_id, ARBITRUM_CHAIN_ID};
-use crate::services::nonce_manager::{get_next_nonce, reserve_nonce, commit_nonce, rollback_nonce, invalidate_cache};
+use crate::types::{TokenAmount, WithdrawOutcome};
+use crate::services::permissions::{verify_protocol_permission, check_usd_limit, set_daily_usage, usage_units};
+use crate::services::rpc_service::get_rpc_service_by_chain_id;
+use crate::services::chain_registry;
+use crate::services::multicall::Multicall;
+use crate::services::{erc20, fee_policy, rebalance_saga, tx_tracker};
+use crate::services::erc20::AllowanceStatus;
+use crate::services::nonce_manager::{reserve_nonce, commit_nonce, rollback_nonce};
 
-// Compound Comet addresses for Arbitrum mainnet
-// Native USDC market (Compound III cUSDCv3 proxy address)
-const COMPOUND_COMET_USDC_ADDRESS: &str = "0x9c4ec768c28520b50860ea7a15bd7213a9ff58bf"; // cUSDCv3 Proxy for USDC on Arbitrum
+/// USDC has 6 decimals
+const USDC_DECIMALS: u8 = 6;
+
+/// Gas limit for a supply sent before its approval is mined, when it can't be estimated
+const SUPPLY_GAS_LIMIT_WITHOUT_ESTIMATE: u128 = 300_000;
 
 // Codegen from ABI file to interact with Compound Comet contract
 sol!(
@@ -52,12 +59,11 @@ fn get_ecdsa_key_name() -> String {
     }
 }
 
-/// Get Compound Comet contract address based on chain_id
-fn get_compound_comet_address(chain_id: u64) -> Result<&'static str, String> {
-    match chain_id {
-        ARBITRUM_CHAIN_ID => Ok(COMPOUND_COMET_USDC_ADDRESS),
-        _ => Err(format!("Compound not supported on chain_id: {}", chain_id))
-    }
+/// Get Compound Comet (USDC market) contract address from the chain registry
+fn get_compound_comet_address(chain_id: u64) -> Result<String, String> {
+    chain_registry::get_protocol_deployment(chain_id, "COMPOUND")
+        .map(|deployment| deployment.contract_address)
+        .ok_or_else(|| format!("Compound not supported on chain_id: {}", chain_id))
 }
 
 /// Supply USDC to Compound with permission verification
@@ -100,7 +106,8 @@ pub async fn supply_usdc_to_compound_with_permissions(
     
     // 5. Convert amount (USDC has 6 decimals)
     ic_cdk::println!("✅ Step 3: Converting amount {} USDC to units...", amount_human);
-    let amount_units = parse_usdc_amount(&amount_human)?;
+    let supplied_amount = TokenAmount::parse(&amount_human, USDC_DECIMALS)?;
+    let amount_units = supplied_amount.to_u256();
     ic_cdk::println!("✅ Step 3 Complete: Amount converted to {} units", amount_units);
     
     // 6. Create signer on behalf of user
@@ -116,82 +123,45 @@ pub async fn supply_usdc_to_compound_with_permissions(
         .wallet(wallet)
         .on_icp(config);
 
-    // Handle nonce management using centralized nonce manager
-    let nonce = get_next_nonce(address, &provider, chain_id).await?;
-    reserve_nonce(address, chain_id, nonce);
-    ic_cdk::println!("Reserved nonce {} for transaction", nonce);
-    
-    // 8. First approve USDC spending by Compound
+    // 8. First approve USDC spending by Compound (skipped if allowance is sufficient)
     ic_cdk::println!("✅ Step 5: Approving USDC spending by Compound...");
     let usdc_address = get_usdc_address(chain_id)?;
-    let usdc_contract = USDC::new(usdc_address.parse::<Address>().unwrap(), &provider);
-    
-    let approve_call = usdc_contract
-        .approve(compound_address.parse::<Address>().unwrap(), amount_units)
-        .nonce(nonce)
-        .chain_id(chain_id)
-        .from(address);
-
-    let approve_receipt = approve_call.send().await.map_err(|e| {
-        // Transaction failed to send - rollback nonce
-        rollback_nonce(address, chain_id, nonce);
-
-        // Check for nonce too low error
-        let error_str = e.to_string();
-        if error_str.contains("nonce too low") {
-            ic_cdk::println!("⚠️ Nonce too low error - invalidating cache");
-            invalidate_cache(address, chain_id);
-        }
-
-        ic_cdk::println!("❌ USDC approve failed: {}", e);
-        format!("USDC approve failed: {}", e)
-    })?;
-
-    let approve_tx_hash = *approve_receipt.tx_hash();
-    ic_cdk::println!("✅ Step 5 Complete: USDC approved, hash: {:?}", approve_tx_hash);
-
-    // Transaction sent - commit nonce
-    commit_nonce(address, chain_id, nonce);
-
-    // Wait for approve transaction and update nonce cache
-    let approve_tx_response = provider.get_transaction_by_hash(approve_tx_hash).await
-        .map_err(|e| format!("Failed to get approve transaction: {}", e))?;
-
-    match approve_tx_response {
-        Some(_tx) => {
-            // Nonce already committed after send() succeeded
-        }
-        None => {
-            return Err("Approve transaction not found after sending".to_string());
-        }
-    }
+    let allowance = ensure_usdc_allowance_for_compound(
+        &provider,
+        usdc_address.parse::<Address>().unwrap(),
+        compound_address.parse::<Address>().unwrap(),
+        amount_units,
+        address,
+        user_principal,
+        chain_id
+    ).await?;
+    ic_cdk::println!("✅ Step 5 Complete: USDC allowance confirmed for Compound");
     
     // 9. Supply USDC to Compound
     ic_cdk::println!("✅ Step 6: Supplying USDC to Compound...");
     let compound_contract = CompoundComet::new(compound_address.parse::<Address>().unwrap(), &provider);
 
-    // Get next nonce for supply transaction (approval was already sent)
-    let supply_nonce = get_next_nonce(address, &provider, chain_id).await?;
-    reserve_nonce(address, chain_id, supply_nonce);
-    ic_cdk::println!("Reserved nonce {} for supply transaction", supply_nonce);
-
     let supply_call = compound_contract
         .supply(usdc_address.parse::<Address>().unwrap(), amount_units)
-        .nonce(supply_nonce)
         .chain_id(chain_id)
         .from(address);
+    let supply_call = if allowance.is_effective() {
+        fee_policy::apply_to_call(supply_call, chain_id).await?
+    } else {
+        // The estimate would revert on the missing allowance until the approval is mined
+        ic_cdk::println!("⏳ Approval not mined yet, using a gas limit of {}", SUPPLY_GAS_LIMIT_WITHOUT_ESTIMATE);
+        fee_policy::apply_to_call_with_gas_limit(supply_call, chain_id, SUPPLY_GAS_LIMIT_WITHOUT_ESTIMATE).await?
+    };
+
+    // Get next nonce for supply transaction (after any approval transaction)
+    let supply_nonce = reserve_nonce(address, &provider, chain_id).await?;
+    ic_cdk::println!("Reserved nonce {} for supply transaction", supply_nonce);
+    let supply_call = supply_call.nonce(supply_nonce);
 
     let supply_receipt = supply_call.send().await.map_err(|e| {
         // Transaction failed to send - rollback nonce
         rollback_nonce(address, chain_id, supply_nonce);
 
-        // Check for nonce too low error
-        let error_str = e.to_string();
-        if error_str.contains("nonce too low") {
-            ic_cdk::println!("⚠️ Nonce too low error - invalidating cache");
-            invalidate_cache(address, chain_id);
-        }
-
         ic_cdk::println!("❌ Compound supply failed: {}", e);
         format!("Compound supply failed: {}", e)
     })?;
@@ -201,13 +171,15 @@ pub async fn supply_usdc_to_compound_with_permissions(
 
     // Transaction sent - commit nonce
     commit_nonce(address, chain_id, supply_nonce);
+    rebalance_saga::record_step_tx(user_principal, format!("{:?}", supply_tx_hash));
 
     // Wait for supply transaction confirmation
     let supply_tx_response = provider.get_transaction_by_hash(supply_tx_hash).await
         .map_err(|e| format!("Failed to get supply transaction: {}", e))?;
 
     match supply_tx_response {
-        Some(_tx) => {
+        Some(tx) => {
+            tx_tracker::record_sent(user_principal, chain_id, &tx);
             ic_cdk::println!("Supply tx confirmed");
         }
         None => {
@@ -220,7 +192,7 @@ pub async fn supply_usdc_to_compound_with_permissions(
     let usage_result = set_daily_usage(
         permissions_id.clone(),
         compound_address.to_string(),
-        amount_units.try_into().unwrap_or(0),
+        usage_units(amount_units),
         user_principal
     );
 
@@ -231,7 +203,7 @@ pub async fn supply_usdc_to_compound_with_permissions(
 
     // 11. Sync user position after successful supply
     ic_cdk::println!("✅ Step 8: Syncing user position...");
-    let usdc_address_str = get_usdc_address(chain_id)?.to_string();
+    let usdc_address_str = get_usdc_address(chain_id)?;
     match crate::services::position_sync::sync_position_after_supply(
         user_principal,
         permissions_id,
@@ -239,7 +211,7 @@ pub async fn supply_usdc_to_compound_with_permissions(
         "USDC".to_string(),
         usdc_address_str,
         chain_id,
-        amount_human.clone(),
+        supplied_amount,
     ).await {
         Ok(_) => ic_cdk::println!("✅ Step 8 Complete: User position synced"),
         Err(e) => ic_cdk::println!("⚠️ Step 8 Warning: Position sync failed: {}", e),
@@ -255,13 +227,156 @@ pub async fn supply_usdc_to_compound_with_permissions(
     Ok(success_message)
 }
 
+/// Approve Compound to spend USDC ahead of a supply
+/// Skips the approval transaction if the current allowance already covers the amount
+pub async fn approve_usdc_for_compound_with_permissions(
+    amount_human: String,
+    permissions_id: String,
+    user_principal: Principal
+) -> Result<String, String> {
+    ic_cdk::println!("🚀 Starting Compound USDC approval: {} USDC for principal {}", amount_human, user_principal);
+
+    let permissions = PERMISSIONS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(permissions_id.clone()))
+            .ok_or_else(|| "Permissions not found".to_string())
+            .map(|p| p.0.clone())
+    })?;
+
+    // Check ownership
+    if permissions.owner != user_principal {
+        return Err("Not authorized to use these permissions".to_string());
+    }
+
+    let chain_id = permissions.chain_id;
+    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
+    let config = IcpConfig::new(rpc_service);
+    let compound_address = get_compound_comet_address(chain_id)?;
+    let usdc_address = get_usdc_address(chain_id)?;
+    let amount_units = parse_usdc_amount(&amount_human)?;
+
+    let signer = create_icp_signer_for_principal(user_principal).await?;
+    let address = signer.address();
+
+    let wallet = EthereumWallet::from(signer);
+    let provider = ProviderBuilder::new()
+        .with_gas_estimation()
+        .wallet(wallet)
+        .on_icp(config);
+
+    let allowance = ensure_usdc_allowance_for_compound(
+        &provider,
+        usdc_address.parse::<Address>().unwrap(),
+        compound_address.parse::<Address>().unwrap(),
+        amount_units,
+        address,
+        user_principal,
+        chain_id
+    ).await?;
+
+    ic_cdk::println!("🎉 Compound USDC approval completed");
+    match allowance {
+        AllowanceStatus::Approved(tx_hash) => Ok(format!("✅ Approved {} USDC for Compound! Transaction: {:?}", amount_human, tx_hash)),
+        AllowanceStatus::Pending(tx_hash) => Ok(format!("⏳ Approval of {} USDC for Compound sent, not mined yet. Transaction: {:?}", amount_human, tx_hash)),
+        AllowanceStatus::Sufficient => Ok(format!("✅ {} USDC allowance already confirmed for Compound", amount_human)),
+    }
+}
+
+/// Ensure Compound is allowed to spend `amount_units` USDC
+/// An approval that is sent is waited for, so the supply that follows can be estimated
+async fn ensure_usdc_allowance_for_compound(
+    provider: &alloy::providers::fillers::FillProvider<
+        alloy::providers::fillers::JoinFill<
+            alloy::providers::fillers::JoinFill<
+                alloy::providers::Identity,
+                alloy::providers::fillers::GasFiller,
+            >,
+            alloy::providers::fillers::WalletFiller<EthereumWallet>,
+        >,
+        alloy::providers::RootProvider<alloy::transports::icp::IcpTransport>,
+        alloy::transports::icp::IcpTransport,
+        alloy::network::Ethereum,
+    >,
+    usdc_address: Address,
+    compound_address: Address,
+    amount_units: U256,
+    owner: Address,
+    user_principal: Principal,
+    chain_id: u64
+) -> Result<AllowanceStatus, String> {
+    let usdc_contract = USDC::new(usdc_address, provider);
+
+    let current_allowance = usdc_contract.allowance(owner, compound_address).call().await
+        .map_err(|e| format!("Failed to get USDC allowance: {}", e))?;
+
+    if current_allowance._0 >= amount_units {
+        ic_cdk::println!("✅ Sufficient USDC allowance already exists, no approval needed");
+        return Ok(AllowanceStatus::Sufficient);
+    }
+
+    let approve_call = usdc_contract
+        .approve(compound_address, amount_units)
+        .chain_id(chain_id)
+        .from(owner);
+    let approve_call = fee_policy::apply_to_call(approve_call, chain_id).await?;
+
+    // Handle nonce management using centralized nonce manager
+    let nonce = reserve_nonce(owner, provider, chain_id).await?;
+    ic_cdk::println!("Reserved nonce {} for approve transaction", nonce);
+    let approve_call = approve_call.nonce(nonce);
+
+    let approve_receipt = approve_call.send().await.map_err(|e| {
+        // Transaction failed to send - rollback nonce
+        rollback_nonce(owner, chain_id, nonce);
+
+        ic_cdk::println!("❌ USDC approve failed: {}", e);
+        format!("USDC approve failed: {}", e)
+    })?;
+
+    let approve_tx_hash = *approve_receipt.tx_hash();
+    ic_cdk::println!("✅ USDC approved, hash: {:?}", approve_tx_hash);
+
+    // Transaction sent - commit nonce
+    commit_nonce(owner, chain_id, nonce);
+
+    // Wait for approve transaction
+    let approve_tx_response = provider.get_transaction_by_hash(approve_tx_hash).await
+        .map_err(|e| format!("Failed to get approve transaction: {}", e))?;
+
+    match approve_tx_response {
+        Some(tx) => tx_tracker::record_sent(user_principal, chain_id, &tx),
+        None => return Err("Approve transaction not found after sending".to_string()),
+    }
+
+    erc20::settle_approval(provider, approve_tx_hash).await
+}
+
 /// Withdraw USDC from Compound with permission verification
+///
+/// `amount_human` may be "max" to withdraw the full Comet balance, including
+/// accrued interest.
 pub async fn withdraw_usdc_from_compound_with_permissions(
     amount_human: String,
     permissions_id: String,
     user_principal: Principal
 ) -> Result<String, String> {
+    withdraw_usdc_from_compound_with_outcome(amount_human, permissions_id, user_principal)
+        .await
+        .map(|outcome| outcome.message)
+}
+
+/// Withdraw USDC from Compound and report the amount that actually reached the wallet
+///
+/// Waits for the withdraw to be mined and takes the amount from the USDC
+/// `Transfer` log to the wallet. If it is not mined yet, the amount requested
+/// from Comet is reported.
+pub async fn withdraw_usdc_from_compound_with_outcome(
+    amount_human: String,
+    permissions_id: String,
+    user_principal: Principal
+) -> Result<WithdrawOutcome, String> {
     ic_cdk::println!("🚀 Starting Compound USDC withdrawal: {} USDC for principal {}", amount_human, user_principal);
+    let withdraw_max = amount_human.eq_ignore_ascii_case("max");
     
     // 1. Get permissions and chain_id
     ic_cdk::println!("✅ Step 1: Getting permissions and chain_id...");
@@ -288,36 +403,21 @@ pub async fn withdraw_usdc_from_compound_with_permissions(
     let compound_address = get_compound_comet_address(chain_id)?;
     ic_cdk::println!("✅ Using Compound Comet address: {}", compound_address);
     
-    // 4. Check permissions
-    ic_cdk::println!("✅ Step 2: Verifying Compound withdraw permissions...");
-    verify_compound_permission(&permissions_id, "withdraw", &amount_human, user_principal, chain_id).await?;
-    ic_cdk::println!("✅ Step 2 Complete: Compound withdraw permissions verified");
-    
-    // 5. Convert amount (USDC has 6 decimals)
-    ic_cdk::println!("✅ Step 3: Converting amount {} USDC to units...", amount_human);
-    let amount_units = parse_usdc_amount(&amount_human)?;
-    ic_cdk::println!("✅ Step 3 Complete: Amount converted to {} units", amount_units);
-    
-    // 6. Create signer on behalf of user
-    ic_cdk::println!("✅ Step 4: Creating ICP signer for principal...");
+    // 4. Create signer on behalf of user
+    ic_cdk::println!("✅ Step 2: Creating ICP signer for principal...");
     let signer = create_icp_signer_for_principal(user_principal).await?;
     let address = signer.address();
-    ic_cdk::println!("✅ Step 4 Complete: Signer created for address: {}", address);
+    ic_cdk::println!("✅ Step 2 Complete: Signer created for address: {}", address);
     
-    // 7. Setup provider and contract
+    // 5. Setup provider and contract
     let wallet = EthereumWallet::from(signer);
     let provider = ProviderBuilder::new()
         .with_gas_estimation()
         .wallet(wallet)
         .on_icp(config);
-
-    // Handle nonce management using centralized nonce manager
-    let nonce = get_next_nonce(address, &provider, chain_id).await?;
-    reserve_nonce(address, chain_id, nonce);
-    ic_cdk::println!("Reserved nonce {} for transaction", nonce);
     
-    // 8. Check cUSDC balance before withdrawal
-    ic_cdk::println!("✅ Step 5: Checking cUSDC balance...");
+    // 6. Check cUSDC balance before withdrawal
+    ic_cdk::println!("✅ Step 3: Checking cUSDC balance...");
     let compound_contract = CompoundComet::new(compound_address.parse::<Address>().unwrap(), &provider);
     
     let user_balance = compound_contract
@@ -326,35 +426,52 @@ pub async fn withdraw_usdc_from_compound_with_permissions(
         .await
         .map_err(|e| format!("Failed to get Compound balance: {}", e))?;
     
-    ic_cdk::println!("✅ Step 5 Complete: cUSDC balance: {} units", user_balance._0);
+    ic_cdk::println!("✅ Step 3 Complete: cUSDC balance: {} units", user_balance._0);
     
-    if user_balance._0 < amount_units {
-        let error_msg = format!("Insufficient cUSDC balance. Have: {} units, Need: {} units", user_balance._0, amount_units);
-        ic_cdk::println!("❌ Compound withdrawal failed: {}", error_msg);
-        return Err(error_msg);
-    }
+    // 7. Resolve the amount to withdraw (USDC has 6 decimals)
+    // For "max" the full Comet balance is withdrawn so accrued interest is included
+    let amount_units = if withdraw_max {
+        if user_balance._0.is_zero() {
+            return Err("No cUSDC balance to withdraw".to_string());
+        }
+        user_balance._0
+    } else {
+        let amount_units = parse_usdc_amount(&amount_human)?;
+        if user_balance._0 < amount_units {
+            let error_msg = format!("Insufficient cUSDC balance. Have: {} units, Need: {} units", user_balance._0, amount_units);
+            ic_cdk::println!("❌ Compound withdrawal failed: {}", error_msg);
+            return Err(error_msg);
+        }
+        amount_units
+    };
+    let expected_amount_human = format_usdc_amount(amount_units)?;
+    ic_cdk::println!("✅ Step 4: Withdrawing {} USDC ({} units)", expected_amount_human, amount_units);
+    
+    // 8. Check permissions
+    ic_cdk::println!("✅ Step 5: Verifying Compound withdraw permissions...");
+    verify_compound_permission(&permissions_id, "withdraw", &expected_amount_human, user_principal, chain_id).await?;
+    ic_cdk::println!("✅ Step 5 Complete: Compound withdraw permissions verified");
     
+    let usdc_address = get_usdc_address(chain_id)?;
+
     // 9. Withdraw USDC from Compound
     ic_cdk::println!("✅ Step 6: Withdrawing USDC from Compound...");
-    let usdc_address = get_usdc_address(chain_id)?;
 
     let withdraw_call = compound_contract
         .withdraw(usdc_address.parse::<Address>().unwrap(), amount_units)
-        .nonce(nonce)
         .chain_id(chain_id)
         .from(address);
+    let withdraw_call = fee_policy::apply_to_call(withdraw_call, chain_id).await?;
+
+    // Handle nonce management using centralized nonce manager
+    let nonce = reserve_nonce(address, &provider, chain_id).await?;
+    ic_cdk::println!("Reserved nonce {} for transaction", nonce);
+    let withdraw_call = withdraw_call.nonce(nonce);
 
     let withdraw_receipt = withdraw_call.send().await.map_err(|e| {
         // Transaction failed to send - rollback nonce
         rollback_nonce(address, chain_id, nonce);
 
-        // Check for nonce too low error
-        let error_str = e.to_string();
-        if error_str.contains("nonce too low") {
-            ic_cdk::println!("⚠️ Nonce too low error - invalidating cache");
-            invalidate_cache(address, chain_id);
-        }
-
         ic_cdk::println!("❌ Compound withdraw failed: {}", e);
         format!("Compound withdraw failed: {}", e)
     })?;
@@ -364,13 +481,15 @@ pub async fn withdraw_usdc_from_compound_with_permissions(
 
     // Transaction sent - commit nonce
     commit_nonce(address, chain_id, nonce);
+    rebalance_saga::record_step_tx(user_principal, format!("{:?}", withdraw_tx_hash));
 
     // Wait for withdraw transaction confirmation
     let withdraw_tx_response = provider.get_transaction_by_hash(withdraw_tx_hash).await
         .map_err(|e| format!("Failed to get withdraw transaction: {}", e))?;
 
     match withdraw_tx_response {
-        Some(_tx) => {
+        Some(tx) => {
+            tx_tracker::record_sent(user_principal, chain_id, &tx);
             ic_cdk::println!("Withdraw tx confirmed");
         }
         None => {
@@ -378,12 +497,35 @@ pub async fn withdraw_usdc_from_compound_with_permissions(
         }
     }
     
-    // 10. Update daily usage for permissions
+    // 10. Take the amount that actually reached the wallet from the receipt
+    let withdrawn_units = match tx_tracker::wait_for_receipt(&provider, withdraw_tx_hash).await? {
+        Some(receipt) if !receipt.status() => {
+            return Err(format!("Compound withdraw transaction {:?} reverted", withdraw_tx_hash));
+        }
+        Some(receipt) => {
+            let received = erc20::received_amount(&receipt, usdc_address.parse::<Address>().unwrap(), address);
+            if received.is_zero() {
+                ic_cdk::println!("⚠️ No USDC transfer in receipt, using requested amount");
+                amount_units
+            } else {
+                received
+            }
+        }
+        None => {
+            ic_cdk::println!("⚠️ Withdraw not mined yet, using requested amount");
+            amount_units
+        }
+    };
+    let withdrawn = TokenAmount::from_u256(withdrawn_units, USDC_DECIMALS)?;
+    let withdrawn_human = withdrawn.to_string();
+    ic_cdk::println!("✅ Withdrawn {} USDC ({} units)", withdrawn_human, withdrawn_units);
+    
+    // 11. Update daily usage for permissions
     ic_cdk::println!("✅ Step 7: Updating protocol usage tracking...");
     let usage_result = set_daily_usage(
         permissions_id,
         compound_address.to_string(),
-        amount_units.try_into().unwrap_or(0),
+        usage_units(withdrawn_units),
         user_principal
     );
 
@@ -392,14 +534,14 @@ pub async fn withdraw_usdc_from_compound_with_permissions(
         Err(e) => ic_cdk::println!("⚠️ Step 7 Warning: Usage tracking failed: {}", e),
     }
 
-    // 11. Sync user position after successful withdrawal
+    // 12. Sync user position after successful withdrawal
     ic_cdk::println!("✅ Step 8: Syncing user position...");
     match crate::services::position_sync::sync_position_after_withdraw(
         user_principal,
         "COMPOUND".to_string(),
         "USDC".to_string(),
         chain_id,
-        amount_human.clone(),
+        withdrawn,
     ).await {
         Ok(_) => ic_cdk::println!("✅ Step 8 Complete: User position synced"),
         Err(e) => ic_cdk::println!("⚠️ Step 8 Warning: Position sync failed: {}", e),
@@ -408,11 +550,15 @@ pub async fn withdraw_usdc_from_compound_with_permissions(
     let tx_hash = format!("{:?}", withdraw_tx_hash);
     let success_message = format!(
         "✅ Successfully withdrew {} USDC from Compound! Transaction: {}",
-        amount_human, tx_hash
+        withdrawn_human, tx_hash
     );
 
     ic_cdk::println!("🎉 Compound withdrawal completed successfully");
-    Ok(success_message)
+    Ok(WithdrawOutcome {
+        tx_hash,
+        amount_withdrawn: withdrawn_human,
+        message: success_message,
+    })
 }
 
 /// Get user's USDC balance in Compound
@@ -446,7 +592,7 @@ pub async fn get_compound_usdc_balance(address: Option<String>, chain_id: u64) -
         .await
         .map_err(|e| format!("Failed to get Compound balance: {}", e))?;
     
-    let balance_human = format_usdc_amount(balance._0);
+    let balance_human = format_usdc_amount(balance._0)?;
     ic_cdk::println!(
         "📊 Compound USDC balance: {} (units: {})",
         balance_human,
@@ -466,7 +612,8 @@ async fn verify_compound_permission(
     chain_id: u64
 ) -> Result<(), String> {
     let compound_address = get_compound_comet_address(chain_id)?;
-    let amount_units: u64 = parse_usdc_amount(amount_human)?.try_into().unwrap_or(0);
+    let amount = TokenAmount::parse(amount_human, USDC_DECIMALS)?;
+    let amount_units = usage_units(amount.to_u256());
     
     let result = verify_protocol_permission(
         permissions_id.to_string(),
@@ -476,84 +623,139 @@ async fn verify_compound_permission(
         user_principal
     )?;
     
-    if result {
-        Ok(())
-    } else {
-        Err("Protocol permission check failed".to_string())
+    if !result {
+        return Err("Protocol permission check failed".to_string());
     }
+
+    check_usd_limit(permissions_id, &compound_address, "USDC", chain_id, &amount).await
 }
 
 /// Parse human-readable USDC amount to units (6 decimals)
 fn parse_usdc_amount(amount_human: &str) -> Result<U256, String> {
-    let amount_f64: f64 = amount_human.parse()
-        .map_err(|_| format!("Invalid amount format: {}", amount_human))?;
-    
-    if amount_f64 < 0.0 {
-        return Err("Amount cannot be negative".to_string());
-    }
-    
-    // USDC has 6 decimals
-    let amount_units = (amount_f64 * 1_000_000.0) as u64;
-    Ok(U256::from(amount_units))
+    Ok(TokenAmount::parse(amount_human, USDC_DECIMALS)?.to_u256())
 }
 
-/// Format USDC units to human-readable amount
-fn format_usdc_amount(amount_units: U256) -> String {
-    let amount_u64: u64 = amount_units.try_into().unwrap_or(0);
-    let amount_f64 = amount_u64 as f64 / 1_000_000.0;
-    format!("{:.6}", amount_f64)
+/// Format USDC units to an exact human-readable amount
+fn format_usdc_amount(amount_units: U256) -> Result<String, String> {
+    Ok(TokenAmount::from_u256(amount_units, USDC_DECIMALS)?.to_string())
 }
 
-/// Get USDC contract address based on chain_id
-fn get_usdc_address(chain_id: u64) -> Result<&'static str, String> {
-    match chain_id {
-        ARBITRUM_CHAIN_ID => Ok("0xaf88d065e77c8cc2239327c5edb3a432268e5831"), // Native USDC on Arbitrum One
-        _ => Err(format!("USDC not configured for chain_id: {}", chain_id))
-    }
+/// Get the USDC address of the chain's Compound deployment
+fn get_usdc_address(chain_id: u64) -> Result<String, String> {
+    chain_registry::get_protocol_deployment(chain_id, "COMPOUND")
+        .and_then(|deployment| deployment.assets.into_iter().find(|asset| asset.symbol == "USDC"))
+        .map(|asset| asset.token_address)
+        .ok_or_else(|| format!("USDC not configured for chain_id: {}", chain_id))
+}
+
+/// Get the Comet contract whose `balanceOf` returns an address's supplied USDC
+pub(crate) fn get_comet_address(chain_id: u64) -> Result<Address, String> {
+    get_compound_comet_address(chain_id)?
+        .parse::<Address>()
+        .map_err(|_| "Invalid Compound Comet address".to_string())
 }
 
 /// Get current supply APY from Compound on-chain
 /// Returns APY as a percentage string (e.g., "5.23")
+///
+/// Utilization and the supply rate model are read in one batched call and the
+/// rate is computed locally, instead of calling getUtilization and then
+/// getSupplyRate(utilization).
 pub async fn get_apy(chain_id: u64) -> Result<String, String> {
     ic_cdk::println!("🔍 Getting Compound APY on chain {}", chain_id);
 
     // 1. Get Compound Comet contract address
-    let compound_address = get_compound_comet_address(chain_id)?;
-    ic_cdk::println!("✅ Using Compound Comet address: {}", compound_address);
-
-    // 2. Create read-only provider (no signer needed)
-    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
-    let config = IcpConfig::new(rpc_service);
-    let provider = ProviderBuilder::new().on_icp(config);
-
-    // 3. Create Compound Comet contract instance
-    let compound_contract = CompoundComet::new(compound_address.parse::<Address>().unwrap(), &provider);
-
-    // 4. Get current utilization
-    ic_cdk::println!("📞 Calling getUtilization...");
-    let utilization = compound_contract.getUtilization().call().await
+    let comet = get_comet_address(chain_id)?;
+    ic_cdk::println!("✅ Using Compound Comet address: 0x{:x}", comet);
+
+    // 2. Read utilization and the supply rate model together
+    let mut multicall = Multicall::new(chain_id);
+    let utilization_index = multicall.add(comet, CompoundComet::getUtilizationCall {});
+    let kink_index = multicall.add(comet, CompoundComet::supplyKinkCall {});
+    let base_index = multicall.add(comet, CompoundComet::supplyPerSecondInterestRateBaseCall {});
+    let slope_low_index = multicall.add(comet, CompoundComet::supplyPerSecondInterestRateSlopeLowCall {});
+    let slope_high_index = multicall.add(comet, CompoundComet::supplyPerSecondInterestRateSlopeHighCall {});
+    let results = multicall.execute().await?;
+
+    let utilization = results.decode_uint(utilization_index)
         .map_err(|e| format!("Failed to get utilization: {}", e))?;
-    ic_cdk::println!("✅ Current utilization: {}", utilization._0);
-
-    // 5. Get supply rate for current utilization
-    ic_cdk::println!("📞 Calling getSupplyRate with utilization {}...", utilization._0);
-    let supply_rate = compound_contract.getSupplyRate(utilization._0).call().await
-        .map_err(|e| format!("Failed to get supply rate: {}", e))?;
+    let model = SupplyRateModel {
+        kink: results.decode_uint(kink_index)?,
+        base: results.decode_uint(base_index)?,
+        slope_low: results.decode_uint(slope_low_index)?,
+        slope_high: results.decode_uint(slope_high_index)?,
+    };
+    ic_cdk::println!("✅ Current utilization: {}", utilization);
 
-    ic_cdk::println!("✅ Supply rate (per-second, 18 decimals): {}", supply_rate._0);
+    // 3. Supply rate for current utilization
+    let supply_rate = model.supply_rate(utilization);
+    ic_cdk::println!("✅ Supply rate (per-second, 18 decimals): {}", supply_rate);
 
-    // 6. Convert per-second rate to annual APY
+    // 4. Convert per-second rate to annual APY
     // Compound III returns rate in 18-decimal fixed-point format (1e18), per-second
     // APR% = (rate / 1e18) * seconds_per_year * 100
     // For APY with compounding: APY = (1 + rate_per_second)^seconds_per_year - 1
     // We use linear approximation (APR) for simplicity: APY% ≈ (rate / 1e18) * seconds_per_year * 100
     // seconds_per_year = 31,536,000 (365 * 24 * 60 * 60)
     const SECONDS_PER_YEAR: f64 = 31_536_000.0;
-    let rate_per_second = (supply_rate._0 as f64) / 1e18;
+    let rate_per_second = (supply_rate.to::<u128>() as f64) / 1e18;
     let apr_percent = rate_per_second * SECONDS_PER_YEAR * 100.0;
 
     let apy_string = format!("{:.2}", apr_percent);
     ic_cdk::println!("🎯 Compound APR (linear approximation): {}%", apy_string);
 
     Ok(apy_string)
-}
\ No newline at end of file
+}
+
+/// Comet supply interest rate model (all values 18-decimal fixed point)
+struct SupplyRateModel {
+    kink: U256,
+    base: U256,
+    slope_low: U256,
+    slope_high: U256,
+}
+
+impl SupplyRateModel {
+    /// Per-second supply rate at a utilization, as Comet's getSupplyRate computes it
+    fn supply_rate(&self, utilization: U256) -> U256 {
+        let factor_scale = U256::from(1_000_000_000_000_000_000u128);
+        let mul_factor = |n: U256, factor: U256| n * factor / factor_scale;
+
+        if utilization <= self.kink {
+            self.base + mul_factor(self.slope_low, utilization)
+        } else {
+            self.base + mul_factor(self.slope_low, self.kink) + mul_factor(self.slope_high, utilization - self.kink)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `value`% in 18-decimal fixed point
+    fn percent(value: u64) -> U256 {
+        U256::from(value) * U256::from(10u64).pow(U256::from(16))
+    }
+
+    fn model() -> SupplyRateModel {
+        SupplyRateModel {
+            kink: percent(90),
+            base: U256::ZERO,
+            slope_low: percent(4),
+            slope_high: percent(50),
+        }
+    }
+
+    #[test]
+    fn supply_rate_below_kink_uses_low_slope() {
+        // 0.04 * 0.5 = 0.02
+        assert_eq!(model().supply_rate(percent(50)), percent(2));
+    }
+
+    #[test]
+    fn supply_rate_above_kink_adds_high_slope() {
+        // 0.04 * 0.9 + 0.5 * 0.05 = 0.036 + 0.025
+        assert_eq!(model().supply_rate(percent(95)), U256::from(61_000_000_000_000_000u128));
+    }
+}
@@ -1,19 +1,30 @@
-use std::cell::RefCell;
-
 use alloy::{
-    network::EthereumWallet,
-    primitives::{Address, U256},
+    network::{EthereumWallet, TransactionBuilder},
+    primitives::{Address, TxHash, U256},
     providers::{Provider, ProviderBuilder},
+    rpc::types::{request::TransactionRequest, TransactionReceipt},
     signers::Signer,
     sol,
-    transports::icp::IcpConfig,
+    sol_types::SolCall,
+    transports::{icp::IcpConfig, Transport},
 };
 use candid::Principal;
-use crate::{PRINCIPAL_TO_ADDRESS_MAP, StorablePrincipal, get_rpc_service_sepolia};
 
-thread_local! {
-    static ERC20_NONCE_CACHE: RefCell<Option<u64>> = const { RefCell::new(None) };
-}
+use crate::services::rpc_service::get_rpc_service_by_chain_id;
+use crate::services::permissions::{record_transfer_usage, verify_transfer_permission, NATIVE_TOKEN_ADDRESS};
+use crate::services::{fee_policy, tx_tracker};
+use crate::services::nonce_manager::{reserve_nonce, commit_nonce, rollback_nonce};
+
+// =============================================================================
+// Native and ERC-20 Transfers
+// =============================================================================
+//
+// Chain-parameterized transfers out of a user's canister-derived address:
+// native sends, ERC-20 transfers and ERC-20 approvals on any enabled chain of
+// the registry. Every transaction takes its nonce from the shared nonce
+// manager, so these paths never race the protocol operations of the same
+// address.
+//
 
 // Universal ERC-20 contract interface using LINK.json as base
 sol!(
@@ -23,20 +34,38 @@ sol!(
     "src/abi/LINK.json"
 );
 
-/// Universal ERC-20 token information
-#[derive(Debug, Clone)]
-pub struct TokenInfo {
-    pub address: Address,
-    pub name: String,
-    pub symbol: String,
-    pub decimals: u8,
+/// Balance the sender must hold before a transaction is sent
+pub(crate) enum BalanceRequirement {
+    /// Nothing is spent (approvals)
+    None,
+    /// Native token value, in wei
+    Native(U256),
+    /// ERC-20 amount, in token units
+    Token(Address, U256),
+}
+
+/// State of a spender's allowance after making sure it covers an amount
+pub(crate) enum AllowanceStatus {
+    /// The allowance already covered the amount
+    Sufficient,
+    /// An approval was sent and mined
+    Approved(TxHash),
+    /// An approval was sent but is not mined yet
+    Pending(TxHash),
+}
+
+impl AllowanceStatus {
+    /// Whether the allowance is in effect on chain, so calls spending it can be estimated
+    pub(crate) fn is_effective(&self) -> bool {
+        !matches!(self, AllowanceStatus::Pending(_))
+    }
 }
 
 /// Helper function to create ICP signer for a specific principal
-async fn create_icp_signer_for_principal(principal: Principal) -> Result<alloy::signers::icp::IcpSigner, String> {
+pub(crate) async fn create_icp_signer_for_principal(principal: Principal) -> Result<alloy::signers::icp::IcpSigner, String> {
     let derivation_path = vec![principal.as_slice().to_vec()];
     let ecdsa_key_name = get_ecdsa_key_name();
-    
+
     alloy::signers::icp::IcpSigner::new(derivation_path, &ecdsa_key_name, None)
         .await
         .map_err(|e| format!("Failed to create ICP signer: {}", e))
@@ -52,369 +81,218 @@ fn get_ecdsa_key_name() -> String {
     }
 }
 
-/// Get token information (name, symbol, decimals)
-pub async fn get_token_info(token_address: String) -> Result<TokenInfo, String> {
-    ic_cdk::println!("🔍 Getting token info for address: {}", token_address);
-    
-    let token_addr = token_address.parse::<Address>()
-        .map_err(|_| "Invalid token address".to_string())?;
-    
-    let rpc_service = get_rpc_service_sepolia();
-    let config = IcpConfig::new(rpc_service);
-    let provider = ProviderBuilder::new().on_icp(config);
-    
-    let contract = ERC20Token::new(token_addr, provider);
-    
-    // Get token details
-    let name = contract.name().call().await
-        .map_err(|e| format!("Failed to get token name: {}", e))?._0;
-    
-    let symbol = contract.symbol().call().await
-        .map_err(|e| format!("Failed to get token symbol: {}", e))?._0;
-    
-    let decimals = contract.decimals().call().await
-        .map_err(|e| format!("Failed to get token decimals: {}", e))?._0;
-    
-    let token_info = TokenInfo {
-        address: token_addr,
-        name,
-        symbol,
-        decimals,
-    };
-    
-    ic_cdk::println!("✅ Token info retrieved: {} ({}) - {} decimals", 
-                    token_info.name, token_info.symbol, token_info.decimals);
-    
-    Ok(token_info)
-}
+/// Send native tokens to an address with permission verification
+pub async fn send_native(
+    chain_id: u64,
+    to_address: Address,
+    amount: U256,
+    user_principal: Principal
+) -> Result<String, String> {
+    ic_cdk::println!("💸 Sending {} wei of native token to 0x{:x} on chain {}", amount, to_address, chain_id);
 
-/// Get token balance for a specific address
-pub async fn get_token_balance(token_address: String, user_address: Option<String>) -> Result<String, String> {
-    ic_cdk::println!("💰 Getting token balance for {}", token_address);
-    
-    let token_addr = token_address.parse::<Address>()
-        .map_err(|_| "Invalid token address".to_string())?;
-    
-    let target_address = match user_address {
-        Some(addr) => addr,
-        None => {
-            let caller = ic_cdk::caller();
-            PRINCIPAL_TO_ADDRESS_MAP.with(|map| {
-                map.borrow()
-                    .get(&StorablePrincipal(caller))
-                    .map(|s| s.0.clone())
-                    .ok_or_else(|| "No EVM address found for caller".to_string())
-            })?
-        }
-    };
-    
-    let user_addr = target_address.parse::<Address>()
-        .map_err(|_| "Invalid user address".to_string())?;
-    
-    let rpc_service = get_rpc_service_sepolia();
-    let config = IcpConfig::new(rpc_service);
-    let provider = ProviderBuilder::new().on_icp(config);
-    
-    let contract = ERC20Token::new(token_addr, provider);
-    
-    let balance = contract.balanceOf(user_addr).call().await
-        .map_err(|e| format!("Failed to get token balance: {}", e))?;
-    
-    ic_cdk::println!("✅ Token balance: {} wei", balance._0);
-    Ok(format!("0x{:x}", balance._0))
+    check_amount(amount)?;
+    let permissions_id = verify_transfer_permission(user_principal, chain_id, NATIVE_TOKEN_ADDRESS, None, amount)?;
+
+    let tx = TransactionRequest::default()
+        .with_to(to_address)
+        .with_value(amount);
+
+    let tx_hash = send_transaction(chain_id, user_principal, tx, BalanceRequirement::Native(amount)).await?;
+    record_transfer_usage(&permissions_id, NATIVE_TOKEN_ADDRESS, amount);
+
+    let success_msg = format!("Native transfer successful: {:?}", tx_hash);
+    ic_cdk::println!("🎉 {}", success_msg);
+    Ok(success_msg)
 }
 
-/// Get token allowance between owner and spender
-pub async fn get_token_allowance(
-    token_address: String,
-    owner_address: Option<String>,
-    spender_address: String
+/// Transfer ERC-20 tokens to an address with permission verification
+pub async fn transfer_token(
+    chain_id: u64,
+    token_address: Address,
+    to_address: Address,
+    amount: U256,
+    user_principal: Principal
 ) -> Result<String, String> {
-    ic_cdk::println!("🔐 Getting token allowance for {} -> {}", 
-                    owner_address.as_deref().unwrap_or("caller"), spender_address);
-    
-    let token_addr = token_address.parse::<Address>()
-        .map_err(|_| "Invalid token address".to_string())?;
-    
-    let owner_addr = match owner_address {
-        Some(addr) => addr.parse::<Address>().map_err(|_| "Invalid owner address".to_string())?,
-        None => {
-            let caller = ic_cdk::caller();
-            let addr_str = PRINCIPAL_TO_ADDRESS_MAP.with(|map| {
-                map.borrow()
-                    .get(&StorablePrincipal(caller))
-                    .map(|s| s.0.clone())
-                    .ok_or_else(|| "No EVM address found for caller".to_string())
-            })?;
-            addr_str.parse::<Address>().map_err(|_| "Invalid caller address".to_string())?
-        }
-    };
-    
-    let spender_addr = spender_address.parse::<Address>()
-        .map_err(|_| "Invalid spender address".to_string())?;
-    
-    let rpc_service = get_rpc_service_sepolia();
-    let config = IcpConfig::new(rpc_service);
-    let provider = ProviderBuilder::new().on_icp(config);
-    
-    let contract = ERC20Token::new(token_addr, provider);
-    
-    let allowance = contract.allowance(owner_addr, spender_addr).call().await
-        .map_err(|e| format!("Failed to get allowance: {}", e))?;
-    
-    ic_cdk::println!("✅ Token allowance: {} wei", allowance._0);
-    Ok(format!("0x{:x}", allowance._0))
+    ic_cdk::println!("💸 Transferring {} units of token 0x{:x} to 0x{:x} on chain {}",
+                    amount, token_address, to_address, chain_id);
+
+    check_amount(amount)?;
+    let token = format!("0x{:x}", token_address);
+    let permissions_id = verify_transfer_permission(user_principal, chain_id, &token, None, amount)?;
+
+    let call = ERC20Token::transferCall { to: to_address, amount };
+    let tx = TransactionRequest::default()
+        .with_to(token_address)
+        .with_input(call.abi_encode());
+
+    let tx_hash = send_transaction(chain_id, user_principal, tx, BalanceRequirement::Token(token_address, amount)).await?;
+    record_transfer_usage(&permissions_id, &token, amount);
+
+    let success_msg = format!("Token transfer successful: {:?}", tx_hash);
+    ic_cdk::println!("🎉 {}", success_msg);
+    Ok(success_msg)
 }
 
-/// Approve token spending for a spender
+/// Approve a spender for ERC-20 tokens with permission verification
+///
+/// The spender must be a whitelisted protocol. An amount of zero revokes the
+/// approval.
 pub async fn approve_token(
-    token_address: String,
-    spender_address: String,
+    chain_id: u64,
+    token_address: Address,
+    spender_address: Address,
     amount: U256,
     user_principal: Principal
 ) -> Result<String, String> {
-    ic_cdk::println!("✅ Approving {} wei of token {} for spender {}", 
-                    amount, token_address, spender_address);
-    
-    let token_addr = token_address.parse::<Address>()
-        .map_err(|_| "Invalid token address".to_string())?;
-    
-    let spender_addr = spender_address.parse::<Address>()
-        .map_err(|_| "Invalid spender address".to_string())?;
-    
-    // Create signer for user
+    ic_cdk::println!("✅ Approving {} units of token 0x{:x} for spender 0x{:x} on chain {}",
+                    amount, token_address, spender_address, chain_id);
+
+    verify_transfer_permission(
+        user_principal,
+        chain_id,
+        &format!("0x{:x}", token_address),
+        Some(&format!("0x{:x}", spender_address)),
+        amount
+    )?;
+
+    let call = ERC20Token::approveCall { spender: spender_address, amount };
+    let tx = TransactionRequest::default()
+        .with_to(token_address)
+        .with_input(call.abi_encode());
+
+    let tx_hash = send_transaction(chain_id, user_principal, tx, BalanceRequirement::None).await?;
+
+    let success_msg = format!("Token approval successful: {:?}", tx_hash);
+    ic_cdk::println!("🎉 {}", success_msg);
+    Ok(success_msg)
+}
+
+/// Sign and send a transaction from the principal's address on a chain
+///
+/// Gas and fees come from the fee policy. The nonce is reserved before
+/// sending, committed once the RPC accepted the transaction and rolled back if
+/// it did not. Permission checks are the caller's responsibility.
+pub(crate) async fn send_transaction(
+    chain_id: u64,
+    user_principal: Principal,
+    tx: TransactionRequest,
+    requirement: BalanceRequirement
+) -> Result<TxHash, String> {
     let signer = create_icp_signer_for_principal(user_principal).await?;
     let user_addr = signer.address();
-    
-    // Setup provider
+
     let wallet = EthereumWallet::from(signer);
-    let rpc_service = get_rpc_service_sepolia();
+    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
     let config = IcpConfig::new(rpc_service);
     let provider = ProviderBuilder::new()
         .with_gas_estimation()
         .wallet(wallet)
         .on_icp(config);
-    
-    // Get nonce
-    let maybe_nonce = ERC20_NONCE_CACHE.with_borrow(|maybe_nonce| {
-        maybe_nonce.map(|nonce| nonce + 1)
-    });
-
-    let nonce = if let Some(nonce) = maybe_nonce {
-        ic_cdk::println!("🔧 Using cached nonce: {}", nonce);
-        nonce
-    } else {
-        let fresh_nonce = provider.get_transaction_count(user_addr).await
-            .map_err(|e| format!("Failed to get nonce: {}", e))?;
-        ic_cdk::println!("🔧 Got fresh nonce: {}", fresh_nonce);
-        fresh_nonce
-    };
-    
-    let contract = ERC20Token::new(token_addr, provider.clone());
-    
-    // Send approval transaction
-    match contract
-        .approve(spender_addr, amount)
-        .nonce(nonce)
-        .chain_id(11155111) // Sepolia chain ID
-        .from(user_addr)
-        .send()
-        .await
-    {
-        Ok(builder) => {
-            let tx_hash = *builder.tx_hash();
-            ic_cdk::println!("✅ Approval transaction sent: {:?}", tx_hash);
-            
-            let tx_response = provider.get_transaction_by_hash(tx_hash).await
-                .map_err(|e| format!("Failed to get transaction: {}", e))?;
 
-            match tx_response {
-                Some(tx) => {
-                    // Update nonce cache
-                    ERC20_NONCE_CACHE.with_borrow_mut(|nonce| {
-                        *nonce = Some(tx.nonce);
-                    });
-                    
-                    let success_msg = format!("Token approval successful: {:?}", tx_hash);
-                    ic_cdk::println!("🎉 {}", success_msg);
-                    Ok(success_msg)
-                }
-                None => {
-                    let error_msg = "Approval transaction not found after sending".to_string();
-                    ic_cdk::println!("❌ {}", error_msg);
-                    Err(error_msg)
-                }
+    match requirement {
+        BalanceRequirement::None => {}
+        BalanceRequirement::Native(amount) => {
+            let balance = provider.get_balance(user_addr).await
+                .map_err(|e| format!("Failed to get native balance: {}", e))?;
+            if balance < amount {
+                let error_msg = format!("Insufficient native balance. Have: {} wei, Need: {} wei", balance, amount);
+                ic_cdk::println!("❌ {}", error_msg);
+                return Err(error_msg);
             }
         }
-        Err(e) => {
-            let error_msg = format!("Approval transaction failed: {:?}", e);
-            ic_cdk::println!("❌ {}", error_msg);
-            Err(error_msg)
+        BalanceRequirement::Token(token_address, amount) => {
+            let contract = ERC20Token::new(token_address, &provider);
+            let balance = contract.balanceOf(user_addr).call().await
+                .map_err(|e| format!("Failed to get token balance: {}", e))?._0;
+            if balance < amount {
+                let error_msg = format!("Insufficient token balance. Have: {}, Need: {}", balance, amount);
+                ic_cdk::println!("❌ {}", error_msg);
+                return Err(error_msg);
+            }
         }
     }
-}
 
-/// Transfer tokens to a specified address
-pub async fn transfer_token(
-    token_address: String,
-    to_address: String,
-    amount: U256,
-    user_principal: Principal
-) -> Result<String, String> {
-    ic_cdk::println!("💸 Transferring {} wei of token {} to {}", 
-                    amount, token_address, to_address);
-    
-    let token_addr = token_address.parse::<Address>()
-        .map_err(|_| "Invalid token address".to_string())?;
-    
-    let to_addr = to_address.parse::<Address>()
-        .map_err(|_| "Invalid recipient address".to_string())?;
-    
-    // Create signer for user
-    let signer = create_icp_signer_for_principal(user_principal).await?;
-    let user_addr = signer.address();
-    
-    // Setup provider
-    let wallet = EthereumWallet::from(signer);
-    let rpc_service = get_rpc_service_sepolia();
-    let config = IcpConfig::new(rpc_service);
-    let provider = ProviderBuilder::new()
-        .with_gas_estimation()
-        .wallet(wallet)
-        .on_icp(config);
-    
-    let contract = ERC20Token::new(token_addr, provider.clone());
-    
-    // Check balance
-    let balance = contract.balanceOf(user_addr).call().await
-        .map_err(|e| format!("Failed to get balance: {}", e))?;
-    
-    if balance._0 < amount {
-        let error_msg = format!("Insufficient token balance. Have: {}, Need: {}", balance._0, amount);
-        ic_cdk::println!("❌ {}", error_msg);
-        return Err(error_msg);
-    }
-    
-    // Get nonce
-    let maybe_nonce = ERC20_NONCE_CACHE.with_borrow(|maybe_nonce| {
-        maybe_nonce.map(|nonce| nonce + 1)
-    });
-
-    let nonce = if let Some(nonce) = maybe_nonce {
-        ic_cdk::println!("🔧 Using cached nonce: {}", nonce);
-        nonce
-    } else {
-        let fresh_nonce = provider.get_transaction_count(user_addr).await
-            .map_err(|e| format!("Failed to get nonce: {}", e))?;
-        ic_cdk::println!("🔧 Got fresh nonce: {}", fresh_nonce);
-        fresh_nonce
-    };
-    
-    // Send transfer transaction
-    match contract
-        .transfer(to_addr, amount)
-        .nonce(nonce)
-        .chain_id(11155111) // Sepolia chain ID
-        .from(user_addr)
-        .send()
-        .await
-    {
+    let tx = tx
+        .with_from(user_addr)
+        .with_chain_id(chain_id);
+    let tx = fee_policy::apply_to_request(tx, &provider, chain_id).await?;
+
+    let nonce = reserve_nonce(user_addr, &provider, chain_id).await?;
+    let tx = tx.with_nonce(nonce);
+
+    match provider.send_transaction(tx).await {
         Ok(builder) => {
             let tx_hash = *builder.tx_hash();
-            ic_cdk::println!("✅ Transfer transaction sent: {:?}", tx_hash);
-            
+            ic_cdk::println!("✅ Transaction sent: {:?}", tx_hash);
+
+            // Transaction sent - commit nonce
+            commit_nonce(user_addr, chain_id, nonce);
+
             let tx_response = provider.get_transaction_by_hash(tx_hash).await
                 .map_err(|e| format!("Failed to get transaction: {}", e))?;
 
             match tx_response {
                 Some(tx) => {
-                    // Update nonce cache
-                    ERC20_NONCE_CACHE.with_borrow_mut(|nonce| {
-                        *nonce = Some(tx.nonce);
-                    });
-                    
-                    let success_msg = format!("Token transfer successful: {:?}", tx_hash);
-                    ic_cdk::println!("🎉 {}", success_msg);
-                    Ok(success_msg)
+                    tx_tracker::record_sent(user_principal, chain_id, &tx);
+                    Ok(tx_hash)
                 }
                 None => {
-                    let error_msg = "Transfer transaction not found after sending".to_string();
+                    let error_msg = "Transaction not found after sending".to_string();
                     ic_cdk::println!("❌ {}", error_msg);
                     Err(error_msg)
                 }
             }
         }
         Err(e) => {
-            let error_msg = format!("Transfer transaction failed: {:?}", e);
+            // Transaction failed to send - rollback nonce
+            rollback_nonce(user_addr, chain_id, nonce);
+
+            let error_msg = format!("Transaction failed: {:?}", e);
             ic_cdk::println!("❌ {}", error_msg);
             Err(error_msg)
         }
     }
 }
 
-/// Convert human-readable amount to Wei format based on token decimals
-pub async fn parse_token_amount(token_address: String, amount_human: String) -> Result<U256, String> {
-    ic_cdk::println!("🧮 Converting {} of token {} to wei", amount_human, token_address);
-    
-    let amount_f64: f64 = amount_human.parse()
-        .map_err(|_| "Invalid amount format".to_string())?;
-    
-    if amount_f64 < 0.0 {
-        return Err("Amount cannot be negative".to_string());
+/// Total amount of `token` transferred to `recipient` by a mined transaction
+///
+/// Sums the ERC-20 `Transfer` logs of the receipt, so it is the amount that
+/// actually arrived rather than the amount requested.
+pub(crate) fn received_amount(receipt: &TransactionReceipt, token: Address, recipient: Address) -> U256 {
+    receipt.inner.logs().iter()
+        .filter(|log| log.address() == token)
+        .filter_map(|log| log.log_decode::<ERC20Token::Transfer>().ok())
+        .filter(|transfer| transfer.inner.data.to == recipient)
+        .fold(U256::ZERO, |total, transfer| total.saturating_add(transfer.inner.data.value))
+}
+
+/// Wait for a sent approval to be mined
+///
+/// A reverted approval is an error. One that is not mined yet, or whose
+/// receipt cannot be read, is reported as pending.
+pub(crate) async fn settle_approval<T, P>(provider: &P, tx_hash: TxHash) -> Result<AllowanceStatus, String>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    match tx_tracker::wait_for_receipt(provider, tx_hash).await {
+        Ok(Some(receipt)) if !receipt.status() => {
+            let error_msg = format!("Approve transaction {:?} reverted", tx_hash);
+            ic_cdk::println!("❌ {}", error_msg);
+            Err(error_msg)
+        }
+        Ok(Some(_)) => Ok(AllowanceStatus::Approved(tx_hash)),
+        Ok(None) => Ok(AllowanceStatus::Pending(tx_hash)),
+        Err(e) => {
+            ic_cdk::println!("⚠️ Approve transaction {:?} receipt unavailable: {}", tx_hash, e);
+            Ok(AllowanceStatus::Pending(tx_hash))
+        }
     }
-    
-    // Get token decimals
-    let token_info = get_token_info(token_address).await?;
-    let decimals = token_info.decimals;
-    
-    // Convert to Wei (multiply by 10^decimals)
-    let multiplier = 10_f64.powi(decimals as i32);
-    let amount_wei = (amount_f64 * multiplier) as u128;
-    let amount_u256 = U256::from(amount_wei);
-    
-    ic_cdk::println!("✅ Converted {} {} to {} wei (decimals: {})", 
-                    amount_human, token_info.symbol, amount_u256, decimals);
-    
-    Ok(amount_u256)
 }
 
-/// Ensure sufficient allowance for a spender
-pub async fn ensure_token_allowance(
-    token_address: String,
-    spender_address: String, 
-    required_amount: U256,
-    user_principal: Principal
-) -> Result<(), String> {
-    ic_cdk::println!("🔍 Ensuring token allowance for {} -> {}", token_address, spender_address);
-    
-    // Get current allowance
-    let current_allowance_str = get_token_allowance(
-        token_address.clone(),
-        None, // Use caller's address
-        spender_address.clone()
-    ).await?;
-    
-    let current_allowance = U256::from_str_radix(&current_allowance_str.replace("0x", ""), 16)
-        .map_err(|_| "Failed to parse current allowance".to_string())?;
-    
-    ic_cdk::println!("📊 Current allowance: {} wei, Required: {} wei", 
-                    current_allowance, required_amount);
-    
-    if current_allowance < required_amount {
-        ic_cdk::println!("⚠️ Insufficient allowance, approving more tokens...");
-        
-        // Approve the required amount
-        approve_token(
-            token_address,
-            spender_address,
-            required_amount,
-            user_principal
-        ).await?;
-        
-        ic_cdk::println!("✅ Token allowance approved successfully");
-    } else {
-        ic_cdk::println!("✅ Sufficient allowance already exists");
+fn check_amount(amount: U256) -> Result<(), String> {
+    if amount.is_zero() {
+        return Err("Amount must be positive".to_string());
     }
-    
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,467 @@
+use alloy::{
+    contract::{CallBuilder, CallDecoder},
+    eips::BlockNumberOrTag,
+    network::{Ethereum, TransactionBuilder},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::request::TransactionRequest,
+    transports::{icp::IcpConfig, Transport},
+};
+use crate::types::{ChainConfig, FeePolicyConfig, FeeQuote, StorableFeePolicyConfig};
+use crate::{StorableString, FEE_POLICY_CONFIG_MAP};
+use crate::services::chain_registry;
+use crate::services::rpc_service::get_rpc_service_by_chain_id;
+
+// =============================================================================
+// Fee Policy
+// =============================================================================
+//
+// Gas limit and fee fields for every transaction the canister signs.
+//
+// - Gas limit: `eth_estimateGas` times `gas_limit_multiplier`. On Arbitrum the
+//   estimate already includes the L1 calldata component.
+// - EIP-1559 chains: the priority fee is the median of the configured reward
+//   percentile over the last `fee_history_blocks` blocks of `eth_feeHistory`;
+//   `maxFeePerGas` is the next block's base fee times `base_fee_multiplier`
+//   plus the priority fee.
+// - Legacy chains: `eth_gasPrice`.
+//
+// The chain's hard caps decide whether to send at all: a fee that has to be
+// paid right now (base fee + priority fee, or the gas price) above
+// `max_fee_per_gas_gwei` refuses the transaction, so callers can defer until
+// fees come down. Headroom above the cap is trimmed, and the priority fee is
+// clamped to `max_priority_fee_per_gas_gwei`. The OP Stack L1 data fee is
+// charged outside gas and is not capped here.
+//
+
+/// Prefix of the error returned when a chain's fee cap blocks a transaction
+pub const FEE_CAP_EXCEEDED: &str = "Fee cap exceeded";
+
+/// Fee increase of a replacement transaction; nodes require at least 10%
+const REPLACEMENT_BUMP_PERCENT: u128 = 15;
+
+const WEI_PER_GWEI: f64 = 1e9;
+
+/// Key of the single entry in `FEE_POLICY_CONFIG_MAP`
+const FEE_POLICY_CONFIG_KEY: &str = "config";
+
+/// Fee fields of a transaction, in wei
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Fees {
+    Eip1559 {
+        base_fee: u128,
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+    },
+    Legacy {
+        gas_price: u128,
+    },
+}
+
+// =============================================================================
+// Configuration
+// =============================================================================
+
+/// Get the fee policy settings
+pub fn get_fee_policy_config() -> FeePolicyConfig {
+    FEE_POLICY_CONFIG_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(FEE_POLICY_CONFIG_KEY.to_string()))
+            .map(|config| config.0)
+            .unwrap_or_default()
+    })
+}
+
+/// Replace the fee policy settings
+pub fn set_fee_policy_config(config: FeePolicyConfig) -> Result<FeePolicyConfig, String> {
+    if !config.gas_limit_multiplier.is_finite() || config.gas_limit_multiplier < 1.0 {
+        return Err("gas_limit_multiplier must be at least 1.0".to_string());
+    }
+    if config.fee_history_blocks == 0 || config.fee_history_blocks > 1024 {
+        return Err("fee_history_blocks must be between 1 and 1024".to_string());
+    }
+    if !(0.0..=100.0).contains(&config.priority_fee_percentile) {
+        return Err("priority_fee_percentile must be between 0 and 100".to_string());
+    }
+    if !config.base_fee_multiplier.is_finite() || config.base_fee_multiplier < 1.0 {
+        return Err("base_fee_multiplier must be at least 1.0".to_string());
+    }
+
+    FEE_POLICY_CONFIG_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(FEE_POLICY_CONFIG_KEY.to_string()),
+            StorableFeePolicyConfig(config.clone())
+        );
+    });
+    ic_cdk::println!("✅ Fee policy updated: gas x{}, {} blocks at p{}, base fee x{}",
+        config.gas_limit_multiplier, config.fee_history_blocks,
+        config.priority_fee_percentile, config.base_fee_multiplier);
+    Ok(config)
+}
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Estimate a contract call and set its gas limit and fees
+///
+/// The call must already have its `from` address. Fails with
+/// `FEE_CAP_EXCEEDED` when the chain's cap blocks the transaction.
+pub async fn apply_to_call<T, P, D>(
+    call: CallBuilder<T, P, D>,
+    chain_id: u64
+) -> Result<CallBuilder<T, P, D>, String>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum>,
+    D: CallDecoder,
+{
+    let estimate = call.estimate_gas().await
+        .map_err(|e| format!("Gas estimation failed: {}", e))?;
+    let fees = quote_fees(&call.provider, chain_id).await?;
+    let gas_limit = gas_limit(estimate, get_fee_policy_config().gas_limit_multiplier);
+    log_fees(chain_id, estimate, gas_limit, &fees);
+
+    Ok(call_with_fees(call.gas(gas_limit), fees))
+}
+
+/// Set a fixed gas limit and the fees of a contract call without estimating it
+///
+/// For calls whose estimate reverts until an earlier transaction is mined,
+/// such as a supply sent while its approval is still pending. Fails with
+/// `FEE_CAP_EXCEEDED` when the chain's cap blocks the transaction.
+pub async fn apply_to_call_with_gas_limit<T, P, D>(
+    call: CallBuilder<T, P, D>,
+    chain_id: u64,
+    gas_limit: u128
+) -> Result<CallBuilder<T, P, D>, String>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum>,
+    D: CallDecoder,
+{
+    let fees = quote_fees(&call.provider, chain_id).await?;
+    log_fees(chain_id, gas_limit, gas_limit, &fees);
+
+    Ok(call_with_fees(call.gas(gas_limit), fees))
+}
+
+/// Set the fee fields of a contract call
+fn call_with_fees<T, P, D>(call: CallBuilder<T, P, D>, fees: Fees) -> CallBuilder<T, P, D>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum>,
+    D: CallDecoder,
+{
+    match fees {
+        Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas, .. } => call
+            .max_fee_per_gas(max_fee_per_gas)
+            .max_priority_fee_per_gas(max_priority_fee_per_gas),
+        Fees::Legacy { gas_price } => call.gas_price(gas_price),
+    }
+}
+
+/// Estimate a transaction request and set its gas limit and fees
+///
+/// The request must already have its `from` address. Fails with
+/// `FEE_CAP_EXCEEDED` when the chain's cap blocks the transaction.
+pub async fn apply_to_request<T, P>(
+    tx: TransactionRequest,
+    provider: &P,
+    chain_id: u64
+) -> Result<TransactionRequest, String>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum>,
+{
+    let estimate = provider.estimate_gas(&tx).await
+        .map_err(|e| format!("Gas estimation failed: {}", e))?;
+    let fees = quote_fees(provider, chain_id).await?;
+    let gas_limit = gas_limit(estimate, get_fee_policy_config().gas_limit_multiplier);
+    log_fees(chain_id, estimate, gas_limit, &fees);
+
+    Ok(with_fees(tx.with_gas_limit(gas_limit), fees))
+}
+
+/// Set the fee fields of a transaction request
+pub(crate) fn with_fees(tx: TransactionRequest, fees: Fees) -> TransactionRequest {
+    match fees {
+        Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas, .. } => tx
+            .with_max_fee_per_gas(max_fee_per_gas)
+            .with_max_priority_fee_per_gas(max_priority_fee_per_gas),
+        Fees::Legacy { gas_price } => tx.with_gas_price(gas_price),
+    }
+}
+
+/// Fees the canister would sign with on a chain right now
+pub async fn get_fee_quote(chain_id: u64) -> Result<FeeQuote, String> {
+    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
+    let config = IcpConfig::new(rpc_service);
+    let provider = ProviderBuilder::new().on_icp(config);
+
+    let quote = match quote_fees(&provider, chain_id).await? {
+        Fees::Eip1559 { base_fee, max_fee_per_gas, max_priority_fee_per_gas } => FeeQuote {
+            chain_id,
+            eip1559: true,
+            base_fee_gwei: Some(to_gwei(base_fee)),
+            max_fee_per_gas_gwei: to_gwei(max_fee_per_gas),
+            max_priority_fee_per_gas_gwei: Some(to_gwei(max_priority_fee_per_gas)),
+        },
+        Fees::Legacy { gas_price } => FeeQuote {
+            chain_id,
+            eip1559: false,
+            base_fee_gwei: None,
+            max_fee_per_gas_gwei: to_gwei(gas_price),
+            max_priority_fee_per_gas_gwei: None,
+        },
+    };
+
+    Ok(quote)
+}
+
+/// Check that a chain's fees are within its caps, without signing anything
+///
+/// Lets the scheduler defer work while fees are spiking.
+pub async fn check_fee_caps(chain_id: u64) -> Result<(), String> {
+    get_fee_quote(chain_id).await.map(|_| ())
+}
+
+// =============================================================================
+// Fee Computation
+// =============================================================================
+
+/// Fees the policy would sign with on a chain right now
+pub(crate) async fn quote_fees<T, P>(provider: &P, chain_id: u64) -> Result<Fees, String>
+where
+    T: Transport + Clone,
+    P: Provider<T, Ethereum>,
+{
+    let chain = chain_registry::get_enabled_chain(chain_id)
+        .ok_or_else(|| format!("Unsupported chain_id: {}", chain_id))?;
+    let config = get_fee_policy_config();
+
+    if chain.eip1559 {
+        let history = provider
+            .get_fee_history(config.fee_history_blocks, BlockNumberOrTag::Latest, &[config.priority_fee_percentile])
+            .await
+            .map_err(|e| format!("Failed to get fee history: {}", e))?;
+
+        // The last entry is the base fee of the next block
+        let base_fee = *history.base_fee_per_gas.last()
+            .ok_or_else(|| "Fee history returned no base fee".to_string())?;
+        let rewards: Vec<u128> = history.reward
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|block| block.first().copied())
+            .collect();
+
+        eip1559_fees(base_fee, &rewards, &config, &chain)
+    } else {
+        let gas_price = provider.get_gas_price().await
+            .map_err(|e| format!("Failed to get gas price: {}", e))?;
+
+        legacy_fees(gas_price, &chain)
+    }
+}
+
+fn eip1559_fees(base_fee: u128, rewards: &[u128], config: &FeePolicyConfig, chain: &ChainConfig) -> Result<Fees, String> {
+    let mut max_priority_fee_per_gas = median(rewards);
+    if let Some(cap) = chain.max_priority_fee_per_gas_gwei.map(from_gwei) {
+        max_priority_fee_per_gas = max_priority_fee_per_gas.min(cap);
+    }
+
+    let mut max_fee_per_gas = (base_fee as f64 * config.base_fee_multiplier) as u128 + max_priority_fee_per_gas;
+    if let Some(cap) = chain.max_fee_per_gas_gwei.map(from_gwei) {
+        let current_fee = base_fee + max_priority_fee_per_gas;
+        if current_fee > cap {
+            return Err(format!("{} on {}: base fee {:.4} gwei + priority fee {:.4} gwei is above {:.4} gwei",
+                FEE_CAP_EXCEEDED, chain.name, to_gwei(base_fee), to_gwei(max_priority_fee_per_gas), to_gwei(cap)));
+        }
+        max_fee_per_gas = max_fee_per_gas.min(cap);
+    }
+
+    Ok(Fees::Eip1559 { base_fee, max_fee_per_gas, max_priority_fee_per_gas })
+}
+
+fn legacy_fees(gas_price: u128, chain: &ChainConfig) -> Result<Fees, String> {
+    if let Some(cap) = chain.max_fee_per_gas_gwei.map(from_gwei) {
+        if gas_price > cap {
+            return Err(format!("{} on {}: gas price {:.4} gwei is above {:.4} gwei",
+                FEE_CAP_EXCEEDED, chain.name, to_gwei(gas_price), to_gwei(cap)));
+        }
+    }
+
+    Ok(Fees::Legacy { gas_price })
+}
+
+/// Fees of a replacement for a pending transaction at the same nonce
+///
+/// Both fee fields are raised by `REPLACEMENT_BUMP_PERCENT` over the previous
+/// transaction, or to the current quote when that is higher. A replacement
+/// cannot be trimmed below the bump, so the chain's caps refuse it instead.
+pub(crate) fn replacement_fees(previous: Fees, current: Fees, chain: &ChainConfig) -> Result<Fees, String> {
+    let fees = match current {
+        Fees::Eip1559 { base_fee, max_fee_per_gas, max_priority_fee_per_gas } => {
+            // A legacy gas price counts as both fee fields
+            let (previous_max_fee, previous_priority_fee) = match previous {
+                Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas, .. } => (max_fee_per_gas, max_priority_fee_per_gas),
+                Fees::Legacy { gas_price } => (gas_price, gas_price),
+            };
+            let priority_fee = bump(previous_priority_fee).max(max_priority_fee_per_gas);
+            Fees::Eip1559 {
+                base_fee,
+                max_fee_per_gas: bump(previous_max_fee).max(max_fee_per_gas).max(base_fee + priority_fee),
+                max_priority_fee_per_gas: priority_fee,
+            }
+        }
+        Fees::Legacy { gas_price } => {
+            let previous_gas_price = match previous {
+                Fees::Eip1559 { max_fee_per_gas, .. } => max_fee_per_gas,
+                Fees::Legacy { gas_price } => gas_price,
+            };
+            Fees::Legacy { gas_price: bump(previous_gas_price).max(gas_price) }
+        }
+    };
+
+    let (max_fee, priority_fee) = match fees {
+        Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas, .. } => (max_fee_per_gas, Some(max_priority_fee_per_gas)),
+        Fees::Legacy { gas_price } => (gas_price, None),
+    };
+    if let Some(cap) = chain.max_fee_per_gas_gwei.map(from_gwei) {
+        if max_fee > cap {
+            return Err(format!("{} on {}: replacement max fee {:.4} gwei is above {:.4} gwei",
+                FEE_CAP_EXCEEDED, chain.name, to_gwei(max_fee), to_gwei(cap)));
+        }
+    }
+    if let (Some(cap), Some(priority_fee)) = (chain.max_priority_fee_per_gas_gwei.map(from_gwei), priority_fee) {
+        if priority_fee > cap {
+            return Err(format!("{} on {}: replacement priority fee {:.4} gwei is above {:.4} gwei",
+                FEE_CAP_EXCEEDED, chain.name, to_gwei(priority_fee), to_gwei(cap)));
+        }
+    }
+
+    Ok(fees)
+}
+
+fn bump(fee: u128) -> u128 {
+    (fee * (100 + REPLACEMENT_BUMP_PERCENT)).div_ceil(100)
+}
+
+/// Gas estimate scaled by the policy multiplier, rounded up
+pub(crate) fn gas_limit(estimate: u128, multiplier: f64) -> u128 {
+    (estimate as f64 * multiplier).ceil() as u128
+}
+
+fn median(values: &[u128]) -> u128 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted.get(sorted.len() / 2).copied().unwrap_or(0)
+}
+
+fn log_fees(chain_id: u64, estimate: u128, gas_limit: u128, fees: &Fees) {
+    match fees {
+        Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas, .. } => {
+            ic_cdk::println!("⛽ Chain {}: gas limit {} (estimate {}), max fee {:.4} gwei, priority fee {:.4} gwei",
+                chain_id, gas_limit, estimate, to_gwei(*max_fee_per_gas), to_gwei(*max_priority_fee_per_gas));
+        }
+        Fees::Legacy { gas_price } => {
+            ic_cdk::println!("⛽ Chain {}: gas limit {} (estimate {}), gas price {:.4} gwei",
+                chain_id, gas_limit, estimate, to_gwei(*gas_price));
+        }
+    }
+}
+
+fn to_gwei(wei: u128) -> f64 {
+    wei as f64 / WEI_PER_GWEI
+}
+
+fn from_gwei(gwei: f64) -> u128 {
+    (gwei * WEI_PER_GWEI) as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GWEI: u128 = 1_000_000_000;
+
+    fn chain(max_fee_gwei: Option<f64>, max_priority_gwei: Option<f64>) -> ChainConfig {
+        ChainConfig {
+            chain_id: 1,
+            name: "Test".to_string(),
+            native_symbol: "ETH".to_string(),
+            native_decimals: 18,
+            rpc_endpoints: vec!["https://rpc.test".to_string()],
+            block_explorer: None,
+            eip1559: true,
+            max_fee_per_gas_gwei: max_fee_gwei,
+            max_priority_fee_per_gas_gwei: max_priority_gwei,
+            protocols: Vec::new(),
+            usdc_address: None,
+            price_feeds: None,
+            cancel_gas_limit: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn eip1559_fees_use_median_reward_and_base_fee_headroom() {
+        let config = FeePolicyConfig::default();
+        let fees = eip1559_fees(10 * GWEI, &[3 * GWEI, GWEI, 2 * GWEI], &config, &chain(None, None)).unwrap();
+
+        assert_eq!(fees, Fees::Eip1559 {
+            base_fee: 10 * GWEI,
+            max_fee_per_gas: 22 * GWEI,
+            max_priority_fee_per_gas: 2 * GWEI,
+        });
+    }
+
+    #[test]
+    fn caps_trim_headroom_and_block_when_current_fee_is_above() {
+        let config = FeePolicyConfig::default();
+
+        let trimmed = eip1559_fees(10 * GWEI, &[5 * GWEI], &config, &chain(Some(15.0), Some(1.0))).unwrap();
+        assert_eq!(trimmed, Fees::Eip1559 {
+            base_fee: 10 * GWEI,
+            max_fee_per_gas: 15 * GWEI,
+            max_priority_fee_per_gas: GWEI,
+        });
+
+        let blocked = eip1559_fees(20 * GWEI, &[GWEI], &config, &chain(Some(15.0), None)).unwrap_err();
+        assert!(blocked.starts_with(FEE_CAP_EXCEEDED));
+
+        assert!(legacy_fees(20 * GWEI, &chain(Some(15.0), None)).is_err());
+        assert!(legacy_fees(10 * GWEI, &chain(Some(15.0), None)).is_ok());
+    }
+
+    #[test]
+    fn replacements_bump_previous_fees_or_follow_the_current_quote() {
+        let previous = Fees::Eip1559 { base_fee: 0, max_fee_per_gas: 20 * GWEI, max_priority_fee_per_gas: 2 * GWEI };
+
+        let calm = Fees::Eip1559 { base_fee: 10 * GWEI, max_fee_per_gas: 21 * GWEI, max_priority_fee_per_gas: GWEI };
+        assert_eq!(replacement_fees(previous, calm, &chain(None, None)).unwrap(), Fees::Eip1559 {
+            base_fee: 10 * GWEI,
+            max_fee_per_gas: 23 * GWEI,
+            max_priority_fee_per_gas: 2_300_000_000,
+        });
+
+        let spiking = Fees::Eip1559 { base_fee: 40 * GWEI, max_fee_per_gas: 83 * GWEI, max_priority_fee_per_gas: 3 * GWEI };
+        assert_eq!(replacement_fees(previous, spiking, &chain(None, None)).unwrap(), Fees::Eip1559 {
+            base_fee: 40 * GWEI,
+            max_fee_per_gas: 83 * GWEI,
+            max_priority_fee_per_gas: 3 * GWEI,
+        });
+
+        let blocked = replacement_fees(previous, calm, &chain(Some(22.0), None)).unwrap_err();
+        assert!(blocked.starts_with(FEE_CAP_EXCEEDED));
+
+        let legacy = replacement_fees(Fees::Legacy { gas_price: 10 * GWEI }, Fees::Legacy { gas_price: 9 * GWEI }, &chain(None, None));
+        assert_eq!(legacy.unwrap(), Fees::Legacy { gas_price: 11_500_000_000 });
+    }
+
+    #[test]
+    fn gas_limit_rounds_up() {
+        assert_eq!(gas_limit(100_001, 1.2), 120_002);
+        assert_eq!(gas_limit(21_000, 1.0), 21_000);
+        assert_eq!(median(&[]), 0);
+    }
+}
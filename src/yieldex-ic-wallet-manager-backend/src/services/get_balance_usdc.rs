@@ -1,13 +1,14 @@
 use alloy::{
     network::TxSigner,
-    primitives::{address, Address},
+    primitives::Address,
     providers::ProviderBuilder,
     sol,
     transports::icp::IcpConfig,
 };
 
 use crate::create_icp_signer;
-use crate::services::rpc_service::{get_rpc_service_by_chain_id, SEPOLIA_CHAIN_ID, ARBITRUM_CHAIN_ID, BASE_CHAIN_ID, OPTIMISM_CHAIN_ID};
+use crate::services::chain_registry;
+use crate::services::rpc_service::{get_rpc_service_by_chain_id, SEPOLIA_CHAIN_ID};
 
 // Codegen from ABI file to interact with the contract.
 sol!(
@@ -17,17 +18,6 @@ sol!(
     "src/abi/USDC.json"
 );
 
-/// Get USDC contract address based on chain_id
-fn get_usdc_address(chain_id: u64) -> Result<Address, String> {
-    match chain_id {
-        SEPOLIA_CHAIN_ID => Ok(address!("1c7d4b196cb0c7b01d743fbc6116a902379c7238")), // USDC on Sepolia (bridged)
-        ARBITRUM_CHAIN_ID => Ok(address!("af88d065e77c8cc2239327c5edb3a432268e5831")), // Native USDC on Arbitrum One
-        BASE_CHAIN_ID => Ok(address!("833589fcd6edb6e08f4c7c32d4f71b54bda02913")), // Native USDC on Base
-        OPTIMISM_CHAIN_ID => Ok(address!("7f5c764cbc14f9669b88837ca1490cca17c31607")), // USDC on Optimism
-        _ => Err(format!("USDC not configured for chain_id: {}", chain_id))
-    }
-}
-
 /// Request the USDC balance of an account for a specific chain.
 #[ic_cdk::update]
 pub async fn get_balance_usdc(address: Option<String>, chain_id: u64) -> Result<String, String> {
@@ -43,7 +33,7 @@ pub async fn get_balance_usdc(address: Option<String>, chain_id: u64) -> Result<
     let config = IcpConfig::new(rpc_service);
     let provider = ProviderBuilder::new().on_icp(config);
 
-    let usdc_address = get_usdc_address(chain_id)?;
+    let usdc_address = chain_registry::get_usdc_address(chain_id)?;
     let contract = USDC::new(usdc_address, provider);
 
     let result = contract.balanceOf(address).call().await;
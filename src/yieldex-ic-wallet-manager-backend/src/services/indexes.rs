@@ -0,0 +1,634 @@
+use candid::Principal;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::cell::RefCell;
+use std::thread::LocalKey;
+
+use crate::types::{
+    UserPosition, Permissions, RebalanceExecution, YieldTenure, SentTransaction, TxStatus, PendingRecommendation,
+    StorableUserPosition, StorablePermissions, StorableRebalanceExecution, StorableYieldTenure,
+    StorableSentTransaction, StorablePendingRecommendation,
+};
+use crate::{
+    USER_POSITIONS_MAP, PERMISSIONS_MAP, REBALANCE_HISTORY_MAP, YIELD_TENURES_MAP, SENT_TRANSACTIONS_MAP,
+    PENDING_RECOMMENDATIONS_MAP,
+    POSITIONS_BY_USER_INDEX, POSITIONS_BY_MARKET_INDEX, PERMISSIONS_BY_OWNER_INDEX, REBALANCE_HISTORY_BY_USER_INDEX,
+    TENURES_BY_USER_INDEX, TENURES_BY_MARKET_INDEX, SENT_TX_BY_OWNER_INDEX, PENDING_TX_INDEX,
+    SETTLED_TX_BY_TIME_INDEX, PROPOSALS_BY_USER_INDEX, PROPOSALS_BY_POSITION_INDEX, StorableString, Memory,
+};
+
+// =============================================================================
+// Secondary Indexes
+// =============================================================================
+//
+// Stable key-only maps that let per-user lookups range-scan a key prefix
+// instead of iterating a whole primary map:
+// - positions by user:    "{principal}|{position_id}"
+// - positions by market:  "{market_key}|{position_id}"
+// - permissions by owner: "{principal}|{permissions_id}"
+// - history by user:      "{principal}|{timestamp:020}|{execution_id}"
+// - tenures by user:      "{principal}|{tenure_id}"
+// - tenures by market:    "{market_key}|{tenure_id}"
+// - transactions by owner: "{owner_digest}|{sent_at:020}|{tx_hash}"
+// - pending transactions:  "{tx_hash}"
+// - settled transactions:  "{updated_at:020}|{tx_hash}"
+// - proposals by user:     "{principal}|{proposal_id}"
+// - proposals by position: "{position_id}|{proposal_id}"
+//
+// Keys must fit StorableString's 128-byte bound. Where a key's parts could
+// exceed it, they are replaced by their fixed-width `compact_key` digest: the
+// market key is the digest of "{principal}:{PROTOCOL}:{ASSET}:{chain_id}", and
+// the owner digest is the digest of the principal.
+//
+// All writes to USER_POSITIONS_MAP, PERMISSIONS_MAP, REBALANCE_HISTORY_MAP,
+// YIELD_TENURES_MAP, SENT_TRANSACTIONS_MAP and PENDING_RECOMMENDATIONS_MAP go
+// through this module so the indexes stay consistent with them.
+//
+
+type IndexMap = StableBTreeMap<StorableString, (), Memory>;
+
+// =============================================================================
+// Positions
+// =============================================================================
+
+/// Insert or update a position and its index entries
+pub fn save_position(position: &UserPosition) {
+    let previous = USER_POSITIONS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(position.position_id.clone()),
+            StorableUserPosition(position.clone())
+        )
+    });
+
+    if let Some(previous) = previous {
+        unindex_position(&previous.0);
+    }
+    index_position(position);
+}
+
+/// Remove a position and its index entries
+pub fn remove_position(position_id: &str) -> Option<UserPosition> {
+    let removed = USER_POSITIONS_MAP.with(|map| {
+        map.borrow_mut().remove(&StorableString(position_id.to_string()))
+    })?;
+
+    unindex_position(&removed.0);
+    Some(removed.0)
+}
+
+/// All positions of a user
+pub fn get_user_positions(user: Principal) -> Vec<UserPosition> {
+    let position_ids = POSITIONS_BY_USER_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", user))
+    });
+
+    load_positions(position_ids)
+        .into_iter()
+        .filter(|p| p.user_principal == user)
+        .collect()
+}
+
+/// Position of a user in a protocol market, if any
+pub fn find_market_position(user: Principal, protocol: &str, asset: &str, chain_id: u64) -> Option<UserPosition> {
+    let position_ids = POSITIONS_BY_MARKET_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", market_key(user, protocol, asset, chain_id)))
+    });
+
+    load_positions(position_ids)
+        .into_iter()
+        .find(|p| {
+            p.user_principal == user &&
+            p.protocol.eq_ignore_ascii_case(protocol) &&
+            p.asset.eq_ignore_ascii_case(asset) &&
+            p.chain_id == chain_id
+        })
+}
+
+fn index_position(position: &UserPosition) {
+    POSITIONS_BY_USER_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(user_position_key(position)), ());
+    });
+    POSITIONS_BY_MARKET_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(market_position_key(position)), ());
+    });
+}
+
+fn unindex_position(position: &UserPosition) {
+    POSITIONS_BY_USER_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(user_position_key(position)));
+    });
+    POSITIONS_BY_MARKET_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(market_position_key(position)));
+    });
+}
+
+fn load_positions(position_ids: Vec<String>) -> Vec<UserPosition> {
+    USER_POSITIONS_MAP.with(|map| {
+        let borrowed = map.borrow();
+        position_ids
+            .into_iter()
+            .filter_map(|id| borrowed.get(&StorableString(id)).map(|p| p.0))
+            .collect()
+    })
+}
+
+fn user_position_key(position: &UserPosition) -> String {
+    format!("{}|{}", position.user_principal, position.position_id)
+}
+
+fn market_position_key(position: &UserPosition) -> String {
+    format!("{}|{}",
+        market_key(position.user_principal, &position.protocol, &position.asset, position.chain_id),
+        position.position_id)
+}
+
+/// Digest of a user's holding in a protocol market, shared by the position and tenure indexes
+fn market_key(user: Principal, protocol: &str, asset: &str, chain_id: u64) -> String {
+    compact_key(&format!("{}:{}:{}:{}", user, protocol.to_uppercase(), asset.to_uppercase(), chain_id))
+}
+
+// =============================================================================
+// Permissions
+// =============================================================================
+
+/// Insert or update permissions and their index entry
+pub fn save_permissions(permissions: &Permissions) {
+    let previous = PERMISSIONS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(permissions.id.clone()),
+            StorablePermissions(permissions.clone())
+        )
+    });
+
+    PERMISSIONS_BY_OWNER_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(previous) = previous {
+            index.remove(&StorableString(owner_permissions_key(&previous.0)));
+        }
+        index.insert(StorableString(owner_permissions_key(permissions)), ());
+    });
+}
+
+/// Remove permissions and their index entry
+pub fn remove_permissions(permissions_id: &str) -> Option<Permissions> {
+    let removed = PERMISSIONS_MAP.with(|map| {
+        map.borrow_mut().remove(&StorableString(permissions_id.to_string()))
+    })?;
+
+    PERMISSIONS_BY_OWNER_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(owner_permissions_key(&removed.0)));
+    });
+    Some(removed.0)
+}
+
+/// All permissions owned by a principal
+pub fn get_owner_permissions(owner: Principal) -> Vec<Permissions> {
+    let permissions_ids = PERMISSIONS_BY_OWNER_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", owner))
+    });
+
+    PERMISSIONS_MAP.with(|map| {
+        let borrowed = map.borrow();
+        permissions_ids
+            .into_iter()
+            .filter_map(|id| borrowed.get(&StorableString(id)).map(|p| p.0))
+            .filter(|p| p.owner == owner)
+            .collect()
+    })
+}
+
+fn owner_permissions_key(permissions: &Permissions) -> String {
+    format!("{}|{}", permissions.owner, permissions.id)
+}
+
+// =============================================================================
+// Rebalance History
+// =============================================================================
+
+/// Store an execution and its index entry (executions are never updated)
+pub fn save_rebalance_execution(execution: RebalanceExecution) {
+    let key = user_execution_key(&execution);
+
+    REBALANCE_HISTORY_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(execution.execution_id.clone()),
+            StorableRebalanceExecution(execution)
+        );
+    });
+    REBALANCE_HISTORY_BY_USER_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(key), ());
+    });
+}
+
+/// Most recent executions of a user, newest first
+pub fn get_user_rebalance_history(user: Principal, limit: usize) -> Vec<RebalanceExecution> {
+    let mut execution_ids = REBALANCE_HISTORY_BY_USER_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", user))
+    });
+
+    // Keys are in time order, so the newest executions are at the end
+    execution_ids.reverse();
+    execution_ids.truncate(limit);
+
+    REBALANCE_HISTORY_MAP.with(|map| {
+        let borrowed = map.borrow();
+        execution_ids
+            .into_iter()
+            .filter_map(|id| borrowed.get(&StorableString(id)).map(|e| e.0))
+            .collect()
+    })
+}
+
+/// Remove all executions and their index entries
+pub fn clear_rebalance_history() -> u64 {
+    let count = REBALANCE_HISTORY_MAP.with(|map| clear_map(&mut map.borrow_mut()));
+    REBALANCE_HISTORY_BY_USER_INDEX.with(|index| clear_map(&mut index.borrow_mut()));
+    count
+}
+
+fn user_execution_key(execution: &RebalanceExecution) -> String {
+    format!("{}|{:020}|{}", execution.user_principal, execution.timestamp, execution.execution_id)
+}
+
+// =============================================================================
+// Yield Tenures
+// =============================================================================
+
+/// Insert or update a tenure and its index entries
+///
+/// A tenure's user and market never change, so updates keep the same entries.
+pub fn save_tenure(tenure: &YieldTenure) {
+    YIELD_TENURES_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(tenure.tenure_id.clone()),
+            StorableYieldTenure(tenure.clone())
+        );
+    });
+    TENURES_BY_USER_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(user_tenure_key(tenure)), ());
+    });
+    TENURES_BY_MARKET_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(market_tenure_key(tenure)), ());
+    });
+}
+
+/// All tenures of a user
+pub fn get_user_tenures(user: Principal) -> Vec<YieldTenure> {
+    let tenure_ids = TENURES_BY_USER_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", user))
+    });
+    load_tenures(tenure_ids)
+}
+
+/// Open tenure of a user's holding in a protocol market, if any
+pub fn find_open_tenure(user: Principal, protocol: &str, asset: &str, chain_id: u64) -> Option<YieldTenure> {
+    let tenure_ids = TENURES_BY_MARKET_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", market_key(user, protocol, asset, chain_id)))
+    });
+
+    load_tenures(tenure_ids)
+        .into_iter()
+        .find(|t| {
+            t.closed_at.is_none() &&
+            t.user_principal == user &&
+            t.protocol.eq_ignore_ascii_case(protocol) &&
+            t.asset.eq_ignore_ascii_case(asset) &&
+            t.chain_id == chain_id
+        })
+}
+
+fn load_tenures(tenure_ids: Vec<String>) -> Vec<YieldTenure> {
+    YIELD_TENURES_MAP.with(|map| {
+        let borrowed = map.borrow();
+        tenure_ids
+            .into_iter()
+            .filter_map(|id| borrowed.get(&StorableString(id)).map(|t| t.0))
+            .collect()
+    })
+}
+
+fn user_tenure_key(tenure: &YieldTenure) -> String {
+    format!("{}|{}", tenure.user_principal, tenure.tenure_id)
+}
+
+fn market_tenure_key(tenure: &YieldTenure) -> String {
+    format!("{}|{}", market_key(tenure.user_principal, &tenure.protocol, &tenure.asset, tenure.chain_id), tenure.tenure_id)
+}
+
+// =============================================================================
+// Sent Transactions
+// =============================================================================
+
+/// Insert or update a sent transaction and its index entries
+pub fn save_sent_transaction(tx: &SentTransaction) {
+    let previous = SENT_TRANSACTIONS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(tx.tx_hash.to_lowercase()),
+            StorableSentTransaction(tx.clone())
+        )
+    });
+
+    if let Some(previous) = previous {
+        unindex_sent_transaction(&previous.0);
+    }
+    index_sent_transaction(tx);
+}
+
+/// Remove a sent transaction and its index entries
+pub fn remove_sent_transaction(tx_hash: &str) -> Option<SentTransaction> {
+    let removed = SENT_TRANSACTIONS_MAP.with(|map| {
+        map.borrow_mut().remove(&StorableString(tx_hash.to_lowercase()))
+    })?;
+
+    unindex_sent_transaction(&removed.0);
+    Some(removed.0)
+}
+
+/// Transactions signed for a principal, newest first
+pub fn get_owner_transactions(owner: Principal) -> Vec<SentTransaction> {
+    let mut tx_hashes = SENT_TX_BY_OWNER_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", compact_key(&owner.to_text())))
+    });
+
+    // Keys are in send order, so the newest transactions are at the end
+    tx_hashes.reverse();
+
+    load_sent_transactions(tx_hashes)
+        .into_iter()
+        .filter(|tx| tx.owner == owner)
+        .collect()
+}
+
+/// All pending transactions
+pub fn get_pending_transactions() -> Vec<SentTransaction> {
+    let tx_hashes = PENDING_TX_INDEX.with(|index| scan_prefix(&index.borrow(), ""));
+
+    load_sent_transactions(tx_hashes)
+        .into_iter()
+        .filter(|tx| tx.status == TxStatus::Pending)
+        .collect()
+}
+
+/// Hashes of settled transactions last updated before `cutoff` (ms), oldest first
+pub fn settled_transactions_before(cutoff: u64) -> Vec<String> {
+    SETTLED_TX_BY_TIME_INDEX.with(|index| {
+        index.borrow()
+            .range(..StorableString(format!("{:020}|", cutoff)))
+            .filter_map(|(key, _)| key.0.rsplit('|').next().map(|hash| hash.to_string()))
+            .collect()
+    })
+}
+
+fn index_sent_transaction(tx: &SentTransaction) {
+    SENT_TX_BY_OWNER_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(owner_transaction_key(tx)), ());
+    });
+    if tx.status == TxStatus::Pending {
+        PENDING_TX_INDEX.with(|index| {
+            index.borrow_mut().insert(StorableString(tx.tx_hash.to_lowercase()), ());
+        });
+    } else {
+        SETTLED_TX_BY_TIME_INDEX.with(|index| {
+            index.borrow_mut().insert(StorableString(settled_transaction_key(tx)), ());
+        });
+    }
+}
+
+fn unindex_sent_transaction(tx: &SentTransaction) {
+    SENT_TX_BY_OWNER_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(owner_transaction_key(tx)));
+    });
+    PENDING_TX_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(tx.tx_hash.to_lowercase()));
+    });
+    SETTLED_TX_BY_TIME_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(settled_transaction_key(tx)));
+    });
+}
+
+fn load_sent_transactions(tx_hashes: Vec<String>) -> Vec<SentTransaction> {
+    SENT_TRANSACTIONS_MAP.with(|map| {
+        let borrowed = map.borrow();
+        tx_hashes
+            .into_iter()
+            .filter_map(|hash| borrowed.get(&StorableString(hash)).map(|tx| tx.0))
+            .collect()
+    })
+}
+
+fn owner_transaction_key(tx: &SentTransaction) -> String {
+    format!("{}|{:020}|{}", compact_key(&tx.owner.to_text()), tx.sent_at, tx.tx_hash.to_lowercase())
+}
+
+fn settled_transaction_key(tx: &SentTransaction) -> String {
+    format!("{:020}|{}", tx.updated_at, tx.tx_hash.to_lowercase())
+}
+
+// =============================================================================
+// Proposals
+// =============================================================================
+
+/// Insert or update a proposal and its index entries
+///
+/// A proposal's user and position never change, so updates keep the same entries.
+pub fn save_proposal(proposal: &PendingRecommendation) {
+    PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(proposal.proposal_id.clone()),
+            StorablePendingRecommendation(proposal.clone())
+        );
+    });
+    PROPOSALS_BY_USER_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(user_proposal_key(proposal)), ());
+    });
+    PROPOSALS_BY_POSITION_INDEX.with(|index| {
+        index.borrow_mut().insert(StorableString(position_proposal_key(proposal)), ());
+    });
+}
+
+/// Remove a proposal and its index entries
+pub fn remove_proposal(proposal_id: &str) -> Option<PendingRecommendation> {
+    let removed = PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        map.borrow_mut().remove(&StorableString(proposal_id.to_string()))
+    })?;
+
+    PROPOSALS_BY_USER_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(user_proposal_key(&removed.0)));
+    });
+    PROPOSALS_BY_POSITION_INDEX.with(|index| {
+        index.borrow_mut().remove(&StorableString(position_proposal_key(&removed.0)));
+    });
+    Some(removed.0)
+}
+
+/// All proposals of a user
+pub fn get_user_proposals(user: Principal) -> Vec<PendingRecommendation> {
+    let proposal_ids = PROPOSALS_BY_USER_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", user))
+    });
+
+    load_proposals(proposal_ids)
+        .into_iter()
+        .filter(|p| p.user_principal == user)
+        .collect()
+}
+
+/// All proposals for a position
+pub fn get_position_proposals(position_id: &str) -> Vec<PendingRecommendation> {
+    let proposal_ids = PROPOSALS_BY_POSITION_INDEX.with(|index| {
+        scan_prefix(&index.borrow(), &format!("{}|", position_id))
+    });
+
+    load_proposals(proposal_ids)
+        .into_iter()
+        .filter(|p| p.position_id == position_id)
+        .collect()
+}
+
+fn load_proposals(proposal_ids: Vec<String>) -> Vec<PendingRecommendation> {
+    PENDING_RECOMMENDATIONS_MAP.with(|map| {
+        let borrowed = map.borrow();
+        proposal_ids
+            .into_iter()
+            .filter_map(|id| borrowed.get(&StorableString(id)).map(|p| p.0))
+            .collect()
+    })
+}
+
+fn user_proposal_key(proposal: &PendingRecommendation) -> String {
+    format!("{}|{}", proposal.user_principal, proposal.proposal_id)
+}
+
+fn position_proposal_key(proposal: &PendingRecommendation) -> String {
+    format!("{}|{}", proposal.position_id, proposal.proposal_id)
+}
+
+// =============================================================================
+// Maintenance
+// =============================================================================
+
+/// Rebuild any index whose size does not match its primary map
+///
+/// Covers canisters upgraded from before the indexes existed.
+pub fn rebuild_indexes_if_needed() {
+    let positions = USER_POSITIONS_MAP.with(|map| map.borrow().len());
+    let by_user = POSITIONS_BY_USER_INDEX.with(|index| index.borrow().len());
+    let by_market = POSITIONS_BY_MARKET_INDEX.with(|index| index.borrow().len());
+    if by_user != positions || by_market != positions {
+        ic_cdk::println!("🗂️ Rebuilding position indexes ({} positions)", positions);
+        let (user_keys, market_keys): (Vec<String>, Vec<String>) = USER_POSITIONS_MAP.with(|map| {
+            map.borrow()
+                .iter()
+                .map(|(_, p)| (user_position_key(&p.0), market_position_key(&p.0)))
+                .unzip()
+        });
+        rebuild_index(&POSITIONS_BY_USER_INDEX, user_keys);
+        rebuild_index(&POSITIONS_BY_MARKET_INDEX, market_keys);
+    }
+
+    let permissions = PERMISSIONS_MAP.with(|map| map.borrow().len());
+    let by_owner = PERMISSIONS_BY_OWNER_INDEX.with(|index| index.borrow().len());
+    if by_owner != permissions {
+        ic_cdk::println!("🗂️ Rebuilding permissions index ({} permissions)", permissions);
+        let keys: Vec<String> = PERMISSIONS_MAP.with(|map| {
+            map.borrow().iter().map(|(_, p)| owner_permissions_key(&p.0)).collect()
+        });
+        rebuild_index(&PERMISSIONS_BY_OWNER_INDEX, keys);
+    }
+
+    let executions = REBALANCE_HISTORY_MAP.with(|map| map.borrow().len());
+    let history_by_user = REBALANCE_HISTORY_BY_USER_INDEX.with(|index| index.borrow().len());
+    if history_by_user != executions {
+        ic_cdk::println!("🗂️ Rebuilding rebalance history index ({} executions)", executions);
+        let keys: Vec<String> = REBALANCE_HISTORY_MAP.with(|map| {
+            map.borrow().iter().map(|(_, e)| user_execution_key(&e.0)).collect()
+        });
+        rebuild_index(&REBALANCE_HISTORY_BY_USER_INDEX, keys);
+    }
+
+    let tenures = YIELD_TENURES_MAP.with(|map| map.borrow().len());
+    let tenures_by_user = TENURES_BY_USER_INDEX.with(|index| index.borrow().len());
+    let tenures_by_market = TENURES_BY_MARKET_INDEX.with(|index| index.borrow().len());
+    if tenures_by_user != tenures || tenures_by_market != tenures {
+        ic_cdk::println!("🗂️ Rebuilding yield tenure indexes ({} tenures)", tenures);
+        let (user_keys, market_keys): (Vec<String>, Vec<String>) = YIELD_TENURES_MAP.with(|map| {
+            map.borrow().iter().map(|(_, t)| (user_tenure_key(&t.0), market_tenure_key(&t.0))).unzip()
+        });
+        rebuild_index(&TENURES_BY_USER_INDEX, user_keys);
+        rebuild_index(&TENURES_BY_MARKET_INDEX, market_keys);
+    }
+
+    let sent_transactions = SENT_TRANSACTIONS_MAP.with(|map| map.borrow().len());
+    let tx_by_owner = SENT_TX_BY_OWNER_INDEX.with(|index| index.borrow().len());
+    let tx_by_status = PENDING_TX_INDEX.with(|index| index.borrow().len())
+        + SETTLED_TX_BY_TIME_INDEX.with(|index| index.borrow().len());
+    if tx_by_owner != sent_transactions || tx_by_status != sent_transactions {
+        ic_cdk::println!("🗂️ Rebuilding sent transaction indexes ({} transactions)", sent_transactions);
+        let transactions: Vec<SentTransaction> = SENT_TRANSACTIONS_MAP.with(|map| {
+            map.borrow().iter().map(|(_, tx)| tx.0).collect()
+        });
+        rebuild_index(&SENT_TX_BY_OWNER_INDEX, transactions.iter().map(owner_transaction_key).collect());
+        rebuild_index(&PENDING_TX_INDEX, transactions.iter()
+            .filter(|tx| tx.status == TxStatus::Pending)
+            .map(|tx| tx.tx_hash.to_lowercase())
+            .collect());
+        rebuild_index(&SETTLED_TX_BY_TIME_INDEX, transactions.iter()
+            .filter(|tx| tx.status != TxStatus::Pending)
+            .map(settled_transaction_key)
+            .collect());
+    }
+
+    let proposals = PENDING_RECOMMENDATIONS_MAP.with(|map| map.borrow().len());
+    let proposals_by_user = PROPOSALS_BY_USER_INDEX.with(|index| index.borrow().len());
+    let proposals_by_position = PROPOSALS_BY_POSITION_INDEX.with(|index| index.borrow().len());
+    if proposals_by_user != proposals || proposals_by_position != proposals {
+        ic_cdk::println!("🗂️ Rebuilding proposal indexes ({} proposals)", proposals);
+        let (user_keys, position_keys): (Vec<String>, Vec<String>) = PENDING_RECOMMENDATIONS_MAP.with(|map| {
+            map.borrow().iter().map(|(_, p)| (user_proposal_key(&p.0), position_proposal_key(&p.0))).unzip()
+        });
+        rebuild_index(&PROPOSALS_BY_USER_INDEX, user_keys);
+        rebuild_index(&PROPOSALS_BY_POSITION_INDEX, position_keys);
+    }
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+/// Fixed-width digest (64-bit FNV-1a, hex) of a string, for key parts that could exceed the bound
+///
+/// Stable across builds, unlike the standard library's hashers.
+pub(crate) fn compact_key(value: &str) -> String {
+    let hash = value.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Trailing ID segment of every index key starting with the prefix
+fn scan_prefix(index: &IndexMap, prefix: &str) -> Vec<String> {
+    index
+        .range(StorableString(prefix.to_string())..)
+        .map(|(key, _)| key.0)
+        .take_while(|key| key.starts_with(prefix))
+        .filter_map(|key| key.rsplit('|').next().map(|id| id.to_string()))
+        .collect()
+}
+
+fn rebuild_index(index: &'static LocalKey<RefCell<IndexMap>>, keys: Vec<String>) {
+    index.with(|index| {
+        let mut index = index.borrow_mut();
+        clear_map(&mut index);
+        for key in keys {
+            index.insert(StorableString(key), ());
+        }
+    });
+}
+
+fn clear_map<V: Storable>(map: &mut StableBTreeMap<StorableString, V, Memory>) -> u64 {
+    let len = map.len();
+    let keys: Vec<StorableString> = map.iter().map(|(k, _)| k).collect();
+    for key in keys {
+        map.remove(&key);
+    }
+    len
+}
@@ -0,0 +1,136 @@
+use candid::Principal;
+
+use crate::types::{OperationLock, StorableOperationLock};
+use crate::{OPERATION_LOCKS_MAP, StorableString, now};
+
+// =============================================================================
+// Operation Locks
+// =============================================================================
+//
+// Prevents overlapping scheduler ticks and concurrent fund-moving operations
+// for the same user. Inter-canister calls interleave at every await, so without
+// these locks two operations can race on balances and nonce reservation.
+//
+// Locks are released by `LockGuard::drop`, which also runs when a call traps
+// after an await (ic-cdk drops the future during cleanup). A guard only
+// releases the lock it acquired: once its lock was overridden as stale, the
+// entry belongs to the new holder and is left alone.
+//
+
+/// Lock key used by the scheduler
+const SCHEDULER_LOCK_KEY: &str = "scheduler";
+
+/// Lock key used by the position reconciler
+const RECONCILER_LOCK_KEY: &str = "position_reconciler";
+
+/// Lock key used by the stuck-transaction watchdog
+const TX_WATCHDOG_LOCK_KEY: &str = "tx_watchdog";
+
+/// Locks older than this are considered abandoned (30 minutes)
+const LOCK_TIMEOUT_MS: u64 = 30 * 60 * 1000;
+
+/// Releases its lock when dropped
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct LockGuard {
+    key: String,
+    /// `acquired_at` of the entry this guard owns
+    acquired_at: u64,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        OPERATION_LOCKS_MAP.with(|map| {
+            let mut borrowed = map.borrow_mut();
+            let key = StorableString(self.key.clone());
+            let owned = borrowed.get(&key)
+                .is_some_and(|lock| lock.0.acquired_at == self.acquired_at);
+
+            if owned {
+                borrowed.remove(&key);
+            } else {
+                ic_cdk::println!("⚠️ Lock {} was taken over by another operation, leaving it in place", self.key);
+            }
+        });
+    }
+}
+
+/// Acquire the global scheduler-running lock
+pub fn try_acquire_scheduler_lock() -> Result<LockGuard, String> {
+    try_acquire(SCHEDULER_LOCK_KEY.to_string(), "scheduler_tick")
+}
+
+/// Acquire the global position-reconciliation lock
+pub fn try_acquire_reconciler_lock() -> Result<LockGuard, String> {
+    try_acquire(RECONCILER_LOCK_KEY.to_string(), "position_reconciliation")
+}
+
+/// Acquire the global stuck-transaction watchdog lock
+pub fn try_acquire_tx_watchdog_lock() -> Result<LockGuard, String> {
+    try_acquire(TX_WATCHDOG_LOCK_KEY.to_string(), "tx_watchdog")
+}
+
+/// Acquire the operation lock of a user
+pub fn try_acquire_user_lock(user: Principal, operation: &str) -> Result<LockGuard, String> {
+    try_acquire(user.to_text(), operation)
+}
+
+/// Acquire a lock, failing if a live lock is already held for the key
+fn try_acquire(key: String, operation: &str) -> Result<LockGuard, String> {
+    let current_time = now();
+
+    OPERATION_LOCKS_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+
+        if let Some(existing) = borrowed.get(&StorableString(key.clone())) {
+            let existing = existing.0;
+            if existing.acquired_at + LOCK_TIMEOUT_MS > current_time {
+                return Err(format!(
+                    "Operation in progress: {} (started at {}), please try again later",
+                    existing.operation, existing.acquired_at
+                ));
+            }
+
+            ic_cdk::println!("⚠️ Overriding stale lock {} held by {} since {}",
+                key, existing.operation, existing.acquired_at);
+        }
+
+        borrowed.insert(
+            StorableString(key.clone()),
+            StorableOperationLock(OperationLock {
+                key: key.clone(),
+                operation: operation.to_string(),
+                acquired_at: current_time,
+            })
+        );
+
+        Ok(LockGuard { key, acquired_at: current_time })
+    })
+}
+
+/// Get all currently held locks
+pub fn get_active_locks() -> Vec<OperationLock> {
+    OPERATION_LOCKS_MAP.with(|map| {
+        map.borrow()
+            .iter()
+            .map(|(_, lock)| lock.0)
+            .collect()
+    })
+}
+
+/// Remove every held lock
+///
+/// Called from `post_upgrade`: any operation that held a lock before the
+/// upgrade can no longer complete, so all remaining locks are stale.
+pub fn clear_all_locks() -> u64 {
+    OPERATION_LOCKS_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        let keys: Vec<StorableString> = borrowed.iter().map(|(key, _)| key).collect();
+        let cleared = keys.len() as u64;
+
+        for key in keys {
+            borrowed.remove(&key);
+        }
+
+        cleared
+    })
+}
@@ -3,6 +3,7 @@ pub mod get_balance_link;
 pub mod get_balance_usdc;
 pub mod transfer_link;
 pub mod send_eth;
+pub mod erc20;
 pub mod approve_usdc;
 pub mod approve_weth;
 pub mod sign_message;
@@ -11,9 +12,25 @@ pub mod uniswap;
 pub mod permissions;
 pub mod aave;
 pub mod rpc_service;
+pub mod chain_registry;
 pub mod compound;
 pub mod rebalance;
+pub mod rebalance_saga;
 pub mod scheduler;
+pub mod approval_queue;
+pub mod locks;
 pub mod apy_parser;
+pub mod apy_retention;
+pub mod apy_analytics;
+pub mod backtester;
+pub mod apy_signals;
 pub mod position_sync;
+pub mod position_reconciler;
+pub mod portfolio;
+pub mod price_oracle;
+pub mod multicall;
+pub mod yield_ledger;
+pub mod indexes;
 pub mod nonce_manager;
+pub mod fee_policy;
+pub mod tx_tracker;
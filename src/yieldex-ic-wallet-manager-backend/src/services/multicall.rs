@@ -0,0 +1,191 @@
+use alloy::{
+    primitives::{address, Address, Bytes, U256},
+    providers::ProviderBuilder,
+    sol,
+    sol_types::{sol_data, SolCall, SolType},
+    transports::icp::IcpConfig,
+};
+
+use crate::services::rpc_service::get_rpc_service_by_chain_id;
+
+// =============================================================================
+// Multicall3 Batching
+// =============================================================================
+//
+// Every `eth_call` through `IcpConfig` is its own HTTPS outcall. Multicall3
+// aggregates many read calls into a single `eth_call`, so a batch of N reads
+// costs one outcall instead of N.
+//
+// Calls are added to a `Multicall`, executed together, and decoded by the
+// index `add` returned. Each call may fail on its own (`allowFailure`), so one
+// reverting read does not discard the rest of the batch.
+//
+
+/// Multicall3, deployed at the same address on every supported chain
+pub const MULTICALL3_ADDRESS: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
+
+/// Largest number of calls sent in one `eth_call`; larger batches are split
+const MAX_CALLS_PER_BATCH: usize = 100;
+
+sol! {
+    #[sol(rpc)]
+    interface IMulticall3 {
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+
+        struct CallResult {
+            bool success;
+            bytes returnData;
+        }
+
+        function aggregate3(Call3[] calldata calls) external payable returns (CallResult[] memory returnData);
+        function getEthBalance(address addr) external view returns (uint256 balance);
+    }
+
+    interface IERC20Read {
+        function balanceOf(address account) external view returns (uint256);
+    }
+}
+
+/// A batch of read calls on one chain
+pub struct Multicall {
+    chain_id: u64,
+    calls: Vec<IMulticall3::Call3>,
+}
+
+/// Raw results of an executed batch, in the order the calls were added
+pub struct MulticallResults {
+    results: Vec<Result<Bytes, String>>,
+}
+
+impl Multicall {
+    pub fn new(chain_id: u64) -> Self {
+        Self { chain_id, calls: Vec::new() }
+    }
+
+    /// Add a call and return its index in the results
+    pub fn add<C: SolCall>(&mut self, target: Address, call: C) -> usize {
+        self.calls.push(IMulticall3::Call3 {
+            target,
+            allowFailure: true,
+            callData: call.abi_encode().into(),
+        });
+        self.calls.len() - 1
+    }
+
+    /// Add an ERC-20 `balanceOf` read (also used for aTokens and Comet)
+    pub fn add_token_balance(&mut self, token: Address, account: Address) -> usize {
+        self.add(token, IERC20Read::balanceOfCall { account })
+    }
+
+    /// Add a native balance read
+    pub fn add_native_balance(&mut self, account: Address) -> usize {
+        self.add(MULTICALL3_ADDRESS, IMulticall3::getEthBalanceCall { addr: account })
+    }
+
+    /// Execute every call, one outcall per `MAX_CALLS_PER_BATCH` calls
+    pub async fn execute(self) -> Result<MulticallResults, String> {
+        if self.calls.is_empty() {
+            return Ok(MulticallResults { results: Vec::new() });
+        }
+
+        let rpc_service = get_rpc_service_by_chain_id(self.chain_id)?;
+        let config = IcpConfig::new(rpc_service);
+        let provider = ProviderBuilder::new().on_icp(config);
+        let multicall = IMulticall3::new(MULTICALL3_ADDRESS, provider);
+
+        let mut results = Vec::with_capacity(self.calls.len());
+        for batch in self.calls.chunks(MAX_CALLS_PER_BATCH) {
+            let response = multicall.aggregate3(batch.to_vec()).call().await
+                .map_err(|e| format!("Multicall failed on chain {}: {}", self.chain_id, e))?;
+
+            if response.returnData.len() != batch.len() {
+                return Err(format!("Multicall returned {} results for {} calls",
+                    response.returnData.len(), batch.len()));
+            }
+
+            results.extend(response.returnData.into_iter().map(|result| {
+                if result.success {
+                    Ok(result.returnData)
+                } else {
+                    Err("Call reverted".to_string())
+                }
+            }));
+        }
+
+        ic_cdk::println!("📦 Multicall on chain {}: {} calls in {} outcalls",
+            self.chain_id, self.calls.len(), self.calls.len().div_ceil(MAX_CALLS_PER_BATCH));
+
+        Ok(MulticallResults { results })
+    }
+}
+
+impl MulticallResults {
+    /// Decode the return value of the call at `index`
+    pub fn decode<C: SolCall>(&self, index: usize) -> Result<C::Return, String> {
+        let data = self.raw(index)?;
+        C::abi_decode_returns(data, true)
+            .map_err(|e| format!("Failed to decode {} result: {}", C::SIGNATURE, e))
+    }
+
+    /// Decode a call returning a single uint256 (balances, allowances, rates)
+    pub fn decode_uint(&self, index: usize) -> Result<U256, String> {
+        let data = self.raw(index)?;
+        <sol_data::Uint<256> as SolType>::abi_decode(data, true)
+            .map_err(|e| format!("Failed to decode uint256 result: {}", e))
+    }
+
+    fn raw(&self, index: usize) -> Result<&Bytes, String> {
+        self.results.get(index)
+            .ok_or_else(|| format!("No multicall result at index {}", index))?
+            .as_ref()
+            .map_err(|e| e.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(results: Vec<Result<Bytes, String>>) -> MulticallResults {
+        MulticallResults { results }
+    }
+
+    fn encoded_uint(value: u64) -> Bytes {
+        <sol_data::Uint<256> as SolType>::abi_encode(&U256::from(value)).into()
+    }
+
+    #[test]
+    fn add_returns_result_indices_in_order() {
+        let account = address!("00000000000000000000000000000000000000aa");
+        let token = address!("00000000000000000000000000000000000000bb");
+
+        let mut multicall = Multicall::new(1);
+        assert_eq!(multicall.add_native_balance(account), 0);
+        assert_eq!(multicall.add_token_balance(token, account), 1);
+        assert_eq!(multicall.calls.len(), 2);
+        assert_eq!(multicall.calls[0].target, MULTICALL3_ADDRESS);
+        assert_eq!(multicall.calls[1].target, token);
+        assert!(multicall.calls.iter().all(|call| call.allowFailure));
+    }
+
+    #[test]
+    fn decodes_uint_and_typed_returns() {
+        let results = results(vec![Ok(encoded_uint(1_500_000))]);
+
+        assert_eq!(results.decode_uint(0).unwrap(), U256::from(1_500_000u64));
+        assert_eq!(results.decode::<IERC20Read::balanceOfCall>(0).unwrap()._0, U256::from(1_500_000u64));
+    }
+
+    #[test]
+    fn failed_and_missing_calls_are_errors() {
+        let results = results(vec![Err("Call reverted".to_string()), Ok(Bytes::new())]);
+
+        assert_eq!(results.decode_uint(0).unwrap_err(), "Call reverted");
+        assert!(results.decode_uint(1).is_err());
+        assert!(results.decode_uint(2).is_err());
+    }
+}
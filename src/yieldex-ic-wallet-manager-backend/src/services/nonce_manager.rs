@@ -1,37 +1,79 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
 use alloy::primitives::Address;
 
-/// Nonce cache entry with state tracking
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum NonceState {
-    /// Available for use
-    Available(u64),
-    /// Reserved for a pending transaction
-    Reserved(u64),
+use crate::types::{NonceReservation, NonceState};
+use crate::{NONCE_STATE_MAP, StorableString, StorableNonceState, now};
+
+// =============================================================================
+// Nonce Manager
+// =============================================================================
+//
+// Every transaction the canister signs takes its nonce from here, so
+// concurrent operations of the same address never collide.
+//
+// State lives in NONCE_STATE_MAP per (chain_id, address):
+// - `next_nonce`: lowest nonce never handed out
+// - `reserved`: nonces held by sends in flight; several can be in flight at once
+// - `released`: nonces given back by failed sends, handed out again first so no
+//   gap blocks the nonces above them
+// - `committed_high_water`: highest nonce the RPC accepted a transaction for
+//
+// The local view is reconciled with `eth_getTransactionCount(pending)` before
+// the first reservation of an address, after every failed send, after an
+// upgrade, and when a reservation outlives `RESERVATION_TTL_MS` (its call
+// trapped before committing or rolling back). A pending count below the
+// committed high-water mark (a node that has not seen the latest sends yet)
+// is raised to it, so committed nonces are never handed out again. The mark
+// is dropped once the pending count passes it, when it was not raised within
+// `RESERVATION_TTL_MS` (the sends were dropped from the mempool), and when an
+// admin resyncs the address.
+//
+// Usage: `reserve_nonce` right before sending, then `commit_nonce` once the
+// RPC accepted the transaction or `rollback_nonce` if it did not. A reverted
+// transaction still consumes its nonce and is committed.
+//
+
+/// Reservations older than this are treated as abandoned (10 minutes)
+const RESERVATION_TTL_MS: u64 = 10 * 60 * 1000;
+
+/// Storage key of an address on a chain
+fn state_key(address: Address, chain_id: u64) -> StorableString {
+    StorableString(format!("{}:0x{:x}", chain_id, address))
 }
 
-// Global nonce cache: (address, chain_id) -> NonceState
-thread_local! {
-    static NONCE_CACHE: RefCell<HashMap<(String, u64), NonceState>> = RefCell::new(HashMap::new());
+fn load(address: Address, chain_id: u64) -> Option<NonceState> {
+    NONCE_STATE_MAP.with(|map| map.borrow().get(&state_key(address, chain_id)).map(|s| s.0))
 }
 
-/// Generate cache key from address and chain_id
-fn cache_key(address: Address, chain_id: u64) -> (String, u64) {
-    (format!("{:x}", address), chain_id)
+fn store(address: Address, chain_id: u64, mut state: NonceState) {
+    state.updated_at = now();
+    NONCE_STATE_MAP.with(|map| {
+        map.borrow_mut().insert(state_key(address, chain_id), StorableNonceState(state));
+    });
 }
 
-/// Get the next available nonce for an address on a specific chain
+fn new_state(address: Address, chain_id: u64) -> NonceState {
+    NonceState {
+        address: format!("0x{:x}", address),
+        chain_id,
+        next_nonce: 0,
+        reserved: Vec::new(),
+        released: Vec::new(),
+        needs_sync: true,
+        committed_high_water: None,
+        committed_high_water_at: None,
+        updated_at: 0,
+    }
+}
+
+/// Reserve the next nonce of an address on a chain
 ///
-/// This function:
-/// 1. Checks the local cache for a cached nonce
-/// 2. If cache miss or invalidated, fetches fresh nonce from network
-/// 3. Returns the next available nonce to use
+/// Reconciles with the pending transaction count first when the local state
+/// is missing or stale.
 ///
-/// NOTE: After getting a nonce, you MUST call either:
+/// NOTE: After reserving a nonce, you MUST call either:
 /// - `commit_nonce()` after successfully sending the transaction
 /// - `rollback_nonce()` if the transaction failed to send
-pub async fn get_next_nonce<T, N, P>(
+pub async fn reserve_nonce<T, N, P>(
     address: Address,
     provider: &P,
     chain_id: u64
@@ -41,125 +83,195 @@ where
     N: alloy::network::Network,
     P: alloy::providers::Provider<T, N>,
 {
-    let key = cache_key(address, chain_id);
-
-    // Try to get cached nonce
-    let cached_nonce = NONCE_CACHE.with(|cache| {
-        cache.borrow().get(&key).copied()
-    });
-
-    match cached_nonce {
-        Some(NonceState::Available(nonce)) => {
-            ic_cdk::println!("🔧 Using cached nonce: {} for address 0x{:x} on chain {}",
-                nonce, address, chain_id);
-            Ok(nonce)
-        }
-        Some(NonceState::Reserved(nonce)) => {
-            // There's a reserved nonce, use the next one
-            let next_nonce = nonce + 1;
-            ic_cdk::println!("⚠️ Nonce {} is reserved, using next available: {} for address 0x{:x} on chain {}",
-                nonce, next_nonce, address, chain_id);
-            Ok(next_nonce)
-        }
-        None => {
-            // Cache miss - fetch from network
-            ic_cdk::println!("🌐 Fetching fresh nonce from network for address 0x{:x} on chain {}...",
+    let timestamp = now();
+    let mut state = match load(address, chain_id) {
+        Some(state) if !state.needs_sync && !has_expired_reservations(&state, timestamp) => state,
+        _ => {
+            ic_cdk::println!("🌐 Reconciling nonce with pending transaction count for address 0x{:x} on chain {}...",
                 address, chain_id);
 
-            let fresh_nonce = provider.get_transaction_count(address)
+            let pending = provider.get_transaction_count(address)
+                .pending()
                 .await
                 .map_err(|e| format!("Failed to get nonce from network: {}", e))?;
 
-            ic_cdk::println!("✅ Got fresh nonce from network: {} for address 0x{:x} on chain {}",
-                fresh_nonce, address, chain_id);
+            // Reload: other calls may have reserved while the count was fetched
+            let mut state = load(address, chain_id).unwrap_or_else(|| new_state(address, chain_id));
+            reconcile(&mut state, pending, now());
 
-            // Don't cache yet - let reserve_nonce or commit_nonce handle caching
-            Ok(fresh_nonce)
+            ic_cdk::println!("✅ Pending nonce {} for address 0x{:x} on chain {}, next available: {}",
+                pending, address, chain_id, state.next_nonce);
+            state
         }
-    }
-}
+    };
 
-/// Reserve a nonce before sending a transaction
-///
-/// Call this BEFORE sending a transaction to mark the nonce as "in-use".
-/// This prevents other concurrent operations from using the same nonce.
-///
-/// You MUST follow up with either:
-/// - `commit_nonce()` if send() succeeded
-/// - `rollback_nonce()` if send() failed
-pub fn reserve_nonce(address: Address, chain_id: u64, nonce: u64) {
-    let key = cache_key(address, chain_id);
-
-    NONCE_CACHE.with(|cache| {
-        cache.borrow_mut().insert(key.clone(), NonceState::Reserved(nonce));
-    });
+    let nonce = allocate(&mut state, now());
+    store(address, chain_id, state);
 
     ic_cdk::println!("🔒 Reserved nonce {} for address 0x{:x} on chain {}",
         nonce, address, chain_id);
+    Ok(nonce)
 }
 
 /// Commit a nonce after successfully sending a transaction
 ///
 /// Call this AFTER send().await returns Ok(builder).
-/// This updates the cache to the NEXT available nonce.
 ///
 /// IMPORTANT: Call this even if the transaction reverts in the blockchain!
 /// A reverted transaction still consumes the nonce.
 pub fn commit_nonce(address: Address, chain_id: u64, used_nonce: u64) {
-    let key = cache_key(address, chain_id);
-    let next_nonce = used_nonce + 1;
-
-    NONCE_CACHE.with(|cache| {
-        cache.borrow_mut().insert(key.clone(), NonceState::Available(next_nonce));
-    });
+    if let Some(mut state) = load(address, chain_id) {
+        commit(&mut state, used_nonce, now());
+        store(address, chain_id, state);
+    }
 
-    ic_cdk::println!("✅ Committed nonce {}, next available: {} for address 0x{:x} on chain {}",
-        used_nonce, next_nonce, address, chain_id);
+    ic_cdk::println!("✅ Committed nonce {} for address 0x{:x} on chain {}",
+        used_nonce, address, chain_id);
 }
 
 /// Rollback a reserved nonce after failing to send a transaction
 ///
-/// Call this AFTER send().await returns Err(e).
-/// This releases the reserved nonce back to available state.
+/// Call this AFTER send().await returns Err(e). The nonce is handed out again
+/// and the next reservation reconciles with the network, as a failed send
+/// may still have reached the mempool.
 ///
-/// Only call this if the transaction FAILED TO SEND (e.g., gas estimation failed).
 /// Do NOT call this if send() succeeded but the transaction reverted later.
 pub fn rollback_nonce(address: Address, chain_id: u64, nonce: u64) {
-    let key = cache_key(address, chain_id);
-
-    NONCE_CACHE.with(|cache| {
-        cache.borrow_mut().insert(key.clone(), NonceState::Available(nonce));
-    });
+    if let Some(mut state) = load(address, chain_id) {
+        release(&mut state, nonce);
+        state.needs_sync = true;
+        store(address, chain_id, state);
+    }
 
     ic_cdk::println!("🔄 Rolled back nonce {} for address 0x{:x} on chain {}",
         nonce, address, chain_id);
 }
 
-/// Invalidate the nonce cache for a specific address on a chain
+/// Reconcile an address with the network before its next reservation
 ///
-/// Call this when:
-/// - You get a "nonce too low" error (cache is stale)
-/// - You want to force fetching a fresh nonce from network
-/// - You suspect the cache is out of sync
-pub fn invalidate_cache(address: Address, chain_id: u64) {
-    let key = cache_key(address, chain_id);
-
-    NONCE_CACHE.with(|cache| {
-        cache.borrow_mut().remove(&key);
-    });
+/// Drops the committed high-water mark, so the pending count is trusted
+/// even if transactions sent recently never reached the network.
+pub fn mark_for_resync(address: Address, chain_id: u64) {
+    if let Some(mut state) = load(address, chain_id) {
+        state.needs_sync = true;
+        state.committed_high_water = None;
+        state.committed_high_water_at = None;
+        store(address, chain_id, state);
+    }
 
-    ic_cdk::println!("🗑️ Invalidated nonce cache for address 0x{:x} on chain {}",
+    ic_cdk::println!("🗑️ Nonce of address 0x{:x} on chain {} will be reconciled before next use",
         address, chain_id);
 }
 
-/// Clear all nonce caches (useful for testing or reset)
-#[allow(dead_code)]
-pub fn clear_all_caches() {
-    NONCE_CACHE.with(|cache| {
-        cache.borrow_mut().clear();
+/// Reconcile every address with the network before its next reservation
+///
+/// Called after an upgrade. No call survives an upgrade, so reservations are
+/// dropped as well.
+pub fn mark_all_for_resync() {
+    NONCE_STATE_MAP.with(|map| {
+        let mut map = map.borrow_mut();
+        let states: Vec<(StorableString, NonceState)> = map.iter()
+            .map(|(key, state)| (key, state.0))
+            .collect();
+        for (key, mut state) in states {
+            state.reserved.clear();
+            state.needs_sync = true;
+            map.insert(key, StorableNonceState(state));
+        }
     });
 
-    ic_cdk::println!("🗑️ Cleared all nonce caches");
+    ic_cdk::println!("🗑️ All nonces will be reconciled before next use");
+}
+
+/// Nonce state of every address the canister signed for
+pub fn get_nonce_states() -> Vec<NonceState> {
+    NONCE_STATE_MAP.with(|map| map.borrow().iter().map(|(_, state)| state.0).collect())
+}
+
+// =============================================================================
+// State Transitions
+// =============================================================================
+
+/// Hand out the lowest released nonce, or the next new one
+fn allocate(state: &mut NonceState, timestamp: u64) -> u64 {
+    let nonce = match state.released.iter().min().copied() {
+        Some(nonce) => {
+            state.released.retain(|n| *n != nonce);
+            nonce
+        }
+        None => {
+            let nonce = state.next_nonce;
+            state.next_nonce += 1;
+            nonce
+        }
+    };
+
+    state.reserved.push(NonceReservation { nonce, reserved_at: timestamp });
+    nonce
+}
+
+/// Mark a reserved nonce as sent
+fn commit(state: &mut NonceState, nonce: u64, timestamp: u64) {
+    state.reserved.retain(|r| r.nonce != nonce);
+    if state.committed_high_water < Some(nonce) {
+        state.committed_high_water = Some(nonce);
+        state.committed_high_water_at = Some(timestamp);
+    }
+}
+
+/// Give a reserved nonce back
+fn release(state: &mut NonceState, nonce: u64) {
+    state.reserved.retain(|r| r.nonce != nonce);
+    if nonce >= state.next_nonce {
+        return;
+    }
+
+    if !state.released.contains(&nonce) {
+        state.released.push(nonce);
+    }
+
+    // Released nonces at the top shrink next_nonce instead of leaving a gap
+    while state.next_nonce > 0 && state.released.contains(&(state.next_nonce - 1)) {
+        state.next_nonce -= 1;
+        let top = state.next_nonce;
+        state.released.retain(|n| *n != top);
+    }
+}
+
+/// Align the local state with the network's pending transaction count
+///
+/// Nonces below `pending` and up to the committed high-water mark are taken,
+/// unless the mark has been passed or has expired.
+/// Reservations in flight are kept and every unreserved nonce between the
+/// first free nonce and them becomes released.
+fn reconcile(state: &mut NonceState, pending: u64, timestamp: u64) {
+    state.reserved.retain(|r| timestamp.saturating_sub(r.reserved_at) <= RESERVATION_TTL_MS);
+
+    if let Some(hwm) = state.committed_high_water {
+        let raised_at = state.committed_high_water_at.unwrap_or(0);
+        if pending > hwm || timestamp.saturating_sub(raised_at) > RESERVATION_TTL_MS {
+            state.committed_high_water = None;
+            state.committed_high_water_at = None;
+        }
+    }
+
+    // The node may lag behind transactions it already accepted
+    let pending = state.committed_high_water.map_or(pending, |hwm| pending.max(hwm + 1));
+
+    let next_nonce = state.reserved.iter()
+        .map(|r| r.nonce + 1)
+        .max()
+        .map_or(pending, |in_flight| in_flight.max(pending));
+
+    state.released = (pending..next_nonce)
+        .filter(|n| !state.reserved.iter().any(|r| r.nonce == *n))
+        .collect();
+    state.next_nonce = next_nonce;
+    state.needs_sync = false;
+}
+
+fn has_expired_reservations(state: &NonceState, timestamp: u64) -> bool {
+    state.reserved.iter().any(|r| timestamp.saturating_sub(r.reserved_at) > RESERVATION_TTL_MS)
 }
 
 #[cfg(test)]
@@ -167,88 +279,138 @@ mod tests {
     use super::*;
     use alloy::primitives::address;
 
-    #[test]
-    fn test_cache_key_generation() {
+    fn synced_state(next_nonce: u64) -> NonceState {
         let addr = address!("0x1234567890123456789012345678901234567890");
-        let chain_id = 42161u64;
-        let key = cache_key(addr, chain_id);
-
-        assert_eq!(key.0, "1234567890123456789012345678901234567890");
-        assert_eq!(key.1, 42161);
+        let mut state = new_state(addr, 42161);
+        reconcile(&mut state, next_nonce, 0);
+        state
     }
 
     #[test]
-    fn test_reserve_and_commit() {
-        clear_all_caches();
-
+    fn test_state_key_generation() {
         let addr = address!("0x1234567890123456789012345678901234567890");
-        let chain_id = 42161u64;
-        let key = cache_key(addr, chain_id);
-
-        // Reserve nonce 10
-        reserve_nonce(addr, chain_id, 10);
+        let key = state_key(addr, 42161);
 
-        let state = NONCE_CACHE.with(|cache| {
-            cache.borrow().get(&key).copied()
-        });
-        assert_eq!(state, Some(NonceState::Reserved(10)));
+        assert_eq!(key.0, "42161:0x1234567890123456789012345678901234567890");
+    }
 
-        // Commit nonce 10
-        commit_nonce(addr, chain_id, 10);
+    #[test]
+    fn test_concurrent_reservations_get_distinct_nonces() {
+        let mut state = synced_state(10);
+
+        assert_eq!(allocate(&mut state, 0), 10);
+        assert_eq!(allocate(&mut state, 0), 11);
+        assert_eq!(allocate(&mut state, 0), 12);
+        assert_eq!(state.next_nonce, 13);
+        assert_eq!(state.reserved.len(), 3);
+    }
 
-        let state = NONCE_CACHE.with(|cache| {
-            cache.borrow().get(&key).copied()
-        });
-        assert_eq!(state, Some(NonceState::Available(11)));
+    #[test]
+    fn test_rollback_below_in_flight_nonce_is_reused() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
+        allocate(&mut state, 0);
+
+        // 10 failed while 11 is still in flight
+        release(&mut state, 10);
+        assert_eq!(state.next_nonce, 12);
+        assert_eq!(state.released, vec![10]);
+
+        assert_eq!(allocate(&mut state, 0), 10);
+        assert_eq!(allocate(&mut state, 0), 12);
     }
 
     #[test]
-    fn test_reserve_and_rollback() {
-        clear_all_caches();
+    fn test_rollback_of_top_nonces_shrinks_next_nonce() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
+        allocate(&mut state, 0);
 
-        let addr = address!("0x1234567890123456789012345678901234567890");
-        let chain_id = 42161u64;
-        let key = cache_key(addr, chain_id);
+        release(&mut state, 10);
+        release(&mut state, 11);
+
+        assert_eq!(state.next_nonce, 10);
+        assert!(state.released.is_empty());
+        assert!(state.reserved.is_empty());
+    }
 
-        // Reserve nonce 10
-        reserve_nonce(addr, chain_id, 10);
+    #[test]
+    fn test_reconcile_keeps_in_flight_reservations() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
+        allocate(&mut state, 0);
+        allocate(&mut state, 0);
+        commit(&mut state, 10, 0);
+
+        // Only 10 reached the mempool; 11 failed without a rollback
+        state.reserved.retain(|r| r.nonce != 11);
+        reconcile(&mut state, 11, 0);
+
+        assert_eq!(state.next_nonce, 13);
+        assert_eq!(state.released, vec![11]);
+        assert_eq!(allocate(&mut state, 0), 11);
+    }
 
-        let state = NONCE_CACHE.with(|cache| {
-            cache.borrow().get(&key).copied()
-        });
-        assert_eq!(state, Some(NonceState::Reserved(10)));
+    #[test]
+    fn test_reconcile_drops_expired_reservations() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
 
-        // Rollback nonce 10
-        rollback_nonce(addr, chain_id, 10);
+        assert!(!has_expired_reservations(&state, RESERVATION_TTL_MS));
+        assert!(has_expired_reservations(&state, RESERVATION_TTL_MS + 1));
 
-        let state = NONCE_CACHE.with(|cache| {
-            cache.borrow().get(&key).copied()
-        });
-        assert_eq!(state, Some(NonceState::Available(10)));
+        reconcile(&mut state, 10, RESERVATION_TTL_MS + 1);
+        assert!(state.reserved.is_empty());
+        assert_eq!(state.next_nonce, 10);
     }
 
     #[test]
-    fn test_invalidate_cache() {
-        clear_all_caches();
-
-        let addr = address!("0x1234567890123456789012345678901234567890");
-        let chain_id = 42161u64;
-        let key = cache_key(addr, chain_id);
+    fn test_reconcile_ignores_lagging_pending_count() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
+        allocate(&mut state, 0);
+        commit(&mut state, 10, 0);
+        commit(&mut state, 11, 0);
+        assert_eq!(state.committed_high_water, Some(11));
+
+        // The node has not seen 10 and 11 yet
+        reconcile(&mut state, 10, 0);
+
+        assert_eq!(state.next_nonce, 12);
+        assert!(state.released.is_empty());
+        assert_eq!(allocate(&mut state, 0), 12);
+    }
 
-        // Set a nonce
-        reserve_nonce(addr, chain_id, 10);
+    #[test]
+    fn test_reconcile_drops_high_water_mark() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
+        commit(&mut state, 10, 0);
+
+        // The node caught up
+        reconcile(&mut state, 11, 0);
+        assert_eq!(state.committed_high_water, None);
+
+        allocate(&mut state, 0);
+        commit(&mut state, 11, 0);
+
+        // 11 was dropped and the pending count never moved past it
+        reconcile(&mut state, 11, RESERVATION_TTL_MS + 1);
+        assert_eq!(state.committed_high_water, None);
+        assert_eq!(state.next_nonce, 11);
+        assert_eq!(allocate(&mut state, 0), 11);
+    }
 
-        let state = NONCE_CACHE.with(|cache| {
-            cache.borrow().get(&key).copied()
-        });
-        assert!(state.is_some());
+    #[test]
+    fn test_reconcile_moves_past_nonces_used_elsewhere() {
+        let mut state = synced_state(10);
+        allocate(&mut state, 0);
+        release(&mut state, 10);
 
-        // Invalidate
-        invalidate_cache(addr, chain_id);
+        reconcile(&mut state, 15, 0);
 
-        let state = NONCE_CACHE.with(|cache| {
-            cache.borrow().get(&key).copied()
-        });
-        assert!(state.is_none());
+        assert_eq!(state.next_nonce, 15);
+        assert!(state.released.is_empty());
+        assert_eq!(allocate(&mut state, 0), 15);
     }
 }
@@ -1,7 +1,17 @@
+use alloy::primitives::U256;
 use candid::Principal;
 use crate::{
-    Permissions, ProtocolPermission, PERMISSIONS_MAP, StorableString, StorablePermissions, now
+    Permissions, ProtocolPermission, PERMISSIONS_MAP, StorableString, now
 };
+use crate::types::TokenAmount;
+use crate::types::permissions::TransferLimit;
+use crate::services::{indexes, price_oracle};
+
+/// Address standing for the chain's native token in token whitelists and transfer limits
+pub const NATIVE_TOKEN_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// Length of a daily transfer limit window (ms)
+const DAY_MS: u64 = 86_400_000;
 
 /// Check if caller is the owner of permissions
 pub fn is_permissions_owner(permissions_id: &str, caller: Principal) -> Result<bool, String> {
@@ -105,6 +115,156 @@ pub fn verify_protocol_permission(
     Err(format!("Protocol {} not found in permissions", normalized_protocol_address))
 }
 
+/// Check the USD per-transaction limit of a protocol permission
+///
+/// The amount is valued with the price oracle. When a limit is set and no
+/// valid price is available the operation is refused.
+pub async fn check_usd_limit(
+    permissions_id: &str,
+    protocol_address: &str,
+    asset: &str,
+    chain_id: u64,
+    amount: &TokenAmount,
+) -> Result<(), String> {
+    let normalized_protocol_address = protocol_address.trim_start_matches("0x").to_lowercase();
+
+    let max_usd = PERMISSIONS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(permissions_id.to_string()))
+            .and_then(|p| {
+                p.0.protocol_permissions
+                    .iter()
+                    .find(|perm| perm.protocol_address.trim_start_matches("0x").to_lowercase() == normalized_protocol_address)
+                    .and_then(|perm| perm.max_usd_per_tx)
+            })
+    });
+
+    let Some(max_usd) = max_usd else {
+        return Ok(());
+    };
+
+    let value_usd = price_oracle::get_usd_value(asset, chain_id, amount).await
+        .map_err(|e| format!("Cannot check USD limit: {}", e))?;
+
+    if value_usd > max_usd {
+        return Err(format!("Transaction value ${:.2} exceeds max limit ${:.2}", value_usd, max_usd));
+    }
+
+    Ok(())
+}
+
+/// Check permission to move tokens out of the wallet or approve a spender
+///
+/// At least one of the owner's permissions on `chain_id` must whitelist the
+/// token (`NATIVE_TOKEN_ADDRESS` for the native token) and, for approvals,
+/// the spender as a protocol. When the token has a transfer limit its
+/// `max_tx_amount` applies, and transfers must also fit in what is left of its
+/// `daily_limit`. Returns the ID of the permissions that allow the operation,
+/// for `record_transfer_usage`.
+pub fn verify_transfer_permission(
+    owner: Principal,
+    chain_id: u64,
+    token_address: &str,
+    spender: Option<&str>,
+    amount: U256,
+) -> Result<String, String> {
+    let permissions: Vec<Permissions> = indexes::get_owner_permissions(owner)
+        .into_iter()
+        .filter(|p| p.chain_id == chain_id)
+        .collect();
+
+    if permissions.is_empty() {
+        return Err(format!("No permissions found for chain_id {}", chain_id));
+    }
+
+    let timestamp = now();
+    let mut last_error = String::new();
+    for p in &permissions {
+        match check_transfer(p, token_address, spender, amount, timestamp) {
+            Ok(()) => return Ok(p.id.clone()),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Count a sent transfer against the token's daily transfer limit
+pub fn record_transfer_usage(permissions_id: &str, token_address: &str, amount: U256) {
+    let Some(mut permissions) = PERMISSIONS_MAP.with(|map| {
+        map.borrow().get(&StorableString(permissions_id.to_string())).map(|p| p.0)
+    }) else {
+        return;
+    };
+
+    let normalized_token_address = token_address.trim_start_matches("0x").to_lowercase();
+    let Some(limit) = permissions.transfer_limits.iter_mut()
+        .find(|l| l.token_address.trim_start_matches("0x").to_lowercase() == normalized_token_address)
+    else {
+        return;
+    };
+
+    let timestamp = now();
+    let used = transfer_used_today(limit, timestamp).saturating_add(usage_units(amount));
+    limit.used_today = Some(used);
+    limit.last_reset_date = Some(timestamp - timestamp % DAY_MS);
+    permissions.updated_at = timestamp;
+
+    ic_cdk::println!("📊 Transfer usage of token {} under permissions {}: {} of {} today",
+        token_address, permissions_id, used, limit.daily_limit);
+    indexes::save_permissions(&permissions);
+}
+
+/// Amount already transferred on the day of `now` under a transfer limit
+fn transfer_used_today(limit: &TransferLimit, now: u64) -> u64 {
+    let today_start = now - now % DAY_MS;
+    match limit.last_reset_date {
+        Some(reset_date) if reset_date >= today_start => limit.used_today.unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Check a transfer or approval against one set of permissions
+fn check_transfer(
+    permissions: &Permissions,
+    token_address: &str,
+    spender: Option<&str>,
+    amount: U256,
+    now: u64,
+) -> Result<(), String> {
+    let normalized_token_address = token_address.trim_start_matches("0x").to_lowercase();
+    let same_address = |address: &str| address.trim_start_matches("0x").to_lowercase() == normalized_token_address;
+
+    if !permissions.whitelisted_tokens.iter().any(|t| same_address(&t.address)) {
+        return Err(format!("Token {} is not whitelisted", token_address));
+    }
+
+    if let Some(spender) = spender {
+        let normalized_spender = spender.trim_start_matches("0x").to_lowercase();
+        let whitelisted = permissions.whitelisted_protocols
+            .iter()
+            .any(|p| p.address.trim_start_matches("0x").to_lowercase() == normalized_spender);
+        if !whitelisted {
+            return Err(format!("Spender {} is not a whitelisted protocol", spender));
+        }
+    }
+
+    if let Some(limit) = permissions.transfer_limits.iter().find(|l| same_address(&l.token_address)) {
+        if amount > U256::from(limit.max_tx_amount) {
+            return Err(format!("Transaction amount {} exceeds max limit {}", amount, limit.max_tx_amount));
+        }
+
+        // Approvals move nothing, so only transfers count against the daily limit
+        let used_today = transfer_used_today(limit, now);
+        if spender.is_none() && U256::from(used_today).saturating_add(amount) > U256::from(limit.daily_limit) {
+            return Err(format!("Daily transfer limit exceeded. Used: {}, Limit: {}, Requested: {}",
+                used_today, limit.daily_limit, amount));
+        }
+    }
+
+    Ok(())
+}
+
 /// Add permission for protocol
 pub fn add_protocol_permission(
     permissions_id: String,
@@ -136,16 +296,18 @@ pub fn add_protocol_permission(
     permissions.updated_at = now();
     
     // Save updated permissions
-    PERMISSIONS_MAP.with(|map| {
-        map.borrow_mut().insert(
-            StorableString(permissions_id), 
-            StorablePermissions(permissions)
-        );
-    });
+    indexes::save_permissions(&permissions);
     
     Ok(true)
 }
 
+/// Token units as the u64 usage limits are kept in, saturating at u64::MAX
+///
+/// Saturating keeps an oversized amount from slipping under a limit.
+pub fn usage_units(amount: U256) -> u64 {
+    u64::try_from(amount).unwrap_or(u64::MAX)
+}
+
 /// Update used limit for today
 pub fn set_daily_usage(
     permissions_id: String,
@@ -187,12 +349,7 @@ pub fn set_daily_usage(
             permissions.updated_at = now;
             
             // Save updated permissions
-            PERMISSIONS_MAP.with(|map| {
-                map.borrow_mut().insert(
-                    StorableString(permissions_id), 
-                    StorablePermissions(permissions)
-                );
-            });
+            indexes::save_permissions(&permissions);
             
             return Ok(true);
         }
@@ -201,3 +358,63 @@ pub fn set_daily_usage(
     Err(format!("Protocol {} not found in permissions", protocol_address))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::permissions::{Protocol, Token};
+
+    const USDC: &str = "0xaf88d065e77c8Cc2239327C5EDb3A432268e5831";
+    const POOL: &str = "0x794a61358D6845594F94dc1DB02A252b5b4814aD";
+
+    fn permissions() -> Permissions {
+        Permissions {
+            id: "perm".to_string(),
+            owner: Principal::anonymous(),
+            chain_id: 42161,
+            whitelisted_protocols: vec![Protocol { name: "AAVE".to_string(), address: POOL.to_string() }],
+            whitelisted_tokens: vec![Token { name: "USDC".to_string(), address: USDC.to_lowercase() }],
+            transfer_limits: vec![TransferLimit {
+                token_address: USDC.to_string(),
+                daily_limit: 1_500_000,
+                max_tx_amount: 1_000_000,
+                used_today: None,
+                last_reset_date: None,
+            }],
+            protocol_permissions: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn transfers_need_whitelisted_token_within_limit() {
+        let p = permissions();
+
+        assert!(check_transfer(&p, USDC, None, U256::from(1_000_000u64), 0).is_ok());
+        assert!(check_transfer(&p, USDC, None, U256::from(1_000_001u64), 0).is_err());
+        assert!(check_transfer(&p, NATIVE_TOKEN_ADDRESS, None, U256::from(1u64), 0).is_err());
+    }
+
+    #[test]
+    fn transfers_count_against_the_daily_limit_until_the_day_ends() {
+        let mut p = permissions();
+        let now = 10 * DAY_MS + 1000;
+        p.transfer_limits[0].used_today = Some(1_000_000);
+        p.transfer_limits[0].last_reset_date = Some(10 * DAY_MS);
+
+        assert!(check_transfer(&p, USDC, None, U256::from(500_000u64), now).is_ok());
+        assert!(check_transfer(&p, USDC, None, U256::from(500_001u64), now).is_err());
+        // Approvals are not transfers
+        assert!(check_transfer(&p, USDC, Some(POOL), U256::from(1_000_000u64), now).is_ok());
+        // Usage from an earlier day no longer counts
+        assert!(check_transfer(&p, USDC, None, U256::from(1_000_000u64), now + DAY_MS).is_ok());
+    }
+
+    #[test]
+    fn approvals_need_whitelisted_spender() {
+        let p = permissions();
+
+        assert!(check_transfer(&p, USDC, Some(&POOL.to_lowercase()), U256::from(1u64), 0).is_ok());
+        assert!(check_transfer(&p, USDC, Some(NATIVE_TOKEN_ADDRESS), U256::from(1u64), 0).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use alloy::primitives::Address;
+use candid::Principal;
+use futures::future::join_all;
+use std::collections::BTreeMap;
+
+use crate::types::{ChainConfig, ChainPortfolio, HoldingKind, Portfolio, PortfolioHolding, TokenAmount};
+use crate::{PRINCIPAL_TO_ADDRESS_MAP, StorablePrincipal, now};
+use crate::services::{apy_parser, chain_registry, price_oracle};
+use crate::services::multicall::Multicall;
+use crate::services::position_reconciler::{self, ProtocolMarket};
+use crate::services::rpc_service::get_supported_chain_ids;
+
+// =============================================================================
+// Portfolio Summary
+// =============================================================================
+//
+// One call returning everything a user holds through their canister-derived
+// address: native gas balances, wallet token balances and protocol positions
+// on every supported chain, each valued in USD.
+//
+// Chains are read concurrently, and all balances of a chain go out as one
+// Multicall3 batch. Holdings are valued with the Chainlink prices of
+// `price_oracle`.
+//
+
+/// A balance to read for a portfolio
+struct HoldingRead<'a> {
+    kind: HoldingKind,
+    asset: &'a str,
+    /// Token contract (None for the native token)
+    token_address: Option<Address>,
+    /// Contract answering `balanceOf` (None for the native token)
+    balance_contract: Option<Address>,
+    decimals: u8,
+    /// Protocol market for protocol balances
+    market: Option<&'a ProtocolMarket>,
+}
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// Build the portfolio of a user across all supported chains
+pub async fn get_portfolio(user: Principal) -> Result<Portfolio, String> {
+    let evm_address = PRINCIPAL_TO_ADDRESS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorablePrincipal(user))
+            .map(|s| s.0.clone())
+            .ok_or_else(|| "No EVM address found for caller. Generate one first.".to_string())
+    })?;
+    let address = evm_address.parse::<Address>()
+        .map_err(|_| "Invalid user address".to_string())?;
+
+    ic_cdk::println!("💼 Building portfolio for {} ({})", user, evm_address);
+
+    let chain_results = join_all(
+        get_supported_chain_ids().into_iter().map(|chain_id| read_chain(chain_id, address))
+    ).await;
+
+    let mut portfolio = Portfolio {
+        evm_address,
+        chains: Vec::new(),
+        native_usd: 0.0,
+        wallet_usd: 0.0,
+        protocol_usd: 0.0,
+        total_usd: 0.0,
+        weighted_apy: None,
+        errors: Vec::new(),
+        generated_at: now(),
+    };
+
+    let mut apy_weighted_sum = 0.0;
+    let mut apy_weight = 0.0;
+
+    for (chain, errors) in chain_results {
+        portfolio.errors.extend(errors);
+
+        for holding in &chain.holdings {
+            let Some(value) = holding.value_usd else { continue };
+            match holding.kind {
+                HoldingKind::Native => portfolio.native_usd += value,
+                HoldingKind::Wallet => portfolio.wallet_usd += value,
+                HoldingKind::Protocol => portfolio.protocol_usd += value,
+            }
+            if let Some(apy) = holding.apy {
+                apy_weighted_sum += apy * value;
+                apy_weight += value;
+            }
+        }
+
+        portfolio.total_usd += chain.total_usd;
+        if !chain.holdings.is_empty() {
+            portfolio.chains.push(chain);
+        }
+    }
+
+    if apy_weight > 0.0 {
+        portfolio.weighted_apy = Some(apy_weighted_sum / apy_weight);
+    }
+
+    ic_cdk::println!("✅ Portfolio built: ${:.2} across {} chains ({} errors)",
+        portfolio.total_usd, portfolio.chains.len(), portfolio.errors.len());
+
+    Ok(portfolio)
+}
+
+// =============================================================================
+// Chain Reads
+// =============================================================================
+
+/// Read and value every balance of an address on one chain
+async fn read_chain(chain_id: u64, address: Address) -> (ChainPortfolio, Vec<String>) {
+    let mut errors = Vec::new();
+    let Some(chain) = chain_registry::get_enabled_chain(chain_id) else {
+        errors.push(format!("Chain {} is not in the registry", chain_id));
+        let chain_name = "Unknown".to_string();
+        return (ChainPortfolio { chain_id, chain_name, holdings: Vec::new(), total_usd: 0.0 }, errors);
+    };
+    let chain_name = chain.name.clone();
+    let markets = position_reconciler::get_protocol_markets(chain_id);
+
+    let targets = match position_reconciler::balance_targets(&markets, chain_id).await {
+        Ok(targets) => targets,
+        Err(e) => {
+            errors.push(format!("Protocol balances on {}: {}", chain_name, e));
+            Vec::new()
+        }
+    };
+    let reads = holding_reads(&chain, &markets, &targets);
+
+    let mut multicall = Multicall::new(chain_id);
+    for read in &reads {
+        match read.balance_contract {
+            Some(contract) => multicall.add_token_balance(contract, address),
+            None => multicall.add_native_balance(address),
+        };
+    }
+    let results = match multicall.execute().await {
+        Ok(results) => results,
+        Err(e) => {
+            errors.push(format!("Balances on {}: {}", chain_name, e));
+            return (ChainPortfolio { chain_id, chain_name, holdings: Vec::new(), total_usd: 0.0 }, errors);
+        }
+    };
+
+    let mut held: Vec<(&HoldingRead, TokenAmount)> = Vec::new();
+    for (index, read) in reads.iter().enumerate() {
+        let balance = results.decode_uint(index)
+            .and_then(|units| TokenAmount::from_u256(units, read.decimals));
+        match balance {
+            Ok(amount) if !amount.is_zero() => held.push((read, amount)),
+            Ok(_) => {}
+            Err(e) => errors.push(format!("{} {} on {}: {}", describe(read), read.asset, chain_name, e)),
+        }
+    }
+
+    let mut assets: Vec<&str> = held.iter().map(|(read, _)| read.asset).collect();
+    assets.sort_unstable();
+    assets.dedup();
+
+    let quotes = join_all(assets.iter().map(|asset| price_oracle::get_price(asset, chain_id))).await;
+    let mut prices: BTreeMap<&str, f64> = BTreeMap::new();
+    for (asset, quote) in assets.into_iter().zip(quotes) {
+        match quote {
+            Ok(price) => {
+                prices.insert(asset, price.price_usd);
+            }
+            Err(e) => errors.push(format!("{} price on {}: {}", asset, chain_name, e)),
+        }
+    }
+
+    let holdings: Vec<PortfolioHolding> = held.into_iter()
+        .map(|(read, amount)| {
+            let price_usd = prices.get(read.asset).copied();
+            let apy = read.market.and_then(|market| {
+                apy_parser::get_last_apy_before(&market.protocol, &market.asset, chain_id, u64::MAX).map(|r| r.apy)
+            });
+            PortfolioHolding {
+                kind: read.kind.clone(),
+                protocol: read.market.map(|market| market.protocol.clone()),
+                asset: read.asset.to_string(),
+                token_address: read.token_address.map(|token| format!("0x{:x}", token)),
+                amount,
+                price_usd,
+                value_usd: price_usd.map(|price| price * amount.to_f64()),
+                apy,
+            }
+        })
+        .collect();
+
+    let total_usd = holdings.iter().filter_map(|h| h.value_usd).sum();
+
+    (ChainPortfolio { chain_id, chain_name, holdings, total_usd }, errors)
+}
+
+/// Native balance, wallet balance of every market token, and every market position
+///
+/// `targets` are the protocol balance contracts of `markets`; protocol
+/// positions are left out when they could not be resolved.
+fn holding_reads<'a>(chain: &'a ChainConfig, markets: &'a [ProtocolMarket], targets: &[Address]) -> Vec<HoldingRead<'a>> {
+    let mut reads = vec![HoldingRead {
+        kind: HoldingKind::Native,
+        asset: &chain.native_symbol,
+        token_address: None,
+        balance_contract: None,
+        decimals: chain.native_decimals,
+        market: None,
+    }];
+
+    for market in markets {
+        let listed = reads.iter().any(|r| r.kind == HoldingKind::Wallet && r.token_address == Some(market.token_address));
+        if !listed {
+            reads.push(HoldingRead {
+                kind: HoldingKind::Wallet,
+                asset: &market.asset,
+                token_address: Some(market.token_address),
+                balance_contract: Some(market.token_address),
+                decimals: market.decimals,
+                market: None,
+            });
+        }
+    }
+
+    reads.extend(markets.iter().zip(targets).map(|(market, target)| HoldingRead {
+        kind: HoldingKind::Protocol,
+        asset: &market.asset,
+        token_address: Some(market.token_address),
+        balance_contract: Some(*target),
+        decimals: market.decimals,
+        market: Some(market),
+    }));
+
+    reads
+}
+
+fn describe(read: &HoldingRead) -> String {
+    match (&read.kind, read.market) {
+        (HoldingKind::Protocol, Some(market)) => market.protocol.clone(),
+        (HoldingKind::Native, _) => "Native".to_string(),
+        _ => "Wallet".to_string(),
+    }
+}
@@ -0,0 +1,517 @@
+use alloy::primitives::Address;
+use candid::Principal;
+use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::types::{
+    PositionDiscrepancy, PositionReconciliationReport, UserPosition, TokenAmount,
+};
+use crate::{USER_POSITIONS_MAP, PERMISSIONS_MAP, PRINCIPAL_TO_ADDRESS_MAP, StorableString, StorablePrincipal, now};
+use crate::services::{aave, chain_registry, compound, indexes, locks, position_sync, yield_ledger};
+use crate::services::multicall::Multicall;
+use crate::services::rpc_service::get_supported_chain_ids;
+
+// =============================================================================
+// Position Reconciliation
+// =============================================================================
+//
+// `position_sync` only updates positions with the amounts our own supplies and
+// withdrawals requested. Interest accrual, actions taken outside the canister
+// and reverted transactions make the recorded sizes drift.
+//
+// The reconciler reads the on-chain aToken/Comet balance of every address that
+// has a tracked position and makes USER_POSITIONS_MAP match it:
+// - Corrects position_size when it differs from the balance
+// - Creates positions for balances we did not know about
+// - Deletes positions whose balance is zero
+//
+// Only USDC positions are reconciled, as those are the ones the scheduler moves.
+//
+// The balances of all accounts on a chain are read in one batched call.
+//
+// Users can also import balances they held before using the canister: every
+// registered protocol market on every supported chain is scanned and non-zero
+// balances become tracked positions.
+//
+
+/// How often the reconciler runs (6 hours)
+const RECONCILE_INTERVAL_SECONDS: u64 = 6 * 3600;
+
+/// Asset whose balances are reconciled
+const RECONCILED_ASSET: &str = "USDC";
+
+thread_local! {
+    /// Active timer ID for periodic reconciliation
+    static RECONCILE_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+
+    /// Report of the most recent reconciliation pass
+    static LAST_RECONCILIATION_REPORT: RefCell<Option<PositionReconciliationReport>> = RefCell::new(None);
+}
+
+/// Protocol market whose balances can be read for an address
+pub(crate) struct ProtocolMarket {
+    /// Protocol ("AAVE" | "COMPOUND")
+    pub(crate) protocol: String,
+    /// Underlying asset symbol
+    pub(crate) asset: String,
+    /// Underlying token address
+    pub(crate) token_address: Address,
+    /// Underlying token decimals
+    pub(crate) decimals: u8,
+}
+
+/// Address whose balances are reconciled
+struct ReconciledAccount {
+    user_principal: Principal,
+    user_evm_address: String,
+    chain_id: u64,
+    /// Permissions used for positions discovered on this account
+    permissions_id: String,
+}
+
+// =============================================================================
+// Reconciliation
+// =============================================================================
+
+/// Reconcile every tracked address against on-chain balances
+pub async fn reconcile_positions() -> Result<PositionReconciliationReport, String> {
+    let _lock = locks::try_acquire_reconciler_lock()?;
+
+    ic_cdk::println!("🔎 Position reconciliation started");
+
+    let mut report = PositionReconciliationReport {
+        balances_checked: 0,
+        updated: 0,
+        created: 0,
+        deleted: 0,
+        users_skipped: 0,
+        errors: Vec::new(),
+        discrepancies: Vec::new(),
+        started_at: now(),
+        completed_at: 0,
+    };
+
+    let accounts = collect_accounts();
+    let mut chain_ids: Vec<u64> = accounts.iter().map(|account| account.chain_id).collect();
+    chain_ids.sort_unstable();
+    chain_ids.dedup();
+
+    for chain_id in chain_ids {
+        let chain_accounts: Vec<&ReconciledAccount> = accounts.iter()
+            .filter(|account| account.chain_id == chain_id)
+            .collect();
+        reconcile_chain(chain_id, chain_accounts, &mut report).await;
+    }
+
+    report.completed_at = now();
+
+    ic_cdk::println!("✅ Position reconciliation finished: {} balances checked, {} updated, {} created, {} deleted, {} skipped, {} errors",
+        report.balances_checked, report.updated, report.created, report.deleted,
+        report.users_skipped, report.errors.len());
+
+    LAST_RECONCILIATION_REPORT.with(|last| {
+        *last.borrow_mut() = Some(report.clone());
+    });
+
+    Ok(report)
+}
+
+/// Reconcile the accounts of one chain
+async fn reconcile_chain(
+    chain_id: u64,
+    accounts: Vec<&ReconciledAccount>,
+    report: &mut PositionReconciliationReport,
+) {
+    let mut locked = Vec::new();
+    for account in accounts {
+        // Skip users with a fund-moving operation in flight; their balances are in motion
+        let guard = match locks::try_acquire_user_lock(account.user_principal, "reconcile_positions") {
+            Ok(guard) => guard,
+            Err(e) => {
+                ic_cdk::println!("  ⏭️ Skipping {}: {}", account.user_principal, e);
+                report.users_skipped += 1;
+                continue;
+            }
+        };
+        match account.user_evm_address.parse::<Address>() {
+            Ok(holder) => locked.push((account, holder, guard)),
+            Err(_) => report.errors.push(format!("{}: invalid EVM address", account.user_evm_address)),
+        }
+    }
+
+    if locked.is_empty() {
+        return;
+    }
+
+    let markets: Vec<ProtocolMarket> = get_protocol_markets(chain_id)
+        .into_iter()
+        .filter(|market| market.asset == RECONCILED_ASSET)
+        .collect();
+    let holders: Vec<Address> = locked.iter().map(|(_, holder, _)| *holder).collect();
+
+    let balances = match read_onchain_balances(&markets, &holders, chain_id).await {
+        Ok(balances) => balances,
+        Err(e) => {
+            let error = format!("Chain {}: {}", chain_id, e);
+            ic_cdk::println!("  ❌ Balance read failed: {}", error);
+            report.errors.push(error);
+            return;
+        }
+    };
+
+    for ((account, _, _user_lock), account_balances) in locked.iter().zip(balances) {
+        for (market, balance) in markets.iter().zip(account_balances) {
+            let onchain_size = match balance {
+                Ok(size) => size,
+                Err(e) => {
+                    let error = format!("{} {} on chain {}: {}",
+                        account.user_evm_address, market.protocol, chain_id, e);
+                    ic_cdk::println!("  ❌ Balance read failed: {}", error);
+                    report.errors.push(error);
+                    continue;
+                }
+            };
+            report.balances_checked += 1;
+
+            reconcile_balance(account, market, onchain_size, report).await;
+        }
+    }
+}
+
+/// Bring one protocol position of an account in line with its on-chain balance
+async fn reconcile_balance(
+    account: &ReconciledAccount,
+    market: &ProtocolMarket,
+    onchain_size: TokenAmount,
+    report: &mut PositionReconciliationReport,
+) {
+    let protocol = market.protocol.as_str();
+    yield_ledger::record_valuation(account.user_principal, protocol, &market.asset, account.chain_id, onchain_size);
+
+    let existing = position_sync::find_user_position(
+        account.user_principal, protocol, RECONCILED_ASSET, account.chain_id
+    );
+
+    match existing {
+        Some(mut position) => {
+            if onchain_size.is_zero() {
+                indexes::remove_position(&position.position_id);
+                report.deleted += 1;
+                record_discrepancy(report, account, protocol, position.position_id, &position.position_size, onchain_size, "deleted");
+            } else if position.size().ok() != Some(onchain_size) {
+                let recorded = position.position_size.clone();
+                position.set_size(onchain_size);
+                position.updated_at = now();
+                indexes::save_position(&position);
+                report.updated += 1;
+                record_discrepancy(report, account, protocol, position.position_id, &recorded, onchain_size, "updated");
+            }
+        },
+        None => {
+            if onchain_size.is_zero() {
+                return;
+            }
+
+            let position = create_position(
+                account.user_principal,
+                account.user_evm_address.clone(),
+                account.permissions_id.clone(),
+                market,
+                account.chain_id,
+                onchain_size,
+                true,
+            ).await;
+            report.created += 1;
+            record_discrepancy(report, account, protocol, position.position_id, "0", onchain_size, "created");
+        }
+    }
+}
+
+/// Log a discrepancy and add it to the report
+fn record_discrepancy(
+    report: &mut PositionReconciliationReport,
+    account: &ReconciledAccount,
+    protocol: &str,
+    position_id: String,
+    recorded_size: &str,
+    onchain_size: TokenAmount,
+    action: &str,
+) {
+    ic_cdk::println!("  ⚖️ Discrepancy for {} {} {} on chain {}: recorded {} vs on-chain {} → {}",
+        account.user_principal, protocol, RECONCILED_ASSET, account.chain_id,
+        recorded_size, onchain_size, action);
+
+    report.discrepancies.push(PositionDiscrepancy {
+        user_principal: account.user_principal,
+        position_id,
+        protocol: protocol.to_string(),
+        asset: RECONCILED_ASSET.to_string(),
+        chain_id: account.chain_id,
+        recorded_size: recorded_size.to_string(),
+        onchain_size: onchain_size.to_string(),
+        action: action.to_string(),
+    });
+}
+
+// =============================================================================
+// Import
+// =============================================================================
+
+/// Import a user's pre-existing protocol balances as positions
+///
+/// Scans every registered market on every supported chain. Positions on the
+/// permissions' chain are tracked; positions on other chains are imported
+/// untracked, since the permissions cannot be used there. Markets that already
+/// have a position are left to the reconciler.
+pub async fn import_positions(user: Principal, permissions_id: String) -> Result<Vec<UserPosition>, String> {
+    ic_cdk::println!("📥 Importing existing positions for user {} (permissions {})", user, permissions_id);
+
+    let permissions = PERMISSIONS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(permissions_id.clone()))
+            .map(|p| p.0)
+            .ok_or_else(|| "Permissions not found".to_string())
+    })?;
+
+    if permissions.owner != user {
+        return Err("Access denied: not the owner".to_string());
+    }
+
+    let user_evm_address = PRINCIPAL_TO_ADDRESS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorablePrincipal(user))
+            .map(|addr| addr.0)
+            .ok_or_else(|| "No EVM address found for caller. Generate one first.".to_string())
+    })?;
+
+    let holder = user_evm_address.parse::<Address>()
+        .map_err(|_| "Invalid user address".to_string())?;
+
+    let _lock = locks::try_acquire_user_lock(user, "import_positions")?;
+
+    let mut imported = Vec::new();
+
+    for chain_id in get_supported_chain_ids() {
+        let markets: Vec<ProtocolMarket> = get_protocol_markets(chain_id)
+            .into_iter()
+            .filter(|market| position_sync::find_user_position(user, &market.protocol, &market.asset, chain_id).is_none())
+            .collect();
+        if markets.is_empty() {
+            continue;
+        }
+
+        let balances = match read_onchain_balances(&markets, &[holder], chain_id).await {
+            Ok(mut balances) => balances.remove(0),
+            Err(e) => {
+                ic_cdk::println!("  ⚠️ Could not read balances on chain {}: {}", chain_id, e);
+                continue;
+            }
+        };
+
+        for (market, balance) in markets.iter().zip(balances) {
+            let balance = match balance {
+                Ok(balance) => balance,
+                Err(e) => {
+                    ic_cdk::println!("  ⚠️ Could not read {} {} on chain {}: {}",
+                        market.protocol, market.asset, chain_id, e);
+                    continue;
+                }
+            };
+
+            if balance.is_zero() {
+                continue;
+            }
+
+            yield_ledger::record_valuation(user, &market.protocol, &market.asset, chain_id, balance);
+
+            let tracked = chain_id == permissions.chain_id;
+            let position = create_position(
+                user,
+                user_evm_address.clone(),
+                permissions_id.clone(),
+                market,
+                chain_id,
+                balance,
+                tracked,
+            ).await;
+
+            ic_cdk::println!("  ➕ Imported {} {} {} on chain {} as {}{}",
+                balance, market.asset, market.protocol, chain_id, position.position_id,
+                if tracked { "" } else { " (untracked: permissions are for another chain)" });
+            imported.push(position);
+        }
+    }
+
+    ic_cdk::println!("✅ Imported {} positions for user {}", imported.len(), user);
+    Ok(imported)
+}
+
+// =============================================================================
+// Timer
+// =============================================================================
+
+/// Start the periodic reconciliation timer
+///
+/// Ticks are skipped while position auto-sync is disabled.
+pub fn start_reconciliation_timer() {
+    RECONCILE_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow().as_ref() {
+            clear_timer(*id);
+        }
+    });
+
+    let timer_id = set_timer_interval(Duration::from_secs(RECONCILE_INTERVAL_SECONDS), || {
+        if !position_sync::is_auto_sync_enabled() {
+            ic_cdk::println!("ℹ️ Position auto-sync is disabled, skipping reconciliation");
+            return;
+        }
+
+        ic_cdk::spawn(async {
+            if let Err(e) = reconcile_positions().await {
+                ic_cdk::println!("⏭️ Reconciliation skipped: {}", e);
+            }
+        });
+    });
+
+    RECONCILE_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+
+    ic_cdk::println!("✅ Position reconciliation timer started with interval: {} seconds", RECONCILE_INTERVAL_SECONDS);
+}
+
+/// Get the report of the most recent reconciliation pass
+pub fn get_last_reconciliation_report() -> Option<PositionReconciliationReport> {
+    LAST_RECONCILIATION_REPORT.with(|last| last.borrow().clone())
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+/// Distinct (user, chain) accounts that hold a tracked position
+fn collect_accounts() -> Vec<ReconciledAccount> {
+    USER_POSITIONS_MAP.with(|map| {
+        let mut accounts: BTreeMap<(Principal, u64), ReconciledAccount> = BTreeMap::new();
+
+        for (_, position) in map.borrow().iter() {
+            let p = position.0;
+            if !p.tracked {
+                continue;
+            }
+
+            accounts.entry((p.user_principal, p.chain_id)).or_insert(ReconciledAccount {
+                user_principal: p.user_principal,
+                user_evm_address: p.user_evm_address,
+                chain_id: p.chain_id,
+                permissions_id: p.permissions_id,
+            });
+        }
+
+        accounts.into_values().collect()
+    })
+}
+
+/// Protocol markets registered on a chain
+pub(crate) fn get_protocol_markets(chain_id: u64) -> Vec<ProtocolMarket> {
+    let Some(chain) = chain_registry::get_enabled_chain(chain_id) else {
+        return Vec::new();
+    };
+
+    chain.protocols
+        .into_iter()
+        .flat_map(|deployment| {
+            let protocol = deployment.protocol;
+            deployment.assets.into_iter().filter_map(move |asset| {
+                Some(ProtocolMarket {
+                    protocol: protocol.clone(),
+                    token_address: asset.token_address.parse::<Address>().ok()?,
+                    asset: asset.symbol,
+                    decimals: asset.decimals,
+                })
+            })
+        })
+        .collect()
+}
+
+/// Contract whose `balanceOf` returns an address's balance in each market
+///
+/// aTokens for AAVE (addresses cached after the first read), Comet for Compound.
+pub(crate) async fn balance_targets(markets: &[ProtocolMarket], chain_id: u64) -> Result<Vec<Address>, String> {
+    let aave_tokens: Vec<Address> = markets.iter()
+        .filter(|market| market.protocol == "AAVE")
+        .map(|market| market.token_address)
+        .collect();
+    let mut atokens = aave::get_atoken_addresses(&aave_tokens, chain_id).await?.into_iter();
+
+    markets.iter()
+        .map(|market| match market.protocol.as_str() {
+            "AAVE" => atokens.next().ok_or_else(|| "Missing aToken address".to_string()),
+            "COMPOUND" => compound::get_comet_address(chain_id),
+            _ => Err(format!("Unsupported protocol for reconciliation: {}", market.protocol)),
+        })
+        .collect()
+}
+
+/// Read the human-readable balances several addresses hold in protocol markets of one chain
+///
+/// All reads go out as one batched call. Results are indexed `[holder][market]`.
+pub(crate) async fn read_onchain_balances(
+    markets: &[ProtocolMarket],
+    holders: &[Address],
+    chain_id: u64,
+) -> Result<Vec<Vec<Result<TokenAmount, String>>>, String> {
+    let targets = balance_targets(markets, chain_id).await?;
+
+    let mut multicall = Multicall::new(chain_id);
+    for holder in holders {
+        for target in &targets {
+            multicall.add_token_balance(*target, *holder);
+        }
+    }
+    let results = multicall.execute().await?;
+
+    Ok((0..holders.len())
+        .map(|holder_index| {
+            markets.iter().enumerate()
+                .map(|(market_index, market)| {
+                    let units = results.decode_uint(holder_index * markets.len() + market_index)?;
+                    TokenAmount::from_u256(units, market.decimals)
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Create and persist a position for an on-chain balance
+async fn create_position(
+    user_principal: Principal,
+    user_evm_address: String,
+    permissions_id: String,
+    market: &ProtocolMarket,
+    chain_id: u64,
+    size: TokenAmount,
+    tracked: bool,
+) -> UserPosition {
+    let timestamp = now();
+    let position = UserPosition {
+        position_id: position_sync::generate_position_id().await,
+        user_principal,
+        user_evm_address,
+        permissions_id,
+        protocol: market.protocol.clone(),
+        asset: market.asset.clone(),
+        token_address: format!("0x{:x}", market.token_address),
+        chain_id,
+        position_size: size.to_string(),
+        position_amount: Some(size),
+        tracked,
+        execution_mode: None,
+        added_at: timestamp,
+        updated_at: timestamp,
+    };
+    indexes::save_position(&position);
+    position
+}
@@ -1,7 +1,7 @@
 use candid::Principal;
-use crate::{USER_POSITIONS_MAP, PRINCIPAL_TO_ADDRESS_MAP, StorableString, StorablePrincipal, now};
-use crate::types::{UserPosition, StorableUserPosition};
-use crate::services::apy_parser;
+use crate::{PRINCIPAL_TO_ADDRESS_MAP, StorablePrincipal, now};
+use crate::types::{UserPosition, TokenAmount};
+use crate::services::{apy_parser, indexes, yield_ledger};
 
 // =============================================================================
 // Position Synchronization Module
@@ -15,6 +15,7 @@ use crate::services::apy_parser;
 // - Auto-create position on first supply
 // - Auto-update position size on supply/withdraw
 // - Auto-delete position when balance reaches zero
+// - Record deposits and withdrawals in the yield ledger
 // - Global enable/disable flag in ApyParserConfig
 // - Search positions by: user_principal + protocol + asset + chain_id
 //
@@ -33,7 +34,7 @@ pub fn is_auto_sync_enabled() -> bool {
 // =============================================================================
 
 /// Find existing user position by user, protocol, asset, and chain
-/// This searches across all positions without requiring permissions_id
+/// Range-scans the market index, so it does not require permissions_id
 pub fn find_user_position(
     user: Principal,
     protocol: &str,
@@ -43,26 +44,14 @@ pub fn find_user_position(
     ic_cdk::println!("🔍 Searching for position: user={}, protocol={}, asset={}, chain_id={}",
         user, protocol, asset, chain_id);
 
-    USER_POSITIONS_MAP.with(|map| {
-        let borrowed = map.borrow();
-        let result = borrowed
-            .iter()
-            .find(|(_, position)| {
-                let p = &position.0;
-                p.user_principal == user &&
-                p.protocol.eq_ignore_ascii_case(protocol) &&
-                p.asset.eq_ignore_ascii_case(asset) &&
-                p.chain_id == chain_id
-            })
-            .map(|(_, position)| position.0.clone());
-
-        match &result {
-            Some(pos) => ic_cdk::println!("✅ Found existing position: {}", pos.position_id),
-            None => ic_cdk::println!("ℹ️ No existing position found"),
-        }
+    let result = indexes::find_market_position(user, protocol, asset, chain_id);
+
+    match &result {
+        Some(pos) => ic_cdk::println!("✅ Found existing position: {}", pos.position_id),
+        None => ic_cdk::println!("ℹ️ No existing position found"),
+    }
 
-        result
-    })
+    result
 }
 
 // =============================================================================
@@ -78,8 +67,11 @@ pub async fn sync_position_after_supply(
     asset: String,
     token_address: String,
     chain_id: u64,
-    amount_supplied: String, // Human-readable amount (e.g., "100.5")
+    amount_supplied: TokenAmount,
 ) -> Result<(), String> {
+    // Yield accounting is kept even when position auto-sync is disabled
+    yield_ledger::record_supply(user_principal, &protocol, &asset, chain_id, amount_supplied);
+
     // Check if auto-sync is enabled
     if !is_auto_sync_enabled() {
         ic_cdk::println!("ℹ️ Position auto-sync is disabled, skipping synchronization");
@@ -89,11 +81,7 @@ pub async fn sync_position_after_supply(
     ic_cdk::println!("🔄 Syncing position after supply: protocol={}, asset={}, amount={}",
         protocol, asset, amount_supplied);
 
-    // Parse supplied amount
-    let amount_f64: f64 = amount_supplied.parse()
-        .map_err(|e| format!("Failed to parse amount: {}", e))?;
-
-    if amount_f64 <= 0.0 {
+    if amount_supplied.is_zero() {
         return Err("Supply amount must be positive".to_string());
     }
 
@@ -103,23 +91,17 @@ pub async fn sync_position_after_supply(
             // Update existing position
             ic_cdk::println!("📝 Updating existing position: {}", existing_position.position_id);
 
-            let old_size: f64 = existing_position.position_size.parse()
-                .unwrap_or(0.0);
-            let new_size = old_size + amount_f64;
+            let old_size = existing_position.size()?;
+            let new_size = old_size.checked_add(&amount_supplied)?;
 
-            existing_position.position_size = format!("{}", new_size);
+            existing_position.set_size(new_size);
             existing_position.updated_at = now();
 
             // Save updated position
-            USER_POSITIONS_MAP.with(|map| {
-                map.borrow_mut().insert(
-                    StorableString(existing_position.position_id.clone()),
-                    StorableUserPosition(existing_position.clone())
-                );
-            });
+            indexes::save_position(&existing_position);
 
             ic_cdk::println!("✅ Position updated: {} → {} (added {})",
-                old_size, new_size, amount_f64);
+                old_size, new_size, amount_supplied);
             Ok(())
         }
         None => {
@@ -147,19 +129,16 @@ pub async fn sync_position_after_supply(
                 asset,
                 token_address,
                 chain_id,
-                position_size: amount_supplied.clone(),
+                position_size: amount_supplied.to_string(),
+                position_amount: Some(amount_supplied),
                 tracked: true, // Default to true, user can disable if needed
+                execution_mode: None,
                 added_at: timestamp,
                 updated_at: timestamp,
             };
 
             // Save new position
-            USER_POSITIONS_MAP.with(|map| {
-                map.borrow_mut().insert(
-                    StorableString(position_id.clone()),
-                    StorableUserPosition(new_position.clone())
-                );
-            });
+            indexes::save_position(&new_position);
 
             ic_cdk::println!("✅ New position created: {} with size {}",
                 position_id, amount_supplied);
@@ -175,8 +154,11 @@ pub async fn sync_position_after_withdraw(
     protocol: String,
     asset: String,
     chain_id: u64,
-    amount_withdrawn: String, // Human-readable amount (e.g., "50.5")
+    amount_withdrawn: TokenAmount,
 ) -> Result<(), String> {
+    // Yield accounting is kept even when position auto-sync is disabled
+    yield_ledger::record_withdraw(user_principal, &protocol, &asset, chain_id, amount_withdrawn);
+
     // Check if auto-sync is enabled
     if !is_auto_sync_enabled() {
         ic_cdk::println!("ℹ️ Position auto-sync is disabled, skipping synchronization");
@@ -186,11 +168,7 @@ pub async fn sync_position_after_withdraw(
     ic_cdk::println!("🔄 Syncing position after withdraw: protocol={}, asset={}, amount={}",
         protocol, asset, amount_withdrawn);
 
-    // Parse withdrawn amount
-    let amount_f64: f64 = amount_withdrawn.parse()
-        .map_err(|e| format!("Failed to parse amount: {}", e))?;
-
-    if amount_f64 <= 0.0 {
+    if amount_withdrawn.is_zero() {
         return Err("Withdraw amount must be positive".to_string());
     }
 
@@ -199,34 +177,26 @@ pub async fn sync_position_after_withdraw(
         Some(mut existing_position) => {
             ic_cdk::println!("📝 Updating position after withdrawal: {}", existing_position.position_id);
 
-            let old_size: f64 = existing_position.position_size.parse()
-                .unwrap_or(0.0);
-            let new_size = old_size - amount_f64;
+            let old_size = existing_position.size()?;
+            let new_size = old_size.saturating_sub(&amount_withdrawn)?;
 
             ic_cdk::println!("💰 Position size change: {} → {} (withdrawn {})",
-                old_size, new_size, amount_f64);
+                old_size, new_size, amount_withdrawn);
 
-            // If new size is zero or negative, delete the position
-            if new_size <= 0.0001 { // Use small epsilon for floating point comparison
+            // Withdrawing accrued interest can exceed the recorded size, so stop at zero
+            if new_size.is_zero() {
                 ic_cdk::println!("🗑️ Position balance reached zero, deleting position: {}",
                     existing_position.position_id);
 
-                USER_POSITIONS_MAP.with(|map| {
-                    map.borrow_mut().remove(&StorableString(existing_position.position_id.clone()));
-                });
+                indexes::remove_position(&existing_position.position_id);
 
                 ic_cdk::println!("✅ Position deleted successfully");
             } else {
                 // Update position with new size
-                existing_position.position_size = format!("{}", new_size);
+                existing_position.set_size(new_size);
                 existing_position.updated_at = now();
 
-                USER_POSITIONS_MAP.with(|map| {
-                    map.borrow_mut().insert(
-                        StorableString(existing_position.position_id.clone()),
-                        StorableUserPosition(existing_position.clone())
-                    );
-                });
+                indexes::save_position(&existing_position);
 
                 ic_cdk::println!("✅ Position updated with new size: {}", new_size);
             }
@@ -247,7 +217,7 @@ pub async fn sync_position_after_withdraw(
 // =============================================================================
 
 /// Generate unique position ID
-async fn generate_position_id() -> String {
+pub(crate) async fn generate_position_id() -> String {
     let timestamp = now();
     let random_result = ic_cdk::api::management_canister::main::raw_rand().await;
     let random_bytes = match random_result {
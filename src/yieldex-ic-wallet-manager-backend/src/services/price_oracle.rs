@@ -0,0 +1,370 @@
+use alloy::{
+    primitives::Address,
+    providers::{Provider, ProviderBuilder},
+    sol,
+    transports::icp::IcpConfig,
+};
+use std::cell::RefCell;
+
+use crate::types::{PriceOracleConfig, StorableTokenPrice, TokenAmount, TokenPrice};
+use crate::{PRICE_CACHE_MAP, StorableString, now};
+use crate::services::{chain_registry, position_reconciler};
+use crate::services::rpc_service::get_rpc_service_by_chain_id;
+
+// =============================================================================
+// Price Oracle
+// =============================================================================
+//
+// USD prices from Chainlink AggregatorV3 feeds, per chain and asset. Feeds
+// are listed per chain in the chain registry.
+//
+// An answer is rejected when it is not positive, when the feed has not
+// updated within its heartbeat (plus a grace period), or when it moves more
+// than `max_deviation_percent` away from a previous price that is still fresh.
+//
+// Accepted prices are cached in PRICE_CACHE_MAP and served for
+// `cache_ttl_seconds`. If a feed read fails, the cached price is served for
+// as long as it is within the feed's heartbeat. If the answer was rejected for
+// deviating from the cached price, that price is only served for
+// `DEVIATION_FALLBACK_MAX_AGE_MS` after it was accepted, so a genuine market
+// move is not hidden behind an old price for a whole heartbeat.
+//
+
+sol! {
+    #[sol(rpc)]
+    interface IAggregatorV3 {
+        function latestRoundData() external view returns (
+            uint80 roundId,
+            int256 answer,
+            uint256 startedAt,
+            uint256 updatedAt,
+            uint80 answeredInRound
+        );
+    }
+}
+
+/// Longest time after acceptance a cached price stands in for answers that deviate from it (30 minutes)
+const DEVIATION_FALLBACK_MAX_AGE_MS: u64 = 30 * 60 * 1000;
+
+thread_local! {
+    /// Cache and validation settings
+    static PRICE_ORACLE_CONFIG: RefCell<PriceOracleConfig> = RefCell::new(PriceOracleConfig::default());
+}
+
+/// Chainlink feed of an asset on a chain
+struct PriceFeed {
+    /// AggregatorV3 proxy address
+    address: Address,
+    /// Answer decimals (all USD feeds use 8)
+    decimals: u8,
+    /// Maximum time between feed updates
+    heartbeat_seconds: u64,
+}
+
+// =============================================================================
+// Configuration
+// =============================================================================
+
+/// Get the price oracle settings
+pub fn get_price_oracle_config() -> PriceOracleConfig {
+    PRICE_ORACLE_CONFIG.with(|c| c.borrow().clone())
+}
+
+/// Replace the price oracle settings
+pub fn set_price_oracle_config(config: PriceOracleConfig) -> Result<PriceOracleConfig, String> {
+    if config.cache_ttl_seconds == 0 {
+        return Err("cache_ttl_seconds must be at least 1".to_string());
+    }
+    if !config.max_deviation_percent.is_finite() || config.max_deviation_percent <= 0.0 {
+        return Err("max_deviation_percent must be positive".to_string());
+    }
+
+    PRICE_ORACLE_CONFIG.with(|c| *c.borrow_mut() = config.clone());
+    ic_cdk::println!("✅ Price oracle config updated: TTL {}s, grace {}s, max deviation {}%",
+        config.cache_ttl_seconds, config.staleness_grace_seconds, config.max_deviation_percent);
+    Ok(config)
+}
+
+// =============================================================================
+// Public API
+// =============================================================================
+
+/// USD price of a token (symbol or address) on a chain
+///
+/// Served from cache while younger than the TTL; otherwise the feed is read.
+pub async fn get_price(token: &str, chain_id: u64) -> Result<TokenPrice, String> {
+    let asset = resolve_asset(token, chain_id)?;
+    let feed = find_feed(&asset, chain_id)?;
+    let config = get_price_oracle_config();
+    let cached = read_cache(&asset, chain_id);
+    let timestamp = now();
+
+    if let Some(price) = &cached {
+        if timestamp.saturating_sub(price.fetched_at) < config.cache_ttl_seconds * 1000 {
+            return Ok(price.clone());
+        }
+    }
+
+    let max_age_ms = (feed.heartbeat_seconds + config.staleness_grace_seconds) * 1000;
+    let answer = read_feed(&feed, chain_id).await.and_then(|(price_usd, updated_at)| {
+        validate_price(price_usd, updated_at, timestamp, max_age_ms)?;
+        Ok(TokenPrice {
+            asset: asset.clone(),
+            chain_id,
+            price_usd,
+            feed_address: format!("0x{:x}", feed.address),
+            updated_at,
+            fetched_at: timestamp,
+        })
+    });
+    // The flag records whether the answer was rejected for deviating from the cached price
+    let fetched = answer.map_err(|e| (e, false)).and_then(|price| {
+        check_deviation(price.price_usd, timestamp, max_age_ms, cached.as_ref(), config.max_deviation_percent)
+            .map_err(|e| (e, true))?;
+        Ok(price)
+    });
+
+    match fetched {
+        Ok(price) => {
+            write_cache(&price);
+            Ok(price)
+        }
+        Err((e, deviated)) => match cached {
+            Some(price) if can_serve_cached(&price, timestamp, max_age_ms, deviated) => {
+                ic_cdk::println!("⚠️ {} price feed on chain {} unavailable ({}), serving cached ${}",
+                    asset, chain_id, e, price.price_usd);
+                Ok(price)
+            }
+            _ => Err(format!("No valid {} price on chain {}: {}", asset, chain_id, e)),
+        },
+    }
+}
+
+/// Cached price that is still within its feed's heartbeat, without reading the feed
+///
+/// For synchronous code paths; async callers should use `get_price`.
+pub fn get_cached_price(token: &str, chain_id: u64) -> Option<TokenPrice> {
+    let asset = resolve_asset(token, chain_id).ok()?;
+    let feed = find_feed(&asset, chain_id).ok()?;
+    let price = read_cache(&asset, chain_id)?;
+    let max_age_ms = (feed.heartbeat_seconds + get_price_oracle_config().staleness_grace_seconds) * 1000;
+
+    (now().saturating_sub(price.updated_at) <= max_age_ms).then_some(price)
+}
+
+/// USD value of a token amount
+pub async fn get_usd_value(token: &str, chain_id: u64, amount: &TokenAmount) -> Result<f64, String> {
+    let price = get_price(token, chain_id).await?;
+    Ok(price.price_usd * amount.to_f64())
+}
+
+/// Cost in USD of spending `gas_units` at the chain's current gas price
+pub async fn estimate_gas_cost_usd(chain_id: u64, gas_units: u64) -> Result<f64, String> {
+    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
+    let config = IcpConfig::new(rpc_service);
+    let provider = ProviderBuilder::new().on_icp(config);
+
+    let gas_price_wei = provider.get_gas_price().await
+        .map_err(|e| format!("Failed to get gas price: {}", e))?;
+    let native_price = get_price("ETH", chain_id).await?;
+
+    Ok(gas_units as f64 * gas_price_wei as f64 / 1e18 * native_price.price_usd)
+}
+
+// =============================================================================
+// Feed Access
+// =============================================================================
+
+/// Read a feed's latest answer as (USD price, update time in ms)
+async fn read_feed(feed: &PriceFeed, chain_id: u64) -> Result<(f64, u64), String> {
+    let rpc_service = get_rpc_service_by_chain_id(chain_id)?;
+    let config = IcpConfig::new(rpc_service);
+    let provider = ProviderBuilder::new().on_icp(config);
+
+    let round = IAggregatorV3::new(feed.address, provider).latestRoundData().call().await
+        .map_err(|e| format!("Failed to read price feed: {}", e))?;
+
+    if round.answer.is_negative() {
+        return Err(format!("Feed returned negative answer {}", round.answer));
+    }
+    let answer: u128 = round.answer.into_raw().try_into()
+        .map_err(|_| format!("Feed answer {} out of range", round.answer))?;
+    let updated_at_seconds: u64 = round.updatedAt.try_into()
+        .map_err(|_| format!("Feed timestamp {} out of range", round.updatedAt))?;
+
+    Ok((scale_answer(answer, feed.decimals), updated_at_seconds.saturating_mul(1000)))
+}
+
+/// Registered feed of an asset on a chain
+fn find_feed(asset: &str, chain_id: u64) -> Result<PriceFeed, String> {
+    let feed = chain_registry::get_price_feeds(chain_id)
+        .into_iter()
+        .find(|feed| feed.asset == asset)
+        .ok_or_else(|| format!("No price feed for {} on chain {}", asset, chain_id))?;
+    let address = feed.feed_address.parse::<Address>()
+        .map_err(|_| format!("Invalid {} price feed address on chain {}: {}", asset, chain_id, feed.feed_address))?;
+
+    Ok(PriceFeed {
+        address,
+        decimals: feed.decimals,
+        heartbeat_seconds: feed.heartbeat_seconds,
+    })
+}
+
+/// Asset symbol of a token given by symbol or address
+///
+/// Wrapped ETH shares the ETH feed.
+fn resolve_asset(token: &str, chain_id: u64) -> Result<String, String> {
+    let symbol = if token.starts_with("0x") {
+        let address = token.parse::<Address>().map_err(|_| format!("Invalid token address: {}", token))?;
+        position_reconciler::get_protocol_markets(chain_id)
+            .into_iter()
+            .find(|market| market.token_address == address)
+            .map(|market| market.asset)
+            .or_else(|| {
+                // Assets of the chain's deployments that are not a lending market
+                chain_registry::get_enabled_chain(chain_id)?
+                    .protocols
+                    .into_iter()
+                    .flat_map(|deployment| deployment.assets)
+                    .find(|asset| asset.token_address.parse::<Address>().ok() == Some(address))
+                    .map(|asset| asset.symbol)
+            })
+            .ok_or_else(|| format!("Unknown token {} on chain {}", token, chain_id))?
+    } else {
+        token.to_uppercase()
+    };
+
+    Ok(match symbol.as_str() {
+        "WETH" => "ETH".to_string(),
+        _ => symbol,
+    })
+}
+
+// =============================================================================
+// Cache
+// =============================================================================
+
+fn cache_key(asset: &str, chain_id: u64) -> StorableString {
+    StorableString(format!("{}|{}", chain_id, asset))
+}
+
+fn read_cache(asset: &str, chain_id: u64) -> Option<TokenPrice> {
+    PRICE_CACHE_MAP.with(|map| map.borrow().get(&cache_key(asset, chain_id)).map(|p| p.0))
+}
+
+fn write_cache(price: &TokenPrice) {
+    PRICE_CACHE_MAP.with(|map| {
+        map.borrow_mut().insert(cache_key(&price.asset, price.chain_id), StorableTokenPrice(price.clone()));
+    });
+}
+
+// =============================================================================
+// Validation
+// =============================================================================
+
+/// Check a feed answer for a non-positive value and staleness
+fn validate_price(price_usd: f64, updated_at: u64, now: u64, max_age_ms: u64) -> Result<(), String> {
+    if !price_usd.is_finite() || price_usd <= 0.0 {
+        return Err(format!("Non-positive price {}", price_usd));
+    }
+
+    let age_ms = now.saturating_sub(updated_at);
+    if age_ms > max_age_ms {
+        return Err(format!("Stale price: last update {}s ago (max {}s)", age_ms / 1000, max_age_ms / 1000));
+    }
+
+    Ok(())
+}
+
+/// Check a feed answer for deviation from a still-fresh previous price
+fn check_deviation(
+    price_usd: f64,
+    now: u64,
+    max_age_ms: u64,
+    previous: Option<&TokenPrice>,
+    max_deviation_percent: f64,
+) -> Result<(), String> {
+    if let Some(previous) = previous {
+        let previous_fresh = now.saturating_sub(previous.updated_at) <= max_age_ms;
+        let deviation = (price_usd - previous.price_usd).abs() / previous.price_usd * 100.0;
+        if previous_fresh && deviation > max_deviation_percent {
+            return Err(format!("Price {} deviates {:.2}% from previous {} (max {}%)",
+                price_usd, deviation, previous.price_usd, max_deviation_percent));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a cached price may stand in for a feed answer that failed or was rejected
+///
+/// The price must be within the feed's heartbeat; when the answer deviated
+/// from it, it must also have been accepted at most
+/// `DEVIATION_FALLBACK_MAX_AGE_MS` ago.
+fn can_serve_cached(price: &TokenPrice, now: u64, max_age_ms: u64, deviated: bool) -> bool {
+    let within_heartbeat = now.saturating_sub(price.updated_at) <= max_age_ms;
+    let within_fallback_cap = !deviated || now.saturating_sub(price.fetched_at) <= DEVIATION_FALLBACK_MAX_AGE_MS;
+    within_heartbeat && within_fallback_cap
+}
+
+fn scale_answer(answer: u128, decimals: u8) -> f64 {
+    answer as f64 / 10f64.powi(decimals as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::rpc_service::ARBITRUM_CHAIN_ID;
+
+    const HOUR_MS: u64 = 3_600_000;
+
+    fn previous(price_usd: f64, updated_at: u64) -> TokenPrice {
+        TokenPrice {
+            asset: "ETH".to_string(),
+            chain_id: ARBITRUM_CHAIN_ID,
+            price_usd,
+            feed_address: String::new(),
+            updated_at,
+            fetched_at: updated_at,
+        }
+    }
+
+    #[test]
+    fn test_rejects_non_positive_and_stale_answers() {
+        let now = 10 * HOUR_MS;
+        assert!(validate_price(0.0, now, now, HOUR_MS).is_err());
+        assert!(validate_price(3000.0, now - 2 * HOUR_MS, now, HOUR_MS).is_err());
+        assert!(validate_price(3000.0, now - HOUR_MS, now, HOUR_MS).is_ok());
+    }
+
+    #[test]
+    fn test_deviation_only_checked_against_fresh_previous_price() {
+        let now = 10 * HOUR_MS;
+        let fresh = previous(3000.0, now - HOUR_MS / 2);
+        let stale = previous(3000.0, now - 5 * HOUR_MS);
+
+        assert!(check_deviation(3300.0, now, HOUR_MS, Some(&fresh), 20.0).is_ok());
+        assert!(check_deviation(4000.0, now, HOUR_MS, Some(&fresh), 20.0).is_err());
+        assert!(check_deviation(4000.0, now, HOUR_MS, Some(&stale), 20.0).is_ok());
+    }
+
+    #[test]
+    fn test_deviation_fallback_age_is_capped() {
+        let now = 10 * HOUR_MS;
+        let recent = previous(3000.0, now - DEVIATION_FALLBACK_MAX_AGE_MS);
+        let old = previous(3000.0, now - DEVIATION_FALLBACK_MAX_AGE_MS - 1);
+
+        assert!(can_serve_cached(&recent, now, 2 * HOUR_MS, true));
+        assert!(!can_serve_cached(&old, now, 2 * HOUR_MS, true));
+        // Unavailable feeds still fall back for the whole heartbeat
+        assert!(can_serve_cached(&old, now, 2 * HOUR_MS, false));
+        assert!(!can_serve_cached(&previous(3000.0, now - 3 * HOUR_MS), now, 2 * HOUR_MS, false));
+    }
+
+    #[test]
+    fn test_scale_answer() {
+        assert_eq!(scale_answer(300_012_345_678, 8), 3000.12345678);
+        assert_eq!(scale_answer(100_000_000, 8), 1.0);
+    }
+}
@@ -1,9 +1,12 @@
 use candid::Principal;
-use alloy::primitives::{address, Address};
-use crate::{PERMISSIONS_MAP, StorableString};
-use crate::types::{Recommendation, ExecutionResult, RecommendationType};
-use crate::services::{aave, compound};
-use crate::services::rpc_service::{is_supported_chain, get_chain_name, SEPOLIA_CHAIN_ID, ARBITRUM_CHAIN_ID};
+use alloy::primitives::Address;
+use crate::{PERMISSIONS_MAP, PRINCIPAL_TO_ADDRESS_MAP, StorableString, StorablePrincipal};
+use crate::types::{Recommendation, ExecutionResult, RecommendationType, SagaState, TokenAmount, WithdrawOutcome};
+use crate::types::amount::decimals_for_symbol;
+use crate::services::{aave, chain_registry, compound, position_reconciler, rebalance_saga};
+use crate::services::multicall::Multicall;
+use crate::services::position_reconciler::ProtocolMarket;
+use crate::services::rpc_service::{is_supported_chain, get_chain_name};
 
 // =============================================================================
 // Recommendation-based Rebalancing Functions
@@ -18,23 +21,8 @@ fn normalize_protocol_name(protocol: &str) -> Result<&str, String> {
     }
 }
 
-/// Get USDC address for a specific chain
-fn get_usdc_address(chain_id: u64) -> Result<Address, String> {
-    match chain_id {
-        ARBITRUM_CHAIN_ID => {
-            // Native USDC on Arbitrum
-            Ok(address!("af88d065e77c8cC2239327C5EDb3A432268e5831"))
-        },
-        SEPOLIA_CHAIN_ID => {
-            // USDC on Sepolia testnet
-            Ok(address!("94a9D9AC8a22534E3FaCa9954e183B2c3736704F"))
-        },
-        _ => Err(format!("USDC not configured for chain_id: {}", chain_id))
-    }
-}
-
 /// Extract transaction hash from result string
-fn extract_tx_hash(result: &str) -> Option<String> {
+pub(crate) fn extract_tx_hash(result: &str) -> Option<String> {
     // Result strings typically contain "Transaction: 0x..." or similar
     if let Some(start) = result.find("0x") {
         let hash_part = &result[start..];
@@ -62,11 +50,11 @@ pub fn validate_recommendation(recommendation: &Recommendation) -> Result<(), St
     normalize_protocol_name(&recommendation.from_protocol)?;
     normalize_protocol_name(&recommendation.to_protocol)?;
 
-    // Check position_size is valid
-    let amount: f64 = recommendation.position_size.parse()
-        .map_err(|_| format!("Invalid position_size: {}", recommendation.position_size))?;
+    // Check position size is valid
+    let amount = recommendation.amount()
+        .map_err(|e| format!("Invalid position_size {}: {}", recommendation.position_size, e))?;
 
-    if amount <= 0.0 {
+    if amount.is_zero() {
         return Err(format!("position_size must be positive, got: {}", amount));
     }
 
@@ -82,21 +70,23 @@ pub fn validate_recommendation(recommendation: &Recommendation) -> Result<(), St
 }
 
 /// Execute withdraw from protocol
-async fn execute_protocol_withdraw(
+///
+/// `amount` may be "max" to withdraw the full on-chain balance.
+pub(crate) async fn execute_protocol_withdraw(
     protocol: &str,
     amount: String,
     permissions_id: String,
     user_principal: Principal,
     chain_id: u64
-) -> Result<String, String> {
+) -> Result<WithdrawOutcome, String> {
     let normalized_protocol = normalize_protocol_name(protocol)?;
 
     ic_cdk::println!("🏦 Executing withdraw from {} protocol...", normalized_protocol);
 
     match normalized_protocol {
         "AAVE" => {
-            let usdc_addr = get_usdc_address(chain_id)?;
-            aave::withdraw_from_aave_with_permissions(
+            let usdc_addr = chain_registry::get_usdc_address(chain_id)?;
+            aave::withdraw_from_aave_with_outcome(
                 usdc_addr,
                 "USDC".to_string(),
                 amount,
@@ -106,7 +96,7 @@ async fn execute_protocol_withdraw(
             ).await
         },
         "COMPOUND" => {
-            compound::withdraw_usdc_from_compound_with_permissions(
+            compound::withdraw_usdc_from_compound_with_outcome(
                 amount,
                 permissions_id,
                 user_principal
@@ -116,8 +106,43 @@ async fn execute_protocol_withdraw(
     }
 }
 
+/// Execute approval of the protocol to spend the supplied amount
+pub(crate) async fn execute_protocol_approve(
+    protocol: &str,
+    amount: String,
+    permissions_id: String,
+    user_principal: Principal,
+    chain_id: u64
+) -> Result<String, String> {
+    let normalized_protocol = normalize_protocol_name(protocol)?;
+
+    ic_cdk::println!("✍️ Executing approval for {} protocol...", normalized_protocol);
+
+    match normalized_protocol {
+        "AAVE" => {
+            let usdc_addr = chain_registry::get_usdc_address(chain_id)?;
+            aave::approve_token_for_aave_with_permissions(
+                usdc_addr,
+                "USDC".to_string(),
+                amount,
+                permissions_id,
+                user_principal,
+                chain_id
+            ).await
+        },
+        "COMPOUND" => {
+            compound::approve_usdc_for_compound_with_permissions(
+                amount,
+                permissions_id,
+                user_principal
+            ).await
+        },
+        _ => Err(format!("Unsupported protocol for approval: {}", protocol))
+    }
+}
+
 /// Execute supply to protocol
-async fn execute_protocol_supply(
+pub(crate) async fn execute_protocol_supply(
     protocol: &str,
     amount: String,
     permissions_id: String,
@@ -130,7 +155,7 @@ async fn execute_protocol_supply(
 
     match normalized_protocol {
         "AAVE" => {
-            let usdc_addr = get_usdc_address(chain_id)?;
+            let usdc_addr = chain_registry::get_usdc_address(chain_id)?;
             aave::supply_to_aave_with_permissions(
                 usdc_addr,
                 "USDC".to_string(),
@@ -151,7 +176,57 @@ async fn execute_protocol_supply(
     }
 }
 
+/// USDC balance of a user in a protocol and in their wallet
+///
+/// Read in one batched call; used to tell whether an interrupted saga step
+/// landed on-chain.
+pub(crate) async fn get_usdc_balances(
+    protocol: &str,
+    user_principal: Principal,
+    chain_id: u64
+) -> Result<(TokenAmount, TokenAmount), String> {
+    let normalized_protocol = normalize_protocol_name(protocol)?;
+    let usdc_addr = chain_registry::get_usdc_address(chain_id)?;
+    let decimals = decimals_for_symbol("USDC")?;
+
+    let holder = PRINCIPAL_TO_ADDRESS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorablePrincipal(user_principal))
+            .map(|s| s.0)
+            .ok_or_else(|| "No EVM address found for user".to_string())
+    })?
+    .parse::<Address>()
+    .map_err(|e| format!("Invalid EVM address: {}", e))?;
+
+    let market = ProtocolMarket {
+        protocol: normalized_protocol.to_string(),
+        asset: "USDC".to_string(),
+        token_address: usdc_addr,
+        decimals,
+    };
+    let protocol_target = position_reconciler::balance_targets(&[market], chain_id).await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("No balance contract for {}", normalized_protocol))?;
+
+    let mut multicall = Multicall::new(chain_id);
+    let protocol_index = multicall.add_token_balance(protocol_target, holder);
+    let wallet_index = multicall.add_token_balance(usdc_addr, holder);
+    let results = multicall.execute().await?;
+
+    Ok((
+        TokenAmount::from_u256(results.decode_uint(protocol_index)?, decimals)?,
+        TokenAmount::from_u256(results.decode_uint(wallet_index)?, decimals)?,
+    ))
+}
+
 /// Execute same-chain same-asset rebalance flow
+///
+/// The flow runs as a persisted saga (withdraw → approve → supply). The whole
+/// on-chain balance is withdrawn, so accrued interest moves with the position,
+/// and the amount that actually arrived is what gets supplied. If the supply
+/// side fails after funds were withdrawn, the saga is retried from a timer
+/// instead of leaving the funds idle in the wallet.
 async fn execute_same_chain_same_asset(
     recommendation: &Recommendation,
     permissions_id: String,
@@ -159,64 +234,57 @@ async fn execute_same_chain_same_asset(
     chain_id: u64
 ) -> Result<ExecutionResult, String> {
     ic_cdk::println!("🔄 Starting same-chain same-asset rebalance flow");
-    ic_cdk::println!("  From: {} | To: {} | Amount: {} USDC",
+    ic_cdk::println!("  From: {} | To: {} | Amount: full balance (recorded size {} USDC)",
         recommendation.from_protocol, recommendation.to_protocol, recommendation.position_size);
 
+    let mut saga = rebalance_saga::start_saga(
+        recommendation.clone(),
+        permissions_id,
+        user_principal,
+        chain_id
+    ).await;
+
+    rebalance_saga::advance_saga(&mut saga).await;
+
     let mut result = ExecutionResult {
         status: "pending".to_string(),
-        withdraw_tx: None,
+        withdraw_tx: saga.withdraw_tx.clone(),
         swap_tx: None,
         supply_tx: None,
-        amount_transferred: Some(recommendation.position_size.clone()),
+        amount_transferred: None,
         actual_gas_cost: None,
         error_details: None,
+        saga_id: Some(saga.saga_id.clone()),
     };
 
-    // Step 1: Withdraw from source protocol
-    ic_cdk::println!("📤 Step 1: Withdrawing from {}...", recommendation.from_protocol);
-    match execute_protocol_withdraw(
-        &recommendation.from_protocol,
-        recommendation.position_size.clone(),
-        permissions_id.clone(),
-        user_principal,
-        chain_id
-    ).await {
-        Ok(withdraw_result) => {
-            ic_cdk::println!("✅ Withdraw successful: {}", withdraw_result);
-            result.withdraw_tx = extract_tx_hash(&withdraw_result);
-            // Note: Position sync is handled automatically by the protocol withdraw function
+    match saga.state {
+        SagaState::Supplied => {
+            result.supply_tx = saga.supply_tx.clone();
+            result.amount_transferred = Some(saga.amount.clone());
+            result.status = "success".to_string();
         },
-        Err(e) => {
-            ic_cdk::println!("❌ Withdraw failed: {}", e);
+        SagaState::Pending if saga.withdraw_tx.is_some() => {
+            result.error_details = Some(format!(
+                "Withdraw from {} was sent but not confirmed: {}. It is checked on-chain and the rebalance continues automatically (saga {}).",
+                recommendation.from_protocol,
+                saga.last_error.clone().unwrap_or_default(),
+                saga.saga_id
+            ));
+        },
+        SagaState::Failed | SagaState::Pending => {
             result.status = "failed".to_string();
-            result.error_details = Some(format!("Withdraw failed: {}", e));
-            return Ok(result);
-        }
-    }
-
-    // Step 2: Supply to target protocol
-    ic_cdk::println!("📥 Step 2: Supplying to {}...", recommendation.to_protocol);
-    match execute_protocol_supply(
-        &recommendation.to_protocol,
-        recommendation.position_size.clone(),
-        permissions_id.clone(),
-        user_principal,
-        chain_id
-    ).await {
-        Ok(supply_result) => {
-            ic_cdk::println!("✅ Supply successful: {}", supply_result);
-            result.supply_tx = extract_tx_hash(&supply_result);
-            result.status = "success".to_string();
-            // Note: Position sync is handled automatically by the protocol supply function
+            result.error_details = Some(format!("Withdraw failed: {}",
+                saga.last_error.clone().unwrap_or_default()));
         },
-        Err(e) => {
-            ic_cdk::println!("❌ Supply failed: {}", e);
+        _ => {
             result.status = "partial".to_string();
+            result.amount_transferred = Some(saga.amount.clone());
             result.error_details = Some(format!(
-                "Supply to {} failed: {}. Funds successfully withdrawn from {} and are now in your wallet. Please check your balance and retry the supply operation manually if needed.",
+                "Supply to {} failed: {}. Funds successfully withdrawn from {} are held in your wallet. Automatic retry is scheduled (saga {}).",
                 recommendation.to_protocol,
-                e,
-                recommendation.from_protocol
+                saga.last_error.clone().unwrap_or_default(),
+                recommendation.from_protocol,
+                saga.saga_id
             ));
         }
     }
@@ -256,7 +324,7 @@ pub async fn execute_recommendation(
 
     let chain_id = permissions.chain_id;
     ic_cdk::println!("✅ Using chain_id: {} ({})",
-        chain_id, get_chain_name(chain_id).unwrap_or("Unknown"));
+        chain_id, get_chain_name(chain_id).unwrap_or_else(|| "Unknown".to_string()));
 
     // Step 3: Validate chain support
     if !is_supported_chain(chain_id) {
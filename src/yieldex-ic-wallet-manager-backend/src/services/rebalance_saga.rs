@@ -0,0 +1,657 @@
+use candid::Principal;
+use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::types::{Recommendation, RebalanceSaga, SagaState, StorableRebalanceSaga, TokenAmount};
+use crate::types::amount::decimals_for_symbol;
+use crate::services::{locks, rebalance, tx_tracker};
+use crate::{REBALANCE_SAGAS_MAP, StorableString, now};
+
+// =============================================================================
+// Rebalance Saga
+// =============================================================================
+//
+// Each rebalance is persisted as a state machine:
+//
+//   Pending → Withdrawn → Approved → Supplied
+//                 ↓           ↓
+//            RecoveringSource → Recovered
+//
+// A failed withdraw ends in Failed (nothing moved). Once funds are withdrawn,
+// failures of the approve/supply steps are retried from a timer with
+// exponential backoff. After MAX_TARGET_ATTEMPTS the saga gives up on the
+// target protocol and supplies the funds back to the source protocol. If that
+// also fails MAX_RECOVERY_ATTEMPTS times the saga waits for a manual resume.
+//
+// Every step is marked in flight (`step_started_at`) before its first await,
+// and the withdraw and supply steps record their transaction hash on the saga
+// as soon as it is broadcast. A trap or an upgrade leaves the marker set; once
+// it is older than STALE_STEP_MS the timer picks the saga up again. A step
+// that fails after broadcasting keeps the marker as well, since its
+// transaction may still land. Before re-running such a step, the receipt of
+// its recorded transaction decides whether it already landed.
+//
+
+/// Failures allowed on the target protocol before falling back to the source
+const MAX_TARGET_ATTEMPTS: u32 = 5;
+
+/// Failures allowed while re-supplying the source before waiting for manual resume
+const MAX_RECOVERY_ATTEMPTS: u32 = 5;
+
+/// Backoff after the first failure (1 minute)
+const RETRY_BASE_DELAY_MS: u64 = 60 * 1000;
+
+/// Backoff cap (1 hour)
+const RETRY_MAX_DELAY_MS: u64 = 3600 * 1000;
+
+/// How often due sagas are retried
+const SAGA_TIMER_INTERVAL_SECONDS: u64 = 60;
+
+/// Maximum sagas retried per timer tick (each retry makes several outcalls)
+const MAX_SAGAS_PER_TICK: usize = 5;
+
+/// A step in flight for longer than this was interrupted (30 minutes)
+///
+/// Matches the lock timeout: a step that is still running holds its user lock
+/// until then.
+const STALE_STEP_MS: u64 = 30 * 60 * 1000;
+
+/// How long finished sagas are kept (30 days)
+const FINISHED_SAGA_RETENTION_MS: u64 = 30 * 24 * 3600 * 1000;
+
+thread_local! {
+    /// Active timer ID for saga retries
+    static SAGA_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+
+    /// Saga whose step is running, per user (the user lock allows one at a time)
+    static STEP_IN_FLIGHT: RefCell<BTreeMap<Principal, String>> = RefCell::new(BTreeMap::new());
+}
+
+// =============================================================================
+// Pure Helpers
+// =============================================================================
+
+/// Backoff before the next retry after `attempts` consecutive failures
+pub fn retry_delay_ms(attempts: u32) -> u64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+    RETRY_BASE_DELAY_MS
+        .saturating_mul(1u64 << exponent)
+        .min(RETRY_MAX_DELAY_MS)
+}
+
+/// Whether a saga has reached a final state
+pub fn is_finished(state: &SagaState) -> bool {
+    matches!(state, SagaState::Supplied | SagaState::Recovered | SagaState::Failed)
+}
+
+/// Whether a saga's step was interrupted and never completed or failed
+pub fn is_interrupted(saga: &RebalanceSaga, current_time: u64) -> bool {
+    !is_finished(&saga.state)
+        && saga.step_started_at.map(|at| at + STALE_STEP_MS <= current_time).unwrap_or(false)
+}
+
+/// Whether the timer should pick a saga up
+fn is_due(saga: &RebalanceSaga, current_time: u64) -> bool {
+    saga.next_retry_at.map(|at| at <= current_time).unwrap_or(false) || is_interrupted(saga, current_time)
+}
+
+/// Transaction of the saga's current step; None for steps that are safe to repeat
+fn step_tx(saga: &mut RebalanceSaga) -> Option<&mut Option<String>> {
+    match saga.state {
+        SagaState::Pending => Some(&mut saga.withdraw_tx),
+        SagaState::Approved | SagaState::RecoveringSource => Some(&mut saga.supply_tx),
+        _ => None,
+    }
+}
+
+// =============================================================================
+// Saga Execution
+// =============================================================================
+
+/// Create and persist a new saga for a recommendation
+pub async fn start_saga(
+    recommendation: Recommendation,
+    permissions_id: String,
+    user_principal: Principal,
+    chain_id: u64,
+) -> RebalanceSaga {
+    let timestamp = now();
+
+    let saga = RebalanceSaga {
+        saga_id: generate_saga_id().await,
+        user_principal,
+        permissions_id,
+        chain_id,
+        amount: recommendation.position_size.clone(),
+        recommendation,
+        state: SagaState::Pending,
+        withdraw_tx: None,
+        approve_tx: None,
+        supply_tx: None,
+        attempts: 0,
+        next_retry_at: None,
+        step_started_at: None,
+        last_error: None,
+        created_at: timestamp,
+        updated_at: timestamp,
+    };
+    save_saga(&saga);
+
+    ic_cdk::println!("🧭 Rebalance saga {} started", saga.saga_id);
+    saga
+}
+
+/// Drive a saga as far as it can go, persisting after every step
+///
+/// Stops at a final state or at the first failed step. Callers hold the
+/// user's lock, so a step still marked in flight was interrupted.
+pub async fn advance_saga(saga: &mut RebalanceSaga) {
+    if saga.step_started_at.is_some() {
+        match recover_interrupted_step(saga).await {
+            Ok(true) => {},
+            Ok(false) => return,
+            Err(e) => {
+                ic_cdk::println!("❌ Saga {}: could not check interrupted {:?} step: {}", saga.saga_id, saga.state, e);
+                saga.last_error = Some(e);
+                saga.next_retry_at = Some(now() + retry_delay_ms(saga.attempts.max(1)));
+                saga.updated_at = now();
+                save_saga(saga);
+                return;
+            }
+        }
+    }
+
+    loop {
+        if is_finished(&saga.state) {
+            break;
+        }
+
+        // Persisted before the first await so an interruption is detectable
+        if let Some(tx) = step_tx(saga) {
+            *tx = None;
+        }
+        saga.step_started_at = Some(now());
+        save_saga(saga);
+        STEP_IN_FLIGHT.with(|steps| steps.borrow_mut().insert(saga.user_principal, saga.saga_id.clone()));
+
+        let step = match saga.state {
+            SagaState::Pending => {
+                ic_cdk::println!("📤 Saga {}: withdrawing from {}...", saga.saga_id, saga.recommendation.from_protocol);
+                // Withdraw the live on-chain balance rather than the recorded size,
+                // then carry the amount that actually arrived through the later steps
+                rebalance::execute_protocol_withdraw(
+                    &saga.recommendation.from_protocol,
+                    "max".to_string(),
+                    saga.permissions_id.clone(),
+                    saga.user_principal,
+                    saga.chain_id
+                ).await.map(|outcome| {
+                    ic_cdk::println!("💰 Saga {}: withdrew {} (recorded size {})",
+                        saga.saga_id, outcome.amount_withdrawn, saga.amount);
+                    saga.withdraw_tx = Some(outcome.tx_hash);
+                    saga.amount = outcome.amount_withdrawn;
+                    SagaState::Withdrawn
+                })
+            },
+            SagaState::Withdrawn => {
+                ic_cdk::println!("✍️ Saga {}: approving {}...", saga.saga_id, saga.recommendation.to_protocol);
+                rebalance::execute_protocol_approve(
+                    &saga.recommendation.to_protocol,
+                    saga.amount.clone(),
+                    saga.permissions_id.clone(),
+                    saga.user_principal,
+                    saga.chain_id
+                ).await.map(|res| {
+                    saga.approve_tx = rebalance::extract_tx_hash(&res);
+                    SagaState::Approved
+                })
+            },
+            SagaState::Approved => {
+                ic_cdk::println!("📥 Saga {}: supplying to {}...", saga.saga_id, saga.recommendation.to_protocol);
+                rebalance::execute_protocol_supply(
+                    &saga.recommendation.to_protocol,
+                    saga.amount.clone(),
+                    saga.permissions_id.clone(),
+                    saga.user_principal,
+                    saga.chain_id
+                ).await.map(|res| {
+                    saga.supply_tx = rebalance::extract_tx_hash(&res);
+                    SagaState::Supplied
+                })
+            },
+            SagaState::RecoveringSource => {
+                ic_cdk::println!("↩️ Saga {}: re-supplying {}...", saga.saga_id, saga.recommendation.from_protocol);
+                rebalance::execute_protocol_supply(
+                    &saga.recommendation.from_protocol,
+                    saga.amount.clone(),
+                    saga.permissions_id.clone(),
+                    saga.user_principal,
+                    saga.chain_id
+                ).await.map(|res| {
+                    saga.supply_tx = rebalance::extract_tx_hash(&res);
+                    SagaState::Recovered
+                })
+            },
+            SagaState::Supplied | SagaState::Recovered | SagaState::Failed => break,
+        };
+        STEP_IN_FLIGHT.with(|steps| steps.borrow_mut().remove(&saga.user_principal));
+
+        // Picked up from the stored copy, where `record_step_tx` put it
+        let sent_tx = get_saga(saga.saga_id.clone()).ok()
+            .and_then(|mut stored| step_tx(&mut stored).and_then(|tx| tx.take()));
+
+        match step {
+            Ok(next_state) => {
+                ic_cdk::println!("✅ Saga {}: {:?} → {:?}", saga.saga_id, saga.state, next_state);
+                saga.state = next_state;
+                saga.attempts = 0;
+                saga.next_retry_at = None;
+                saga.step_started_at = None;
+                saga.last_error = None;
+                saga.updated_at = now();
+                save_saga(saga);
+            },
+            Err(e) => {
+                match sent_tx {
+                    Some(tx_hash) => record_unconfirmed(saga, tx_hash, e),
+                    None => record_failure(saga, e),
+                }
+                save_saga(saga);
+                break;
+            }
+        }
+    }
+}
+
+/// Record the transaction the running step of a user's saga just broadcast
+///
+/// Called by the protocol modules right after a withdraw or supply is sent,
+/// before its confirmation is awaited. Does nothing outside a saga step.
+pub(crate) fn record_step_tx(user_principal: Principal, tx_hash: String) {
+    let Some(saga_id) = STEP_IN_FLIGHT.with(|steps| steps.borrow().get(&user_principal).cloned()) else {
+        return;
+    };
+    let Ok(mut saga) = get_saga(saga_id) else {
+        return;
+    };
+
+    if let Some(tx) = step_tx(&mut saga) {
+        *tx = Some(tx_hash.clone());
+        saga.updated_at = now();
+        save_saga(&saga);
+        ic_cdk::println!("📝 Saga {}: {:?} step sent {}", saga.saga_id, saga.state, tx_hash);
+    }
+}
+
+/// Record a step that failed after broadcasting its transaction
+///
+/// The step stays marked in flight, so the next attempt first checks whether
+/// the transaction landed instead of sending it again.
+fn record_unconfirmed(saga: &mut RebalanceSaga, tx_hash: String, error: String) {
+    ic_cdk::println!("⚠️ Saga {}: {:?} step sent {} but failed: {}", saga.saga_id, saga.state, tx_hash, error);
+
+    if let Some(tx) = step_tx(saga) {
+        *tx = Some(tx_hash);
+    }
+    saga.attempts += 1;
+    saga.last_error = Some(error);
+    saga.next_retry_at = Some(now() + retry_delay_ms(saga.attempts));
+    saga.updated_at = now();
+}
+
+/// Record a failed step and decide what happens next
+fn record_failure(saga: &mut RebalanceSaga, error: String) {
+    ic_cdk::println!("❌ Saga {} failed in {:?}: {}", saga.saga_id, saga.state, error);
+
+    let current_time = now();
+    saga.attempts += 1;
+    saga.last_error = Some(error);
+    saga.step_started_at = None;
+    saga.updated_at = current_time;
+
+    match saga.state {
+        // Nothing was moved, so there is nothing to recover
+        SagaState::Pending => {
+            saga.state = SagaState::Failed;
+            saga.next_retry_at = None;
+        },
+        SagaState::Withdrawn | SagaState::Approved if saga.attempts >= MAX_TARGET_ATTEMPTS => {
+            ic_cdk::println!("↩️ Saga {}: giving up on {} after {} attempts, returning funds to {}",
+                saga.saga_id, saga.recommendation.to_protocol, saga.attempts, saga.recommendation.from_protocol);
+            saga.state = SagaState::RecoveringSource;
+            saga.attempts = 0;
+            saga.next_retry_at = Some(current_time + RETRY_BASE_DELAY_MS);
+        },
+        SagaState::RecoveringSource if saga.attempts >= MAX_RECOVERY_ATTEMPTS => {
+            ic_cdk::println!("🛑 Saga {}: recovery failed {} times, waiting for manual resume",
+                saga.saga_id, saga.attempts);
+            saga.next_retry_at = None;
+        },
+        _ => {
+            saga.next_retry_at = Some(current_time + retry_delay_ms(saga.attempts));
+        }
+    }
+}
+
+/// Find out on-chain whether an interrupted step landed and move past it if so
+///
+/// - A step with a recorded transaction is decided by its receipt: mined
+///   successfully means the step landed, reverted or dropped is recorded as
+///   a failed attempt, and not mined yet is checked again later.
+/// - A step without one never broadcast and is re-run, as is approve, which
+///   is safe to repeat.
+/// - After a landed withdraw, the amount carried on is the wallet's USDC
+///   balance capped at the recorded size, as the wallet may hold USDC of its own.
+///
+/// Returns whether the saga can keep advancing.
+async fn recover_interrupted_step(saga: &mut RebalanceSaga) -> Result<bool, String> {
+    ic_cdk::println!("🔎 Saga {}: checking interrupted {:?} step on-chain...", saga.saga_id, saga.state);
+
+    let tx_hash = step_tx(saga).and_then(|tx| tx.clone());
+    let succeeded = match &tx_hash {
+        Some(tx_hash) => Some(
+            tx_tracker::transaction_succeeded(saga.chain_id, tx_hash).await?
+                .ok_or_else(|| format!("Transaction {} not mined yet", tx_hash))?
+        ),
+        None => None,
+    };
+
+    match succeeded {
+        Some(true) => {
+            let next_state = match saga.state {
+                SagaState::Pending => {
+                    let decimals = decimals_for_symbol("USDC")?;
+                    let amount = TokenAmount::parse(&saga.amount, decimals)?;
+                    let (_, in_wallet) = rebalance::get_usdc_balances(
+                        &saga.recommendation.from_protocol,
+                        saga.user_principal,
+                        saga.chain_id
+                    ).await?;
+
+                    let withdrawn = if in_wallet.raw < amount.raw { in_wallet } else { amount };
+                    saga.amount = withdrawn.to_string();
+                    SagaState::Withdrawn
+                },
+                SagaState::Approved => SagaState::Supplied,
+                _ => SagaState::Recovered,
+            };
+
+            ic_cdk::println!("✅ Saga {}: interrupted {:?} step had landed → {:?}", saga.saga_id, saga.state, next_state);
+            saga.state = next_state;
+            saga.attempts = 0;
+            saga.last_error = None;
+        },
+        Some(false) => {
+            record_failure(saga, format!("Transaction {} reverted or was dropped",
+                tx_hash.unwrap_or_default()));
+            save_saga(saga);
+            return Ok(false);
+        },
+        None => {
+            ic_cdk::println!("🔁 Saga {}: interrupted {:?} step did not broadcast, re-running it", saga.saga_id, saga.state);
+        }
+    }
+
+    saga.step_started_at = None;
+    saga.next_retry_at = None;
+    saga.updated_at = now();
+    save_saga(saga);
+    Ok(true)
+}
+
+/// Retry a saga now, regardless of its backoff
+///
+/// Resets the failure count of sagas that were waiting for a manual resume.
+pub async fn resume_saga(saga_id: String, user: Option<Principal>) -> Result<RebalanceSaga, String> {
+    let mut saga = get_saga(saga_id.clone())?;
+
+    if let Some(user) = user {
+        if saga.user_principal != user {
+            return Err("You do not own this saga".to_string());
+        }
+    }
+
+    if is_finished(&saga.state) {
+        return Err(format!("Saga {} cannot be resumed (state: {:?})", saga_id, saga.state));
+    }
+
+    let _lock = locks::try_acquire_user_lock(saga.user_principal, "resume_rebalance_saga")?;
+
+    ic_cdk::println!("🔁 Resuming saga {} from {:?}", saga_id, saga.state);
+    if saga.next_retry_at.is_none() {
+        saga.attempts = 0;
+    }
+
+    advance_saga(&mut saga).await;
+    Ok(saga)
+}
+
+// =============================================================================
+// Retry Timer
+// =============================================================================
+
+/// Retry sagas whose backoff has elapsed or whose step was interrupted, and prune old finished sagas
+async fn process_due_sagas() {
+    let current_time = now();
+
+    let due: Vec<RebalanceSaga> = REBALANCE_SAGAS_MAP.with(|map| {
+        map.borrow()
+            .iter()
+            .map(|(_, saga)| saga.0)
+            .filter(|saga| is_due(saga, current_time))
+            .take(MAX_SAGAS_PER_TICK)
+            .collect()
+    });
+
+    for mut saga in due {
+        // Skip users with another operation in progress; retried next tick
+        let _lock = match locks::try_acquire_user_lock(saga.user_principal, "rebalance_saga_retry") {
+            Ok(guard) => guard,
+            Err(e) => {
+                ic_cdk::println!("⏳ Saga {} retry deferred: {}", saga.saga_id, e);
+                continue;
+            }
+        };
+
+        ic_cdk::println!("🔁 Retrying saga {} (state: {:?}, attempt {})",
+            saga.saga_id, saga.state, saga.attempts + 1);
+        advance_saga(&mut saga).await;
+    }
+
+    prune_finished_sagas(current_time);
+}
+
+/// Remove finished sagas older than the retention period
+fn prune_finished_sagas(current_time: u64) {
+    REBALANCE_SAGAS_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        let expired: Vec<StorableString> = borrowed
+            .iter()
+            .filter(|(_, saga)| {
+                is_finished(&saga.0.state) && saga.0.updated_at + FINISHED_SAGA_RETENTION_MS <= current_time
+            })
+            .map(|(key, _)| key)
+            .collect();
+
+        for key in expired {
+            borrowed.remove(&key);
+        }
+    });
+}
+
+/// Start the periodic saga retry timer
+pub fn start_saga_retry_timer() {
+    SAGA_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow().as_ref() {
+            clear_timer(*id);
+        }
+    });
+
+    let timer_id = set_timer_interval(Duration::from_secs(SAGA_TIMER_INTERVAL_SECONDS), || {
+        ic_cdk::spawn(async {
+            process_due_sagas().await;
+        });
+    });
+
+    SAGA_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+
+    ic_cdk::println!("✅ Saga retry timer started with interval: {} seconds", SAGA_TIMER_INTERVAL_SECONDS);
+}
+
+// =============================================================================
+// Queries
+// =============================================================================
+
+/// Get a saga by ID
+pub fn get_saga(saga_id: String) -> Result<RebalanceSaga, String> {
+    REBALANCE_SAGAS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(saga_id.clone()))
+            .map(|saga| saga.0)
+            .ok_or_else(|| format!("Saga {} not found", saga_id))
+    })
+}
+
+/// Get sagas (most recent first), optionally only unfinished ones and only for one user
+pub fn get_sagas(user: Option<Principal>, active_only: bool, limit: Option<u64>) -> Vec<RebalanceSaga> {
+    REBALANCE_SAGAS_MAP.with(|map| {
+        let mut all: Vec<RebalanceSaga> = map.borrow()
+            .iter()
+            .map(|(_, saga)| saga.0)
+            .filter(|saga| user.map(|u| saga.user_principal == u).unwrap_or(true))
+            .filter(|saga| !active_only || !is_finished(&saga.state))
+            .collect();
+
+        // Sort by creation time descending
+        all.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        let limit = limit.unwrap_or(100) as usize;
+        all.into_iter().take(limit).collect()
+    })
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+/// Persist a saga
+fn save_saga(saga: &RebalanceSaga) {
+    REBALANCE_SAGAS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(saga.saga_id.clone()),
+            StorableRebalanceSaga(saga.clone())
+        );
+    });
+}
+
+/// Generate unique saga ID
+async fn generate_saga_id() -> String {
+    let timestamp = now();
+    let random_result = ic_cdk::api::management_canister::main::raw_rand().await;
+    let random_bytes = match random_result {
+        Ok((bytes,)) => bytes,
+        Err((_, err)) => {
+            ic_cdk::println!("Warning: Random generation failed: {}", err);
+            vec![0u8; 8]
+        }
+    };
+
+    let mut id_bytes = timestamp.to_be_bytes().to_vec();
+    if random_bytes.len() >= 8 {
+        id_bytes.extend_from_slice(&random_bytes[0..8]);
+    }
+
+    format!("saga_{}", hex::encode(id_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_backs_off_and_caps() {
+        assert_eq!(retry_delay_ms(1), 60_000);
+        assert_eq!(retry_delay_ms(2), 120_000);
+        assert_eq!(retry_delay_ms(3), 240_000);
+        assert_eq!(retry_delay_ms(10), 3_600_000);
+        assert_eq!(retry_delay_ms(u32::MAX), 3_600_000);
+    }
+
+    #[test]
+    fn test_finished_states() {
+        assert!(is_finished(&SagaState::Supplied));
+        assert!(is_finished(&SagaState::Recovered));
+        assert!(is_finished(&SagaState::Failed));
+        assert!(!is_finished(&SagaState::Withdrawn));
+        assert!(!is_finished(&SagaState::RecoveringSource));
+    }
+
+    fn test_saga() -> RebalanceSaga {
+        RebalanceSaga {
+            saga_id: "saga_test".to_string(),
+            user_principal: Principal::anonymous(),
+            permissions_id: "perm".to_string(),
+            chain_id: 42161,
+            recommendation: Recommendation {
+                asset: "USDC".to_string(),
+                to_asset: "USDC".to_string(),
+                from_chain: "arbitrum".to_string(),
+                to_chain: None,
+                from_protocol: "aave-v3".to_string(),
+                to_protocol: "compound-v3".to_string(),
+                current_apy: 3.0,
+                target_apy: 4.0,
+                estimated_profit: 1.0,
+                gas_cost: 0.1,
+                position_size: "100".to_string(),
+                position_amount: None,
+                pool_id: None,
+                recommendation_type: crate::types::RecommendationType::StandardTransfer,
+                swap_details: None,
+            },
+            amount: "100".to_string(),
+            state: SagaState::Pending,
+            withdraw_tx: None,
+            approve_tx: None,
+            supply_tx: None,
+            attempts: 0,
+            next_retry_at: None,
+            step_started_at: Some(1_000),
+            last_error: None,
+            created_at: 1_000,
+            updated_at: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_stale_step_marker_makes_saga_due() {
+        let mut saga = test_saga();
+
+        assert!(!is_due(&saga, 1_000 + STALE_STEP_MS - 1));
+        assert!(is_due(&saga, 1_000 + STALE_STEP_MS));
+
+        saga.state = SagaState::Supplied;
+        assert!(!is_due(&saga, 1_000 + STALE_STEP_MS));
+    }
+
+    #[test]
+    fn test_step_tx_follows_state() {
+        let mut saga = test_saga();
+        saga.withdraw_tx = Some("0xwithdraw".to_string());
+        saga.supply_tx = Some("0xsupply".to_string());
+
+        assert_eq!(step_tx(&mut saga).cloned(), Some(Some("0xwithdraw".to_string())));
+
+        saga.state = SagaState::Withdrawn;
+        assert!(step_tx(&mut saga).is_none());
+
+        for state in [SagaState::Approved, SagaState::RecoveringSource] {
+            saga.state = state;
+            assert_eq!(step_tx(&mut saga).cloned(), Some(Some("0xsupply".to_string())));
+        }
+    }
+}
@@ -1,25 +1,68 @@
 use candid::Principal;
-use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use ic_cdk_timers::{set_timer, set_timer_interval, clear_timer, TimerId};
 use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
 use std::time::Duration;
 
 use crate::types::{
     SchedulerConfig, SchedulerStatus, UserPosition,
     RebalanceExecution, SchedulerExecutionSummary, Recommendation,
-    RecommendationType, StorableRebalanceExecution,
+    RecommendationType, ApySignal,
+    DryRunResult, DryRunTick, UserSchedulerSettings,
+    StorableDryRunTick, StorableUserSchedulerSettings, ExecutionMode,
+    SchedulerRun, SchedulerRunEntry, StorableSchedulerRun, StorableSchedulerRunEntry,
+    StorableSchedulerExecutionSummary, StorableSchedulerConfig,
+};
+use crate::services::{fee_policy, indexes, locks, price_oracle};
+use crate::{
+    REBALANCE_HISTORY_MAP, DRY_RUN_TICKS_MAP, USER_SCHEDULER_SETTINGS_MAP, SCHEDULER_RUN_MAP,
+    SCHEDULER_RUN_ENTRIES_MAP, SCHEDULER_SUMMARIES_MAP, SCHEDULER_CONFIG_MAP, SCHEDULER_RUN_QUEUE_MAP,
+    StorableString, StorablePrincipal,
 };
-use crate::{REBALANCE_HISTORY_MAP, StorableString};
 
 // =============================================================================
 // Global State (will be integrated into lib.rs)
 // =============================================================================
 
-thread_local! {
-    /// Global scheduler configuration
-    static SCHEDULER_CONFIG: RefCell<Option<SchedulerConfig>> = RefCell::new(None);
+/// Maximum number of dry-run ticks kept in stable memory
+const MAX_DRY_RUN_TICKS: u64 = 100;
+
+/// Maximum number of tick summaries kept in stable memory
+const MAX_TICK_SUMMARIES: u64 = 1000;
+
+/// Gas used by a rebalance (withdraw, approve and supply)
+const REBALANCE_GAS_UNITS: u64 = 600_000;
 
+/// Gas cost assumed when the gas price or native token price cannot be read
+const FALLBACK_GAS_COST_USD: f64 = 5.0;
+
+/// Key of the single entry in `SCHEDULER_CONFIG_MAP`
+const SCHEDULER_CONFIG_KEY: &str = "config";
+
+thread_local! {
     /// Active timer ID for the scheduler
     static SCHEDULER_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+
+    /// One-shot timer ID for the next batch of the active run
+    static SCHEDULER_BATCH_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+
+    /// Prices and gas costs read during the active run
+    static RUN_MARKET_DATA: RefCell<RunMarketData> = RefCell::new(RunMarketData::default());
+}
+
+/// Chain data read once per chain per run and shared by its positions
+///
+/// Kept on the heap: after an upgrade the remaining batches read it again.
+#[derive(Default)]
+struct RunMarketData {
+    /// Run the data was read for
+    run_id: String,
+    /// Rebalance gas cost per chain
+    gas_cost_usd: BTreeMap<u64, f64>,
+    /// Asset price per (asset, chain), None if the oracle had no valid price
+    asset_usd: BTreeMap<(String, u64), Option<f64>>,
+    /// Fee cap check per chain, Err while the chain's fees are above its cap
+    fee_caps: BTreeMap<u64, Result<(), String>>,
 }
 
 // =============================================================================
@@ -36,29 +79,37 @@ pub fn init_scheduler() {
         interval_seconds: 3600, // 1 hour default
         apy_threshold_percent: 0.5, // 0.5% APY difference
         min_position_size: "100".to_string(), // $100 USDC minimum
+        apy_signal: ApySignal::TimeWeightedAverage,
+        signal_window_seconds: 21600, // 6 hours
+        ema_half_life_seconds: 7200, // 2 hours
+        min_apy_samples: 4,
+        dry_run: false,
+        proposal_ttl_seconds: 86400, // 24 hours
+        batch_size: 10,
+        batch_interval_seconds: 2,
         last_execution: None,
         created_at: now,
         updated_at: now,
     };
 
-    SCHEDULER_CONFIG.with(|c| {
-        *c.borrow_mut() = Some(config.clone());
-    });
+    store_config(&config);
 
     ic_cdk::println!("✅ Scheduler initialized with default config:");
     ic_cdk::println!("  - Enabled: {}", config.enabled);
     ic_cdk::println!("  - Interval: {} seconds", config.interval_seconds);
     ic_cdk::println!("  - APY Threshold: {}%", config.apy_threshold_percent);
     ic_cdk::println!("  - Min Position Size: ${}", config.min_position_size);
+    ic_cdk::println!("  - APY Signal: {:?} ({}s window, min {} samples)",
+        config.apy_signal, config.signal_window_seconds, config.min_apy_samples);
+    ic_cdk::println!("  - Dry Run: {}", config.dry_run);
+    ic_cdk::println!("  - Batch Size: {} positions every {}s", config.batch_size, config.batch_interval_seconds);
 }
 
 /// Start the scheduler timer
 pub fn start_scheduler_timer() {
     ic_cdk::println!("🚀 Starting scheduler timer...");
 
-    let config = SCHEDULER_CONFIG.with(|c| c.borrow().clone());
-
-    let Some(config) = config else {
+    let Some(config) = load_config() else {
         ic_cdk::println!("❌ Cannot start timer: Scheduler not initialized");
         return;
     };
@@ -108,9 +159,7 @@ pub fn stop_scheduler_timer() {
 
 /// Check if scheduler is enabled
 pub fn is_scheduler_enabled() -> bool {
-    SCHEDULER_CONFIG.with(|c| {
-        c.borrow().as_ref().map(|cfg| cfg.enabled).unwrap_or(false)
-    })
+    load_config().map(|cfg| cfg.enabled).unwrap_or(false)
 }
 
 // =============================================================================
@@ -119,129 +168,568 @@ pub fn is_scheduler_enabled() -> bool {
 
 /// Main scheduler tick - called by the timer
 async fn execute_scheduler_tick() {
-    ic_cdk::println!("⏰ Scheduler tick started at {}", crate::now());
+    if let Err(e) = start_scheduler_run(false).await {
+        ic_cdk::println!("⚠️ Scheduler tick skipped: {}", e);
+    }
+}
 
-    let config = SCHEDULER_CONFIG.with(|c| c.borrow().clone());
+/// Start a scheduler run and process its first batch
+///
+/// The run snapshots the tracked positions into a work queue stored in
+/// stable memory; remaining batches are processed by one-shot timers.
+/// When `force_dry_run` is set every position is evaluated in shadow mode,
+/// even if the scheduler itself is disabled.
+/// Returns the run ID, or None if the scheduler is disabled.
+async fn start_scheduler_run(force_dry_run: bool) -> Result<Option<String>, String> {
+    ic_cdk::println!("⏰ Scheduler tick started at {}", crate::now());
 
-    let Some(mut config) = config else {
+    let Some(config) = load_config() else {
         ic_cdk::println!("❌ Scheduler not initialized");
-        return;
+        return Ok(None);
     };
 
-    if !config.enabled {
+    if !force_dry_run && !config.enabled {
         ic_cdk::println!("⚠️ Scheduler is disabled, skipping tick");
-        return;
+        return Ok(None);
     }
 
-    let mut summary = SchedulerExecutionSummary {
-        timestamp: crate::now(),
-        positions_checked: 0,
-        rebalances_triggered: 0,
-        rebalances_successful: 0,
-        rebalances_failed: 0,
-        execution_ids: Vec::new(),
-        errors: Vec::new(),
+    if let Some(run) = get_active_run() {
+        // Make sure the active run keeps making progress
+        if SCHEDULER_BATCH_TIMER_ID.with(|id| id.borrow().is_none()) {
+            schedule_next_batch(0);
+        }
+        return Err(format!("Scheduler run {} still in progress ({}/{} positions processed)",
+            run.run_id, run.next_index, run.position_count));
+    }
+
+    let timestamp = crate::now();
+    let run_id = format!("tick_{:020}", timestamp);
+
+    // Get all tracked positions and queue them fairly across users
+    let position_ids = build_work_queue(get_tracked_positions());
+    let position_count = position_ids.len() as u64;
+    save_run_queue(&run_id, position_ids);
+
+    ic_cdk::println!("📊 Queued {} tracked positions in batches of {}...",
+        position_count, config.batch_size);
+
+    let run = SchedulerRun {
+        run_id: run_id.clone(),
+        force_dry_run,
+        global_dry_run: config.dry_run,
+        position_count,
+        next_index: 0,
+        summary: SchedulerExecutionSummary {
+            tick_id: run_id.clone(),
+            timestamp,
+            completed_at: None,
+            dry_run: force_dry_run,
+            positions_checked: 0,
+            rebalances_triggered: 0,
+            rebalances_successful: 0,
+            rebalances_failed: 0,
+            dry_run_evaluations: 0,
+            proposals_queued: 0,
+            execution_ids: Vec::new(),
+            errors: Vec::new(),
+        },
+        started_at: timestamp,
+        updated_at: timestamp,
     };
+    save_run(&run);
 
-    // Get all tracked positions (currently mock, will be from DB)
-    let positions = get_tracked_positions();
-    summary.positions_checked = positions.len() as u64;
+    process_next_batch().await;
 
-    ic_cdk::println!("📊 Checking {} tracked positions...", positions.len());
+    Ok(Some(run_id))
+}
 
-    // Process each position
+/// Order positions round-robin across users so one user with many
+/// positions cannot delay everyone else
+fn build_work_queue(positions: Vec<UserPosition>) -> Vec<String> {
+    let mut by_user: BTreeMap<Principal, VecDeque<String>> = BTreeMap::new();
     for position in positions {
-        ic_cdk::println!("🔍 Processing position: {} for user {}",
-            position.position_id, position.user_principal);
+        by_user.entry(position.user_principal)
+            .or_default()
+            .push_back(position.position_id);
+    }
 
-        match process_position(&position, &config).await {
-            Ok(Some(execution)) => {
-                summary.rebalances_triggered += 1;
+    let mut queue = Vec::new();
+    while !by_user.is_empty() {
+        by_user.retain(|_, ids| {
+            if let Some(id) = ids.pop_front() {
+                queue.push(id);
+            }
+            !ids.is_empty()
+        });
+    }
 
-                if execution.result.status == "success" {
-                    summary.rebalances_successful += 1;
-                } else {
-                    summary.rebalances_failed += 1;
-                }
+    queue
+}
 
-                summary.execution_ids.push(execution.execution_id.clone());
+/// Process the next batch of the active run
+///
+/// The checkpoint is advanced before each position is processed, so a run
+/// interrupted by an upgrade resumes after the last started position and
+/// never executes the same position twice.
+async fn process_next_batch() {
+    SCHEDULER_BATCH_TIMER_ID.with(|id| *id.borrow_mut() = None);
+
+    // Held for the duration of the batch so batches never overlap
+    let _scheduler_lock = match locks::try_acquire_scheduler_lock() {
+        Ok(guard) => guard,
+        Err(e) => {
+            ic_cdk::println!("⚠️ Scheduler batch skipped: {}", e);
+            return;
+        }
+    };
 
-                // Store execution in history
-                REBALANCE_HISTORY_MAP.with(|map| {
-                    map.borrow_mut().insert(
-                        StorableString(execution.execution_id.clone()),
-                        StorableRebalanceExecution(execution)
-                    );
-                });
+    let Some(mut run) = get_active_run() else {
+        ic_cdk::println!("ℹ️ No active scheduler run");
+        return;
+    };
+
+    let Some(config) = load_config() else {
+        ic_cdk::println!("❌ Scheduler not initialized, run {} paused", run.run_id);
+        return;
+    };
+
+    // Manual dry runs finish even while the scheduler is disabled
+    if !run.force_dry_run && !config.enabled {
+        ic_cdk::println!("⚠️ Scheduler is disabled, run {} paused", run.run_id);
+        return;
+    }
+
+    let total = run.position_count;
+    let batch_end = (run.next_index + config.batch_size.max(1)).min(total);
+
+    ic_cdk::println!("📦 Run {}: processing positions {}..{} of {}",
+        run.run_id, run.next_index, batch_end, total);
+
+    while run.next_index < batch_end {
+        let index = run.next_index;
+        run.next_index += 1;
+        run.updated_at = crate::now();
+        save_run(&run);
+
+        let Some(position_id) = get_queued_position(&run.run_id, index) else {
+            continue;
+        };
+
+        // The position may have been deleted or untracked since the run started
+        let position = match crate::services::apy_parser::get_position_by_id(position_id.clone()) {
+            Ok(position) if position.tracked => position,
+            _ => {
+                ic_cdk::println!("ℹ️ Position {} no longer tracked, skipping", position_id);
+                continue;
+            }
+        };
+
+        if let Some(entry) = process_run_position(&mut run, &position, &config).await {
+            save_run_entry(&run.run_id, index, entry);
+        }
+        run.updated_at = crate::now();
+        save_run(&run);
+    }
+
+    if run.next_index >= total {
+        finish_run(run);
+    } else {
+        schedule_next_batch(config.batch_interval_seconds);
+    }
+}
+
+/// Process a single position within a run
+///
+/// Counters are updated on the run; the outcome to keep is returned.
+async fn process_run_position(
+    run: &mut SchedulerRun,
+    position: &UserPosition,
+    config: &SchedulerConfig,
+) -> Option<SchedulerRunEntry> {
+    ic_cdk::println!("🔍 Processing position: {} for user {}",
+        position.position_id, position.user_principal);
+
+    run.summary.positions_checked += 1;
+    let prices = load_position_prices(&run.run_id, position).await;
+
+    if run.force_dry_run || is_dry_run_for_user(position.user_principal, run.global_dry_run) {
+        ic_cdk::println!("🧪 Dry-run mode: evaluating without executing");
+        run.summary.dry_run_evaluations += 1;
+        return Some(SchedulerRunEntry {
+            dry_run_result: Some(evaluate_position_dry_run(position, config, &prices)),
+            ..run_entry(position)
+        });
+    }
+
+    if requires_approval(position) {
+        ic_cdk::println!("📬 Approval-required mode: queueing recommendation");
+        return match propose_position(position, config, &prices).await {
+            Ok(true) => {
+                run.summary.proposals_queued += 1;
+                None
             },
-            Ok(None) => {
-                // No rebalance needed
+            Ok(false) => {
                 ic_cdk::println!("✅ No rebalance needed for position {}", position.position_id);
+                None
             },
             Err(e) => {
-                summary.rebalances_failed += 1;
-                summary.errors.push(format!("Position {}: {}", position.position_id, e));
                 ic_cdk::println!("❌ Error processing position {}: {}", position.position_id, e);
+                Some(run_error(position, e))
+            }
+        };
+    }
+
+    // Defer while the chain's fees are above its cap; the position is revisited next tick
+    if let Err(e) = check_run_fee_caps(&run.run_id, position.chain_id).await {
+        ic_cdk::println!("⏳ Deferring position {}: {}", position.position_id, e);
+        return Some(run_error(position, format!("deferred, {}", e)));
+    }
+
+    // Skip users with a fund-moving operation already in progress
+    let _user_lock = match locks::try_acquire_user_lock(position.user_principal, "scheduler_rebalance") {
+        Ok(guard) => guard,
+        Err(e) => {
+            ic_cdk::println!("⏳ Skipping position {}: {}", position.position_id, e);
+            return Some(run_error(position, e));
+        }
+    };
+
+    match process_position(position, config, &prices).await {
+        Ok(Some(execution)) => {
+            run.summary.rebalances_triggered += 1;
+
+            if execution.result.status == "success" {
+                run.summary.rebalances_successful += 1;
+            } else {
+                run.summary.rebalances_failed += 1;
             }
+
+            let entry = SchedulerRunEntry {
+                execution_id: Some(execution.execution_id.clone()),
+                ..run_entry(position)
+            };
+
+            // Store execution in history
+            store_rebalance_execution(execution);
+            Some(entry)
+        },
+        Ok(None) => {
+            // No rebalance needed
+            ic_cdk::println!("✅ No rebalance needed for position {}", position.position_id);
+            None
+        },
+        Err(e) => {
+            run.summary.rebalances_failed += 1;
+            ic_cdk::println!("❌ Error processing position {}: {}", position.position_id, e);
+            Some(run_error(position, e))
         }
     }
+}
 
-    // Update last execution time
-    config.last_execution = Some(crate::now());
-    config.updated_at = crate::now();
-    SCHEDULER_CONFIG.with(|c| {
-        *c.borrow_mut() = Some(config);
+fn run_entry(position: &UserPosition) -> SchedulerRunEntry {
+    SchedulerRunEntry {
+        position_id: position.position_id.clone(),
+        execution_id: None,
+        error: None,
+        dry_run_result: None,
+    }
+}
+
+fn run_error(position: &UserPosition, error: String) -> SchedulerRunEntry {
+    SchedulerRunEntry {
+        error: Some(error),
+        ..run_entry(position)
+    }
+}
+
+/// Complete a run: record its summary and dry-run results, update last execution time and drop the checkpoint
+fn finish_run(mut run: SchedulerRun) {
+    run.summary.completed_at = Some(crate::now());
+    RUN_MARKET_DATA.with(|data| *data.borrow_mut() = RunMarketData::default());
+
+    let mut dry_run_results = Vec::new();
+    for entry in take_run_entries(&run.run_id) {
+        if let Some(execution_id) = entry.execution_id {
+            run.summary.execution_ids.push(execution_id);
+        }
+        if let Some(error) = entry.error {
+            run.summary.errors.push(format!("Position {}: {}", entry.position_id, error));
+        }
+        if let Some(result) = entry.dry_run_result {
+            dry_run_results.push(result);
+        }
+    }
+
+    if !dry_run_results.is_empty() {
+        store_dry_run_tick(DryRunTick {
+            tick_id: run.run_id.clone(),
+            timestamp: run.started_at,
+            results: dry_run_results,
+        });
+    }
+
+    // Manual dry runs do not count as executions
+    if !run.force_dry_run {
+        let _ = update_config(|config| {
+            config.last_execution = Some(crate::now());
+            config.updated_at = crate::now();
+        });
+    }
+
+    SCHEDULER_RUN_MAP.with(|map| {
+        map.borrow_mut().remove(&StorableString(run.run_id.clone()));
     });
+    clear_run_queue(&run.run_id);
 
+    let summary = run.summary;
     ic_cdk::println!("📋 Scheduler tick summary:");
+    ic_cdk::println!("  - Tick ID: {}", summary.tick_id);
     ic_cdk::println!("  - Positions checked: {}", summary.positions_checked);
+    ic_cdk::println!("  - Dry-run evaluations: {}", summary.dry_run_evaluations);
+    ic_cdk::println!("  - Proposals queued: {}", summary.proposals_queued);
     ic_cdk::println!("  - Rebalances triggered: {}", summary.rebalances_triggered);
     ic_cdk::println!("  - Successful: {}", summary.rebalances_successful);
     ic_cdk::println!("  - Failed: {}", summary.rebalances_failed);
     ic_cdk::println!("  - Errors: {}", summary.errors.len());
 
+    store_tick_summary(summary);
+
     ic_cdk::println!("✅ Scheduler tick completed");
 }
 
-/// Process a single position and determine if rebalance is needed
-async fn process_position(
+/// Schedule the next batch of the active run
+fn schedule_next_batch(delay_seconds: u64) {
+    SCHEDULER_BATCH_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow().as_ref() {
+            clear_timer(*id);
+        }
+    });
+
+    let timer_id = set_timer(Duration::from_secs(delay_seconds), || {
+        ic_cdk::spawn(async {
+            process_next_batch().await;
+        });
+    });
+
+    SCHEDULER_BATCH_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+}
+
+/// Get the active scheduler run checkpoint, if any
+pub fn get_active_run() -> Option<SchedulerRun> {
+    SCHEDULER_RUN_MAP.with(|map| {
+        map.borrow().iter().next().map(|(_, run)| run.0)
+    })
+}
+
+/// Persist the run checkpoint
+fn save_run(run: &SchedulerRun) {
+    SCHEDULER_RUN_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(run.run_id.clone()),
+            StorableSchedulerRun(run.clone())
+        );
+    });
+}
+
+/// Persist the work queue of a run, one entry per position
+fn save_run_queue(run_id: &str, position_ids: Vec<String>) {
+    SCHEDULER_RUN_QUEUE_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        for (index, position_id) in position_ids.into_iter().enumerate() {
+            borrowed.insert(
+                StorableString(run_entry_key(run_id, index as u64)),
+                StorableString(position_id)
+            );
+        }
+    });
+}
+
+/// Position ID at `index` of a run's work queue
+fn get_queued_position(run_id: &str, index: u64) -> Option<String> {
+    SCHEDULER_RUN_QUEUE_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(run_entry_key(run_id, index)))
+            .map(|position_id| position_id.0)
+    })
+}
+
+/// Drop the work queue of a run
+fn clear_run_queue(run_id: &str) {
+    SCHEDULER_RUN_QUEUE_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        let prefix = format!("{}|", run_id);
+        let keys: Vec<StorableString> = borrowed
+            .range(StorableString(prefix.clone())..)
+            .take_while(|(key, _)| key.0.starts_with(&prefix))
+            .map(|(key, _)| key)
+            .collect();
+
+        for key in keys {
+            borrowed.remove(&key);
+        }
+    });
+}
+
+/// Persist the outcome of the position at `index` of a run
+fn save_run_entry(run_id: &str, index: u64, entry: SchedulerRunEntry) {
+    SCHEDULER_RUN_ENTRIES_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(run_entry_key(run_id, index)),
+            StorableSchedulerRunEntry(entry)
+        );
+    });
+}
+
+/// Remove and return the outcomes of a run, in queue order
+fn take_run_entries(run_id: &str) -> Vec<SchedulerRunEntry> {
+    SCHEDULER_RUN_ENTRIES_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        let prefix = format!("{}|", run_id);
+        let entries: Vec<(StorableString, SchedulerRunEntry)> = borrowed
+            .range(StorableString(prefix.clone())..)
+            .take_while(|(key, _)| key.0.starts_with(&prefix))
+            .map(|(key, entry)| (key, entry.0))
+            .collect();
+
+        for (key, _) in &entries {
+            borrowed.remove(key);
+        }
+
+        entries.into_iter().map(|(_, entry)| entry).collect()
+    })
+}
+
+/// Key of a run queue entry or outcome; zero-padded so entries sort in queue order
+fn run_entry_key(run_id: &str, index: u64) -> String {
+    format!("{}|{:010}", run_id, index)
+}
+
+/// Resume an interrupted run (called after upgrade)
+pub fn resume_scheduler_run() {
+    if let Some(run) = get_active_run() {
+        ic_cdk::println!("🔄 Resuming scheduler run {} at position {}/{}",
+            run.run_id, run.next_index, run.position_count);
+        schedule_next_batch(0);
+    }
+}
+
+/// Abandon the active run, if any
+fn cancel_active_run() {
+    SCHEDULER_BATCH_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow_mut().take() {
+            clear_timer(id);
+        }
+    });
+
+    if let Some(run) = get_active_run() {
+        SCHEDULER_RUN_MAP.with(|map| {
+            map.borrow_mut().remove(&StorableString(run.run_id.clone()));
+        });
+        take_run_entries(&run.run_id);
+        clear_run_queue(&run.run_id);
+        ic_cdk::println!("🛑 Cancelled scheduler run {} at position {}/{}",
+            run.run_id, run.next_index, run.position_count);
+    }
+}
+
+/// USD prices a position evaluation needs, read before evaluating
+struct PositionPrices {
+    /// Price of the position's asset, if the oracle has a valid one
+    asset_usd: Option<f64>,
+    /// Estimated cost of a rebalance
+    gas_cost_usd: f64,
+}
+
+/// Result of evaluating a position against the current APY signals
+struct PositionEvaluation {
+    current_apy: Option<f64>,
+    alternative_apy: Option<f64>,
+    apy_difference: Option<f64>,
+    recommendation: Option<Recommendation>,
+    reasoning: String,
+}
+
+/// Evaluate a position and decide whether a rebalance is warranted
+///
+/// This is pure decision logic: nothing is signed or sent.
+fn evaluate_position(
     position: &UserPosition,
     config: &SchedulerConfig,
-) -> Result<Option<RebalanceExecution>, String> {
-    // Check if position size meets minimum threshold
-    let position_amount: f64 = position.position_size.parse()
-        .map_err(|_| "Invalid position size")?;
-    let min_amount: f64 = config.min_position_size.parse()
-        .map_err(|_| "Invalid min position size")?;
-
-    if position_amount < min_amount {
-        ic_cdk::println!("  Position size ${} below minimum ${}, skipping",
-            position_amount, min_amount);
-        return Ok(None);
+    prices: &PositionPrices,
+) -> Result<PositionEvaluation, String> {
+    let mut evaluation = PositionEvaluation {
+        current_apy: None,
+        alternative_apy: None,
+        apy_difference: None,
+        recommendation: None,
+        reasoning: String::new(),
+    };
+
+    // Check if position value meets the minimum (min_position_size is in USD)
+    let position_amount = position.size()
+        .map_err(|e| format!("Invalid position size: {}", e))?;
+    let min_value_usd: f64 = config.min_position_size.parse()
+        .map_err(|e| format!("Invalid min position size: {}", e))?;
+
+    let Some(price_usd) = prices.asset_usd else {
+        ic_cdk::println!("  No USD price for {} on chain {}, skipping", position.asset, position.chain_id);
+        evaluation.reasoning = format!("No USD price for {} on chain {}", position.asset, position.chain_id);
+        return Ok(evaluation);
+    };
+    let position_value_usd = position_amount.to_f64() * price_usd;
+
+    if position_value_usd < min_value_usd {
+        ic_cdk::println!("  Position value ${:.2} below minimum ${}, skipping",
+            position_value_usd, min_value_usd);
+        evaluation.reasoning = format!("Position value ${:.2} below minimum ${}", position_value_usd, min_value_usd);
+        return Ok(evaluation);
     }
 
     // Get APY rates for current protocol and alternative
     let current_protocol = &position.protocol;
-    let alternative_protocol = if current_protocol == "AAVE" { "COMPOUND" } else { "AAVE" };
+    let alternative_protocol = alternative_protocol(current_protocol);
 
     ic_cdk::println!("  Comparing APY: {} vs {}", current_protocol, alternative_protocol);
 
-    let current_apy = get_latest_apy(current_protocol, &position.asset, position.chain_id).await?;
-    let alternative_apy = get_latest_apy(alternative_protocol, &position.asset, position.chain_id).await?;
+    let Some(current_apy) = get_apy_signal(current_protocol, &position.asset, position.chain_id, config)? else {
+        ic_cdk::println!("  Not enough APY history for {} (need {} samples), skipping",
+            current_protocol, config.min_apy_samples);
+        evaluation.reasoning = format!("Not enough APY history for {} (need {} samples)",
+            current_protocol, config.min_apy_samples);
+        return Ok(evaluation);
+    };
+    evaluation.current_apy = Some(current_apy);
+
+    let Some(alternative_apy) = get_apy_signal(alternative_protocol, &position.asset, position.chain_id, config)? else {
+        ic_cdk::println!("  Not enough APY history for {} (need {} samples), skipping",
+            alternative_protocol, config.min_apy_samples);
+        evaluation.reasoning = format!("Not enough APY history for {} (need {} samples)",
+            alternative_protocol, config.min_apy_samples);
+        return Ok(evaluation);
+    };
+    evaluation.alternative_apy = Some(alternative_apy);
 
     ic_cdk::println!("  Current APY ({}): {}%", current_protocol, current_apy);
     ic_cdk::println!("  Alternative APY ({}): {}%", alternative_protocol, alternative_apy);
 
     // Calculate APY difference
     let apy_difference = alternative_apy - current_apy;
+    evaluation.apy_difference = Some(apy_difference);
 
     ic_cdk::println!("  APY Difference: {}%", apy_difference);
     ic_cdk::println!("  Threshold: {}%", config.apy_threshold_percent);
 
     // Check if rebalance is profitable
-    if apy_difference < config.apy_threshold_percent {
+    if !should_rebalance(apy_difference, config) {
         ic_cdk::println!("  APY difference below threshold, no rebalance needed");
-        return Ok(None);
+        evaluation.reasoning = format!(
+            "{:?} APY difference {:.4}% ({} {:.4}% vs {} {:.4}%) below threshold {}%",
+            config.apy_signal, apy_difference, alternative_protocol, alternative_apy,
+            current_protocol, current_apy, config.apy_threshold_percent
+        );
+        return Ok(evaluation);
     }
 
     ic_cdk::println!("  ✅ APY difference exceeds threshold, generating recommendation...");
@@ -253,25 +741,129 @@ async fn process_position(
         alternative_protocol,
         current_apy,
         alternative_apy,
+        position_value_usd,
+        prices.gas_cost_usd,
     )?;
 
     ic_cdk::println!("  📝 Recommendation generated: {} -> {}",
         recommendation.from_protocol, recommendation.to_protocol);
 
-    // Execute recommendation
+    evaluation.reasoning = format!(
+        "{:?} APY difference {:.4}% ({} {:.4}% vs {} {:.4}%) exceeds threshold {}%",
+        config.apy_signal, apy_difference, alternative_protocol, alternative_apy,
+        current_protocol, current_apy, config.apy_threshold_percent
+    );
+    evaluation.recommendation = Some(recommendation);
+
+    Ok(evaluation)
+}
+
+/// Protocol a position in `current_protocol` is compared against
+pub(crate) fn alternative_protocol(current_protocol: &str) -> &'static str {
+    if current_protocol == "AAVE" { "COMPOUND" } else { "AAVE" }
+}
+
+/// Whether an APY difference (alternative minus current) warrants a rebalance
+pub(crate) fn should_rebalance(apy_difference: f64, config: &SchedulerConfig) -> bool {
+    apy_difference >= config.apy_threshold_percent
+}
+
+/// Evaluate a position in shadow mode and describe the outcome
+fn evaluate_position_dry_run(position: &UserPosition, config: &SchedulerConfig, prices: &PositionPrices) -> DryRunResult {
+    match evaluate_position(position, config, prices) {
+        Ok(evaluation) => DryRunResult {
+            user_principal: position.user_principal,
+            position_id: position.position_id.clone(),
+            decision: if evaluation.recommendation.is_some() { "rebalance" } else { "skip" }.to_string(),
+            reasoning: evaluation.reasoning,
+            current_apy: evaluation.current_apy,
+            alternative_apy: evaluation.alternative_apy,
+            apy_difference: evaluation.apy_difference,
+            recommendation: evaluation.recommendation,
+        },
+        Err(e) => {
+            ic_cdk::println!("❌ Dry-run evaluation failed for position {}: {}", position.position_id, e);
+            DryRunResult {
+                user_principal: position.user_principal,
+                position_id: position.position_id.clone(),
+                decision: "error".to_string(),
+                reasoning: e,
+                current_apy: None,
+                alternative_apy: None,
+                apy_difference: None,
+                recommendation: None,
+            }
+        }
+    }
+}
+
+/// Process a single position and determine if rebalance is needed
+async fn process_position(
+    position: &UserPosition,
+    config: &SchedulerConfig,
+    prices: &PositionPrices,
+) -> Result<Option<RebalanceExecution>, String> {
+    let evaluation = evaluate_position(position, config, prices)?;
+
+    let (Some(recommendation), Some(apy_difference)) = (evaluation.recommendation, evaluation.apy_difference) else {
+        return Ok(None);
+    };
+
+    let execution = execute_rebalance(
+        position.user_principal,
+        position.position_id.clone(),
+        position.permissions_id.clone(),
+        recommendation,
+        apy_difference,
+    ).await?;
+
+    Ok(Some(execution))
+}
+
+/// Evaluate a position and queue its recommendation for user approval
+/// Returns true if a proposal was queued
+async fn propose_position(
+    position: &UserPosition,
+    config: &SchedulerConfig,
+    prices: &PositionPrices,
+) -> Result<bool, String> {
+    let evaluation = evaluate_position(position, config, prices)?;
+
+    let (Some(recommendation), Some(apy_difference)) = (evaluation.recommendation, evaluation.apy_difference) else {
+        return Ok(false);
+    };
+
+    crate::services::approval_queue::propose_recommendation(
+        position,
+        recommendation,
+        apy_difference,
+        config.proposal_ttl_seconds,
+    ).await?;
+
+    Ok(true)
+}
+
+/// Execute a recommendation for a position and build the execution record
+pub(crate) async fn execute_rebalance(
+    user_principal: Principal,
+    position_id: String,
+    permissions_id: String,
+    recommendation: Recommendation,
+    apy_difference: f64,
+) -> Result<RebalanceExecution, String> {
     ic_cdk::println!("  🚀 Executing rebalance...");
     let result = crate::services::rebalance::execute_recommendation(
         recommendation.clone(),
-        position.permissions_id.clone(),
-        position.user_principal,
+        permissions_id,
+        user_principal,
     ).await?;
 
     // Create execution record
     let execution_id = generate_execution_id().await;
     let execution = RebalanceExecution {
         execution_id: execution_id.clone(),
-        user_principal: position.user_principal,
-        position_id: position.position_id.clone(),
+        user_principal,
+        position_id,
         recommendation,
         result,
         apy_difference,
@@ -281,7 +873,12 @@ async fn process_position(
     ic_cdk::println!("  ✅ Rebalance executed: {} (status: {})",
         execution_id, execution.result.status);
 
-    Ok(Some(execution))
+    Ok(execution)
+}
+
+/// Store an execution in rebalance history
+pub(crate) fn store_rebalance_execution(execution: RebalanceExecution) {
+    indexes::save_rebalance_execution(execution);
 }
 
 /// Generate a recommendation based on position and APY comparison
@@ -291,15 +888,13 @@ fn generate_recommendation(
     target_protocol: &str,
     current_apy: f64,
     target_apy: f64,
+    position_value_usd: f64,
+    gas_cost_usd: f64,
 ) -> Result<Recommendation, String> {
-    // Estimate profit (simplified - just APY difference)
-    let position_amount: f64 = position.position_size.parse()
-        .map_err(|_| "Invalid position size")?;
+    // Estimate annual profit in USD from the APY difference
+    let position_amount = position.size()?;
     let apy_diff = target_apy - current_apy;
-    let estimated_annual_profit = position_amount * (apy_diff / 100.0);
-
-    // Simplified gas cost estimate (will be more accurate with real data)
-    let estimated_gas_cost = 5.0; // $5 estimate
+    let estimated_annual_profit = position_value_usd * (apy_diff / 100.0);
 
     Ok(Recommendation {
         asset: position.asset.clone(),
@@ -311,8 +906,9 @@ fn generate_recommendation(
         current_apy,
         target_apy,
         estimated_profit: estimated_annual_profit,
-        gas_cost: estimated_gas_cost,
-        position_size: position.position_size.clone(),
+        gas_cost: gas_cost_usd,
+        position_size: position_amount.to_string(),
+        position_amount: Some(position_amount),
         pool_id: None,
         recommendation_type: RecommendationType::StandardTransfer,
         swap_details: None,
@@ -331,19 +927,82 @@ fn get_tracked_positions() -> Vec<UserPosition> {
     crate::services::apy_parser::get_tracked_positions()
 }
 
-/// Get latest APY for a protocol (uses APY parser with fallback to live queries)
-async fn get_latest_apy(protocol: &str, asset: &str, chain_id: u64) -> Result<f64, String> {
-    ic_cdk::println!("  Getting APY for {} {} on chain {}", protocol, asset, chain_id);
+/// Get the configured APY signal for a protocol from stored history
+/// Returns None when there are not enough samples in the signal window
+fn get_apy_signal(protocol: &str, asset: &str, chain_id: u64, config: &SchedulerConfig) -> Result<Option<f64>, String> {
+    ic_cdk::println!("  Getting APY signal for {} {} on chain {}", protocol, asset, chain_id);
 
-    // Use APY parser which will check cache first, then fall back to live query
-    crate::services::apy_parser::get_latest_apy(protocol, asset, chain_id).await
+    crate::services::apy_signals::get_apy_signal(protocol, asset, chain_id, config)
+}
+
+/// Read the asset price and estimate the rebalance gas cost of a position
+///
+/// Each chain's gas cost and each asset's price are read once per run and
+/// reused for the run's other positions.
+async fn load_position_prices(run_id: &str, position: &UserPosition) -> PositionPrices {
+    reset_run_market_data(run_id);
+    let asset_key = (position.asset.to_uppercase(), position.chain_id);
+
+    let cached_asset = RUN_MARKET_DATA.with(|data| data.borrow().asset_usd.get(&asset_key).copied());
+    let asset_usd = match cached_asset {
+        Some(asset_usd) => asset_usd,
+        None => {
+            let asset_usd = match price_oracle::get_price(&position.asset, position.chain_id).await {
+                Ok(price) => Some(price.price_usd),
+                Err(e) => {
+                    ic_cdk::println!("  ⚠️ {}", e);
+                    None
+                }
+            };
+            RUN_MARKET_DATA.with(|data| data.borrow_mut().asset_usd.insert(asset_key, asset_usd));
+            asset_usd
+        }
+    };
+
+    let cached_gas = RUN_MARKET_DATA.with(|data| data.borrow().gas_cost_usd.get(&position.chain_id).copied());
+    let gas_cost_usd = match cached_gas {
+        Some(gas_cost_usd) => gas_cost_usd,
+        None => {
+            let gas_cost_usd = price_oracle::estimate_gas_cost_usd(position.chain_id, REBALANCE_GAS_UNITS).await
+                .unwrap_or_else(|e| {
+                    ic_cdk::println!("  ⚠️ Gas cost estimate failed ({}), assuming ${}", e, FALLBACK_GAS_COST_USD);
+                    FALLBACK_GAS_COST_USD
+                });
+            RUN_MARKET_DATA.with(|data| data.borrow_mut().gas_cost_usd.insert(position.chain_id, gas_cost_usd));
+            gas_cost_usd
+        }
+    };
+
+    PositionPrices { asset_usd, gas_cost_usd }
+}
+
+/// Check the chain's fee caps once per run and reuse the result for its other positions
+async fn check_run_fee_caps(run_id: &str, chain_id: u64) -> Result<(), String> {
+    reset_run_market_data(run_id);
+
+    if let Some(result) = RUN_MARKET_DATA.with(|data| data.borrow().fee_caps.get(&chain_id).cloned()) {
+        return result;
+    }
+
+    let result = fee_policy::check_fee_caps(chain_id).await;
+    RUN_MARKET_DATA.with(|data| data.borrow_mut().fee_caps.insert(chain_id, result.clone()));
+    result
+}
+
+/// Drop chain data read for an earlier run
+fn reset_run_market_data(run_id: &str) {
+    RUN_MARKET_DATA.with(|data| {
+        let mut data = data.borrow_mut();
+        if data.run_id != run_id {
+            *data = RunMarketData { run_id: run_id.to_string(), ..RunMarketData::default() };
+        }
+    });
 }
 
 /// Get chain name from chain ID
 fn get_chain_name(chain_id: u64) -> String {
     crate::services::rpc_service::get_chain_name(chain_id)
-        .unwrap_or("Unknown")
-        .to_string()
+        .unwrap_or_else(|| "Unknown".to_string())
 }
 
 /// Generate unique execution ID
@@ -370,13 +1029,35 @@ async fn generate_execution_id() -> String {
 // Configuration Management Functions
 // =============================================================================
 
+/// Scheduler configuration, None until the scheduler is initialized
+fn load_config() -> Option<SchedulerConfig> {
+    SCHEDULER_CONFIG_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(SCHEDULER_CONFIG_KEY.to_string()))
+            .map(|config| config.0)
+    })
+}
+
+fn store_config(config: &SchedulerConfig) {
+    SCHEDULER_CONFIG_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(SCHEDULER_CONFIG_KEY.to_string()),
+            StorableSchedulerConfig(config.clone())
+        );
+    });
+}
+
+/// Apply a change to the stored configuration
+fn update_config(change: impl FnOnce(&mut SchedulerConfig)) -> Result<SchedulerConfig, String> {
+    let mut config = load_config().ok_or_else(|| "Scheduler not initialized".to_string())?;
+    change(&mut config);
+    store_config(&config);
+    Ok(config)
+}
+
 /// Get current scheduler configuration
 pub fn get_scheduler_config() -> Result<SchedulerConfig, String> {
-    SCHEDULER_CONFIG.with(|c| {
-        c.borrow()
-            .clone()
-            .ok_or_else(|| "Scheduler not initialized".to_string())
-    })
+    load_config().ok_or_else(|| "Scheduler not initialized".to_string())
 }
 
 /// Update scheduler configuration
@@ -386,9 +1067,7 @@ pub fn update_scheduler_config(new_config: SchedulerConfig) -> Result<SchedulerC
     let mut config = new_config;
     config.updated_at = crate::now();
 
-    SCHEDULER_CONFIG.with(|c| {
-        *c.borrow_mut() = Some(config.clone());
-    });
+    store_config(&config);
 
     ic_cdk::println!("✅ Configuration updated");
     Ok(config)
@@ -398,15 +1077,9 @@ pub fn update_scheduler_config(new_config: SchedulerConfig) -> Result<SchedulerC
 pub fn enable_scheduler() -> Result<String, String> {
     ic_cdk::println!("▶️ Enabling scheduler...");
 
-    SCHEDULER_CONFIG.with(|c| {
-        let mut borrowed = c.borrow_mut();
-        if let Some(ref mut config) = *borrowed {
-            config.enabled = true;
-            config.updated_at = crate::now();
-        } else {
-            return Err("Scheduler not initialized".to_string());
-        }
-        Ok(())
+    update_config(|config| {
+        config.enabled = true;
+        config.updated_at = crate::now();
     })?;
 
     start_scheduler_timer();
@@ -417,18 +1090,18 @@ pub fn enable_scheduler() -> Result<String, String> {
 pub fn disable_scheduler() -> Result<String, String> {
     ic_cdk::println!("⏸️ Disabling scheduler...");
 
-    SCHEDULER_CONFIG.with(|c| {
-        let mut borrowed = c.borrow_mut();
-        if let Some(ref mut config) = *borrowed {
-            config.enabled = false;
-            config.updated_at = crate::now();
-        } else {
-            return Err("Scheduler not initialized".to_string());
-        }
-        Ok(())
+    update_config(|config| {
+        config.enabled = false;
+        config.updated_at = crate::now();
     })?;
 
     stop_scheduler_timer();
+
+    // Abandon a run in progress (manual dry runs are left to finish)
+    if get_active_run().map(|run| !run.force_dry_run).unwrap_or(false) {
+        cancel_active_run();
+    }
+
     Ok("Scheduler disabled and timer stopped".to_string())
 }
 
@@ -442,15 +1115,9 @@ pub fn set_scheduler_interval(seconds: u64) -> Result<String, String> {
 
     let was_enabled = is_scheduler_enabled();
 
-    SCHEDULER_CONFIG.with(|c| {
-        let mut borrowed = c.borrow_mut();
-        if let Some(ref mut config) = *borrowed {
-            config.interval_seconds = seconds;
-            config.updated_at = crate::now();
-        } else {
-            return Err("Scheduler not initialized".to_string());
-        }
-        Ok(())
+    update_config(|config| {
+        config.interval_seconds = seconds;
+        config.updated_at = crate::now();
     })?;
 
     // Restart timer if it was running
@@ -470,15 +1137,9 @@ pub fn set_apy_threshold(percent: f64) -> Result<String, String> {
         return Err("APY threshold must be positive".to_string());
     }
 
-    SCHEDULER_CONFIG.with(|c| {
-        let mut borrowed = c.borrow_mut();
-        if let Some(ref mut config) = *borrowed {
-            config.apy_threshold_percent = percent;
-            config.updated_at = crate::now();
-        } else {
-            return Err("Scheduler not initialized".to_string());
-        }
-        Ok(())
+    update_config(|config| {
+        config.apy_threshold_percent = percent;
+        config.updated_at = crate::now();
     })?;
 
     Ok(format!("APY threshold updated to {}%", percent))
@@ -492,25 +1153,63 @@ pub fn set_min_position_size(amount_usd: f64) -> Result<String, String> {
         return Err("Minimum position size must be positive".to_string());
     }
 
-    SCHEDULER_CONFIG.with(|c| {
-        let mut borrowed = c.borrow_mut();
-        if let Some(ref mut config) = *borrowed {
-            config.min_position_size = amount_usd.to_string();
-            config.updated_at = crate::now();
-        } else {
-            return Err("Scheduler not initialized".to_string());
-        }
-        Ok(())
+    update_config(|config| {
+        config.min_position_size = amount_usd.to_string();
+        config.updated_at = crate::now();
     })?;
 
     Ok(format!("Minimum position size updated to ${}", amount_usd))
 }
 
+/// Set the APY signal that drives rebalance decisions
+pub fn set_apy_signal(
+    signal: ApySignal,
+    window_seconds: u64,
+    min_samples: u64,
+) -> Result<String, String> {
+    ic_cdk::println!("📈 Setting APY signal to {:?} ({}s window, min {} samples)...",
+        signal, window_seconds, min_samples);
+
+    if window_seconds < 60 {
+        return Err("Signal window must be at least 60 seconds".to_string());
+    }
+
+    if min_samples == 0 {
+        return Err("Minimum samples must be at least 1".to_string());
+    }
+
+    update_config(|config| {
+        config.apy_signal = signal.clone();
+        config.signal_window_seconds = window_seconds;
+        config.min_apy_samples = min_samples;
+        config.updated_at = crate::now();
+    })?;
+
+    Ok(format!("APY signal updated to {:?} ({}s window, min {} samples)", signal, window_seconds, min_samples))
+}
+
+/// Set the EMA half-life in seconds
+pub fn set_ema_half_life(seconds: u64) -> Result<String, String> {
+    ic_cdk::println!("📈 Setting EMA half-life to {} seconds...", seconds);
+
+    if seconds == 0 {
+        return Err("EMA half-life must be positive".to_string());
+    }
+
+    update_config(|config| {
+        config.ema_half_life_seconds = seconds;
+        config.updated_at = crate::now();
+    })?;
+
+    Ok(format!("EMA half-life updated to {} seconds", seconds))
+}
+
 /// Manually trigger scheduler execution
+/// The first batch is processed immediately, the rest by batch timers
 pub async fn trigger_manual_execution() -> Result<Vec<RebalanceExecution>, String> {
     ic_cdk::println!("🔨 Manual scheduler execution triggered...");
 
-    execute_scheduler_tick().await;
+    start_scheduler_run(false).await?;
 
     // Return recent executions from this tick
     REBALANCE_HISTORY_MAP.with(|map| {
@@ -528,6 +1227,191 @@ pub async fn trigger_manual_execution() -> Result<Vec<RebalanceExecution>, Strin
     })
 }
 
+// =============================================================================
+// Dry-Run (Shadow) Mode
+// =============================================================================
+
+/// Whether positions of this user are evaluated in dry-run mode
+/// A per-user override takes precedence over the global setting
+fn is_dry_run_for_user(user: Principal, global_dry_run: bool) -> bool {
+    get_user_scheduler_settings(user)
+        .and_then(|settings| settings.dry_run)
+        .unwrap_or(global_dry_run)
+}
+
+/// Store dry-run results of a tick, dropping the oldest ticks beyond retention
+fn store_dry_run_tick(tick: DryRunTick) {
+    ic_cdk::println!("📝 Storing {} dry-run results for {}", tick.results.len(), tick.tick_id);
+
+    DRY_RUN_TICKS_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        borrowed.insert(StorableString(tick.tick_id.clone()), StorableDryRunTick(tick));
+
+        // Tick IDs are zero-padded timestamps, so the first key is the oldest tick
+        while borrowed.len() > MAX_DRY_RUN_TICKS {
+            let oldest = borrowed.iter().next().map(|(k, _)| k);
+            match oldest {
+                Some(key) => { borrowed.remove(&key); }
+                None => break,
+            }
+        }
+    });
+}
+
+/// Enable or disable global dry-run mode
+pub fn set_dry_run(enabled: bool) -> Result<String, String> {
+    ic_cdk::println!("🧪 Setting global dry-run mode to {}...", enabled);
+
+    update_config(|config| {
+        config.dry_run = enabled;
+        config.updated_at = crate::now();
+    })?;
+
+    Ok(format!("Global dry-run mode {}", if enabled { "enabled" } else { "disabled" }))
+}
+
+/// Get per-user scheduler settings, if any
+pub fn get_user_scheduler_settings(user: Principal) -> Option<UserSchedulerSettings> {
+    USER_SCHEDULER_SETTINGS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorablePrincipal(user))
+            .map(|s| s.0)
+    })
+}
+
+/// Set the dry-run override for a user (None = follow the global setting)
+pub fn set_user_dry_run(user: Principal, dry_run: Option<bool>) -> Result<UserSchedulerSettings, String> {
+    ic_cdk::println!("🧪 Setting dry-run override for user {} to {:?}...", user, dry_run);
+
+    let mut settings = get_user_scheduler_settings(user).unwrap_or(UserSchedulerSettings {
+        user_principal: user,
+        dry_run: None,
+        execution_mode: None,
+        updated_at: 0,
+    });
+    settings.dry_run = dry_run;
+    settings.updated_at = crate::now();
+
+    USER_SCHEDULER_SETTINGS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorablePrincipal(user),
+            StorableUserSchedulerSettings(settings.clone())
+        );
+    });
+
+    ic_cdk::println!("✅ User scheduler settings updated");
+    Ok(settings)
+}
+
+// =============================================================================
+// Execution Mode (Auto-Execute vs Approval-Required)
+// =============================================================================
+
+/// Whether recommendations for a position must be approved by the user
+/// A position override beats the user setting; the default is auto-execute
+fn requires_approval(position: &UserPosition) -> bool {
+    let mode = position.execution_mode.clone().or_else(|| {
+        get_user_scheduler_settings(position.user_principal).and_then(|s| s.execution_mode)
+    });
+
+    mode == Some(ExecutionMode::ApprovalRequired)
+}
+
+/// Set the execution mode for a user (None = auto-execute)
+pub fn set_user_execution_mode(user: Principal, execution_mode: Option<ExecutionMode>) -> Result<UserSchedulerSettings, String> {
+    ic_cdk::println!("📬 Setting execution mode for user {} to {:?}...", user, execution_mode);
+
+    let mut settings = get_user_scheduler_settings(user).unwrap_or(UserSchedulerSettings {
+        user_principal: user,
+        dry_run: None,
+        execution_mode: None,
+        updated_at: 0,
+    });
+    settings.execution_mode = execution_mode;
+    settings.updated_at = crate::now();
+
+    USER_SCHEDULER_SETTINGS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorablePrincipal(user),
+            StorableUserSchedulerSettings(settings.clone())
+        );
+    });
+
+    ic_cdk::println!("✅ User scheduler settings updated");
+    Ok(settings)
+}
+
+/// Set how many positions each scheduler batch processes and the delay between batches
+pub fn set_batch_size(batch_size: u64, batch_interval_seconds: u64) -> Result<String, String> {
+    ic_cdk::println!("📦 Setting scheduler batch size to {} (every {}s)...", batch_size, batch_interval_seconds);
+
+    if batch_size == 0 || batch_size > 100 {
+        return Err("Batch size must be between 1 and 100".to_string());
+    }
+
+    update_config(|config| {
+        config.batch_size = batch_size;
+        config.batch_interval_seconds = batch_interval_seconds;
+        config.updated_at = crate::now();
+    })?;
+
+    Ok(format!("Scheduler batches set to {} positions every {} seconds", batch_size, batch_interval_seconds))
+}
+
+/// Set how long pending recommendations stay approvable
+pub fn set_proposal_ttl(seconds: u64) -> Result<String, String> {
+    ic_cdk::println!("📬 Setting proposal TTL to {} seconds...", seconds);
+
+    if seconds < 300 {
+        return Err("Proposal TTL must be at least 300 seconds (5 minutes)".to_string());
+    }
+
+    update_config(|config| {
+        config.proposal_ttl_seconds = seconds;
+        config.updated_at = crate::now();
+    })?;
+
+    Ok(format!("Proposal TTL updated to {} seconds", seconds))
+}
+
+/// Manually run a dry-run tick over all tracked positions
+/// Returns the tick ID; its results are stored when the run's last batch
+/// completes and can then be read with `get_dry_run_tick`
+pub async fn trigger_dry_run() -> Result<String, String> {
+    ic_cdk::println!("🧪 Manual dry-run tick triggered...");
+
+    get_scheduler_config()?;
+
+    start_scheduler_run(true).await?
+        .ok_or_else(|| "Scheduler not initialized".to_string())
+}
+
+/// Get dry-run results of a specific tick
+pub fn get_dry_run_tick(tick_id: String) -> Result<DryRunTick, String> {
+    DRY_RUN_TICKS_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(tick_id.clone()))
+            .map(|t| t.0)
+            .ok_or_else(|| format!("Dry-run tick {} not found", tick_id))
+    })
+}
+
+/// Get the most recent dry-run ticks (most recent first)
+pub fn get_dry_run_ticks(limit: Option<u64>) -> Vec<DryRunTick> {
+    let limit = limit.unwrap_or(10) as usize;
+
+    DRY_RUN_TICKS_MAP.with(|map| {
+        let mut all: Vec<DryRunTick> = map.borrow()
+            .iter()
+            .map(|(_, tick)| tick.0)
+            .collect();
+
+        // Sort by timestamp descending
+        all.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        all.into_iter().take(limit).collect()
+    })
+}
+
 // =============================================================================
 // Monitoring Functions
 // =============================================================================
@@ -542,20 +1426,13 @@ pub fn get_scheduler_status() -> Result<SchedulerStatus, String> {
 
     let total_positions_tracked = crate::services::apy_parser::get_tracked_positions().len() as u64;
 
-    let last_result = REBALANCE_HISTORY_MAP.with(|map| {
-        let borrowed = map.borrow();
-        let mut all: Vec<RebalanceExecution> = borrowed
-            .iter()
-            .map(|(_, exec)| exec.0.clone())
-            .collect();
+    let last_tick_summary = get_latest_tick_summary();
 
-        // Sort by timestamp descending
-        all.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-
-        all.first().map(|exec| {
-            format!("ID: {}, Status: {}, User: {}",
-                exec.execution_id, exec.result.status, exec.user_principal)
-        })
+    let last_result = last_tick_summary.as_ref().map(|summary| {
+        format!("Tick: {}, Checked: {}, Triggered: {}, Successful: {}, Failed: {}, Proposals: {}, Errors: {}",
+            summary.tick_id, summary.positions_checked, summary.rebalances_triggered,
+            summary.rebalances_successful, summary.rebalances_failed,
+            summary.proposals_queued, summary.errors.len())
     });
 
     Ok(SchedulerStatus {
@@ -564,9 +1441,73 @@ pub fn get_scheduler_status() -> Result<SchedulerStatus, String> {
         total_positions_tracked,
         total_rebalances_executed: total_rebalances,
         last_execution_result: last_result,
+        last_tick_summary,
     })
 }
 
+// =============================================================================
+// Tick Summary History
+// =============================================================================
+
+/// Store a completed tick summary, keeping at most MAX_TICK_SUMMARIES
+fn store_tick_summary(summary: SchedulerExecutionSummary) {
+    SCHEDULER_SUMMARIES_MAP.with(|map| {
+        let mut borrowed = map.borrow_mut();
+        borrowed.insert(
+            StorableString(summary.tick_id.clone()),
+            StorableSchedulerExecutionSummary(summary)
+        );
+
+        // Tick IDs are zero-padded timestamps, so the first key is the oldest tick
+        while borrowed.len() > MAX_TICK_SUMMARIES {
+            let oldest = borrowed.iter().next().map(|(k, _)| k);
+            match oldest {
+                Some(key) => { borrowed.remove(&key); }
+                None => break,
+            }
+        }
+    });
+}
+
+/// Get the most recently completed tick summary
+pub fn get_latest_tick_summary() -> Option<SchedulerExecutionSummary> {
+    SCHEDULER_SUMMARIES_MAP.with(|map| {
+        map.borrow().last_key_value().map(|(_, summary)| summary.0)
+    })
+}
+
+/// Get tick summaries (most recent first), skipping `offset` entries
+pub fn get_tick_summaries(offset: Option<u64>, limit: Option<u64>) -> Vec<SchedulerExecutionSummary> {
+    let offset = offset.unwrap_or(0) as usize;
+    let limit = limit.unwrap_or(20).min(100) as usize;
+
+    // Tick IDs are zero-padded timestamps, so reverse key order is newest first
+    SCHEDULER_SUMMARIES_MAP.with(|map| {
+        map.borrow()
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, summary)| summary.0)
+            .collect()
+    })
+}
+
+/// Get the summary of a specific tick
+pub fn get_tick_summary(tick_id: String) -> Result<SchedulerExecutionSummary, String> {
+    SCHEDULER_SUMMARIES_MAP.with(|map| {
+        map.borrow()
+            .get(&StorableString(tick_id.clone()))
+            .map(|summary| summary.0)
+            .ok_or_else(|| format!("Tick summary {} not found", tick_id))
+    })
+}
+
+/// Number of stored tick summaries
+pub fn get_tick_summary_count() -> u64 {
+    SCHEDULER_SUMMARIES_MAP.with(|map| map.borrow().len())
+}
+
 /// Get rebalance history (most recent first)
 pub fn get_rebalance_history(limit: Option<u64>) -> Vec<RebalanceExecution> {
     REBALANCE_HISTORY_MAP.with(|map| {
@@ -586,42 +1527,15 @@ pub fn get_rebalance_history(limit: Option<u64>) -> Vec<RebalanceExecution> {
 
 /// Get rebalance history for specific user
 pub fn get_user_rebalance_history(user: Principal, limit: Option<u64>) -> Vec<RebalanceExecution> {
-    REBALANCE_HISTORY_MAP.with(|map| {
-        let borrowed = map.borrow();
-        let mut user_history: Vec<RebalanceExecution> = borrowed
-            .iter()
-            .filter_map(|(_, exec)| {
-                let e = exec.0.clone();
-                if e.user_principal == user {
-                    Some(e)
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        // Sort by timestamp descending
-        user_history.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-
-        let limit = limit.unwrap_or(50) as usize;
-        user_history.into_iter().take(limit).collect()
-    })
+    let limit = limit.unwrap_or(50) as usize;
+    indexes::get_user_rebalance_history(user, limit)
 }
 
 /// Clear all rebalance history (Admin only - for data migration)
 pub fn clear_rebalance_history() -> Result<String, String> {
     ic_cdk::println!("🗑️ Clearing all rebalance history...");
 
-    let count = REBALANCE_HISTORY_MAP.with(|map| {
-        let len = map.borrow().len();
-        // Get all keys
-        let keys: Vec<_> = map.borrow().iter().map(|(k, _)| k).collect();
-        // Remove all entries
-        for key in keys {
-            map.borrow_mut().remove(&key);
-        }
-        len
-    });
+    let count = indexes::clear_rebalance_history();
 
     ic_cdk::println!("✅ Cleared {} rebalance history records", count);
     Ok(format!("Cleared {} rebalance history records", count))
@@ -0,0 +1,518 @@
+use alloy::{
+    network::{EthereumWallet, TransactionBuilder},
+    primitives::{Address, Bytes, TxHash, U256},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::{request::TransactionRequest, Transaction, TransactionReceipt},
+    signers::Signer,
+    transports::{icp::IcpConfig, Transport},
+};
+use candid::Principal;
+use ic_cdk_timers::{set_timer_interval, clear_timer, TimerId};
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::types::{ChainConfig, SentTransaction, TxStatus};
+use crate::{SENT_TRANSACTIONS_MAP, StorableString, now};
+use crate::services::{chain_registry, erc20, indexes, locks};
+use crate::services::fee_policy::{self, Fees};
+use crate::services::rpc_service::get_rpc_service_by_chain_id;
+
+// =============================================================================
+// Sent Transaction Tracking
+// =============================================================================
+//
+// Every transaction the canister signs is recorded in SENT_TRANSACTIONS_MAP
+// with the fields needed to re-sign it. A transaction stuck in the mempool can
+// then be replaced at the same nonce:
+// - Speed-up: same recipient, value and calldata with bumped fees
+// - Cancel: zero-value self-transfer with bumped fees
+//
+// Replacement fees come from `fee_policy::replacement_fees`, so the chain's fee
+// caps still apply. The nonce is already consumed in the nonce manager and is
+// not touched here.
+//
+// A watchdog timer settles pending records (Confirmed when the transaction has
+// a receipt, Dropped when its nonce was mined by another transaction, in which
+// case the earlier version that was mined is marked Confirmed) and speeds up
+// transactions pending for longer than `STUCK_AFTER_SECONDS`.
+//
+// Writes go through `indexes`, which keeps the by-owner, pending and settled
+// indexes consistent with SENT_TRANSACTIONS_MAP.
+//
+
+/// How often the watchdog runs (5 minutes)
+const WATCHDOG_INTERVAL_SECONDS: u64 = 300;
+
+/// Pending time after which the watchdog speeds a transaction up (10 minutes)
+const STUCK_AFTER_SECONDS: u64 = 600;
+
+/// Fee bumps the watchdog applies to a nonce before leaving it to the user
+const MAX_AUTO_BUMPS: u32 = 3;
+
+/// Settled records older than this are pruned (7 days)
+const RETENTION_SECONDS: u64 = 7 * 24 * 3600;
+
+const MILLIS_PER_SECOND: u64 = 1_000;
+
+/// Receipt polls made by `wait_for_receipt`; each poll is an HTTPS outcall
+/// that takes a few seconds to reach consensus
+const MAX_RECEIPT_POLLS: u32 = 10;
+
+thread_local! {
+    /// Active timer ID for the stuck-transaction watchdog
+    static WATCHDOG_TIMER_ID: RefCell<Option<TimerId>> = RefCell::new(None);
+}
+
+// =============================================================================
+// Journal
+// =============================================================================
+
+/// Record a transaction the canister just sent for a principal
+pub fn record_sent(owner: Principal, chain_id: u64, tx: &Transaction) {
+    let (max_fee_per_gas, max_priority_fee_per_gas) = match tx.max_fee_per_gas {
+        Some(max_fee_per_gas) => (max_fee_per_gas, tx.max_priority_fee_per_gas),
+        None => (tx.gas_price.unwrap_or_default(), None),
+    };
+
+    let timestamp = now();
+    save(SentTransaction {
+        tx_hash: format!("{:?}", tx.hash),
+        owner,
+        chain_id,
+        from: format!("0x{:x}", tx.from),
+        to: tx.to.map(|to| format!("0x{:x}", to)),
+        nonce: tx.nonce,
+        value: tx.value.to_string(),
+        input: tx.input.to_string(),
+        gas_limit: to_u64(tx.gas),
+        max_fee_per_gas: to_u64(max_fee_per_gas),
+        max_priority_fee_per_gas: max_priority_fee_per_gas.map(to_u64),
+        status: TxStatus::Pending,
+        replaces: None,
+        replaced_by: None,
+        is_cancel: false,
+        bump_count: 0,
+        sent_at: timestamp,
+        updated_at: timestamp,
+    });
+}
+
+/// Transactions signed for a principal, newest first
+pub fn get_user_transactions(owner: Principal) -> Vec<SentTransaction> {
+    indexes::get_owner_transactions(owner)
+}
+
+fn get_transaction(tx_hash: &str) -> Option<SentTransaction> {
+    SENT_TRANSACTIONS_MAP.with(|map| {
+        map.borrow().get(&StorableString(tx_hash.to_lowercase())).map(|tx| tx.0)
+    })
+}
+
+fn save(tx: SentTransaction) {
+    indexes::save_sent_transaction(&tx);
+}
+
+fn set_status(tx_hash: &str, status: TxStatus, replaced_by: Option<String>) {
+    if let Some(mut tx) = get_transaction(tx_hash) {
+        tx.status = status;
+        if replaced_by.is_some() {
+            tx.replaced_by = replaced_by;
+        }
+        tx.updated_at = now();
+        save(tx);
+    }
+}
+
+/// Wait until a sent transaction is mined and return its receipt
+///
+/// Returns None when it is still pending after `MAX_RECEIPT_POLLS`; the
+/// watchdog keeps tracking it. A mined record is marked Confirmed.
+pub async fn wait_for_receipt<T, P>(provider: &P, tx_hash: TxHash) -> Result<Option<TransactionReceipt>, String>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    for poll in 1..=MAX_RECEIPT_POLLS {
+        let receipt = provider.get_transaction_receipt(tx_hash).await
+            .map_err(|e| format!("Failed to get transaction receipt: {}", e))?;
+
+        if let Some(receipt) = receipt {
+            ic_cdk::println!("⛏️ Transaction {:?} mined in block {:?} (poll {})", tx_hash, receipt.block_number, poll);
+            set_status(&format!("{:?}", tx_hash), TxStatus::Confirmed, None);
+            return Ok(Some(receipt));
+        }
+    }
+
+    ic_cdk::println!("⏳ Transaction {:?} not mined after {} polls", tx_hash, MAX_RECEIPT_POLLS);
+    Ok(None)
+}
+
+/// Whether a sent transaction, or a speed-up that replaced it, was mined successfully
+///
+/// A mined cancellation, a transaction whose nonce was used by another one and
+/// a hash the node never returned all count as unsuccessful. Returns None while
+/// the transaction may still be mined.
+pub(crate) async fn transaction_succeeded(chain_id: u64, tx_hash: &str) -> Result<Option<bool>, String> {
+    let provider = ProviderBuilder::new().on_icp(IcpConfig::new(get_rpc_service_by_chain_id(chain_id)?));
+
+    let mut hashes = vec![tx_hash.to_string()];
+    let mut latest = get_transaction(tx_hash);
+    while let Some(next) = latest.as_ref().and_then(|tx| tx.replaced_by.as_deref()).and_then(get_transaction) {
+        hashes.push(next.tx_hash.clone());
+        latest = Some(next);
+    }
+
+    for hash in &hashes {
+        let parsed = hash.parse::<TxHash>()
+            .map_err(|e| format!("Invalid transaction hash: {}", e))?;
+        let receipt = provider.get_transaction_receipt(parsed).await
+            .map_err(|e| format!("Failed to get transaction receipt: {}", e))?;
+        if let Some(receipt) = receipt {
+            let is_cancel = get_transaction(hash).map(|tx| tx.is_cancel).unwrap_or(false);
+            return Ok(Some(receipt.status() && !is_cancel));
+        }
+    }
+
+    match latest {
+        Some(record) => {
+            let from = record.from.parse::<Address>()
+                .map_err(|e| format!("Invalid sender address: {}", e))?;
+            let mined_nonce = provider.get_transaction_count(from).latest().await
+                .map_err(|e| format!("Failed to get transaction count: {}", e))?;
+            Ok(if mined_nonce > record.nonce { Some(false) } else { None })
+        }
+        // Never journaled: the node did not return it after it was sent
+        None => {
+            let parsed = tx_hash.parse::<TxHash>()
+                .map_err(|e| format!("Invalid transaction hash: {}", e))?;
+            let known = provider.get_transaction_by_hash(parsed).await
+                .map_err(|e| format!("Failed to get transaction: {}", e))?
+                .is_some();
+            Ok(if known { None } else { Some(false) })
+        }
+    }
+}
+
+// =============================================================================
+// Speed-up and Cancel
+// =============================================================================
+
+/// Re-sign a pending transaction at the same nonce with bumped fees
+///
+/// A hash that was already replaced resolves to its latest replacement.
+pub async fn speed_up_transaction(owner: Principal, tx_hash: &str) -> Result<SentTransaction, String> {
+    let _user_lock = locks::try_acquire_user_lock(owner, "speed_up_tx")?;
+    let record = latest_pending(owner, tx_hash)?;
+    replace(record, false).await
+}
+
+/// Replace a pending transaction with a zero-value self-transfer at the same nonce
+pub async fn cancel_transaction(owner: Principal, tx_hash: &str) -> Result<SentTransaction, String> {
+    let _user_lock = locks::try_acquire_user_lock(owner, "cancel_tx")?;
+    let record = latest_pending(owner, tx_hash)?;
+    replace(record, true).await
+}
+
+/// Follow the replacements of an owner's transaction to the one in the mempool
+fn latest_pending(owner: Principal, tx_hash: &str) -> Result<SentTransaction, String> {
+    let mut record = get_transaction(tx_hash)
+        .filter(|tx| tx.owner == owner)
+        .ok_or_else(|| format!("Transaction {} not found", tx_hash))?;
+
+    while let Some(next) = record.replaced_by.as_deref().and_then(get_transaction) {
+        record = next;
+    }
+
+    if record.status != TxStatus::Pending {
+        return Err(format!("Transaction {} is not pending ({:?})", record.tx_hash, record.status));
+    }
+    Ok(record)
+}
+
+async fn replace(record: SentTransaction, cancel: bool) -> Result<SentTransaction, String> {
+    let action = if cancel { "Cancelling" } else { "Speeding up" };
+    ic_cdk::println!("🚀 {} transaction {} (nonce {}, chain {})", action, record.tx_hash, record.nonce, record.chain_id);
+
+    let chain = chain_registry::get_enabled_chain(record.chain_id)
+        .ok_or_else(|| format!("Unsupported chain_id: {}", record.chain_id))?;
+
+    let signer = erc20::create_icp_signer_for_principal(record.owner).await?;
+    let from = signer.address();
+    if format!("0x{:x}", from) != record.from.to_lowercase() {
+        return Err(format!("Transaction {} was not signed by the owner's address", record.tx_hash));
+    }
+
+    let wallet = EthereumWallet::from(signer);
+    let rpc_service = get_rpc_service_by_chain_id(record.chain_id)?;
+    let config = IcpConfig::new(rpc_service);
+    let provider = ProviderBuilder::new()
+        .with_gas_estimation()
+        .wallet(wallet)
+        .on_icp(config);
+
+    if let Some(status) = settle(&provider, &record).await? {
+        return Err(format!("Transaction {} is no longer pending ({:?})", record.tx_hash, status));
+    }
+
+    let current = fee_policy::quote_fees(&provider, record.chain_id).await?;
+    let fees = fee_policy::replacement_fees(previous_fees(&record), current, &chain)?;
+
+    let (to, value, input, gas_limit) = if cancel {
+        (from, U256::ZERO, Bytes::new(), cancel_gas_limit(&provider, from, &chain).await)
+    } else {
+        let to = record.to.as_deref()
+            .ok_or_else(|| "Contract creations cannot be sped up".to_string())?
+            .parse::<Address>()
+            .map_err(|e| format!("Invalid recipient address: {}", e))?;
+        let value = record.value.parse::<U256>()
+            .map_err(|e| format!("Invalid value: {}", e))?;
+        let input = record.input.parse::<Bytes>()
+            .map_err(|e| format!("Invalid calldata: {}", e))?;
+        (to, value, input, record.gas_limit)
+    };
+
+    let tx = TransactionRequest::default()
+        .with_from(from)
+        .with_to(to)
+        .with_value(value)
+        .with_input(input.clone())
+        .with_nonce(record.nonce)
+        .with_chain_id(record.chain_id)
+        .with_gas_limit(gas_limit as u128);
+    let tx = fee_policy::with_fees(tx, fees);
+
+    let builder = provider.send_transaction(tx).await
+        .map_err(|e| format!("Replacement failed: {:?}", e))?;
+    let tx_hash = format!("{:?}", builder.tx_hash());
+    ic_cdk::println!("✅ Replacement sent: {} replaces {}", tx_hash, record.tx_hash);
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) = match fees {
+        Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas, .. } => (max_fee_per_gas, Some(max_priority_fee_per_gas)),
+        Fees::Legacy { gas_price } => (gas_price, None),
+    };
+
+    let timestamp = now();
+    let replacement = SentTransaction {
+        tx_hash: tx_hash.clone(),
+        owner: record.owner,
+        chain_id: record.chain_id,
+        from: record.from.clone(),
+        to: Some(format!("0x{:x}", to)),
+        nonce: record.nonce,
+        value: value.to_string(),
+        input: input.to_string(),
+        gas_limit,
+        max_fee_per_gas: to_u64(max_fee_per_gas),
+        max_priority_fee_per_gas: max_priority_fee_per_gas.map(to_u64),
+        status: TxStatus::Pending,
+        replaces: Some(record.tx_hash.clone()),
+        replaced_by: None,
+        is_cancel: cancel,
+        bump_count: record.bump_count + 1,
+        sent_at: timestamp,
+        updated_at: timestamp,
+    };
+    save(replacement.clone());
+    set_status(&record.tx_hash, TxStatus::Replaced, Some(tx_hash));
+
+    Ok(replacement)
+}
+
+/// Gas limit of a cancellation: the estimate of a self-transfer, floored at the chain's minimum
+async fn cancel_gas_limit<T, P>(provider: &P, from: Address, chain: &ChainConfig) -> u64
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let floor = chain.cancel_gas_limit.unwrap_or(chain_registry::TRANSFER_GAS);
+    let tx = TransactionRequest::default()
+        .with_from(from)
+        .with_to(from)
+        .with_value(U256::ZERO);
+
+    match provider.estimate_gas(&tx).await {
+        Ok(estimate) => {
+            let gas_limit = fee_policy::gas_limit(estimate, fee_policy::get_fee_policy_config().gas_limit_multiplier);
+            to_u64(gas_limit).max(floor)
+        }
+        Err(e) => {
+            ic_cdk::println!("⚠️ Cancel gas estimation failed ({}), using {}", e, floor);
+            floor
+        }
+    }
+}
+
+/// Settle a pending record that is no longer in the mempool and return its new status
+///
+/// When its nonce was mined by an earlier version of the transaction, that
+/// version is marked Confirmed.
+async fn settle<T, P>(provider: &P, record: &SentTransaction) -> Result<Option<TxStatus>, String>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let Some(status) = settled_status(provider, record).await? else {
+        return Ok(None);
+    };
+    set_status(&record.tx_hash, status.clone(), None);
+
+    if status == TxStatus::Dropped {
+        if let Some(mined) = mined_predecessor(provider, record).await? {
+            ic_cdk::println!("✅ Nonce {} of {} was mined by {}", record.nonce, record.tx_hash, mined);
+            set_status(&mined, TxStatus::Confirmed, None);
+        }
+    }
+    Ok(Some(status))
+}
+
+/// Earlier version of a replaced transaction that has a receipt, if any
+async fn mined_predecessor<T, P>(provider: &P, record: &SentTransaction) -> Result<Option<String>, String>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let mut previous = record.replaces.as_deref().and_then(get_transaction);
+    while let Some(tx) = previous {
+        let hash = tx.tx_hash.parse::<TxHash>()
+            .map_err(|e| format!("Invalid transaction hash: {}", e))?;
+        let receipt = provider.get_transaction_receipt(hash).await
+            .map_err(|e| format!("Failed to get transaction receipt: {}", e))?;
+        if receipt.is_some() {
+            return Ok(Some(tx.tx_hash));
+        }
+        previous = tx.replaces.as_deref().and_then(get_transaction);
+    }
+    Ok(None)
+}
+
+/// Status of a pending record that is no longer in the mempool, if any
+async fn settled_status<T, P>(provider: &P, record: &SentTransaction) -> Result<Option<TxStatus>, String>
+where
+    T: Transport + Clone,
+    P: Provider<T>,
+{
+    let hash = record.tx_hash.parse::<TxHash>()
+        .map_err(|e| format!("Invalid transaction hash: {}", e))?;
+    let receipt = provider.get_transaction_receipt(hash).await
+        .map_err(|e| format!("Failed to get transaction receipt: {}", e))?;
+    if receipt.is_some() {
+        return Ok(Some(TxStatus::Confirmed));
+    }
+
+    let from = record.from.parse::<Address>()
+        .map_err(|e| format!("Invalid sender address: {}", e))?;
+    let mined_nonce = provider.get_transaction_count(from).latest().await
+        .map_err(|e| format!("Failed to get transaction count: {}", e))?;
+    if mined_nonce > record.nonce {
+        return Ok(Some(TxStatus::Dropped));
+    }
+
+    Ok(None)
+}
+
+fn previous_fees(record: &SentTransaction) -> Fees {
+    match record.max_priority_fee_per_gas {
+        Some(max_priority_fee_per_gas) => Fees::Eip1559 {
+            base_fee: 0,
+            max_fee_per_gas: record.max_fee_per_gas as u128,
+            max_priority_fee_per_gas: max_priority_fee_per_gas as u128,
+        },
+        None => Fees::Legacy { gas_price: record.max_fee_per_gas as u128 },
+    }
+}
+
+fn to_u64(value: u128) -> u64 {
+    u64::try_from(value).unwrap_or(u64::MAX)
+}
+
+// =============================================================================
+// Watchdog
+// =============================================================================
+
+/// Start the periodic stuck-transaction watchdog
+pub fn start_tx_watchdog_timer() {
+    WATCHDOG_TIMER_ID.with(|timer_id| {
+        if let Some(id) = timer_id.borrow().as_ref() {
+            clear_timer(*id);
+        }
+    });
+
+    let timer_id = set_timer_interval(Duration::from_secs(WATCHDOG_INTERVAL_SECONDS), || {
+        ic_cdk::spawn(async {
+            if let Err(e) = run_tx_watchdog().await {
+                ic_cdk::println!("⏭️ Transaction watchdog skipped: {}", e);
+            }
+        });
+    });
+
+    WATCHDOG_TIMER_ID.with(|id| {
+        *id.borrow_mut() = Some(timer_id);
+    });
+
+    ic_cdk::println!("✅ Transaction watchdog timer started with interval: {} seconds", WATCHDOG_INTERVAL_SECONDS);
+}
+
+/// Settle pending transactions and speed up the stuck ones
+pub async fn run_tx_watchdog() -> Result<(), String> {
+    let _watchdog_lock = locks::try_acquire_tx_watchdog_lock()?;
+
+    prune_settled();
+
+    let pending = indexes::get_pending_transactions();
+    if pending.is_empty() {
+        return Ok(());
+    }
+    ic_cdk::println!("🔍 Transaction watchdog checking {} pending transactions", pending.len());
+
+    for record in pending {
+        let provider = match get_rpc_service_by_chain_id(record.chain_id) {
+            Ok(rpc_service) => ProviderBuilder::new().on_icp(IcpConfig::new(rpc_service)),
+            Err(e) => {
+                ic_cdk::println!("⚠️ Skipping {}: {}", record.tx_hash, e);
+                continue;
+            }
+        };
+
+        match settle(&provider, &record).await {
+            Ok(Some(status)) => {
+                ic_cdk::println!("✅ Transaction {} settled: {:?}", record.tx_hash, status);
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                ic_cdk::println!("⚠️ Failed to check {}: {}", record.tx_hash, e);
+                continue;
+            }
+        }
+
+        let pending_seconds = now().saturating_sub(record.sent_at) / MILLIS_PER_SECOND;
+        if pending_seconds < STUCK_AFTER_SECONDS || record.bump_count >= MAX_AUTO_BUMPS {
+            continue;
+        }
+
+        // Leave the nonce alone while the user is operating
+        let _user_lock = match locks::try_acquire_user_lock(record.owner, "tx_watchdog") {
+            Ok(lock) => lock,
+            Err(e) => {
+                ic_cdk::println!("⏳ Not bumping {}: {}", record.tx_hash, e);
+                continue;
+            }
+        };
+
+        match replace(record.clone(), false).await {
+            Ok(replacement) => ic_cdk::println!("⛽ Stuck transaction {} bumped to {} (bump {}/{})",
+                record.tx_hash, replacement.tx_hash, replacement.bump_count, MAX_AUTO_BUMPS),
+            Err(e) => ic_cdk::println!("⚠️ Failed to bump {}: {}", record.tx_hash, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove settled records older than the retention period
+fn prune_settled() {
+    let cutoff = now().saturating_sub(RETENTION_SECONDS * MILLIS_PER_SECOND);
+    for tx_hash in indexes::settled_transactions_before(cutoff) {
+        indexes::remove_sent_transaction(&tx_hash);
+    }
+}
@@ -1,5 +1,3 @@
-use std::cell::RefCell;
-
 use alloy::{
     network::EthereumWallet,
     primitives::{address, U256},
@@ -10,6 +8,8 @@ use alloy::{
 };
 
 use crate::create_icp_signer;
+use crate::services::{fee_policy, tx_tracker};
+use crate::services::nonce_manager::{reserve_nonce, commit_nonce, rollback_nonce};
 use crate::services::rpc_service::{get_rpc_service_by_chain_id, SEPOLIA_CHAIN_ID, BASE_CHAIN_ID, OPTIMISM_CHAIN_ID};
 use alloy::primitives::Address;
 
@@ -23,10 +23,6 @@ fn get_weth_address(chain_id: u64) -> Result<Address, String> {
     }
 }
 
-thread_local! {
-    static NONCE: RefCell<Option<u64>> = const { RefCell::new(None) };
-}
-
 // Codegen from ABI file to interact with the WETH contract.
 sol!(
     #[allow(missing_docs, clippy::too_many_arguments)]
@@ -81,18 +77,6 @@ pub async fn wrap_eth(amount: String) -> Result<String, String> {
         ));
     }
 
-    // Handle nonce management
-    let maybe_nonce = NONCE.with_borrow(|maybe_nonce| {
-        maybe_nonce.map(|nonce| nonce + 1)
-    });
-
-    let nonce = if let Some(nonce) = maybe_nonce {
-        nonce
-    } else {
-        provider.get_transaction_count(address).await
-            .map_err(|e| format!("Failed to get nonce: {}", e))?
-    };
-
     // Create WETH contract instance
     let contract = WETH::new(
         get_weth_address(SEPOLIA_CHAIN_ID)?,
@@ -100,26 +84,29 @@ pub async fn wrap_eth(amount: String) -> Result<String, String> {
     );
 
     // Execute the deposit transaction (payable function)
-    match contract
+    let call = contract
         .deposit()
         .value(amount) // Send ETH with the transaction
-        .nonce(nonce)
         .chain_id(11155111) // Sepolia chain ID
-        .from(address)
+        .from(address);
+    let call = fee_policy::apply_to_call(call, SEPOLIA_CHAIN_ID).await?;
+    let nonce = reserve_nonce(address, &provider, SEPOLIA_CHAIN_ID).await?;
+    match call
+        .nonce(nonce)
         .send()
         .await
     {
         Ok(builder) => {
+            // Transaction sent - commit nonce
+            commit_nonce(address, SEPOLIA_CHAIN_ID, nonce);
+
             let tx_hash = *builder.tx_hash();
             let tx_response = provider.get_transaction_by_hash(tx_hash).await
                 .map_err(|e| format!("Failed to get transaction: {}", e))?;
 
             match tx_response {
                 Some(tx) => {
-                    // Update nonce cache
-                    NONCE.with_borrow_mut(|nonce| {
-                        *nonce = Some(tx.nonce);
-                    });
+                    tx_tracker::record_sent(ic_cdk::caller(), SEPOLIA_CHAIN_ID, &tx);
                     
                     // Log the wrap
                     ic_cdk::println!(
@@ -138,7 +125,11 @@ pub async fn wrap_eth(amount: String) -> Result<String, String> {
                 None => Err("Transaction not found after sending".to_string()),
             }
         }
-        Err(e) => Err(format!("Wrap transaction failed: {:?}", e)),
+        Err(e) => {
+            // Transaction failed to send - rollback nonce
+            rollback_nonce(address, SEPOLIA_CHAIN_ID, nonce);
+            Err(format!("Wrap transaction failed: {:?}", e))
+        }
     }
 }
 
@@ -191,18 +182,6 @@ pub async fn unwrap_weth(amount: String) -> Result<String, String> {
         .wallet(wallet)
         .on_icp(config);
 
-    // Handle nonce management
-    let maybe_nonce = NONCE.with_borrow(|maybe_nonce| {
-        maybe_nonce.map(|nonce| nonce + 1)
-    });
-
-    let nonce = if let Some(nonce) = maybe_nonce {
-        nonce
-    } else {
-        provider.get_transaction_count(address).await
-            .map_err(|e| format!("Failed to get nonce: {}", e))?
-    };
-
     // Create WETH contract instance
     let contract = WETH::new(
         get_weth_address(SEPOLIA_CHAIN_ID)?,
@@ -222,25 +201,28 @@ pub async fn unwrap_weth(amount: String) -> Result<String, String> {
     }
 
     // Execute the withdraw transaction
-    match contract
+    let call = contract
         .withdraw(amount)
-        .nonce(nonce)
         .chain_id(11155111) // Sepolia chain ID
-        .from(address)
+        .from(address);
+    let call = fee_policy::apply_to_call(call, SEPOLIA_CHAIN_ID).await?;
+    let nonce = reserve_nonce(address, &provider, SEPOLIA_CHAIN_ID).await?;
+    match call
+        .nonce(nonce)
         .send()
         .await
     {
         Ok(builder) => {
+            // Transaction sent - commit nonce
+            commit_nonce(address, SEPOLIA_CHAIN_ID, nonce);
+
             let tx_hash = *builder.tx_hash();
             let tx_response = provider.get_transaction_by_hash(tx_hash).await
                 .map_err(|e| format!("Failed to get transaction: {}", e))?;
 
             match tx_response {
                 Some(tx) => {
-                    // Update nonce cache
-                    NONCE.with_borrow_mut(|nonce| {
-                        *nonce = Some(tx.nonce);
-                    });
+                    tx_tracker::record_sent(ic_cdk::caller(), SEPOLIA_CHAIN_ID, &tx);
                     
                     // Log the unwrap
                     ic_cdk::println!(
@@ -259,7 +241,11 @@ pub async fn unwrap_weth(amount: String) -> Result<String, String> {
                 None => Err("Transaction not found after sending".to_string()),
             }
         }
-        Err(e) => Err(format!("Unwrap transaction failed: {:?}", e)),
+        Err(e) => {
+            // Transaction failed to send - rollback nonce
+            rollback_nonce(address, SEPOLIA_CHAIN_ID, nonce);
+            Err(format!("Unwrap transaction failed: {:?}", e))
+        }
     }
 }
 
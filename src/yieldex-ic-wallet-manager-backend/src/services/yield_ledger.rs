@@ -0,0 +1,392 @@
+use candid::Principal;
+use std::collections::BTreeMap;
+
+use crate::types::{
+    TokenAmount, YieldTenure, YieldSnapshot, TenureYield, AssetYield, YieldReport,
+    StorableYieldTenure, StorableYieldSnapshot,
+};
+use crate::{YIELD_TENURES_MAP, YIELD_SNAPSHOTS_MAP, StorableString, now};
+use crate::services::indexes;
+use crate::services::indexes::find_open_tenure;
+
+// =============================================================================
+// Yield Ledger
+// =============================================================================
+//
+// Per-position accounting of what a user has earned. Each continuous holding
+// of an asset in a protocol is a tenure that tracks:
+// - principal deposited (our supplies, or the balance found on first sight)
+// - amount withdrawn (principal and interest)
+// - latest on-chain value (from the reconciler's aToken/Comet balance reads)
+//
+// Accrued interest = value + withdrawn − deposited. A tenure closes when its
+// value reaches zero, which makes its interest realized; a rebalance therefore
+// realizes yield in the source protocol and opens a new tenure in the target.
+//
+// Every change writes a snapshot so interest can be reported for any period.
+// Snapshots older than the retention job's `yield_snapshot_retention_days`
+// are pruned, keeping each tenure's latest one before the cutoff so reports
+// from the cutoff on stay exact.
+//
+// Tenure IDs are the fixed-width `indexes::compact_key` digest of
+// "{principal}:{PROTOCOL}:{ASSET}:{chain_id}:{opened_at}", which keeps
+// snapshot keys ("{tenure_id}#{timestamp:020}") at 37 bytes.
+//
+
+// =============================================================================
+// Recording
+// =============================================================================
+
+/// Record a supply made through the canister
+pub fn record_supply(user: Principal, protocol: &str, asset: &str, chain_id: u64, amount: TokenAmount) {
+    if amount.is_zero() {
+        return;
+    }
+
+    let timestamp = now();
+    let mut tenure = find_open_tenure(user, protocol, asset, chain_id)
+        .unwrap_or_else(|| open_tenure(user, protocol, asset, chain_id, amount.decimals, timestamp));
+
+    tenure.principal_deposited.raw = tenure.principal_deposited.raw.saturating_add(amount.raw);
+    tenure.current_value.raw = tenure.current_value.raw.saturating_add(amount.raw);
+    tenure.valued_at = timestamp;
+
+    ic_cdk::println!("📒 Yield ledger: +{} {} deposited to {} (tenure {})",
+        amount, asset, protocol, tenure.tenure_id);
+    save_tenure_with_snapshot(&tenure, timestamp);
+}
+
+/// Record a withdraw made through the canister
+///
+/// Closes the tenure when the withdraw empties the recorded value.
+pub fn record_withdraw(user: Principal, protocol: &str, asset: &str, chain_id: u64, amount: TokenAmount) {
+    if amount.is_zero() {
+        return;
+    }
+
+    let Some(mut tenure) = find_open_tenure(user, protocol, asset, chain_id) else {
+        ic_cdk::println!("⚠️ Yield ledger: no open {} {} tenure for withdraw of {}", protocol, asset, amount);
+        return;
+    };
+
+    let timestamp = now();
+    tenure.principal_withdrawn.raw = tenure.principal_withdrawn.raw.saturating_add(amount.raw);
+    tenure.current_value.raw = tenure.current_value.raw.saturating_sub(amount.raw);
+    tenure.valued_at = timestamp;
+
+    ic_cdk::println!("📒 Yield ledger: -{} {} withdrawn from {} (tenure {})",
+        amount, asset, protocol, tenure.tenure_id);
+
+    if tenure.current_value.is_zero() {
+        close_tenure(&mut tenure, timestamp);
+    }
+    save_tenure_with_snapshot(&tenure, timestamp);
+}
+
+/// Record an on-chain balance read
+///
+/// Opens a tenure for balances without one (treating the balance as principal,
+/// since its cost basis is unknown) and closes tenures whose balance is gone.
+pub fn record_valuation(user: Principal, protocol: &str, asset: &str, chain_id: u64, value: TokenAmount) {
+    let timestamp = now();
+
+    let mut tenure = match find_open_tenure(user, protocol, asset, chain_id) {
+        Some(tenure) => tenure,
+        None if value.is_zero() => return,
+        None => {
+            let mut tenure = open_tenure(user, protocol, asset, chain_id, value.decimals, timestamp);
+            tenure.principal_deposited = value;
+            ic_cdk::println!("📒 Yield ledger: opened {} {} tenure {} with existing balance {}",
+                protocol, asset, tenure.tenure_id, value);
+            tenure
+        }
+    };
+
+    if tenure.current_value.decimals != value.decimals {
+        ic_cdk::println!("⚠️ Yield ledger: decimals mismatch for tenure {}, skipping valuation", tenure.tenure_id);
+        return;
+    }
+
+    tenure.current_value = value;
+    tenure.valued_at = timestamp;
+
+    if value.is_zero() {
+        close_tenure(&mut tenure, timestamp);
+    }
+    save_tenure_with_snapshot(&tenure, timestamp);
+}
+
+// =============================================================================
+// Reporting
+// =============================================================================
+
+/// Build a user's yield report for a period
+///
+/// Defaults to the whole history up to now.
+pub fn get_yield_report(user: Principal, from: Option<u64>, to: Option<u64>) -> Result<YieldReport, String> {
+    let generated_at = now();
+    let period_start = from.unwrap_or(0);
+    let period_end = to.unwrap_or(generated_at);
+
+    if period_start > period_end {
+        return Err(format!("Invalid period: start {} is after end {}", period_start, period_end));
+    }
+
+    let mut tenures: Vec<YieldTenure> = indexes::get_user_tenures(user)
+        .into_iter()
+        .filter(|t| t.opened_at <= period_end && t.closed_at.map_or(true, |closed| closed >= period_start))
+        .collect();
+    tenures.sort_by(|a, b| a.opened_at.cmp(&b.opened_at));
+
+    let mut totals: BTreeMap<String, AssetYield> = BTreeMap::new();
+    let mut tenure_yields = Vec::with_capacity(tenures.len());
+
+    for tenure in tenures {
+        let decimals = tenure.principal_deposited.decimals;
+        let interest_at_start = interest_at(&tenure, period_start);
+        let interest_at_end = interest_at(&tenure, period_end);
+        let period_interest = TokenAmount { raw: interest_at_end.raw.saturating_sub(interest_at_start.raw), ..interest_at_end };
+
+        let realized_yield = tenure.closed_at.map(|_| tenure.accrued_interest());
+
+        let total = totals.entry(tenure.asset.clone()).or_insert(AssetYield {
+            asset: tenure.asset.clone(),
+            period_interest: TokenAmount::zero(decimals),
+            realized_yield: TokenAmount::zero(decimals),
+        });
+        if total.period_interest.decimals == decimals {
+            total.period_interest.raw = total.period_interest.raw.saturating_add(period_interest.raw);
+            if let (Some(closed_at), Some(realized)) = (tenure.closed_at, realized_yield) {
+                if closed_at >= period_start && closed_at <= period_end {
+                    total.realized_yield.raw = total.realized_yield.raw.saturating_add(realized.raw);
+                }
+            }
+        }
+
+        tenure_yields.push(TenureYield {
+            tenure_id: tenure.tenure_id.clone(),
+            protocol: tenure.protocol.clone(),
+            asset: tenure.asset.clone(),
+            chain_id: tenure.chain_id,
+            opened_at: tenure.opened_at,
+            closed_at: tenure.closed_at,
+            principal_deposited: tenure.principal_deposited,
+            principal_withdrawn: tenure.principal_withdrawn,
+            current_value: tenure.current_value,
+            accrued_interest: tenure.accrued_interest(),
+            realized_yield,
+            period_interest,
+        });
+    }
+
+    Ok(YieldReport {
+        user_principal: user,
+        period_start,
+        period_end,
+        tenures: tenure_yields,
+        totals: totals.into_values().collect(),
+        generated_at,
+    })
+}
+
+/// Interest a tenure had accrued at a point in time
+fn interest_at(tenure: &YieldTenure, timestamp: u64) -> TokenAmount {
+    let decimals = tenure.principal_deposited.decimals;
+    if timestamp < tenure.opened_at {
+        return TokenAmount::zero(decimals);
+    }
+
+    let start = StorableString(snapshot_key(&tenure.tenure_id, tenure.opened_at));
+    let end = StorableString(snapshot_key(&tenure.tenure_id, timestamp));
+
+    YIELD_SNAPSHOTS_MAP.with(|map| {
+        map.borrow()
+            .range(start..=end)
+            .last()
+            .map(|(_, snapshot)| snapshot.0.accrued_interest())
+            .unwrap_or_else(|| TokenAmount::zero(decimals))
+    })
+}
+
+// =============================================================================
+// Helper Functions
+// =============================================================================
+
+/// Start a new, empty tenure (not persisted until saved)
+fn open_tenure(user: Principal, protocol: &str, asset: &str, chain_id: u64, decimals: u8, timestamp: u64) -> YieldTenure {
+    YieldTenure {
+        tenure_id: tenure_id(user, protocol, asset, chain_id, timestamp),
+        user_principal: user,
+        protocol: protocol.to_uppercase(),
+        asset: asset.to_uppercase(),
+        chain_id,
+        principal_deposited: TokenAmount::zero(decimals),
+        principal_withdrawn: TokenAmount::zero(decimals),
+        current_value: TokenAmount::zero(decimals),
+        valued_at: timestamp,
+        opened_at: timestamp,
+        closed_at: None,
+    }
+}
+
+/// Close a tenure, realizing its interest
+fn close_tenure(tenure: &mut YieldTenure, timestamp: u64) {
+    tenure.closed_at = Some(timestamp);
+    ic_cdk::println!("📒 Yield ledger: closed tenure {} with realized yield {} {}",
+        tenure.tenure_id, tenure.accrued_interest(), tenure.asset);
+}
+
+/// Persist a tenure and snapshot its accounting state
+fn save_tenure_with_snapshot(tenure: &YieldTenure, timestamp: u64) {
+    indexes::save_tenure(tenure);
+
+    let snapshot = YieldSnapshot {
+        tenure_id: tenure.tenure_id.clone(),
+        timestamp,
+        principal_deposited: tenure.principal_deposited,
+        principal_withdrawn: tenure.principal_withdrawn,
+        current_value: tenure.current_value,
+    };
+
+    YIELD_SNAPSHOTS_MAP.with(|map| {
+        map.borrow_mut().insert(
+            StorableString(snapshot_key(&tenure.tenure_id, timestamp)),
+            StorableYieldSnapshot(snapshot)
+        );
+    });
+}
+
+/// Tenure ID of a holding opened at a point in time
+fn tenure_id(user: Principal, protocol: &str, asset: &str, chain_id: u64, opened_at: u64) -> String {
+    indexes::compact_key(&format!("{}:{}:{}:{}:{}",
+        user, protocol.to_uppercase(), asset.to_uppercase(), chain_id, opened_at))
+}
+
+/// Snapshot key; zero-padded timestamps keep a tenure's snapshots in time order
+fn snapshot_key(tenure_id: &str, timestamp: u64) -> String {
+    format!("{}#{:020}", tenure_id, timestamp)
+}
+
+// =============================================================================
+// Maintenance
+// =============================================================================
+
+/// Delete snapshots before the cutoff, keeping each tenure's latest one before it
+///
+/// Called by the retention job; stops once `budget` snapshots were deleted.
+pub(crate) fn prune_snapshots(cutoff: u64, budget: &mut u64) -> u64 {
+    let mut pruned = 0;
+    let mut next = first_snapshot_tenure(None);
+
+    while let Some(tenure_id) = next {
+        if *budget == 0 {
+            break;
+        }
+
+        let start = StorableString(snapshot_key(&tenure_id, 0));
+        let end = StorableString(snapshot_key(&tenure_id, cutoff));
+        let mut expired: Vec<StorableString> = YIELD_SNAPSHOTS_MAP.with(|map| {
+            map.borrow()
+                .range(start..end)
+                .take(*budget as usize + 1)
+                .map(|(key, _)| key)
+                .collect()
+        });
+
+        // The latest snapshot before the cutoff still answers queries after it
+        expired.pop();
+
+        YIELD_SNAPSHOTS_MAP.with(|map| {
+            let mut map = map.borrow_mut();
+            for key in &expired {
+                map.remove(key);
+            }
+        });
+
+        pruned += expired.len() as u64;
+        *budget -= expired.len() as u64;
+        next = first_snapshot_tenure(Some(&tenure_id));
+    }
+
+    pruned
+}
+
+/// Tenure of the first snapshot after all snapshots of `after` (or of the first snapshot)
+fn first_snapshot_tenure(after: Option<&str>) -> Option<String> {
+    // '$' sorts right after '#', so this seeks past every key of the tenure
+    let start = after.map(|id| format!("{}$", id)).unwrap_or_default();
+
+    YIELD_SNAPSHOTS_MAP.with(|map| {
+        map.borrow()
+            .range(StorableString(start)..)
+            .next()
+            .map(|(_, snapshot)| snapshot.0.tenure_id)
+    })
+}
+
+/// Re-key tenures stored under legacy descriptive IDs
+///
+/// Legacy IDs embedded the full descriptor, leaving snapshot keys close to
+/// StorableString's 128-byte bound. The compact ID is the digest of that same
+/// descriptor. Runs after an upgrade and does nothing once every ID is compact.
+pub fn migrate_legacy_tenure_ids() {
+    let legacy: Vec<YieldTenure> = YIELD_TENURES_MAP.with(|map| {
+        map.borrow()
+            .iter()
+            .map(|(_, tenure)| tenure.0)
+            .filter(|t| !is_compact_id(&t.tenure_id))
+            .collect()
+    });
+    if legacy.is_empty() {
+        return;
+    }
+
+    for mut tenure in legacy.iter().cloned() {
+        let legacy_id = tenure.tenure_id.clone();
+        tenure.tenure_id = indexes::compact_key(&legacy_id);
+
+        let prefix = format!("{}#", legacy_id);
+        YIELD_SNAPSHOTS_MAP.with(|map| {
+            let mut map = map.borrow_mut();
+            let snapshots: Vec<(StorableString, YieldSnapshot)> = map
+                .range(StorableString(prefix.clone())..)
+                .take_while(|(key, _)| key.0.starts_with(&prefix))
+                .map(|(key, snapshot)| (key, snapshot.0))
+                .collect();
+
+            for (key, mut snapshot) in snapshots {
+                map.remove(&key);
+                snapshot.tenure_id = tenure.tenure_id.clone();
+                map.insert(StorableString(snapshot_key(&tenure.tenure_id, snapshot.timestamp)), StorableYieldSnapshot(snapshot));
+            }
+        });
+
+        YIELD_TENURES_MAP.with(|map| {
+            let mut map = map.borrow_mut();
+            map.remove(&StorableString(legacy_id));
+            map.insert(StorableString(tenure.tenure_id.clone()), StorableYieldTenure(tenure));
+        });
+    }
+
+    ic_cdk::println!("🗂️ Re-keyed {} yield tenures to compact IDs", legacy.len());
+}
+
+fn is_compact_id(tenure_id: &str) -> bool {
+    tenure_id.len() == 16 && tenure_id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_keys_are_fixed_width() {
+        let user = Principal::from_text("2vxsx-fae").unwrap();
+        let id = tenure_id(user, "compound", "usdc", 42161, 1_700_000_000_000);
+
+        assert!(is_compact_id(&id));
+        assert_eq!(id, tenure_id(user, "COMPOUND", "USDC", 42161, 1_700_000_000_000));
+        assert_ne!(id, tenure_id(user, "AAVE", "USDC", 42161, 1_700_000_000_000));
+        assert_eq!(snapshot_key(&id, u64::MAX).len(), 37);
+    }
+}
@@ -0,0 +1,222 @@
+use alloy::primitives::U256;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Largest supported number of decimals; 10^38 is the largest power of ten in a u128
+pub const MAX_DECIMALS: u8 = 38;
+
+/// Exact token amount in raw integer units
+///
+/// Replaces f64 arithmetic on human-readable strings. `raw` is the amount in
+/// the token's smallest unit (e.g. 1 USDC = 1_000_000 with 6 decimals).
+#[derive(Clone, Copy, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenAmount {
+    /// Amount in the token's smallest unit
+    pub raw: u128,
+
+    /// Number of decimals of the token
+    pub decimals: u8,
+}
+
+impl TokenAmount {
+    /// Amount from raw units
+    pub fn from_raw(raw: u128, decimals: u8) -> Result<Self, String> {
+        check_decimals(decimals)?;
+        Ok(Self { raw, decimals })
+    }
+
+    /// Zero amount
+    pub fn zero(decimals: u8) -> Self {
+        Self { raw: 0, decimals }
+    }
+
+    /// Amount from an on-chain U256 value
+    pub fn from_u256(value: U256, decimals: u8) -> Result<Self, String> {
+        check_decimals(decimals)?;
+        let raw: u128 = value.try_into()
+            .map_err(|_| format!("Amount {} does not fit in 128 bits", value))?;
+        Ok(Self { raw, decimals })
+    }
+
+    /// Parse a human-readable decimal amount (e.g. "100.5")
+    ///
+    /// Fractional digits beyond `decimals` are truncated.
+    pub fn parse(amount: &str, decimals: u8) -> Result<Self, String> {
+        let amount = amount.trim();
+        if amount.is_empty() {
+            return Err("Amount is empty".to_string());
+        }
+        if amount.starts_with('-') {
+            return Err("Amount cannot be negative".to_string());
+        }
+
+        let (whole, fraction) = match amount.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (amount, ""),
+        };
+
+        let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(format!("Invalid amount format: {}", amount));
+        }
+
+        check_decimals(decimals)?;
+        let scale = 10u128.pow(decimals as u32);
+
+        let whole_raw = if whole.is_empty() {
+            0
+        } else {
+            whole.parse::<u128>().map_err(|_| format!("Amount too large: {}", amount))?
+        };
+
+        let fraction: String = fraction.chars()
+            .chain(std::iter::repeat('0'))
+            .take(decimals as usize)
+            .collect();
+        let fraction_raw = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<u128>().map_err(|_| format!("Invalid amount format: {}", amount))?
+        };
+
+        let raw = whole_raw.checked_mul(scale)
+            .and_then(|w| w.checked_add(fraction_raw))
+            .ok_or_else(|| format!("Amount too large: {}", amount))?;
+
+        Ok(Self { raw, decimals })
+    }
+
+    /// Parse a human-readable amount of a known token symbol
+    pub fn parse_for_symbol(amount: &str, token_symbol: &str) -> Result<Self, String> {
+        Self::parse(amount, decimals_for_symbol(token_symbol)?)
+    }
+
+    /// Whether the amount is zero
+    pub fn is_zero(&self) -> bool {
+        self.raw == 0
+    }
+
+    /// Amount as an on-chain U256 value
+    pub fn to_u256(&self) -> U256 {
+        U256::from(self.raw)
+    }
+
+    /// Approximate amount as f64, for estimates only (never for balances)
+    pub fn to_f64(&self) -> f64 {
+        self.raw as f64 / 10f64.powi(self.decimals as i32)
+    }
+
+    /// Add two amounts of the same token
+    pub fn checked_add(&self, other: &TokenAmount) -> Result<TokenAmount, String> {
+        self.ensure_same_decimals(other)?;
+        self.raw.checked_add(other.raw)
+            .map(|raw| Self { raw, decimals: self.decimals })
+            .ok_or_else(|| "Amount overflow".to_string())
+    }
+
+    /// Subtract an amount of the same token, stopping at zero
+    pub fn saturating_sub(&self, other: &TokenAmount) -> Result<TokenAmount, String> {
+        self.ensure_same_decimals(other)?;
+        Ok(Self { raw: self.raw.saturating_sub(other.raw), decimals: self.decimals })
+    }
+
+    fn ensure_same_decimals(&self, other: &TokenAmount) -> Result<(), String> {
+        if self.decimals != other.decimals {
+            return Err(format!("Decimals mismatch: {} vs {}", self.decimals, other.decimals));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    /// Exact human-readable amount without trailing zeros (e.g. "100.5")
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Decimals beyond MAX_DECIMALS can only come from a decoded value; every
+        // u128 is below their scale, so the whole part is zero
+        let (whole, fraction) = match 10u128.checked_pow(self.decimals as u32) {
+            Some(scale) => (self.raw / scale, self.raw % scale),
+            None => (0, self.raw),
+        };
+
+        if fraction == 0 {
+            return write!(f, "{}", whole);
+        }
+
+        let fraction = format!("{:0>width$}", fraction, width = self.decimals as usize);
+        write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+    }
+}
+
+fn check_decimals(decimals: u8) -> Result<(), String> {
+    if decimals > MAX_DECIMALS {
+        return Err(format!("Unsupported decimals: {} (max {})", decimals, MAX_DECIMALS));
+    }
+    Ok(())
+}
+
+/// Decimals of a supported token symbol
+pub fn decimals_for_symbol(token_symbol: &str) -> Result<u8, String> {
+    match token_symbol.to_uppercase().as_str() {
+        "LINK" | "WETH" | "ETH" => Ok(18),
+        "USDC" | "USDT" => Ok(6),
+        _ => Err(format!("Unsupported token: {}", token_symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_round_trip() {
+        let amount = TokenAmount::parse("100.5", 6).unwrap();
+        assert_eq!(amount.raw, 100_500_000);
+        assert_eq!(amount.to_string(), "100.5");
+
+        assert_eq!(TokenAmount::parse("0.000001", 6).unwrap().raw, 1);
+        assert_eq!(TokenAmount::parse(".5", 6).unwrap().raw, 500_000);
+        assert_eq!(TokenAmount::parse("7", 18).unwrap().to_string(), "7");
+        assert_eq!(TokenAmount::zero(6).to_string(), "0");
+    }
+
+    #[test]
+    fn parse_truncates_excess_precision() {
+        assert_eq!(TokenAmount::parse("1.23456789", 6).unwrap().raw, 1_234_567);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_input() {
+        assert!(TokenAmount::parse("", 6).is_err());
+        assert!(TokenAmount::parse("-1", 6).is_err());
+        assert!(TokenAmount::parse("1.2.3", 6).is_err());
+        assert!(TokenAmount::parse("abc", 6).is_err());
+        assert!(TokenAmount::parse(".", 6).is_err());
+    }
+
+    #[test]
+    fn large_amounts_are_exact() {
+        let amount = TokenAmount::parse("123456789012.123456789012345678", 18).unwrap();
+        assert_eq!(amount.to_string(), "123456789012.123456789012345678");
+    }
+
+    #[test]
+    fn arithmetic_requires_matching_decimals() {
+        let a = TokenAmount::parse("1.5", 6).unwrap();
+        let b = TokenAmount::parse("2", 6).unwrap();
+        assert_eq!(a.checked_add(&b).unwrap().to_string(), "3.5");
+        assert!(a.saturating_sub(&b).unwrap().is_zero());
+        assert!(a.checked_add(&TokenAmount::zero(18)).is_err());
+    }
+
+    #[test]
+    fn rejects_decimals_above_max() {
+        assert!(TokenAmount::from_raw(1, MAX_DECIMALS).is_ok());
+        assert!(TokenAmount::from_raw(1, MAX_DECIMALS + 1).is_err());
+        assert!(TokenAmount::from_u256(U256::from(1u8), MAX_DECIMALS + 1).is_err());
+        assert!(TokenAmount::parse("1", MAX_DECIMALS + 1).is_err());
+
+        let decoded = TokenAmount { raw: 5, decimals: 40 };
+        assert_eq!(decoded.to_string(), "0.0000000000000000000000000000000000000005");
+    }
+}
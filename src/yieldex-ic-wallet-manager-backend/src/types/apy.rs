@@ -40,3 +40,69 @@ pub struct ApyParserStatus {
     /// Monitored chains
     pub monitored_chains: Vec<u64>,
 }
+
+// =============================================================================
+// APY Analytics Types
+// =============================================================================
+
+/// APY statistics of one protocol over an analytics window
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ApyProtocolStatistics {
+    /// Protocol name (e.g., "AAVE")
+    pub protocol: String,
+    /// Number of samples in the window (raw samples and rolled-up buckets)
+    pub sample_count: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// Population standard deviation of the samples
+    pub std_dev: f64,
+    /// Mean weighted by how long each value held
+    pub time_weighted_mean: f64,
+    /// Share of the compared time during which this protocol had the highest APY
+    pub lead_time_percent: f64,
+}
+
+/// Share of time the absolute spread reached a threshold
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ApySpreadThreshold {
+    /// Spread threshold in APY percentage points
+    pub threshold_percent: f64,
+    /// Share of the compared time with |spread| >= threshold
+    pub time_percent: f64,
+}
+
+/// Time-weighted distribution of the APY spread between two protocols
+///
+/// Spread is `protocol_a` APY minus `protocol_b` APY in percentage points,
+/// the same unit as `apy_threshold_percent`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ApySpreadDistribution {
+    pub protocol_a: String,
+    pub protocol_b: String,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub exceedance: Vec<ApySpreadThreshold>,
+}
+
+/// APY analytics of one asset on one chain across protocols
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ApyAnalytics {
+    pub asset: String,
+    pub chain_id: u64,
+    pub window_start: u64,
+    pub window_end: u64,
+    /// Statistics of each protocol with samples in the window
+    pub protocols: Vec<ApyProtocolStatistics>,
+    /// Spread distribution of each protocol pair
+    pub spreads: Vec<ApySpreadDistribution>,
+    /// Time (ms) during which every protocol had a known APY; base of the time shares
+    pub compared_duration_ms: u64,
+}
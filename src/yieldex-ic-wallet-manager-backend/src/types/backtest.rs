@@ -0,0 +1,186 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::types::{ApyHistoryRecord, ApySignal};
+
+/// Gas cost model applied to every simulated rebalance
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct BacktestGasModel {
+    /// Gas units consumed by one rebalance (withdraw + approve + supply)
+    pub gas_units_per_move: u64,
+
+    /// Gas price in gwei
+    pub gas_price_gwei: f64,
+
+    /// Price of the chain's native token in USD
+    pub native_token_price_usd: f64,
+}
+
+impl BacktestGasModel {
+    /// Cost of one rebalance in USD
+    pub fn move_cost_usd(&self) -> f64 {
+        self.gas_units_per_move as f64 * self.gas_price_gwei * 1e-9 * self.native_token_price_usd
+    }
+}
+
+impl Default for BacktestGasModel {
+    fn default() -> Self {
+        Self {
+            gas_units_per_move: 450_000,
+            gas_price_gwei: 0.1,
+            native_token_price_usd: 3000.0,
+        }
+    }
+}
+
+/// Backtest parameters
+///
+/// Unset strategy fields fall back to the live scheduler configuration.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BacktestRequest {
+    /// Asset symbol (e.g., "USDC")
+    pub asset: String,
+
+    /// Chain ID
+    pub chain_id: u64,
+
+    /// Protocol the simulated position starts in ("AAVE" | "COMPOUND")
+    pub start_protocol: String,
+
+    /// Starting position value in USD
+    pub initial_value_usd: f64,
+
+    /// Simulation start (ms)
+    pub from: u64,
+
+    /// Simulation end (ms)
+    pub to: u64,
+
+    /// Signal override
+    pub apy_signal: Option<ApySignal>,
+
+    /// APY threshold override (percentage points)
+    pub apy_threshold_percent: Option<f64>,
+
+    /// Signal window override in seconds
+    pub signal_window_seconds: Option<u64>,
+
+    /// EMA half-life override in seconds
+    pub ema_half_life_seconds: Option<u64>,
+
+    /// Minimum samples override
+    pub min_apy_samples: Option<u64>,
+
+    /// Evaluation interval override in seconds
+    pub interval_seconds: Option<u64>,
+
+    /// Minimum time between two moves in seconds (0 = no cooldown)
+    pub cooldown_seconds: u64,
+
+    /// Gas model (defaults to `BacktestGasModel::default()`)
+    pub gas: Option<BacktestGasModel>,
+
+    /// Replay these records instead of stored history
+    pub fixtures: Option<Vec<ApyHistoryRecord>>,
+}
+
+/// A simulated rebalance
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct BacktestMove {
+    /// Evaluation timestamp (ms)
+    pub timestamp: u64,
+
+    /// Protocol moved out of
+    pub from_protocol: String,
+
+    /// Protocol moved into
+    pub to_protocol: String,
+
+    /// Signal value of the protocol moved out of
+    pub current_apy: f64,
+
+    /// Signal value of the protocol moved into
+    pub target_apy: f64,
+
+    /// Gas cost of the move in USD
+    pub gas_cost_usd: f64,
+
+    /// Position value after paying gas
+    pub value_after_usd: f64,
+}
+
+/// Result of holding one protocol for the whole backtest
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct BacktestBuyAndHold {
+    /// Protocol held
+    pub protocol: String,
+
+    /// Final position value in USD
+    pub final_value_usd: f64,
+
+    /// Yield earned in USD
+    pub yield_usd: f64,
+
+    /// Annualized yield in percent
+    pub apy_percent: f64,
+
+    /// Strategy net yield minus this protocol's yield (positive = strategy wins)
+    pub strategy_excess_usd: f64,
+}
+
+/// Backtest outcome
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct BacktestReport {
+    /// Asset symbol
+    pub asset: String,
+
+    /// Chain ID
+    pub chain_id: u64,
+
+    /// Simulation start (ms)
+    pub from: u64,
+
+    /// Simulation end (ms)
+    pub to: u64,
+
+    /// Signal used
+    pub apy_signal: ApySignal,
+
+    /// APY threshold used (percentage points)
+    pub apy_threshold_percent: f64,
+
+    /// Starting position value in USD
+    pub initial_value_usd: f64,
+
+    /// Final position value in USD, after gas
+    pub final_value_usd: f64,
+
+    /// Protocol held at the end
+    pub final_protocol: String,
+
+    /// Number of rebalances
+    pub move_count: u64,
+
+    /// Total gas spent in USD
+    pub gas_spent_usd: f64,
+
+    /// Yield earned before gas in USD
+    pub gross_yield_usd: f64,
+
+    /// Yield earned after gas in USD
+    pub net_yield_usd: f64,
+
+    /// Annualized net yield in percent
+    pub net_apy_percent: f64,
+
+    /// Number of evaluation ticks
+    pub ticks_evaluated: u64,
+
+    /// Ticks skipped because a signal lacked samples
+    pub ticks_without_signal: u64,
+
+    /// Every simulated rebalance, oldest first
+    pub moves: Vec<BacktestMove>,
+
+    /// Buy-and-hold comparison per protocol
+    pub buy_and_hold: Vec<BacktestBuyAndHold>,
+}
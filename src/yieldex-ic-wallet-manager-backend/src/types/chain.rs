@@ -0,0 +1,91 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Token listed in a protocol deployment
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct DeploymentAsset {
+    /// Asset symbol (e.g., "USDC")
+    pub symbol: String,
+
+    /// Underlying token address
+    pub token_address: String,
+
+    /// Token decimals
+    pub decimals: u8,
+}
+
+/// Lending protocol deployed on a chain
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct ProtocolDeployment {
+    /// Protocol ("AAVE" | "COMPOUND")
+    pub protocol: String,
+
+    /// Entry contract: AAVE V3 Pool or Compound III Comet
+    pub contract_address: String,
+
+    /// Assets the yield strategy uses on this deployment
+    pub assets: Vec<DeploymentAsset>,
+}
+
+/// Chainlink USD price feed on a chain
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct PriceFeedConfig {
+    /// Asset symbol the feed prices (e.g., "ETH")
+    pub asset: String,
+
+    /// AggregatorV3 proxy address
+    pub feed_address: String,
+
+    /// Answer decimals (all USD feeds use 8)
+    pub decimals: u8,
+
+    /// Maximum time between feed updates
+    pub heartbeat_seconds: u64,
+}
+
+/// EVM network known to the canister
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct ChainConfig {
+    /// EIP-155 chain ID
+    pub chain_id: u64,
+
+    /// Human-readable name (e.g., "Arbitrum One")
+    pub name: String,
+
+    /// Gas token symbol (e.g., "ETH")
+    pub native_symbol: String,
+
+    /// Gas token decimals
+    pub native_decimals: u8,
+
+    /// JSON-RPC endpoint, as a one-element list (requests have no fallback endpoint)
+    pub rpc_endpoints: Vec<String>,
+
+    /// Block explorer base URL
+    pub block_explorer: Option<String>,
+
+    /// Whether the chain accepts EIP-1559 transactions
+    pub eip1559: bool,
+
+    /// Hard cap on `maxFeePerGas` (gas price on legacy chains) in gwei; None = uncapped
+    pub max_fee_per_gas_gwei: Option<f64>,
+
+    /// Hard cap on `maxPriorityFeePerGas` in gwei; None = uncapped
+    pub max_priority_fee_per_gas_gwei: Option<f64>,
+
+    /// Lending protocol deployments
+    pub protocols: Vec<ProtocolDeployment>,
+
+    /// USDC token the strategy holds on this chain; None = no USDC
+    pub usdc_address: Option<String>,
+
+    /// Chainlink USD price feeds; None = no feeds
+    pub price_feeds: Option<Vec<PriceFeedConfig>>,
+
+    /// Minimum gas limit of a cancellation (zero-value self-transfer); None = 21,000.
+    /// Set where a plain transfer costs more, e.g. the L1 component on Arbitrum
+    pub cancel_gas_limit: Option<u64>,
+
+    /// Disabled chains are kept but reported as unsupported
+    pub enabled: bool,
+}
@@ -0,0 +1,50 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Gas and fee settings applied to every transaction the canister signs
+///
+/// Per-chain hard caps live on the chain's `ChainConfig`.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct FeePolicyConfig {
+    /// Safety multiplier applied to the `eth_estimateGas` result for the gas limit
+    pub gas_limit_multiplier: f64,
+
+    /// Number of recent blocks sampled with `eth_feeHistory`
+    pub fee_history_blocks: u64,
+
+    /// Reward percentile of each sampled block used for the priority fee
+    pub priority_fee_percentile: f64,
+
+    /// Multiplier on the next block's base fee, as headroom in `maxFeePerGas`
+    pub base_fee_multiplier: f64,
+}
+
+impl Default for FeePolicyConfig {
+    fn default() -> Self {
+        Self {
+            gas_limit_multiplier: 1.2,
+            fee_history_blocks: 10,
+            priority_fee_percentile: 50.0,
+            base_fee_multiplier: 2.0,
+        }
+    }
+}
+
+/// Fees the canister would sign with on a chain right now
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct FeeQuote {
+    /// Chain the quote was taken on
+    pub chain_id: u64,
+
+    /// Whether the fees are EIP-1559 fields or a legacy gas price
+    pub eip1559: bool,
+
+    /// Next block's base fee in gwei (EIP-1559 chains)
+    pub base_fee_gwei: Option<f64>,
+
+    /// `maxFeePerGas` in gwei, or the gas price on legacy chains
+    pub max_fee_per_gas_gwei: f64,
+
+    /// `maxPriorityFeePerGas` in gwei (EIP-1559 chains)
+    pub max_priority_fee_per_gas_gwei: Option<f64>,
+}
@@ -0,0 +1,13 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// Lock held while an operation that moves funds or runs the scheduler is in progress
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct OperationLock {
+    /// Lock key ("scheduler" or the user's principal text)
+    pub key: String,
+    /// Operation holding the lock (e.g., "supply_to_aave")
+    pub operation: String,
+    /// Timestamp when the lock was acquired
+    pub acquired_at: u64,
+}
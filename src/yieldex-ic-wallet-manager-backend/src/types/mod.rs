@@ -5,6 +5,17 @@ pub mod recommendation;
 pub mod storable;
 pub mod apy;
 pub mod scheduler;
+pub mod locks;
+pub mod saga;
+pub mod amount;
+pub mod yield_ledger;
+pub mod backtest;
+pub mod portfolio;
+pub mod price;
+pub mod chain;
+pub mod fee;
+pub mod transaction;
+pub mod nonce;
 
 // Re-export commonly used types for convenience
 pub use permissions::{
@@ -13,19 +24,73 @@ pub use permissions::{
 };
 
 pub use recommendation::{
-    RecommendationType, Recommendation, ExecutionResult,
+    RecommendationType, Recommendation, ExecutionResult, WithdrawOutcome,
 };
 
 pub use storable::{
     StorablePrincipal, StorableString, StorablePermissions,
-    StorableUserPosition, StorableApyHistoryRecord, StorableRebalanceExecution,
+    StorableUserPosition, StorableApyHistoryRecord, StorableApyHistoryKey,
+    StorableApyAggregate, StorableApyAggregateKey, StorableRebalanceExecution,
+    StorableUserSchedulerSettings, StorableDryRunTick, StorablePendingRecommendation,
+    StorableOperationLock, StorableSchedulerRun, StorableSchedulerRunEntry, StorableSchedulerExecutionSummary,
+    StorableRebalanceSaga, StorableYieldTenure, StorableYieldSnapshot, StorableTokenPrice,
+    StorableChainConfig, StorableSentTransaction, StorableNonceState, StorableApyRetentionConfig,
+    StorableFeePolicyConfig, StorableSchedulerConfig,
 };
 
 pub use apy::{
     ProtocolApyInfo, ApyResponse, ApyParserStatus,
+    ApyProtocolStatistics, ApySpreadThreshold, ApySpreadDistribution, ApyAnalytics,
 };
 
 pub use scheduler::{
-    SchedulerConfig, SchedulerStatus, UserPosition, ApyHistoryRecord,
-    RebalanceExecution, SchedulerExecutionSummary,
+    SchedulerConfig, SchedulerStatus, UserPosition, ApyHistoryRecord, ApyHistoryKey, ApyHistoryPage,
+    ApyResolution, ApyAggregateKey, ApyAggregate, ApyRetentionConfig, ApyRetentionReport,
+    RebalanceExecution, SchedulerExecutionSummary, ApySignal, ApySignalSnapshot,
+    UserSchedulerSettings, DryRunResult, DryRunTick, ExecutionMode, PendingRecommendation,
+    SchedulerRun, SchedulerRunEntry, PositionDiscrepancy, PositionReconciliationReport,
+};
+
+pub use locks::{
+    OperationLock,
+};
+
+pub use saga::{
+    SagaState, RebalanceSaga,
+};
+
+pub use amount::{
+    TokenAmount,
+};
+
+pub use yield_ledger::{
+    YieldTenure, YieldSnapshot, TenureYield, AssetYield, YieldReport,
+};
+
+pub use backtest::{
+    BacktestGasModel, BacktestRequest, BacktestMove, BacktestBuyAndHold, BacktestReport,
+};
+
+pub use portfolio::{
+    HoldingKind, PortfolioHolding, ChainPortfolio, Portfolio,
+};
+
+pub use price::{
+    TokenPrice, PriceOracleConfig,
+};
+
+pub use chain::{
+    DeploymentAsset, ProtocolDeployment, PriceFeedConfig, ChainConfig,
+};
+
+pub use fee::{
+    FeePolicyConfig, FeeQuote,
+};
+
+pub use transaction::{
+    TxStatus, SentTransaction,
+};
+
+pub use nonce::{
+    NonceReservation, NonceState,
 };
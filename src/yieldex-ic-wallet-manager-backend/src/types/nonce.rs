@@ -0,0 +1,43 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Nonce handed out to a transaction that has not been sent yet
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct NonceReservation {
+    /// Reserved nonce
+    pub nonce: u64,
+
+    /// When the nonce was reserved (ms)
+    pub reserved_at: u64,
+}
+
+/// Nonce bookkeeping of one address on one chain
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct NonceState {
+    /// Sender address (0x-prefixed, lowercase)
+    pub address: String,
+
+    /// Chain the nonces are used on
+    pub chain_id: u64,
+
+    /// Lowest nonce never handed out
+    pub next_nonce: u64,
+
+    /// Nonces reserved by in-flight sends
+    pub reserved: Vec<NonceReservation>,
+
+    /// Nonces below `next_nonce` given back by failed sends, reused first
+    pub released: Vec<u64>,
+
+    /// Whether to reconcile with the pending transaction count before the next reservation
+    pub needs_sync: bool,
+
+    /// Highest nonce the RPC accepted a transaction for; None until the first commit
+    pub committed_high_water: Option<u64>,
+
+    /// When the high-water mark was last raised (ms)
+    pub committed_high_water_at: Option<u64>,
+
+    /// When the state last changed (ms)
+    pub updated_at: u64,
+}
@@ -24,6 +24,8 @@ pub struct TransferLimit {
     pub token_address: TokenAddress,
     pub daily_limit: u64,
     pub max_tx_amount: u64,
+    pub used_today: Option<u64>, // Amount transferred on `last_reset_date`'s day
+    pub last_reset_date: Option<u64>, // Start of the day `used_today` counts (ms)
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -31,6 +33,7 @@ pub struct ProtocolPermission {
     pub protocol_address: String,
     pub allowed_functions: Vec<String>, // ["supply", "withdraw", "borrow"]
     pub max_amount_per_tx: Option<u64>,
+    pub max_usd_per_tx: Option<f64>, // Valued with the price oracle; refused if no price
     pub daily_limit: Option<u64>,
     pub total_used_today: u64,
     pub last_reset_date: u64, // Timestamp for daily limit reset
@@ -0,0 +1,89 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::types::TokenAmount;
+
+/// Where a portfolio holding sits
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum HoldingKind {
+    /// Native gas token of the chain
+    Native,
+    /// ERC-20 balance held by the wallet
+    Wallet,
+    /// Balance supplied to a lending protocol
+    Protocol,
+}
+
+/// A single balance in a user's portfolio
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PortfolioHolding {
+    /// Native, wallet or protocol balance
+    pub kind: HoldingKind,
+
+    /// Protocol name for protocol balances ("AAVE" | "COMPOUND")
+    pub protocol: Option<String>,
+
+    /// Asset symbol (e.g., "USDC", "ETH")
+    pub asset: String,
+
+    /// Token address (None for the native token)
+    pub token_address: Option<String>,
+
+    /// Exact balance
+    pub amount: TokenAmount,
+
+    /// USD price of one token, if known
+    pub price_usd: Option<f64>,
+
+    /// USD value of the balance, if priced
+    pub value_usd: Option<f64>,
+
+    /// Latest stored supply APY for protocol balances
+    pub apy: Option<f64>,
+}
+
+/// Holdings of a user on one chain
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ChainPortfolio {
+    /// Chain ID
+    pub chain_id: u64,
+
+    /// Chain name
+    pub chain_name: String,
+
+    /// Non-zero balances on this chain
+    pub holdings: Vec<PortfolioHolding>,
+
+    /// USD value of the priced holdings on this chain
+    pub total_usd: f64,
+}
+
+/// Portfolio of a user across all supported chains
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Portfolio {
+    /// User's EVM address
+    pub evm_address: String,
+
+    /// Per-chain holdings
+    pub chains: Vec<ChainPortfolio>,
+
+    /// USD value of native gas balances
+    pub native_usd: f64,
+
+    /// USD value of wallet token balances
+    pub wallet_usd: f64,
+
+    /// USD value of protocol balances
+    pub protocol_usd: f64,
+
+    /// Sum of all priced holdings
+    pub total_usd: f64,
+
+    /// Protocol balances' APY weighted by USD value
+    pub weighted_apy: Option<f64>,
+
+    /// Reads or prices that failed; their holdings are missing or unpriced
+    pub errors: Vec<String>,
+
+    /// Generation timestamp
+    pub generated_at: u64,
+}
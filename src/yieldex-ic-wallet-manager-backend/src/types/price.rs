@@ -0,0 +1,47 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// USD price of a token read from a Chainlink feed
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct TokenPrice {
+    /// Asset symbol, uppercase (e.g., "ETH", "USDC")
+    pub asset: String,
+
+    /// Chain the feed was read on
+    pub chain_id: u64,
+
+    /// Price of one token in USD
+    pub price_usd: f64,
+
+    /// Feed contract address
+    pub feed_address: String,
+
+    /// When the feed last updated its answer (ms)
+    pub updated_at: u64,
+
+    /// When the canister read the feed (ms)
+    pub fetched_at: u64,
+}
+
+/// Price oracle settings
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct PriceOracleConfig {
+    /// How long a fetched price is served from cache before re-reading the feed
+    pub cache_ttl_seconds: u64,
+
+    /// Extra time past a feed's heartbeat before its answer counts as stale
+    pub staleness_grace_seconds: u64,
+
+    /// Largest accepted move from the previous price while that price is fresh (percent)
+    pub max_deviation_percent: f64,
+}
+
+impl Default for PriceOracleConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl_seconds: 300, // 5 minutes
+            staleness_grace_seconds: 600, // 10 minutes
+            max_deviation_percent: 20.0,
+        }
+    }
+}
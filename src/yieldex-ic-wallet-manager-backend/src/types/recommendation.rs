@@ -1,5 +1,6 @@
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
+use crate::types::TokenAmount;
 
 // --- Recommendation Types ---
 
@@ -34,11 +35,22 @@ pub struct Recommendation {
     pub estimated_profit: f64,                // Estimated profit
     pub gas_cost: f64,                        // Gas cost
     pub position_size: String,                // Amount in human-readable format "1000"
+    pub position_amount: Option<TokenAmount>, // Exact amount in token units (preferred over position_size)
     pub pool_id: Option<String>,              // Pool identifier
     pub recommendation_type: RecommendationType, // StandardTransfer or CrossChainTransfer
     pub swap_details: Option<SwapDetails>,    // For swap operations
 }
 
+impl Recommendation {
+    /// Exact amount to move, falling back to parsing `position_size`
+    pub fn amount(&self) -> Result<TokenAmount, String> {
+        match self.position_amount {
+            Some(amount) => Ok(amount),
+            None => TokenAmount::parse_for_symbol(&self.position_size, &self.asset),
+        }
+    }
+}
+
 /// Recommendation execution result
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct ExecutionResult {
@@ -49,4 +61,13 @@ pub struct ExecutionResult {
     pub amount_transferred: Option<String>,   // Amount actually transferred
     pub actual_gas_cost: Option<f64>,         // Actual gas cost
     pub error_details: Option<String>,        // Error details
+    pub saga_id: Option<String>,              // Rebalance saga tracking recovery of this execution
+}
+
+/// Outcome of a protocol withdraw
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WithdrawOutcome {
+    pub tx_hash: String,                      // Withdraw transaction hash
+    pub amount_withdrawn: String,             // Amount that reached the wallet, human-readable
+    pub message: String,                      // Human-readable success message
 }
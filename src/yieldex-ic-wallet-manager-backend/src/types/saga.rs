@@ -0,0 +1,79 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use crate::types::Recommendation;
+
+/// Step a rebalance saga has reached
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum SagaState {
+    /// Nothing has been moved yet
+    Pending,
+    /// Funds withdrawn from the source protocol and held in the user's wallet
+    Withdrawn,
+    /// Target protocol approved to spend the withdrawn funds
+    Approved,
+    /// Funds supplied to the target protocol (terminal)
+    Supplied,
+    /// Supplying the target kept failing; returning funds to the source protocol
+    RecoveringSource,
+    /// Funds supplied back to the source protocol (terminal)
+    Recovered,
+    /// Withdraw failed and nothing was moved (terminal)
+    Failed,
+}
+
+/// Persisted state machine of a single rebalance: withdraw → approve → supply
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RebalanceSaga {
+    /// Unique saga ID
+    pub saga_id: String,
+
+    /// User whose funds are being moved
+    pub user_principal: Principal,
+
+    /// Permissions ID used for every step
+    pub permissions_id: String,
+
+    /// Chain where the rebalance happens
+    pub chain_id: u64,
+
+    /// Recommendation being executed
+    pub recommendation: Recommendation,
+
+    /// Amount being moved in human-readable format
+    ///
+    /// Starts as the recorded position size and is replaced by the amount
+    /// actually withdrawn once the withdraw step completes.
+    pub amount: String,
+
+    /// Current step
+    pub state: SagaState,
+
+    /// Withdraw transaction hash
+    pub withdraw_tx: Option<String>,
+
+    /// Approval transaction hash (None if the allowance was already sufficient)
+    pub approve_tx: Option<String>,
+
+    /// Supply transaction hash (target, or source when recovered)
+    pub supply_tx: Option<String>,
+
+    /// Consecutive failures in the current step
+    pub attempts: u32,
+
+    /// When the next automatic retry is due (None = terminal or waiting for manual resume)
+    pub next_retry_at: Option<u64>,
+
+    /// When the step in flight was started (None = no step in flight)
+    ///
+    /// Stays set when the step is interrupted by a trap or an upgrade.
+    pub step_started_at: Option<u64>,
+
+    /// Error of the most recent failed step
+    pub last_error: Option<String>,
+
+    /// Timestamp when the saga started
+    pub created_at: u64,
+
+    /// Timestamp of the last state change
+    pub updated_at: u64,
+}
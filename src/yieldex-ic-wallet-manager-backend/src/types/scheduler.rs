@@ -1,11 +1,34 @@
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
-use crate::types::{Recommendation, ExecutionResult};
+use crate::types::{Recommendation, ExecutionResult, TokenAmount};
+use crate::types::amount::decimals_for_symbol;
 
 // =============================================================================
 // Scheduler Configuration Types
 // =============================================================================
 
+/// APY signal used by the scheduler to compare protocols
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum ApySignal {
+    /// Most recent stored sample
+    Latest,
+    /// Time-weighted average over the signal window
+    TimeWeightedAverage,
+    /// Exponential moving average with a configurable half-life
+    Ema,
+    /// Lowest sample observed within the signal window
+    WindowMin,
+}
+
+/// How scheduler-generated recommendations are handled for a user or position
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum ExecutionMode {
+    /// Execute recommendations immediately
+    AutoExecute,
+    /// Deposit recommendations in the approval queue for the user to approve
+    ApprovalRequired,
+}
+
 /// Global scheduler configuration
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct SchedulerConfig {
@@ -18,9 +41,33 @@ pub struct SchedulerConfig {
     /// Minimum APY difference percentage to trigger rebalance (e.g., 0.5 for 0.5%)
     pub apy_threshold_percent: f64,
 
-    /// Minimum position size to consider for rebalancing (human-readable format, e.g., "100")
+    /// Minimum position value in USD to consider for rebalancing (e.g., "100")
     pub min_position_size: String,
 
+    /// APY signal that drives rebalance decisions
+    pub apy_signal: ApySignal,
+
+    /// Lookback window for the APY signal in seconds (e.g., 21600 = 6 hours)
+    pub signal_window_seconds: u64,
+
+    /// Half-life used by the EMA signal in seconds
+    pub ema_half_life_seconds: u64,
+
+    /// Minimum number of APY samples in the window required before acting
+    pub min_apy_samples: u64,
+
+    /// Shadow mode: evaluate positions and store recommendations without executing them
+    pub dry_run: bool,
+
+    /// How long a pending recommendation stays approvable, in seconds
+    pub proposal_ttl_seconds: u64,
+
+    /// Maximum number of positions processed per scheduler batch
+    pub batch_size: u64,
+
+    /// Delay between scheduler batches in seconds
+    pub batch_interval_seconds: u64,
+
     /// Timestamp of last scheduler execution
     pub last_execution: Option<u64>,
 
@@ -37,7 +84,15 @@ impl Default for SchedulerConfig {
             enabled: false,
             interval_seconds: 3600, // 1 hour default
             apy_threshold_percent: 0.5, // 0.5% APY difference
-            min_position_size: "100".to_string(), // $100 USDC minimum
+            min_position_size: "100".to_string(), // $100 minimum
+            apy_signal: ApySignal::TimeWeightedAverage,
+            signal_window_seconds: 21600, // 6 hours
+            ema_half_life_seconds: 7200, // 2 hours
+            min_apy_samples: 4,
+            dry_run: false,
+            proposal_ttl_seconds: 86400, // 24 hours
+            batch_size: 10,
+            batch_interval_seconds: 2,
             last_execution: None,
             created_at: 0,
             updated_at: 0,
@@ -53,6 +108,23 @@ pub struct SchedulerStatus {
     pub total_positions_tracked: u64,
     pub total_rebalances_executed: u64,
     pub last_execution_result: Option<String>,
+    pub last_tick_summary: Option<SchedulerExecutionSummary>,
+}
+
+/// Per-user scheduler settings that override the global configuration
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct UserSchedulerSettings {
+    /// User these settings apply to
+    pub user_principal: Principal,
+
+    /// Dry-run override (None = follow the global setting)
+    pub dry_run: Option<bool>,
+
+    /// Execution mode override (None = auto-execute)
+    pub execution_mode: Option<ExecutionMode>,
+
+    /// Last time these settings were updated
+    pub updated_at: u64,
 }
 
 // =============================================================================
@@ -90,9 +162,15 @@ pub struct UserPosition {
     /// Current position size in human-readable format
     pub position_size: String,
 
+    /// Exact position size in token units (None for positions stored before it existed)
+    pub position_amount: Option<TokenAmount>,
+
     /// Whether this position should be tracked for auto-rebalancing
     pub tracked: bool,
 
+    /// Execution mode override for this position (None = follow user settings)
+    pub execution_mode: Option<ExecutionMode>,
+
     /// Timestamp when position was added
     pub added_at: u64,
 
@@ -100,6 +178,81 @@ pub struct UserPosition {
     pub updated_at: u64,
 }
 
+impl UserPosition {
+    /// Exact position size, falling back to parsing `position_size` for older records
+    pub fn size(&self) -> Result<TokenAmount, String> {
+        match self.position_amount {
+            Some(amount) => Ok(amount),
+            None => TokenAmount::parse(&self.position_size, decimals_for_symbol(&self.asset)?),
+        }
+    }
+
+    /// Set the position size, keeping the human-readable copy in sync
+    pub fn set_size(&mut self, amount: TokenAmount) {
+        self.position_size = amount.to_string();
+        self.position_amount = Some(amount);
+    }
+}
+
+/// Mismatch between a recorded position and the on-chain balance
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PositionDiscrepancy {
+    /// User who owns the position
+    pub user_principal: Principal,
+
+    /// Position that was updated, created or deleted
+    pub position_id: String,
+
+    /// Protocol ("AAVE" | "COMPOUND")
+    pub protocol: String,
+
+    /// Asset symbol (e.g., "USDC")
+    pub asset: String,
+
+    /// Chain ID
+    pub chain_id: u64,
+
+    /// Size recorded before reconciliation ("0" if untracked)
+    pub recorded_size: String,
+
+    /// Size read from chain
+    pub onchain_size: String,
+
+    /// Action taken: "updated" | "created" | "deleted"
+    pub action: String,
+}
+
+/// Result of one reconciliation pass over tracked positions
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PositionReconciliationReport {
+    /// Number of (address, protocol, chain) balances read
+    pub balances_checked: u64,
+
+    /// Number of positions whose size was corrected
+    pub updated: u64,
+
+    /// Number of positions discovered on chain and created
+    pub created: u64,
+
+    /// Number of positions deleted because the on-chain balance is zero
+    pub deleted: u64,
+
+    /// Users skipped because another operation held their lock
+    pub users_skipped: u64,
+
+    /// Balance reads or writes that failed
+    pub errors: Vec<String>,
+
+    /// Every discrepancy found
+    pub discrepancies: Vec<PositionDiscrepancy>,
+
+    /// When the pass started
+    pub started_at: u64,
+
+    /// When the pass finished
+    pub completed_at: u64,
+}
+
 // =============================================================================
 // APY History Types (Future DB)
 // =============================================================================
@@ -130,6 +283,190 @@ pub struct ApyHistoryRecord {
     pub timestamp: u64,
 }
 
+/// Ordered key of an APY sample: (protocol, chain, token, timestamp)
+///
+/// Field order defines the ordering, so each series is one contiguous range
+/// sorted by time.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApyHistoryKey {
+    /// Protocol name, uppercase
+    pub protocol: String,
+
+    /// Chain ID
+    pub chain_id: u64,
+
+    /// Asset symbol, uppercase
+    pub asset: String,
+
+    /// Timestamp of the sample
+    pub timestamp: u64,
+}
+
+impl ApyHistoryKey {
+    pub fn new(protocol: &str, chain_id: u64, asset: &str, timestamp: u64) -> Self {
+        Self {
+            protocol: protocol.to_uppercase(),
+            chain_id,
+            asset: asset.to_uppercase(),
+            timestamp,
+        }
+    }
+
+    /// Key of an existing record
+    pub fn for_record(record: &ApyHistoryRecord) -> Self {
+        Self::new(&record.protocol, record.chain_id, &record.asset, record.timestamp)
+    }
+
+    /// Whether two keys belong to the same series
+    pub fn same_series(&self, other: &ApyHistoryKey) -> bool {
+        self.protocol == other.protocol && self.chain_id == other.chain_id && self.asset == other.asset
+    }
+}
+
+/// One page of an APY series, oldest first
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ApyHistoryPage {
+    pub records: Vec<ApyHistoryRecord>,
+
+    /// Timestamp to pass as `cursor` for the next page (None = no more records)
+    pub next_cursor: Option<u64>,
+}
+
+/// Time resolution of an APY aggregate
+#[derive(Clone, Copy, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApyResolution {
+    Hourly,
+    Daily,
+}
+
+impl ApyResolution {
+    /// Bucket length in milliseconds
+    pub fn bucket_ms(&self) -> u64 {
+        match self {
+            ApyResolution::Hourly => 3600 * 1000,
+            ApyResolution::Daily => 24 * 3600 * 1000,
+        }
+    }
+}
+
+/// Ordered key of an APY aggregate: (resolution, protocol, chain, token, bucket)
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApyAggregateKey {
+    pub resolution: ApyResolution,
+
+    /// Protocol name, uppercase
+    pub protocol: String,
+
+    pub chain_id: u64,
+
+    /// Asset symbol, uppercase
+    pub asset: String,
+
+    /// Start of the time bucket
+    pub bucket_start: u64,
+}
+
+/// APY samples of one series rolled up over a time bucket
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ApyAggregate {
+    pub protocol: String,
+    pub asset: String,
+    pub chain_id: u64,
+    pub resolution: ApyResolution,
+
+    /// Start of the time bucket
+    pub bucket_start: u64,
+
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+
+    /// Most recent APY in the bucket
+    pub last: f64,
+
+    /// Timestamp of the most recent sample in the bucket
+    pub last_timestamp: u64,
+
+    /// Number of raw samples rolled into the bucket
+    pub sample_count: u64,
+}
+
+impl ApyAggregate {
+    /// Key of this aggregate
+    pub fn key(&self) -> ApyAggregateKey {
+        ApyAggregateKey {
+            resolution: self.resolution,
+            protocol: self.protocol.to_uppercase(),
+            chain_id: self.chain_id,
+            asset: self.asset.to_uppercase(),
+            bucket_start: self.bucket_start,
+        }
+    }
+}
+
+/// APY history retention policy
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ApyRetentionConfig {
+    /// Whether the background retention timer rolls up and prunes history
+    pub enabled: bool,
+
+    /// Days raw samples are kept before being rolled into hourly aggregates
+    pub raw_retention_days: u64,
+
+    /// Days hourly aggregates are kept before being rolled into daily aggregates
+    pub hourly_retention_days: u64,
+
+    /// Days daily aggregates are kept (None = forever)
+    pub daily_retention_days: Option<u64>,
+
+    /// Days yield ledger snapshots are kept (None = forever)
+    pub yield_snapshot_retention_days: Option<u64>,
+
+    /// Maximum records rolled up or pruned per run, to bound instructions
+    pub max_records_per_run: u64,
+}
+
+/// Outcome of one retention run
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ApyRetentionReport {
+    /// Raw samples rolled into hourly aggregates
+    pub raw_rolled_up: u64,
+
+    /// Hourly aggregates rolled into daily aggregates
+    pub hourly_rolled_up: u64,
+
+    /// Daily aggregates past retention that were deleted
+    pub daily_pruned: u64,
+
+    /// Yield ledger snapshots past retention that were deleted
+    pub yield_snapshots_pruned: u64,
+
+    /// Whether the per-run budget ran out before all eligible data was processed
+    pub budget_exhausted: bool,
+
+    pub started_at: u64,
+    pub completed_at: u64,
+}
+
+/// Snapshot of all APY signals for a protocol/asset/chain
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ApySignalSnapshot {
+    pub protocol: String,
+    pub asset: String,
+    pub chain_id: u64,
+
+    /// Number of samples inside the signal window
+    pub samples_in_window: u64,
+
+    /// Window start timestamp (milliseconds)
+    pub window_start: u64,
+
+    pub latest: Option<f64>,
+    pub time_weighted_average: Option<f64>,
+    pub ema: Option<f64>,
+    pub window_min: Option<f64>,
+}
+
 // =============================================================================
 // Rebalance Execution History
 // =============================================================================
@@ -159,12 +496,106 @@ pub struct RebalanceExecution {
     pub timestamp: u64,
 }
 
+/// Outcome of evaluating a single position in dry-run mode
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct DryRunResult {
+    /// User who owns the position
+    pub user_principal: Principal,
+
+    /// Position that was evaluated
+    pub position_id: String,
+
+    /// "rebalance" | "skip" | "error"
+    pub decision: String,
+
+    /// Human-readable explanation of the decision
+    pub reasoning: String,
+
+    /// APY signal for the current protocol
+    pub current_apy: Option<f64>,
+
+    /// APY signal for the alternative protocol
+    pub alternative_apy: Option<f64>,
+
+    /// APY difference (alternative - current)
+    pub apy_difference: Option<f64>,
+
+    /// Recommendation that would have been executed
+    pub recommendation: Option<Recommendation>,
+}
+
+/// All dry-run results produced by one scheduler tick
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct DryRunTick {
+    /// Tick identifier ("tick_<zero-padded timestamp>")
+    pub tick_id: String,
+
+    /// Timestamp of the tick
+    pub timestamp: u64,
+
+    /// One entry per position evaluated in dry-run mode
+    pub results: Vec<DryRunResult>,
+}
+
+// =============================================================================
+// Approval Queue
+// =============================================================================
+
+/// Scheduler recommendation waiting for user approval
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PendingRecommendation {
+    /// Unique proposal ID
+    pub proposal_id: String,
+
+    /// User who must approve the proposal
+    pub user_principal: Principal,
+
+    /// Position the recommendation applies to
+    pub position_id: String,
+
+    /// Permissions ID used when the proposal is executed
+    pub permissions_id: String,
+
+    /// Recommendation generated by the scheduler
+    pub recommendation: Recommendation,
+
+    /// APY difference that triggered the recommendation
+    pub apy_difference: f64,
+
+    /// "pending" | "executing" | "executed" | "failed" | "rejected" | "superseded"
+    pub status: String,
+
+    /// Execution ID in rebalance history once executed
+    pub execution_id: Option<String>,
+
+    /// Error message if execution failed
+    pub error: Option<String>,
+
+    /// Timestamp when the proposal was created
+    pub created_at: u64,
+
+    /// Timestamp after which the proposal can no longer be approved
+    pub expires_at: u64,
+
+    /// Timestamp when the proposal was approved, rejected or superseded
+    pub resolved_at: Option<u64>,
+}
+
 /// Summary of a scheduler execution tick
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct SchedulerExecutionSummary {
+    /// Tick identifier ("tick_<zero-padded timestamp>")
+    pub tick_id: String,
+
     /// Timestamp of this execution
     pub timestamp: u64,
 
+    /// Timestamp when the last batch of the tick finished
+    pub completed_at: Option<u64>,
+
+    /// Whether the tick was a manually triggered dry run
+    pub dry_run: bool,
+
     /// Number of positions checked
     pub positions_checked: u64,
 
@@ -177,9 +608,64 @@ pub struct SchedulerExecutionSummary {
     /// Number of failed rebalances
     pub rebalances_failed: u64,
 
+    /// Number of positions evaluated in dry-run mode
+    pub dry_run_evaluations: u64,
+
+    /// Number of recommendations queued for user approval
+    pub proposals_queued: u64,
+
     /// List of execution IDs for this tick
     pub execution_ids: Vec<String>,
 
     /// Any errors encountered during the tick
     pub errors: Vec<String>,
 }
+
+/// Checkpoint of a scheduler run that is processed in batches
+///
+/// Saved after every position, so it only holds the cursor and counters.
+/// The work queue lives in SCHEDULER_RUN_QUEUE_MAP and per-position outcomes
+/// in SCHEDULER_RUN_ENTRIES_MAP until the run finishes. The dry-run mode is fixed when the run starts; thresholds and
+/// batch sizes are read from the current scheduler configuration.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SchedulerRun {
+    /// Run identifier ("tick_<zero-padded timestamp>")
+    pub run_id: String,
+
+    /// Whether every position is evaluated in dry-run mode
+    pub force_dry_run: bool,
+
+    /// Global dry-run setting when the run started (per-user overrides still apply)
+    pub global_dry_run: bool,
+
+    /// Number of positions in the work queue
+    pub position_count: u64,
+
+    /// Index of the next position to process
+    pub next_index: u64,
+
+    /// Counters accumulated so far (execution IDs and errors are filled in when the run finishes)
+    pub summary: SchedulerExecutionSummary,
+
+    /// Timestamp when the run started
+    pub started_at: u64,
+
+    /// Timestamp of the last checkpoint
+    pub updated_at: u64,
+}
+
+/// Outcome of one position of a scheduler run
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SchedulerRunEntry {
+    /// Position that was processed
+    pub position_id: String,
+
+    /// Rebalance execution ID, if one was executed
+    pub execution_id: Option<String>,
+
+    /// Error or deferral reason
+    pub error: Option<String>,
+
+    /// Dry-run evaluation, in dry-run mode
+    pub dry_run_result: Option<DryRunResult>,
+}
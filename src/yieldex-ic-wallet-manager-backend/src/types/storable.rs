@@ -4,7 +4,15 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 
 use super::permissions::Permissions;
-use super::scheduler::{UserPosition, ApyHistoryRecord, RebalanceExecution};
+use super::locks::OperationLock;
+use super::saga::RebalanceSaga;
+use super::yield_ledger::{YieldTenure, YieldSnapshot};
+use super::price::TokenPrice;
+use super::chain::ChainConfig;
+use super::transaction::SentTransaction;
+use super::nonce::NonceState;
+use super::fee::FeePolicyConfig;
+use super::scheduler::{UserPosition, ApyHistoryRecord, ApyHistoryKey, ApyAggregate, ApyAggregateKey, RebalanceExecution, UserSchedulerSettings, DryRunTick, PendingRecommendation, SchedulerRun, SchedulerRunEntry, SchedulerExecutionSummary, ApyRetentionConfig, SchedulerConfig};
 
 // --- Storable Wrapper Types ---
 
@@ -94,6 +102,57 @@ impl Storable for StorableApyHistoryRecord {
     const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StorableApyHistoryKey(pub ApyHistoryKey);
+
+impl Storable for StorableApyHistoryKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode ApyHistoryKey");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let key: ApyHistoryKey = candid::decode_one(&bytes).expect("Failed to decode ApyHistoryKey");
+        StorableApyHistoryKey(key)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StorableApyAggregateKey(pub ApyAggregateKey);
+
+impl Storable for StorableApyAggregateKey {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode ApyAggregateKey");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let key: ApyAggregateKey = candid::decode_one(&bytes).expect("Failed to decode ApyAggregateKey");
+        StorableApyAggregateKey(key)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableApyAggregate(pub ApyAggregate);
+
+impl Storable for StorableApyAggregate {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode ApyAggregate");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let aggregate: ApyAggregate = candid::decode_one(&bytes).expect("Failed to decode ApyAggregate");
+        StorableApyAggregate(aggregate)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct StorableRebalanceExecution(pub RebalanceExecution);
 
@@ -110,3 +169,292 @@ impl Storable for StorableRebalanceExecution {
 
     const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 }
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableUserSchedulerSettings(pub UserSchedulerSettings);
+
+impl Storable for StorableUserSchedulerSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode UserSchedulerSettings");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let settings: UserSchedulerSettings = candid::decode_one(&bytes).expect("Failed to decode UserSchedulerSettings");
+        StorableUserSchedulerSettings(settings)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableDryRunTick(pub DryRunTick);
+
+impl Storable for StorableDryRunTick {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode DryRunTick");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let tick: DryRunTick = candid::decode_one(&bytes).expect("Failed to decode DryRunTick");
+        StorableDryRunTick(tick)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorablePendingRecommendation(pub PendingRecommendation);
+
+impl Storable for StorablePendingRecommendation {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode PendingRecommendation");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let proposal: PendingRecommendation = candid::decode_one(&bytes).expect("Failed to decode PendingRecommendation");
+        StorablePendingRecommendation(proposal)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableOperationLock(pub OperationLock);
+
+impl Storable for StorableOperationLock {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode OperationLock");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let lock: OperationLock = candid::decode_one(&bytes).expect("Failed to decode OperationLock");
+        StorableOperationLock(lock)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableSchedulerRun(pub SchedulerRun);
+
+impl Storable for StorableSchedulerRun {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode SchedulerRun");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let run: SchedulerRun = candid::decode_one(&bytes).expect("Failed to decode SchedulerRun");
+        StorableSchedulerRun(run)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableSchedulerRunEntry(pub SchedulerRunEntry);
+
+impl Storable for StorableSchedulerRunEntry {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode SchedulerRunEntry");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let entry: SchedulerRunEntry = candid::decode_one(&bytes).expect("Failed to decode SchedulerRunEntry");
+        StorableSchedulerRunEntry(entry)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableSchedulerExecutionSummary(pub SchedulerExecutionSummary);
+
+impl Storable for StorableSchedulerExecutionSummary {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode SchedulerExecutionSummary");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let summary: SchedulerExecutionSummary = candid::decode_one(&bytes).expect("Failed to decode SchedulerExecutionSummary");
+        StorableSchedulerExecutionSummary(summary)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableRebalanceSaga(pub RebalanceSaga);
+
+impl Storable for StorableRebalanceSaga {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode RebalanceSaga");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let saga: RebalanceSaga = candid::decode_one(&bytes).expect("Failed to decode RebalanceSaga");
+        StorableRebalanceSaga(saga)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableYieldTenure(pub YieldTenure);
+
+impl Storable for StorableYieldTenure {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode YieldTenure");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let tenure: YieldTenure = candid::decode_one(&bytes).expect("Failed to decode YieldTenure");
+        StorableYieldTenure(tenure)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableYieldSnapshot(pub YieldSnapshot);
+
+impl Storable for StorableYieldSnapshot {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode YieldSnapshot");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let snapshot: YieldSnapshot = candid::decode_one(&bytes).expect("Failed to decode YieldSnapshot");
+        StorableYieldSnapshot(snapshot)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableTokenPrice(pub TokenPrice);
+
+impl Storable for StorableTokenPrice {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode TokenPrice");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let price: TokenPrice = candid::decode_one(&bytes).expect("Failed to decode TokenPrice");
+        StorableTokenPrice(price)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableChainConfig(pub ChainConfig);
+
+impl Storable for StorableChainConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode ChainConfig");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let config: ChainConfig = candid::decode_one(&bytes).expect("Failed to decode ChainConfig");
+        StorableChainConfig(config)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableSentTransaction(pub SentTransaction);
+
+impl Storable for StorableSentTransaction {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode SentTransaction");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let tx: SentTransaction = candid::decode_one(&bytes).expect("Failed to decode SentTransaction");
+        StorableSentTransaction(tx)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableNonceState(pub NonceState);
+
+impl Storable for StorableNonceState {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode NonceState");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let state: NonceState = candid::decode_one(&bytes).expect("Failed to decode NonceState");
+        StorableNonceState(state)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableApyRetentionConfig(pub ApyRetentionConfig);
+
+impl Storable for StorableApyRetentionConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode ApyRetentionConfig");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let config: ApyRetentionConfig = candid::decode_one(&bytes).expect("Failed to decode ApyRetentionConfig");
+        StorableApyRetentionConfig(config)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableFeePolicyConfig(pub FeePolicyConfig);
+
+impl Storable for StorableFeePolicyConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode FeePolicyConfig");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let config: FeePolicyConfig = candid::decode_one(&bytes).expect("Failed to decode FeePolicyConfig");
+        StorableFeePolicyConfig(config)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StorableSchedulerConfig(pub SchedulerConfig);
+
+impl Storable for StorableSchedulerConfig {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        let bytes = candid::encode_one(&self.0).expect("Failed to encode SchedulerConfig");
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        let config: SchedulerConfig = candid::decode_one(&bytes).expect("Failed to decode SchedulerConfig");
+        StorableSchedulerConfig(config)
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
@@ -0,0 +1,74 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle of a transaction signed by the canister
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum TxStatus {
+    /// Sent, not mined yet
+    Pending,
+    /// Mined (successful or reverted)
+    Confirmed,
+    /// Superseded by a speed-up or cancel at the same nonce
+    Replaced,
+    /// Its nonce was mined by another transaction (an earlier version of it,
+    /// or one sent outside the canister)
+    Dropped,
+}
+
+/// Transaction signed by the canister, kept to speed it up or cancel it
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub struct SentTransaction {
+    /// Transaction hash (0x-prefixed)
+    pub tx_hash: String,
+
+    /// Principal whose derived address signed the transaction
+    pub owner: Principal,
+
+    /// Chain the transaction was sent on
+    pub chain_id: u64,
+
+    /// Sender address
+    pub from: String,
+
+    /// Recipient or contract address
+    pub to: Option<String>,
+
+    /// Sender nonce
+    pub nonce: u64,
+
+    /// Value in wei (decimal)
+    pub value: String,
+
+    /// Calldata (0x-prefixed hex)
+    pub input: String,
+
+    /// Gas limit
+    pub gas_limit: u64,
+
+    /// `maxFeePerGas` in wei, or the gas price of a legacy transaction
+    pub max_fee_per_gas: u64,
+
+    /// `maxPriorityFeePerGas` in wei (None for legacy transactions)
+    pub max_priority_fee_per_gas: Option<u64>,
+
+    /// Current status
+    pub status: TxStatus,
+
+    /// Hash of the transaction this one replaced
+    pub replaces: Option<String>,
+
+    /// Hash of the transaction that replaced this one
+    pub replaced_by: Option<String>,
+
+    /// Whether this is a zero-value self-transfer cancelling its nonce
+    pub is_cancel: bool,
+
+    /// Number of fee bumps that led to this transaction
+    pub bump_count: u32,
+
+    /// When the transaction was sent (ms)
+    pub sent_at: u64,
+
+    /// When the status last changed (ms)
+    pub updated_at: u64,
+}
@@ -0,0 +1,173 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use crate::types::TokenAmount;
+
+/// Interest earned by a position: value + withdrawn − deposited, floored at zero
+pub fn interest_earned(
+    current_value: &TokenAmount,
+    principal_withdrawn: &TokenAmount,
+    principal_deposited: &TokenAmount,
+) -> TokenAmount {
+    let returned = current_value.raw.saturating_add(principal_withdrawn.raw);
+    TokenAmount { raw: returned.saturating_sub(principal_deposited.raw), ..*principal_deposited }
+}
+
+/// Continuous holding of one asset in one protocol, from first deposit to full exit
+///
+/// A rebalance closes the tenure in the source protocol and opens a new one in
+/// the target, so realized yield is kept per protocol tenure.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct YieldTenure {
+    /// Unique tenure ID
+    pub tenure_id: String,
+
+    /// User who holds the funds
+    pub user_principal: Principal,
+
+    /// Protocol ("AAVE" | "COMPOUND")
+    pub protocol: String,
+
+    /// Asset symbol (e.g., "USDC")
+    pub asset: String,
+
+    /// Chain ID
+    pub chain_id: u64,
+
+    /// Total principal deposited during the tenure
+    pub principal_deposited: TokenAmount,
+
+    /// Total withdrawn during the tenure (principal and interest)
+    pub principal_withdrawn: TokenAmount,
+
+    /// Latest known on-chain value
+    pub current_value: TokenAmount,
+
+    /// When current_value was last updated
+    pub valued_at: u64,
+
+    /// When the tenure started
+    pub opened_at: u64,
+
+    /// When the balance reached zero (None = still open)
+    pub closed_at: Option<u64>,
+}
+
+impl YieldTenure {
+    /// Interest accrued so far (realized once the tenure is closed)
+    pub fn accrued_interest(&self) -> TokenAmount {
+        interest_earned(&self.current_value, &self.principal_withdrawn, &self.principal_deposited)
+    }
+}
+
+/// Accounting state of a tenure at a point in time
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct YieldSnapshot {
+    /// Tenure this snapshot belongs to
+    pub tenure_id: String,
+
+    /// When the snapshot was taken
+    pub timestamp: u64,
+
+    /// Cumulative principal deposited
+    pub principal_deposited: TokenAmount,
+
+    /// Cumulative amount withdrawn
+    pub principal_withdrawn: TokenAmount,
+
+    /// On-chain value at the time
+    pub current_value: TokenAmount,
+}
+
+impl YieldSnapshot {
+    /// Interest accrued up to this snapshot
+    pub fn accrued_interest(&self) -> TokenAmount {
+        interest_earned(&self.current_value, &self.principal_withdrawn, &self.principal_deposited)
+    }
+}
+
+/// Yield of a single tenure within a report period
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TenureYield {
+    pub tenure_id: String,
+    pub protocol: String,
+    pub asset: String,
+    pub chain_id: u64,
+    pub opened_at: u64,
+    pub closed_at: Option<u64>,
+
+    /// Lifetime principal deposited
+    pub principal_deposited: TokenAmount,
+
+    /// Lifetime amount withdrawn
+    pub principal_withdrawn: TokenAmount,
+
+    /// Latest known on-chain value (zero once closed)
+    pub current_value: TokenAmount,
+
+    /// Lifetime interest accrued so far
+    pub accrued_interest: TokenAmount,
+
+    /// Interest realized when the tenure closed (None while open)
+    pub realized_yield: Option<TokenAmount>,
+
+    /// Interest earned within the report period
+    pub period_interest: TokenAmount,
+}
+
+/// Interest earned on one asset across all tenures
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AssetYield {
+    pub asset: String,
+
+    /// Interest earned within the report period
+    pub period_interest: TokenAmount,
+
+    /// Interest realized by tenures closed within the report period
+    pub realized_yield: TokenAmount,
+}
+
+/// Yield report of a user for a period
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct YieldReport {
+    pub user_principal: Principal,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub tenures: Vec<TenureYield>,
+    pub totals: Vec<AssetYield>,
+    pub generated_at: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usdc(raw: u128) -> TokenAmount {
+        TokenAmount::from_raw(raw, 6).unwrap()
+    }
+
+    #[test]
+    fn interest_is_value_plus_withdrawn_minus_deposited() {
+        // 100 deposited, now worth 103
+        assert_eq!(interest_earned(&usdc(103_000_000), &usdc(0), &usdc(100_000_000)), usdc(3_000_000));
+
+        // 100 deposited, 50 withdrawn, 52.5 left
+        assert_eq!(interest_earned(&usdc(52_500_000), &usdc(50_000_000), &usdc(100_000_000)), usdc(2_500_000));
+
+        // Fully withdrawn with interest: realized yield
+        assert_eq!(interest_earned(&usdc(0), &usdc(104_000_001), &usdc(100_000_000)), usdc(4_000_001));
+    }
+
+    #[test]
+    fn interest_is_floored_at_zero() {
+        // Value dropped below principal (e.g. a stale valuation)
+        assert!(interest_earned(&usdc(99_000_000), &usdc(0), &usdc(100_000_000)).is_zero());
+        assert!(interest_earned(&usdc(0), &usdc(0), &usdc(100_000_000)).is_zero());
+    }
+
+    #[test]
+    fn interest_keeps_decimals_and_saturates() {
+        let interest = interest_earned(&usdc(u128::MAX), &usdc(u128::MAX), &usdc(1));
+        assert_eq!(interest.decimals, 6);
+        assert_eq!(interest.raw, u128::MAX - 1);
+    }
+}